@@ -4,14 +4,20 @@ use super::exploration::ExplorationManager;
 use super::fleet::FleetManager;
 use super::join_handles::JoinHandles;
 use super::ledger::Ledger;
+use super::safe_mode::{self, PanicTracker};
+use crate::api_client::circuit_breaker::BreakerState;
 use crate::broker::CargoBroker;
+use crate::clock::SystemClock;
+use crate::config::CONFIG;
+use crate::mining_stats::MiningStats;
 use crate::models::*;
+use crate::nav_calibration::{NAV_CALIBRATION, NAV_CALIBRATION_KEY};
 use crate::survey_manager::SurveyManager;
 use crate::{
     api_client::ApiClient,
     database::DbClient,
     models::{Agent, Ship},
-    tasks::LogisticTaskManager,
+    tasks::{LogisticTaskManager, TaskManagerRegistry},
     universe::Universe,
 };
 use chrono::Utc;
@@ -19,10 +25,16 @@ use dashmap::DashMap;
 use futures::future::BoxFuture;
 use log::*;
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use strum::EnumString;
 use tokio::time::MissedTickBehavior;
 
+// How long the API must sit idle before a graceful shutdown proceeds, and the most it
+// will wait for that
+const SHUTDOWN_QUIET_SECONDS: u64 = 3;
+const SHUTDOWN_TIMEOUT_SECONDS: u64 = 60;
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, EnumString)]
 pub enum AgentEra {
     StartingSystem1,
@@ -51,7 +63,13 @@ pub struct AgentController {
     pub contracts: ContractManager,
     pub exploration: ExplorationManager,
 
+    // the start system's manager, also registered in task_managers
     pub task_manager: Arc<LogisticTaskManager>,
+    pub task_managers: Arc<TaskManagerRegistry>,
+    pub safe_mode: Arc<PanicTracker>,
+    // set by `shutdown`: no new ship scripts or controller ticks, and ships park at
+    // their next safe point
+    stopping: Arc<AtomicBool>,
 }
 
 impl AgentController {
@@ -89,6 +107,9 @@ impl AgentController {
     pub fn get_current_contract_id(&self) -> Option<String> {
         self.contracts.get_current_contract_id()
     }
+    pub fn get_current_contract(&self) -> Option<Contract> {
+        self.contracts.get_current_contract()
+    }
     pub fn spawn_contract_task(&self) {
         self.contracts.spawn_contract_task();
     }
@@ -122,7 +143,7 @@ impl AgentController {
         // caches before the controller starts, so the first try_buy_ships pass
         // (generate_ship_config, under the buy-lock) hits cache instead of doing
         // ~30 serial API round-trips and stalling the lock past its timeout.
-        universe.ensure_system_loaded(&system_symbol).await;
+        universe.ensure_system_loaded(&system_symbol, false).await;
 
         let job_assignments: DashMap<String, String> = db
             .get_value(&format!("{}/ship_assignments", callsign))
@@ -140,6 +161,9 @@ impl AgentController {
         let explorer_reservations = db.get_explorer_reservations(callsign).await;
         let t5_system_reservations = db.get_t5_system_reservations(callsign).await;
         let task_manager = LogisticTaskManager::new(universe, db, &system_symbol).await;
+        if let Some(calibration) = db.get_value(NAV_CALIBRATION_KEY).await {
+            NAV_CALIBRATION.restore(calibration);
+        }
         let survey_manager = SurveyManager::new(db).await;
 
         let initial_credits = {
@@ -162,6 +186,24 @@ impl AgentController {
             .get_value(&format!("{}/state", callsign))
             .await
             .unwrap_or_default();
+        let pauses = db
+            .get_value(&format!("{}/pauses", callsign))
+            .await
+            .unwrap_or_default();
+        let vacated_jobs = db
+            .get_value(&format!("{}/vacated_jobs", callsign))
+            .await
+            .unwrap_or_default();
+        let task_panics = db
+            .get_value(&format!("{}/task_panics", callsign))
+            .await
+            .unwrap_or_default();
+        let safe_mode = Arc::new(PanicTracker::new(
+            CONFIG.safe_mode_panic_threshold,
+            chrono::Duration::minutes(CONFIG.safe_mode_window_minutes),
+            task_panics,
+            chrono::Utc::now(),
+        ));
 
         let ctx = Arc::new(AgentContext {
             callsign: callsign.to_string(),
@@ -172,13 +214,31 @@ impl AgentController {
             db: db.clone(),
             universe: universe.clone(),
             cargo_broker: Arc::new(CargoBroker::new()),
+            mining_stats: Arc::new(MiningStats::new()),
             survey_manager: Arc::new(survey_manager),
             ledger: Arc::new(ledger),
             ship_state_description: Arc::new(DashMap::new()),
+            clock: Arc::new(SystemClock),
         });
 
-        let hdls = Arc::new(JoinHandles::new());
+        let hdls = {
+            let safe_mode = safe_mode.clone();
+            let db = db.clone();
+            let key = format!("{}/task_panics", callsign);
+            Arc::new(JoinHandles::with_panic_hook(Box::new(move |name| {
+                let safe_mode = safe_mode.clone();
+                let db = db.clone();
+                let key = key.clone();
+                Box::pin(async move {
+                    if safe_mode::is_ship_task(&name) {
+                        safe_mode.record(chrono::Utc::now());
+                        db.set_value(&key, &safe_mode.panics()).await;
+                    }
+                })
+            })))
+        };
         let task_manager = Arc::new(task_manager);
+        let task_managers = Arc::new(TaskManagerRegistry::new(&task_manager, universe, db));
 
         let fleet = FleetManager::new(
             ctx.clone(),
@@ -187,7 +247,10 @@ impl AgentController {
             Arc::new(job_assignments_rev),
             hdls.clone(),
             task_manager.clone(),
+            pauses,
         );
+        fleet.restore_vacated_jobs(vacated_jobs);
+        fleet.repair_assignments();
 
         let contracts = ContractManager::new(ctx.clone(), fleet.clone());
         let exploration = ExplorationManager::new(
@@ -204,9 +267,12 @@ impl AgentController {
             contracts,
             exploration,
             task_manager,
+            task_managers,
+            safe_mode,
+            stopping: Arc::new(AtomicBool::new(false)),
         };
         agent_controller
-            .task_manager
+            .task_managers
             .set_agent_controller(&agent_controller);
         let credits = agent_controller.ctx.ledger.credits();
         let num_ships = agent_controller.num_ships();
@@ -246,9 +312,15 @@ impl AgentController {
         &self,
         purchaser: Option<String>,
     ) -> (Vec<String>, Option<WaypointSymbol>) {
+        if self.is_stopping() {
+            return (vec![], None);
+        }
         self.fleet.try_buy_ships(purchaser).await
     }
     pub fn spawn_run_ship(&self, ship_symbol: String) -> BoxFuture<'_, ()> {
+        if self.is_stopping() {
+            return Box::pin(async {});
+        }
         self.fleet.spawn_run_ship(self, ship_symbol)
     }
 
@@ -290,7 +362,58 @@ impl AgentController {
         self.fleet.hdls.join().await;
     }
 
+    // Start a graceful stop: nothing new is spawned or bought, and each ship parks at
+    // its script's next safe point (see `ship_scripts::wait_while_paused`). Follow with
+    // `drain`, then `abort`.
+    pub fn shutdown(&self) {
+        warn!("Shutting down agent controller");
+        self.stopping.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::Relaxed)
+    }
+
+    // Wait for the API to go quiet, with nothing queued or on the wire for
+    // SHUTDOWN_QUIET_SECONDS, so no response is cut off before it's been applied. Gives
+    // up after SHUTDOWN_TIMEOUT_SECONDS. Then persist the state only saved per tick.
+    pub async fn drain(&self) {
+        let started = tokio::time::Instant::now();
+        let timeout = tokio::time::Duration::from_secs(SHUTDOWN_TIMEOUT_SECONDS);
+        let mut quiet = 0;
+        while quiet < SHUTDOWN_QUIET_SECONDS {
+            if started.elapsed() > timeout {
+                warn!(
+                    "API still busy after {}s, shutting down anyway",
+                    SHUTDOWN_TIMEOUT_SECONDS
+                );
+                break;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            let snapshot = self.ctx.api_client.metrics_snapshot();
+            quiet = match snapshot.queued + snapshot.in_flight {
+                0 => quiet + 1,
+                _ => 0,
+            };
+        }
+        self.save_ledger().await;
+        info!("Agent controller drained");
+    }
+
+    // Stop every task `run` started (ship scripts included); `run` then returns
+    pub fn abort(&self) {
+        warn!("Aborting agent controller tasks");
+        self.fleet.hdls.shutdown();
+    }
+
     async fn run_agent(&self) {
+        if self.is_stopping() {
+            return;
+        }
+        if self.safe_mode.is_tripped() {
+            error!("SAFE MODE: not starting any ship scripts");
+            return;
+        }
         let (_bought, _tasks) = self.fleet.try_buy_ships(None).await;
         for ship in self.ctx.ships.iter() {
             let ship_symbol = ship.key().clone();
@@ -299,24 +422,80 @@ impl AgentController {
     }
 
     async fn controller_loop(&self) {
-        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(60));
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(
+            CONFIG.controller_tick_seconds,
+        ));
         interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut api_report = tokio::time::interval(tokio::time::Duration::from_secs(60));
+        api_report.set_missed_tick_behavior(MissedTickBehavior::Skip);
         loop {
-            interval.tick().await;
-            self.controller_tick().await;
+            tokio::select! {
+                _ = interval.tick() => self.controller_tick().await,
+                _ = api_report.tick() => self.log_api_metrics(),
+            }
         }
     }
 
+    // Where the request budget has gone since startup: the busiest endpoints
+    fn log_api_metrics(&self) {
+        let snapshot = self.ctx.api_client.metrics_snapshot();
+        let busiest = snapshot
+            .busiest()
+            .into_iter()
+            .take(5)
+            .map(|(endpoint, e)| {
+                format!(
+                    "{} {} ({} errors, p50 {}ms, p99 {}ms)",
+                    endpoint,
+                    e.requests,
+                    e.errors,
+                    e.p50_latency_ms(),
+                    e.p99_latency_ms()
+                )
+            })
+            .collect::<Vec<_>>();
+        info!(
+            "API requests since startup: {} ({} queued, {} in flight). Busiest: {}",
+            snapshot.total_requests(),
+            snapshot.queued,
+            snapshot.in_flight,
+            busiest.join(", ")
+        );
+    }
+
     async fn controller_tick(&self) {
         debug!("controller_tick");
+        if self.is_stopping() {
+            return;
+        }
+        let breaker = self.ctx.api_client.breaker_state();
+        if breaker != BreakerState::Closed {
+            // requests would only queue behind the breaker; ship tasks are already
+            // parked inside theirs
+            warn!("API circuit {:?}: skipping controller tick", breaker);
+            return;
+        }
         self.record_metrics().await;
+        if self.safe_mode.is_tripped() {
+            warn!("SAFE MODE: observe only, skipping buying, contracts and rebalancing");
+            return;
+        }
         self.fleet.check_era_advance().await;
-        let (bought, _shipyard_task_waypoint) = self.fleet.try_buy_ships(None).await;
-        for ship_symbol in bought {
-            debug!("Controller tick bought ship {}", ship_symbol);
-            self.fleet.spawn_run_ship(self, ship_symbol).await;
+        // The purchases a dry run would make are logged once, at startup (run_agent)
+        if !CONFIG.dry_run {
+            let (bought, _shipyard_task_waypoint) = self.fleet.try_buy_ships(None).await;
+            for ship_symbol in bought {
+                debug!("Controller tick bought ship {}", ship_symbol);
+                self.fleet.spawn_run_ship(self, ship_symbol).await;
+            }
+            self.contract_tick(true).await;
+        } else {
+            self.dry_run_plan_logistics().await;
         }
-        self.contract_tick(true).await;
+        self.fleet.rebalance_fleet(&self.task_managers).await;
+        self.ctx
+            .mining_stats
+            .tick(CONFIG.mining_opportunity_cost_per_hour);
     }
 
     // Append a KPI snapshot for time-series analysis (equity curve, fleet size).
@@ -344,6 +523,19 @@ impl AgentController {
                 debug!("Cash reconciliation OK (gap {} credits)", gap);
             }
         }
+        let reserved = self.ctx.ledger.effective_reserved_credits();
+        let available = self.ctx.ledger.available_credits();
+        info!(
+            "Ledger: {} credits, {} reserved, {} available",
+            credits, reserved, available
+        );
+        if available < 0 {
+            warn!(
+                "Reservations exceed credits by {}, purchasing is blocked: {:?}",
+                -available,
+                self.ctx.ledger.reservations()
+            );
+        }
         let cargo_value = self.ctx.ledger.cargo_value();
         // net worth ~= liquid credits + in-transit cargo + ship cost basis
         let net_worth = credits + cargo_value + self.ctx.db.ship_cost_basis().await;
@@ -352,8 +544,8 @@ impl AgentController {
             .insert_agent_metrics(
                 Utc::now(),
                 credits,
-                self.ctx.ledger.available_credits(),
-                self.ctx.ledger.effective_reserved_credits(),
+                available,
+                reserved,
                 cargo_value,
                 self.num_ships() as i32,
                 net_worth,
@@ -361,6 +553,10 @@ impl AgentController {
             .await;
         // Persist cargo cost basis each tick so it survives a restart (cheap:
         // a small map, written once per controller tick rather than per trade).
+        self.save_ledger().await;
+    }
+
+    async fn save_ledger(&self) {
         self.ctx
             .db
             .set_value(
@@ -1,7 +1,9 @@
 use crate::api_client::ApiClient;
 use crate::api_client::api_models::TransferResponse;
 use crate::broker::{CargoBroker, TransferActor};
+use crate::clock::Clock;
 use crate::database::DbClient;
+use crate::mining_stats::{MiningEvent, MiningStats};
 use crate::models::*;
 use crate::survey_manager::SurveyManager;
 use crate::universe::Universe;
@@ -26,10 +28,47 @@ pub struct AgentContext {
     pub ledger: Arc<Ledger>,
     pub survey_manager: Arc<SurveyManager>,
     pub cargo_broker: Arc<CargoBroker>,
+    pub mining_stats: Arc<MiningStats>,
     pub ship_state_description: Arc<DashMap<String, String>>,
+    pub clock: Arc<dyn Clock>,
 }
 
 impl AgentContext {
+    // Test seam: an agent with no ships, no DB and no API, on the given clock
+    #[cfg(test)]
+    pub(crate) fn for_test(clock: Arc<dyn Clock>) -> AgentContext {
+        let api_client = ApiClient::for_test();
+        let db = DbClient::disconnected();
+        AgentContext {
+            universe: Arc::new(Universe::from_caches_for_test(
+                api_client.clone(),
+                db.clone(),
+                vec![],
+                vec![],
+                vec![],
+            )),
+            api_client,
+            callsign: "TEST".to_string(),
+            agent: Arc::new(Mutex::new(Agent {
+                account_id: None,
+                symbol: "TEST".to_string(),
+                headquarters: WaypointSymbol::new("X1-S1-A1"),
+                credits: 0,
+                starting_faction: "COSMIC".to_string(),
+                ship_count: 0,
+            })),
+            ships: Arc::new(DashMap::new()),
+            contract: Arc::new(Mutex::new(None)),
+            ledger: Arc::new(Ledger::new(0)),
+            survey_manager: Arc::new(SurveyManager::empty_for_test(&db)),
+            cargo_broker: Arc::new(CargoBroker::new()),
+            mining_stats: Arc::new(MiningStats::new()),
+            ship_state_description: Arc::new(DashMap::new()),
+            clock,
+            db,
+        }
+    }
+
     pub fn agent(&self) -> Agent {
         self.agent.lock().unwrap().clone()
     }
@@ -102,6 +141,8 @@ impl AgentContext {
             src_ship.cargo = cargo;
             dest_ship.cargo = target_cargo;
         }
+        self.mining_stats
+            .record(&src_ship_symbol, MiningEvent::Transferred { good, units });
         debug!("agent_context::transfer_cargo done");
     }
 }
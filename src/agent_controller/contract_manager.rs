@@ -10,7 +10,9 @@ use std::sync::Arc;
 pub enum ContractStatus {
     CouldNotNegotiate,
     WillNotFulfill(&'static str),
-    RequiresLogisticsTask(WaypointSymbol, WaypointSymbol, MarketTradeGood, i64),
+    // source market, destination, trade at the source, units missing, and the share of
+    // the fulfillment payment those units earn
+    RequiresLogisticsTask(WaypointSymbol, WaypointSymbol, MarketTradeGood, i64, i64),
     Skipped,
 }
 
@@ -258,6 +260,11 @@ impl ContractManager {
                                         deliver.destination_symbol.clone(),
                                         (*trade).clone(),
                                         missing,
+                                        amortized_payment(
+                                            contract.terms.payment.on_fulfilled,
+                                            deliver.units_required,
+                                            missing,
+                                        ),
                                     )
                                 }
                             }
@@ -286,6 +293,15 @@ impl ContractManager {
     }
 }
 
+// The part of the fulfillment payment earned by delivering `units` of `units_required`.
+// on_accepted was paid up front, so only on_fulfilled is still to be earned.
+pub fn amortized_payment(on_fulfilled: i64, units_required: i64, units: i64) -> i64 {
+    if units_required <= 0 {
+        return 0;
+    }
+    on_fulfilled * units / units_required
+}
+
 // Split a contract payout across delivering ships in proportion to units delivered.
 // Returns one share per entry of `deliveries` (same order); the last ship absorbs the
 // integer-division remainder so the shares sum to `amount` exactly (the cash journal
@@ -313,7 +329,7 @@ fn split_payment_by_units(deliveries: &[(String, i64)], amount: i64) -> Vec<i64>
 
 #[cfg(test)]
 mod tests {
-    use super::split_payment_by_units;
+    use super::{amortized_payment, split_payment_by_units};
 
     fn d(pairs: &[(&str, i64)]) -> Vec<(String, i64)> {
         pairs.iter().map(|(s, u)| (s.to_string(), *u)).collect()
@@ -347,4 +363,12 @@ mod tests {
         assert!(split_payment_by_units(&[], 1000).is_empty());
         assert!(split_payment_by_units(&d(&[("A", 0)]), 1000).is_empty());
     }
+
+    #[test]
+    fn amortizes_fulfillment_payment_over_units() {
+        assert_eq!(amortized_payment(90_000, 60, 60), 90_000);
+        assert_eq!(amortized_payment(90_000, 60, 20), 30_000);
+        assert_eq!(amortized_payment(90_000, 60, 0), 0);
+        assert_eq!(amortized_payment(90_000, 0, 0), 0);
+    }
 }
@@ -0,0 +1,83 @@
+// A dry run starts no ship scripts, so the logistics planner would never run. Instead the
+// controller tick plans each logistics ship read-only, as the what-if does, and logs the
+// schedule it would follow. Nothing is queued, reserved or marked in progress.
+
+use super::AgentController;
+use super::fleet::logistics_job_system;
+use crate::config::CONFIG;
+use crate::models::ShipBehaviour;
+use log::*;
+
+impl AgentController {
+    pub(super) async fn dry_run_plan_logistics(&self) {
+        let jobs = self.fleet.get_ship_config();
+        let mut ship_symbols = self
+            .ctx
+            .ships
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect::<Vec<_>>();
+        ship_symbols.sort();
+        for ship_symbol in ship_symbols {
+            let Some(job) = self
+                .fleet
+                .assigned_job(&ship_symbol)
+                .and_then(|job_id| jobs.iter().find(|job| job.id == job_id))
+            else {
+                continue;
+            };
+            let ShipBehaviour::Logistics(config) = &job.behaviour else {
+                continue;
+            };
+            if !CONFIG.job_id_filter.is_match(&job.id) {
+                continue;
+            }
+            let system = logistics_job_system(config, self.task_manager.system());
+            let ship = self.fleet.ship_controller(&ship_symbol);
+            let waypoint = ship.waypoint();
+            // The planner starts ships from a market in the manager's system
+            let at_market = ship.system() == system
+                && self
+                    .ctx
+                    .universe
+                    .get_system_waypoints(&system)
+                    .await
+                    .iter()
+                    .any(|w| w.symbol == waypoint && w.is_market());
+            if !at_market {
+                info!(
+                    "DRY RUN: not planning {} (job {}): not at a market in {}",
+                    ship_symbol, job.id, system
+                );
+                continue;
+            }
+            let plan = self
+                .task_managers
+                .get(&system)
+                .await
+                .plan_hypothetical_ship(
+                    &waypoint,
+                    ship.cargo_capacity(),
+                    ship.engine_speed(),
+                    ship.fuel_capacity(),
+                )
+                .await;
+            info!(
+                "DRY RUN: {} (job {}) would take {} of {} trades from {}, worth ${} over {}s",
+                ship_symbol,
+                job.id,
+                plan.tasks_assigned,
+                plan.tasks_available,
+                waypoint,
+                plan.value,
+                plan.plan_length_secs
+            );
+            for action in &plan.actions {
+                info!(
+                    "DRY RUN: {}   +{:.0}s {} {:?}",
+                    ship_symbol, action.timestamp, action.waypoint, action.action
+                );
+            }
+        }
+    }
+}
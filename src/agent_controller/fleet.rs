@@ -1,21 +1,32 @@
 use super::AgentController;
 use super::context::AgentContext;
 use super::join_handles::JoinHandles;
+use super::pause::{self, PauseScope};
+use super::progression::{self, ProgressionContext};
 use crate::api_client::api_models::{BuyShipResponse, WaypointDetailed};
-use crate::config::CONFIG;
+use crate::config::{CONFIG, Config};
 use crate::models::{ShipNavStatus::*, *};
 use crate::ship_config::ship_config_starter_system;
 use crate::universe::WaypointFilter;
-use crate::{ship_controller::ShipController, ship_scripts, tasks::LogisticTaskManager};
+use crate::{
+    ship_controller::ShipController,
+    ship_scripts,
+    tasks::{LogisticTaskManager, TaskManagerRegistry},
+};
 use dashmap::DashMap;
 use futures::future::BoxFuture;
 use log::*;
 use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ops::Deref;
 use std::sync::{Arc, Mutex};
 
 use super::agent_controller::{AgentEra, AgentState};
 
+// A shipyard listing older than this may no longer be true (the shipyard can stop selling
+// a model). Static probes refresh their shipyard hourly.
+const SHIPYARD_LISTING_STALE_MINUTES: i64 = 90;
+
 #[derive(Clone, Debug)]
 enum BuyShipResult {
     Bought(String),
@@ -23,6 +34,132 @@ enum BuyShipResult {
     FailedLowCredits,
     FailedNoShipyards,
     FailedNoPurchaser(Option<WaypointSymbol>),
+    // every listing is stale: send a ship to the cheapest to re-check it
+    FailedStaleListings(WaypointSymbol),
+    // CONFIG.dry_run: the purchase was logged, not made
+    DryRun,
+}
+
+// The shipyards worth trying, cheapest first. A stale listing is only worth it when a
+// ship is there to re-check it before buying.
+fn buy_candidates(
+    shipyards: &[(WaypointSymbol, i64)],
+    is_stale: impl Fn(&WaypointSymbol) -> bool,
+    has_purchaser: impl Fn(&WaypointSymbol) -> bool,
+) -> Vec<(WaypointSymbol, i64)> {
+    let mut candidates = shipyards
+        .iter()
+        .filter(|(shipyard, _)| !is_stale(shipyard) || has_purchaser(shipyard))
+        .cloned()
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|(_, price)| *price);
+    candidates
+}
+
+// The cheapest affordable candidate that hasn't just failed a re-check. `candidates` is
+// cheapest first (see buy_candidates).
+fn next_buy_shipyard(
+    candidates: &[(WaypointSymbol, i64)],
+    rejected: &BTreeSet<WaypointSymbol>,
+    affordable: impl Fn(i64) -> bool,
+) -> Option<WaypointSymbol> {
+    candidates
+        .iter()
+        .find(|(shipyard, cost)| !rejected.contains(shipyard) && affordable(*cost))
+        .map(|(shipyard, _)| shipyard.clone())
+}
+
+// How often roaming probes should lap their markets. Early on a looser target saves
+// probe purchases while credits are scarce; later, fresher data pays for itself.
+pub fn probe_refresh_target(era: AgentEra) -> chrono::Duration {
+    let minutes = match era {
+        AgentEra::StartingSystem1 => CONFIG.probe_refresh_minutes * 2,
+        _ => CONFIG.probe_refresh_minutes,
+    };
+    chrono::Duration::minutes(minutes)
+}
+
+// The open job a ship should take: the first of its model, except that a probe whose
+// job was re-partitioned away prefers a probe job covering where it already is.
+fn pick_job<'a>(
+    open_jobs: impl Iterator<Item = &'a ShipConfig>,
+    at: &WaypointSymbol,
+) -> Option<&'a ShipConfig> {
+    let open_jobs = open_jobs.collect::<Vec<_>>();
+    let covering = open_jobs.iter().copied().find(|job| match &job.behaviour {
+        ShipBehaviour::Probe(config) => config.waypoints.contains(at),
+        _ => false,
+    });
+    covering.or(open_jobs.first().copied())
+}
+
+// How long a logistics ship must have had nothing to do before it's moved elsewhere
+const REBALANCE_IDLE_MINUTES: i64 = 30;
+
+// The system a logistics job trades in: a job pinned to waypoints trades in their
+// system; otherwise home
+pub fn logistics_job_system(config: &LogisticsScriptConfig, home: &SystemSymbol) -> SystemSymbol {
+    match config.waypoint_allowlist.as_ref().and_then(|w| w.first()) {
+        Some(waypoint) => waypoint.system(),
+        None => home.clone(),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct IdleShip {
+    ship_symbol: String,
+    job_id: String,
+    ship_model: String,
+    system: SystemSymbol,
+}
+
+// A logistics job with no ship
+#[derive(Debug, Clone)]
+struct JobOpening {
+    job_id: String,
+    ship_model: String,
+    system: SystemSymbol,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Reassignment {
+    ship_symbol: String,
+    from_job: String,
+    from_system: SystemSymbol,
+    to_job: String,
+    to_system: SystemSymbol,
+}
+
+// Fill openings in other, underserved systems with idle ships of the same model, each
+// going to the reachable opening it's nearest to (in opening order). `travel_cost` is
+// None when no route is known.
+fn plan_rebalance(
+    idle: &[IdleShip],
+    openings: &[JobOpening],
+    underserved: impl Fn(&SystemSymbol) -> bool,
+    travel_cost: impl Fn(&SystemSymbol, &SystemSymbol) -> Option<i64>,
+) -> Vec<Reassignment> {
+    let mut moved = BTreeSet::new();
+    let mut reassignments = vec![];
+    for opening in openings.iter().filter(|o| underserved(&o.system)) {
+        let nearest = idle
+            .iter()
+            .filter(|ship| !moved.contains(&ship.ship_symbol))
+            .filter(|ship| ship.ship_model == opening.ship_model && ship.system != opening.system)
+            .filter_map(|ship| travel_cost(&ship.system, &opening.system).map(|cost| (ship, cost)))
+            .min_by_key(|(_, cost)| *cost);
+        if let Some((ship, _)) = nearest {
+            moved.insert(ship.ship_symbol.clone());
+            reassignments.push(Reassignment {
+                ship_symbol: ship.ship_symbol.clone(),
+                from_job: ship.job_id.clone(),
+                from_system: ship.system.clone(),
+                to_job: opening.job_id.clone(),
+                to_system: opening.system.clone(),
+            });
+        }
+    }
+    reassignments
 }
 
 #[derive(Clone)]
@@ -34,6 +171,10 @@ pub struct FleetManager {
     pub(super) job_assignments_rev: Arc<DashMap<String, String>>,
     pub(super) hdls: Arc<JoinHandles>,
     task_manager: Arc<LogisticTaskManager>,
+    pauses: Arc<Mutex<Vec<PauseScope>>>,
+    // jobs a rebalance moved the ship out of, with the system it left: not bought for
+    // again until that system is underserved
+    vacated_jobs: Arc<Mutex<BTreeMap<String, SystemSymbol>>>,
     try_buy_ships_mutex_guard: Arc<tokio::sync::Mutex<()>>,
 }
 
@@ -45,6 +186,7 @@ impl FleetManager {
         job_assignments_rev: Arc<DashMap<String, String>>,
         hdls: Arc<JoinHandles>,
         task_manager: Arc<LogisticTaskManager>,
+        pauses: Vec<PauseScope>,
     ) -> Self {
         Self {
             ctx,
@@ -54,6 +196,8 @@ impl FleetManager {
             job_assignments_rev,
             hdls,
             task_manager,
+            pauses: Arc::new(Mutex::new(pauses)),
+            vacated_jobs: Arc::new(Mutex::new(BTreeMap::new())),
             try_buy_ships_mutex_guard: Arc::new(tokio::sync::Mutex::new(())),
         }
     }
@@ -88,6 +232,125 @@ impl FleetManager {
         self.job_assignments.contains_key(job_id)
     }
 
+    pub fn assigned_job(&self, ship_symbol: &str) -> Option<String> {
+        self.job_assignments_rev
+            .get(ship_symbol)
+            .map(|job_id| job_id.clone())
+    }
+
+    pub fn pauses(&self) -> Vec<PauseScope> {
+        self.pauses.lock().unwrap().clone()
+    }
+
+    async fn set_pauses(&self, update: impl FnOnce(&mut Vec<PauseScope>)) -> Vec<PauseScope> {
+        let pauses = {
+            let mut pauses = self.pauses.lock().unwrap();
+            update(&mut pauses);
+            pauses.clone()
+        };
+        self.ctx
+            .db
+            .set_value(&format!("{}/pauses", self.ctx.callsign), &pauses)
+            .await;
+        pauses
+    }
+
+    pub fn restore_vacated_jobs(&self, jobs: BTreeMap<String, SystemSymbol>) {
+        *self.vacated_jobs.lock().unwrap() = jobs;
+    }
+
+    fn job_vacated(&self, job_id: &str) -> bool {
+        self.vacated_jobs.lock().unwrap().contains_key(job_id)
+    }
+
+    async fn set_vacated_jobs(&self, update: impl FnOnce(&mut BTreeMap<String, SystemSymbol>)) {
+        let jobs = {
+            let mut jobs = self.vacated_jobs.lock().unwrap();
+            update(&mut jobs);
+            jobs.clone()
+        };
+        self.ctx
+            .db
+            .set_value(&format!("{}/vacated_jobs", self.ctx.callsign), &jobs)
+            .await;
+    }
+
+    pub async fn pause(&self, scope: PauseScope) -> Vec<PauseScope> {
+        info!("Pausing {:?}", scope);
+        self.set_pauses(|pauses| {
+            if !pauses.contains(&scope) {
+                pauses.push(scope);
+            }
+        })
+        .await
+    }
+
+    pub async fn resume(&self, scope: &PauseScope) -> Vec<PauseScope> {
+        info!("Resuming {:?}", scope);
+        self.set_pauses(|pauses| pauses.retain(|s| s != scope))
+            .await
+    }
+
+    // `system` stands in for a roaming job's system (see pause::job_system)
+    fn job_paused(&self, job: &ShipConfig, system: &SystemSymbol) -> bool {
+        let pauses = self.pauses.lock().unwrap();
+        let system =
+            pause::job_system(job, self.task_manager.system()).unwrap_or_else(|| system.clone());
+        pauses.iter().any(|scope| scope.matches(job, &system))
+    }
+
+    pub fn is_paused(&self, job_id: &str, system: &SystemSymbol) -> bool {
+        self.get_ship_config()
+            .iter()
+            .find(|job| job.id == job_id)
+            .is_some_and(|job| self.job_paused(job, system))
+    }
+
+    pub fn is_ship_paused(&self, ship_symbol: &str) -> bool {
+        let Some(job_id) = self.assigned_job(ship_symbol) else {
+            return false;
+        };
+        let Some(system) = self
+            .ctx
+            .ships
+            .get(ship_symbol)
+            .map(|ship| ship.lock().unwrap().nav.system_symbol.clone())
+        else {
+            return false;
+        };
+        self.is_paused(&job_id, &system)
+    }
+
+    // Make job_assignments and job_assignments_rev a consistent one-to-one pair again
+    // if the persisted state (or a bug) has left them otherwise. Run at startup and on
+    // every refresh_ship_config.
+    pub fn repair_assignments(&self) {
+        let forward = self
+            .job_assignments
+            .iter()
+            .map(|x| (x.key().clone(), x.value().clone()))
+            .collect();
+        let reverse = self
+            .job_assignments_rev
+            .iter()
+            .map(|x| (x.key().clone(), x.value().clone()))
+            .collect();
+        let (repaired, issues) = repair_assignment_maps(forward, reverse);
+        if issues.is_empty() {
+            return;
+        }
+        for issue in &issues {
+            warn!("Assignment state corrupt: {}", issue);
+        }
+        self.job_assignments
+            .retain(|job_id, ship_symbol| repaired.get(job_id) == Some(ship_symbol));
+        self.job_assignments_rev
+            .retain(|ship_symbol, job_id| repaired.get(job_id) == Some(ship_symbol));
+        for (job_id, ship_symbol) in repaired {
+            self.job_assignments_rev.insert(ship_symbol, job_id);
+        }
+    }
+
     pub fn probed_waypoints(&self) -> Vec<(String, Vec<WaypointSymbol>)> {
         let ship_config = self.ship_config.lock().unwrap();
         ship_config
@@ -140,15 +403,11 @@ impl FleetManager {
     }
 
     pub fn reserve_credits_for_job(&self, job: &ShipConfig, ship_symbol: &str) {
-        match &job.behaviour {
-            ShipBehaviour::Logistics(_) => {}
-            _ => return,
-        }
         let ship = self.ctx.ships.get(ship_symbol).unwrap();
-        let ship = ship.lock().unwrap();
-        self.ctx
-            .ledger
-            .reserve_credits(ship_symbol, ship.cargo.capacity * 5000);
+        let capacity = ship.lock().unwrap().cargo.capacity;
+        if let Some(amount) = job_reservation(job, capacity, &CONFIG) {
+            self.ctx.ledger.reserve_credits(ship_symbol, amount);
+        }
     }
 
     async fn buy_ship(&self, shipyard: &WaypointSymbol, ship_model: &str) -> String {
@@ -212,6 +471,16 @@ impl FleetManager {
         }
     }
 
+    pub fn shipyard_listing_stale(
+        &self,
+        shipyard: &WaypointSymbol,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> bool {
+        self.ctx.universe.get_shipyard(shipyard).is_none_or(|s| {
+            now - s.timestamp > chrono::Duration::minutes(SHIPYARD_LISTING_STALE_MINUTES)
+        })
+    }
+
     async fn try_buy_ship(&self, purchaser: &Option<String>, job: &ShipConfig) -> BuyShipResult {
         let purchase_criteria = &job.purchase_criteria;
         debug!(
@@ -226,38 +495,25 @@ impl FleetManager {
             None => self.ctx.starting_system(),
         };
 
-        let mut shipyards = self
+        let all_shipyards = self
             .ctx
             .universe
             .search_shipyards(&purchase_system, &job.ship_model)
             .await;
-        shipyards.sort_by_key(|x| x.1);
-
-        if shipyards.is_empty() {
+        if all_shipyards.is_empty() {
             return BuyShipResult::FailedNoShipyards;
         }
-        let job_credit_reservation = match &job.behaviour {
-            ShipBehaviour::Logistics(_) => {
-                SHIP_MODELS[job.ship_model.as_str()].cargo_capacity * 5000
-            }
-            _ => 0,
-        };
-        let current_credits = self.ctx.ledger.available_credits();
-        let cheapest_shipard = shipyards[0].0.clone();
-        let can_afford_cheapest = current_credits >= shipyards[0].1 + job_credit_reservation;
-        debug!("try_buy_ship Credits available: {}", current_credits);
-        debug!(
-            "try_buy_ship Extra credits for job reservation: {}",
-            job_credit_reservation
-        );
-
+        let now = chrono::Utc::now();
+        let is_stale = |shipyard: &WaypointSymbol| self.shipyard_listing_stale(shipyard, now);
+        let job_credit_reservation = job_reservation(
+            job,
+            SHIP_MODELS[job.ship_model.as_str()].cargo_capacity,
+            &CONFIG,
+        )
+        .unwrap_or(0);
         let static_probes = self.statically_probed_waypoints();
-        for (shipyard, cost) in &shipyards {
-            if current_credits < cost + job_credit_reservation {
-                break;
-            }
-            let ship_symbol: Option<String> = self
-                .ctx
+        let purchaser_at = |shipyard: &WaypointSymbol| -> Option<String> {
+            self.ctx
                 .ships
                 .iter()
                 .find(|ship| {
@@ -272,7 +528,37 @@ impl FleetManager {
                     };
                     is_static_probe || is_purchaser
                 })
-                .map(|ship| ship.key().clone());
+                .map(|ship| ship.key().clone())
+        };
+        let shipyards = buy_candidates(&all_shipyards, is_stale, |shipyard| {
+            purchaser_at(shipyard).is_some()
+        });
+        let current_credits = self.ctx.ledger.available_credits();
+        if shipyards.is_empty() {
+            let (cheapest_stale, cost) = all_shipyards
+                .iter()
+                .min_by_key(|(_, price)| *price)
+                .unwrap();
+            if current_credits < cost + job_credit_reservation {
+                return BuyShipResult::FailedLowCredits;
+            }
+            return BuyShipResult::FailedStaleListings(cheapest_stale.clone());
+        }
+
+        let can_afford_cheapest = current_credits >= shipyards[0].1 + job_credit_reservation;
+        // shipyards that failed the fresh re-check below
+        let mut rejected = BTreeSet::new();
+        debug!("try_buy_ship Credits available: {}", current_credits);
+        debug!(
+            "try_buy_ship Extra credits for job reservation: {}",
+            job_credit_reservation
+        );
+
+        for (shipyard, cost) in &shipyards {
+            if current_credits < cost + job_credit_reservation {
+                break;
+            }
+            let ship_symbol = purchaser_at(shipyard);
             let ship_controller = match &ship_symbol {
                 Some(ship_symbol) => self.ship_controller(ship_symbol),
                 None => {
@@ -283,6 +569,42 @@ impl FleetManager {
                     }
                 }
             };
+            // The listing may predate the shipyard dropping the model or raising its
+            // price: re-check first rather than send a purchase that fails.
+            ship_controller.refresh_shipyard().await;
+            let fresh_price = self.ctx.universe.get_shipyard(shipyard).and_then(|s| {
+                s.data
+                    .ships
+                    .iter()
+                    .find(|s| s.ship_type == job.ship_model)
+                    .map(|s| s.purchase_price)
+            });
+            match fresh_price {
+                None => {
+                    warn!(
+                        "Shipyard {} no longer sells {}; skipping it",
+                        shipyard, job.ship_model
+                    );
+                    rejected.insert(shipyard.clone());
+                    continue;
+                }
+                Some(price) if current_credits < price + job_credit_reservation => {
+                    warn!(
+                        "Shipyard {} now sells {} for ${} (listed ${}); can't afford it, skipping it",
+                        shipyard, job.ship_model, price, cost
+                    );
+                    rejected.insert(shipyard.clone());
+                    continue;
+                }
+                Some(_) => {}
+            }
+            if CONFIG.dry_run {
+                info!(
+                    "DRY RUN: would buy {} at {} for ${} for job {}",
+                    job.ship_model, shipyard, cost, job.id
+                );
+                return BuyShipResult::DryRun;
+            }
             let bought_ship_symbol = self.buy_ship(shipyard, &job.ship_model).await;
             ship_controller.refresh_shipyard().await;
             let assigned = self.try_assign_ship(&bought_ship_symbol).await;
@@ -292,10 +614,16 @@ impl FleetManager {
         if !can_afford_cheapest {
             return BuyShipResult::FailedLowCredits;
         }
-        if purchase_criteria.allow_logistic_task {
-            BuyShipResult::FailedNoPurchaser(Some(cheapest_shipard))
-        } else {
-            BuyShipResult::FailedNoPurchaser(None)
+        // Where to send a ship next: never back to a shipyard that just failed its
+        // re-check
+        let cheapest_shipyard = next_buy_shipyard(&shipyards, &rejected, |cost| {
+            current_credits >= cost + job_credit_reservation
+        });
+        match cheapest_shipyard {
+            Some(shipyard) if purchase_criteria.allow_logistic_task => {
+                BuyShipResult::FailedNoPurchaser(Some(shipyard))
+            }
+            _ => BuyShipResult::FailedNoPurchaser(None),
         }
     }
 
@@ -326,12 +654,18 @@ impl FleetManager {
                 // dev run (e.g. JOB_ID_FILTER=^t5_trader/1$) can't purchase other jobs.
                 // Default ".*" matches everything, so prod behaviour is unchanged.
                 && CONFIG.job_id_filter.is_match(&job.id)
+                // Paused jobs get no new ships until resumed
+                && !self.job_paused(job, self.task_manager.system())
+                // nor do jobs whose ship was rebalanced away from an idle system
+                && !self.job_vacated(&job.id)
         }) {
             let result = self.try_buy_ship(&purchaser, job).await;
             match result {
                 BuyShipResult::Bought(ship_symbol) => {
                     purchased_ships.push(ship_symbol);
                 }
+                // credits aren't spent, so carry on: every job we'd buy for gets logged
+                BuyShipResult::DryRun => {}
                 BuyShipResult::FailedNeverPurchase => {
                     debug!("Not buying ship {}: never_purchase", job.ship_model);
                     return (purchased_ships, None);
@@ -344,6 +678,13 @@ impl FleetManager {
                     debug!("Not buying ship {}: no shipyards", job.ship_model);
                     return (purchased_ships, None);
                 }
+                BuyShipResult::FailedStaleListings(waypoint) => {
+                    debug!(
+                        "Not buying ship {}: all listings stale. Adding task @ {}",
+                        job.ship_model, waypoint
+                    );
+                    return (purchased_ships, Some(waypoint));
+                }
                 BuyShipResult::FailedNoPurchaser(waypoint) => {
                     if let Some(waypoint) = waypoint {
                         debug!(
@@ -363,11 +704,17 @@ impl FleetManager {
     pub async fn try_assign_ship(&self, ship_symbol: &str) -> bool {
         assert!(!self.job_assignments_rev.contains_key(ship_symbol));
         let ship = self.ctx.ships.get(ship_symbol).unwrap();
-        let ship_model = { ship.lock().unwrap().model().unwrap() };
+        let (ship_model, at) = {
+            let ship = ship.lock().unwrap();
+            (ship.model().unwrap(), ship.nav.waypoint_symbol.clone())
+        };
         let ship_config = self.get_ship_config();
-        let job_opt = ship_config.iter().find(|job| {
-            !self.job_assignments.contains_key(&job.id) && job.ship_model == ship_model
-        });
+        let job_opt = pick_job(
+            ship_config.iter().filter(|job| {
+                !self.job_assignments.contains_key(&job.id) && job.ship_model == ship_model
+            }),
+            &at,
+        );
         match job_opt {
             Some(job) => {
                 self.job_assignments
@@ -488,6 +835,7 @@ impl FleetManager {
                 use_nonstatic_probes,
                 incl_outer_probes_and_siphons,
                 in_home_phase,
+                probe_refresh_target(era),
             ));
         }
 
@@ -598,6 +946,7 @@ impl FleetManager {
     pub async fn refresh_ship_config(&self) {
         let ship_config = self.generate_ship_config().await;
         self.set_ship_config(ship_config.clone());
+        self.repair_assignments();
 
         let mut keys_to_remove = Vec::new();
         for it in self.job_assignments.iter() {
@@ -642,14 +991,156 @@ impl FleetManager {
         if self.is_jumpgate_finished().await {
             self.ctx.ledger.reserve_credits("JUMPGATE_COSTS", 500_000);
         }
+        let mut reserving_ships = BTreeSet::new();
         for ship_config in ship_config {
             if let Some(ship_symbol) = &self.job_assignments.get(&ship_config.id) {
                 let ship_symbol: &String = ship_symbol.value();
                 self.reserve_credits_for_job(&ship_config, ship_symbol);
+                if matches!(ship_config.behaviour, ShipBehaviour::Logistics(_)) {
+                    reserving_ships.insert(ship_symbol.clone());
+                }
+            }
+        }
+        // A reservation for a ship that's gone or no longer trading would otherwise hold
+        // its credits back from purchasing for good
+        for (key, amount) in self.ctx.ledger.reservations() {
+            if key == "FUEL" || key == "JUMPGATE_COSTS" || reserving_ships.contains(&key) {
+                continue;
             }
+            warn!(
+                "Releasing stale reservation of {} credits for {}",
+                amount, key
+            );
+            self.ctx.ledger.release_credits(&key);
         }
     }
 
+    // Move logistics ships that have been idle a while to logistics jobs in other
+    // systems that have no ship yet and more work than ships. Only the assignment
+    // changes here: the ship's script notices at its next idle check, hands off, and the
+    // new job's script flies it over. The job it leaves isn't bought for until its own
+    // system is underserved again.
+    pub async fn rebalance_fleet(&self, task_managers: &TaskManagerRegistry) {
+        let managers = task_managers.managers();
+        // a system without a task manager has no logistics ships at all
+        let underserved = |system: &SystemSymbol| {
+            managers
+                .iter()
+                .find(|manager| manager.system() == system)
+                .is_none_or(|manager| manager.is_underserved())
+        };
+        let reopened = self
+            .vacated_jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, system)| underserved(system))
+            .map(|(job_id, _)| job_id.clone())
+            .collect::<Vec<_>>();
+        if !reopened.is_empty() {
+            info!("Reopening vacated jobs {:?} for purchase", reopened);
+            self.set_vacated_jobs(|jobs| jobs.retain(|job_id, _| !reopened.contains(job_id)))
+                .await;
+        }
+
+        if !CONFIG.fleet_rebalancing
+            || !matches!(
+                self.state().era,
+                AgentEra::InterSystem1 | AgentEra::InterSystem2
+            )
+        {
+            return;
+        }
+        let ship_config = self.get_ship_config();
+        let now = chrono::Utc::now();
+        let mut idle = vec![];
+        for manager in &managers {
+            for (ship_symbol, since) in manager.idle_ships() {
+                if now - since < chrono::Duration::minutes(REBALANCE_IDLE_MINUTES) {
+                    continue;
+                }
+                let Some(job_id) = self.assigned_job(&ship_symbol) else {
+                    continue;
+                };
+                let Some(job) = ship_config.iter().find(|job| job.id == job_id) else {
+                    continue;
+                };
+                if matches!(job.behaviour, ShipBehaviour::Logistics(_)) {
+                    idle.push(IdleShip {
+                        ship_symbol,
+                        job_id,
+                        ship_model: job.ship_model.clone(),
+                        system: manager.system().clone(),
+                    });
+                }
+            }
+        }
+        if idle.is_empty() {
+            return;
+        }
+        let openings = ship_config
+            .iter()
+            .filter(|job| !self.job_assigned(&job.id) && !self.job_vacated(&job.id))
+            .filter_map(|job| match &job.behaviour {
+                ShipBehaviour::Logistics(config) => Some(JobOpening {
+                    job_id: job.id.clone(),
+                    ship_model: job.ship_model.clone(),
+                    system: logistics_job_system(config, self.task_manager.system()),
+                }),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut travel_costs = BTreeMap::new();
+        for ship in &idle {
+            for opening in &openings {
+                let key = (ship.system.clone(), opening.system.clone());
+                if key.0 == key.1 || travel_costs.contains_key(&key) {
+                    continue;
+                }
+                let universe = &self.ctx.universe;
+                let cost = match (
+                    universe.get_jumpgate_opt(&key.0).await,
+                    universe.get_jumpgate_opt(&key.1).await,
+                ) {
+                    (Some(from), Some(to)) => universe.jumpgate_cost(&from, &to).await,
+                    _ => None,
+                };
+                travel_costs.insert(key, cost);
+            }
+        }
+        let reassignments = plan_rebalance(&idle, &openings, underserved, |from, to| {
+            travel_costs
+                .get(&(from.clone(), to.clone()))
+                .copied()
+                .flatten()
+        });
+        if reassignments.is_empty() {
+            return;
+        }
+        for r in reassignments {
+            info!(
+                "Rebalancing idle ship {} from job {} to {} in {}",
+                r.ship_symbol, r.from_job, r.to_job, r.to_system
+            );
+            self.job_assignments.remove(&r.from_job);
+            self.job_assignments
+                .insert(r.to_job.clone(), r.ship_symbol.clone());
+            self.job_assignments_rev.insert(r.ship_symbol, r.to_job);
+            self.set_vacated_jobs(|jobs| {
+                jobs.insert(r.from_job, r.from_system);
+            })
+            .await;
+        }
+        self.ctx
+            .db
+            .set_value(
+                &format!("{}/ship_assignments", self.ctx.callsign),
+                self.job_assignments.deref(),
+            )
+            .await;
+    }
+
     pub async fn update_era(&self, era: AgentEra) {
         let state = {
             let mut state = self.state.lock().unwrap();
@@ -674,39 +1165,18 @@ impl FleetManager {
             }
             return;
         }
-        loop {
-            let current_era = self.state().era;
-            let next_era = match current_era {
-                AgentEra::StartingSystem1 => {
-                    let credits = self.ctx.ledger.available_credits();
-                    if credits >= 800_000 {
-                        Some(AgentEra::StartingSystem2)
-                    } else {
-                        None
-                    }
-                }
-                AgentEra::StartingSystem2 => {
-                    // Once the home jump gate is built, start charting the network.
-                    if self.is_jumpgate_finished().await {
-                        Some(AgentEra::InterSystem1)
-                    } else {
-                        None
-                    }
-                }
-                AgentEra::InterSystem1 => None,
-                AgentEra::InterSystem2 => None,
-            };
-            match next_era {
-                None => break,
-                Some(next_era) => {
-                    assert_ne!(current_era, next_era);
-                    info!(
-                        "Agent {} advancing to era {:?}",
-                        self.ctx.callsign, next_era
-                    );
-                    self.update_era(next_era).await;
-                }
-            }
+        let ctx = ProgressionContext {
+            credits: self.ctx.ledger.available_credits(),
+            jumpgate_finished: self.is_jumpgate_finished().await,
+            fleet_size: self.ctx.ships.len(),
+        };
+        let strategy = CONFIG.era_strategy.strategy();
+        for next_era in progression::advance(strategy.as_ref(), self.state().era, &ctx) {
+            info!(
+                "Agent {} advancing to era {:?} ({:?} strategy)",
+                self.ctx.callsign, next_era, CONFIG.era_strategy
+            );
+            self.update_era(next_era).await;
         }
     }
 
@@ -722,6 +1192,17 @@ impl FleetManager {
         debug!("Spawning task for {}", ship_symbol);
 
         let job_id_opt = self.job_assignments_rev.get(&ship_symbol);
+        if CONFIG.dry_run {
+            // every script's first action is a mutation
+            info!(
+                "DRY RUN: not starting {} (job {})",
+                ship_symbol,
+                job_id_opt
+                    .as_deref()
+                    .map_or("none", |job_id| job_id.as_str())
+            );
+            return;
+        }
         let scrap = CONFIG.scrap_all_ships || (job_id_opt.is_none() && CONFIG.scrap_unassigned);
         if scrap {
             let ship_controller = self.ship_controller(&ship_symbol);
@@ -744,90 +1225,77 @@ impl FleetManager {
                     return;
                 }
                 let ship_controller = self.ship_controller(&ship_symbol);
-                let ship = ship_controller.ship();
-                if ship.engine.condition.unwrap() < 0.0 {
+                let condition = ship_controller.worst_condition();
+                let broken = condition < 0.0;
+                let repair = broken || condition < CONFIG.repair_condition_threshold;
+                if repair {
                     warn!(
-                        "Ship {} has engine condition {}",
-                        ship_symbol,
-                        ship.engine.condition.unwrap()
+                        "Ship {} has condition {:.2}, repairing before {}",
+                        ship_symbol, condition, job_spec.id
                     );
-                    return;
-                }
-                if ship.frame.condition.unwrap() < 0.0 {
-                    warn!(
-                        "Ship {} has frame condition {}",
-                        ship_symbol,
-                        ship.frame.condition.unwrap()
-                    );
-                    return;
-                }
-                if ship.reactor.condition.unwrap() < 0.0 {
-                    warn!(
-                        "Ship {} has reactor condition {}",
-                        ship_symbol,
-                        ship.reactor.condition.unwrap()
-                    );
-                    return;
                 }
+                let repairer = ship_controller.clone();
 
-                let join_hdl = match &job_spec.behaviour {
+                let script: BoxFuture<'static, ()> = match &job_spec.behaviour {
                     ShipBehaviour::Probe(config) => {
                         let config = config.clone();
-                        tokio::spawn(async move {
-                            ship_scripts::probe::run(ship_controller, &config).await;
+                        let ac = ac.clone();
+                        Box::pin(async move {
+                            ship_scripts::probe::run(ship_controller, &config, ac).await;
                         })
                     }
                     ShipBehaviour::Logistics(config) => {
-                        let task_manager = self.task_manager.clone();
+                        let system = logistics_job_system(config, self.task_manager.system());
                         let ac = ac.clone();
                         let config = config.clone();
-                        tokio::spawn(async move {
+                        Box::pin(async move {
+                            let task_manager = ac.task_managers.get(&system).await;
                             ship_scripts::logistics::run(ship_controller, task_manager, config, ac)
                                 .await;
                         })
                     }
                     ShipBehaviour::SiphonDrone => {
                         let ac = ac.clone();
-                        tokio::spawn(async move {
+                        Box::pin(async move {
                             ship_scripts::siphon::run_drone(ship_controller, ac).await;
                         })
                     }
                     ShipBehaviour::SiphonShuttle => {
                         let db = self.ctx.db.clone();
                         let ac = ac.clone();
-                        tokio::spawn(async move {
+                        Box::pin(async move {
                             ship_scripts::siphon::run_shuttle(ship_controller, db, ac).await;
                         })
                     }
                     ShipBehaviour::MiningDrone => {
                         let ac = ac.clone();
-                        tokio::spawn(async move {
+                        Box::pin(async move {
                             ship_scripts::mining::run_mining_drone(ship_controller, ac).await;
                         })
                     }
                     ShipBehaviour::MiningShuttle => {
                         let db = self.ctx.db.clone();
                         let ac = ac.clone();
-                        tokio::spawn(async move {
+                        Box::pin(async move {
                             ship_scripts::mining::run_shuttle(ship_controller, db, ac).await;
                         })
                     }
                     ShipBehaviour::MiningSurveyor => {
                         let ac = ac.clone();
-                        tokio::spawn(async move {
+                        Box::pin(async move {
                             ship_scripts::mining::run_surveyor(ship_controller, ac).await;
                         })
                     }
                     ShipBehaviour::ConstructionHauler => {
                         let db = self.ctx.db.clone();
                         let ac = ac.clone();
-                        tokio::spawn(async move {
+                        Box::pin(async move {
                             ship_scripts::construction::run_hauler(ship_controller, db, ac).await;
                         })
                     }
                     ShipBehaviour::JumpgateProbe => {
                         let ac = ac.clone();
-                        tokio::spawn(async move {
+                        Box::pin(async move {
                             ship_scripts::probe_exploration::run_jumpgate_probe(
                                 ship_controller,
                                 ac,
@@ -838,18 +1306,32 @@ impl FleetManager {
                     ShipBehaviour::Explorer => {
                         let db = self.ctx.db.clone();
                         let ac = ac.clone();
-                        tokio::spawn(async move {
+                        Box::pin(async move {
                             ship_scripts::exploration::run_explorer(ship_controller, db, ac).await;
                         })
                     }
                     ShipBehaviour::T5Trader => {
                         let db = self.ctx.db.clone();
                         let ac = ac.clone();
-                        tokio::spawn(async move {
+                        Box::pin(async move {
                             ship_scripts::t5_trader::run_t5_trader(ship_controller, db, ac).await;
                         })
                     }
                 };
+                // A broken ship (negative condition) may not be able to fly, so only
+                // repair it where it stands; otherwise it sits out as before.
+                let join_hdl = tokio::spawn(async move {
+                    let repaired = repair && ship_scripts::repair::run(&repairer, !broken).await;
+                    if broken && !repaired {
+                        warn!(
+                            "Ship {} is broken (condition {:.2}) and can't be repaired here",
+                            repairer.symbol(),
+                            condition
+                        );
+                        return;
+                    }
+                    script.await;
+                });
                 let name = format!("{}:{}", ship_symbol, job_spec.id);
                 self.hdls.push(&name, join_hdl);
             }
@@ -859,3 +1341,243 @@ impl FleetManager {
         }
     }
 }
+
+// The consistent job -> ship assignments to keep, and a description of each problem
+// found. A ship assigned to several jobs keeps the first by job id (the maps'
+// iteration order isn't stable, so "first" has to mean something); the reverse map is
+// rebuilt from what's kept, so stale or disagreeing reverse entries are dropped.
+fn repair_assignment_maps(
+    mut forward: Vec<(String, String)>,
+    reverse: Vec<(String, String)>,
+) -> (BTreeMap<String, String>, Vec<String>) {
+    forward.sort();
+    let mut issues = Vec::new();
+    let mut kept = BTreeMap::new();
+    let mut ship_jobs: BTreeMap<String, String> = BTreeMap::new();
+    for (job_id, ship_symbol) in forward {
+        match ship_jobs.get(&ship_symbol) {
+            Some(first) => issues.push(format!(
+                "ship {} is assigned to both {} and {}; unassigning it from {}",
+                ship_symbol, first, job_id, job_id
+            )),
+            None => {
+                ship_jobs.insert(ship_symbol.clone(), job_id.clone());
+                kept.insert(job_id, ship_symbol);
+            }
+        }
+    }
+    for (ship_symbol, job_id) in &reverse {
+        if ship_jobs.get(ship_symbol) != Some(job_id) {
+            issues.push(format!(
+                "reverse map has ship {} on job {}, forward map has {:?}",
+                ship_symbol,
+                job_id,
+                ship_jobs.get(ship_symbol)
+            ));
+        }
+    }
+    for (ship_symbol, job_id) in &ship_jobs {
+        if !reverse.iter().any(|(s, j)| s == ship_symbol && j == job_id) {
+            issues.push(format!(
+                "ship {} on job {} is missing from the reverse map",
+                ship_symbol, job_id
+            ));
+        }
+    }
+    (kept, issues)
+}
+
+// Credits held back for a ship taking this job, so it can fill its hold. Only
+// logistics ships trade on their own account.
+pub(crate) fn job_reservation(
+    job: &ShipConfig,
+    cargo_capacity: i64,
+    config: &Config,
+) -> Option<i64> {
+    match &job.behaviour {
+        ShipBehaviour::Logistics(_) => Some(config.cargo_reservation(cargo_capacity)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // The cheaper shipyard's listing is stale and nobody is there to re-check it, so
+    // the purchase goes to the confirmed one.
+    #[test]
+    fn test_stale_shipyard_skipped() {
+        let stale = WaypointSymbol::new("X1-S1-A1");
+        let confirmed = WaypointSymbol::new("X1-S1-B1");
+        let shipyards = vec![(confirmed.clone(), 120_000), (stale.clone(), 90_000)];
+        assert_eq!(
+            buy_candidates(&shipyards, |s| s == &stale, |_| false),
+            vec![(confirmed.clone(), 120_000)]
+        );
+        // with a ship there to re-check it, the stale one is still worth trying first
+        assert_eq!(
+            buy_candidates(&shipyards, |s| s == &stale, |s| s == &stale),
+            vec![(stale.clone(), 90_000), (confirmed.clone(), 120_000)]
+        );
+        assert!(buy_candidates(&shipyards, |_| true, |_| false).is_empty());
+    }
+
+    // The purchaser found the cheapest shipyard no longer sells the model: the next
+    // attempt goes to the cheapest of the rest it can afford.
+    #[test]
+    fn test_next_buy_shipyard_skips_rejected() {
+        let a = WaypointSymbol::new("X1-S1-A1");
+        let b = WaypointSymbol::new("X1-S1-B1");
+        let c = WaypointSymbol::new("X1-S1-C1");
+        let candidates = vec![
+            (a.clone(), 90_000),
+            (b.clone(), 120_000),
+            (c.clone(), 150_000),
+        ];
+        let rejected = BTreeSet::from([a.clone()]);
+        assert_eq!(
+            next_buy_shipyard(&candidates, &BTreeSet::new(), |_| true),
+            Some(a.clone())
+        );
+        assert_eq!(
+            next_buy_shipyard(&candidates, &rejected, |_| true),
+            Some(b.clone())
+        );
+        assert_eq!(
+            next_buy_shipyard(&candidates, &rejected, |cost| cost <= 100_000),
+            None
+        );
+    }
+
+    // A probe whose tour was re-partitioned away takes the new tour covering where it
+    // is; other ships just take the first open job of their model.
+    #[test]
+    fn test_probe_remapped_to_covering_tour() {
+        let probe_job = |id: &str, waypoints: &[&str]| ShipConfig {
+            id: id.to_string(),
+            ship_model: "SHIP_PROBE".to_string(),
+            purchase_criteria: PurchaseCriteria::default(),
+            behaviour: ShipBehaviour::Probe(ProbeScriptConfig {
+                waypoints: waypoints.iter().map(|w| WaypointSymbol::new(w)).collect(),
+                refresh_market: true,
+            }),
+        };
+        let jobs = [
+            probe_job("probe/(0,0)", &["X1-S1-A1", "X1-S1-A2"]),
+            probe_job("probe/(50,0)", &["X1-S1-B1", "X1-S1-C1"]),
+        ];
+        let at = |w: &str| WaypointSymbol::new(w);
+        assert_eq!(
+            pick_job(jobs.iter(), &at("X1-S1-C1")).map(|j| j.id.as_str()),
+            Some("probe/(50,0)")
+        );
+        assert_eq!(
+            pick_job(jobs.iter(), &at("X1-S1-Z9")).map(|j| j.id.as_str()),
+            Some("probe/(0,0)")
+        );
+        assert!(pick_job(jobs.iter().skip(2), &at("X1-S1-A1")).is_none());
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(a, b)| (a.to_string(), b.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_repair_corrupt_assignments() {
+        // SHIP-1 is in two jobs, the reverse map still has SHIP-1 on the second one,
+        // SHIP-3 is missing from it, and SHIP-9 is a leftover with no forward entry.
+        let forward = pairs(&[
+            ("job/b", "SHIP-1"),
+            ("job/a", "SHIP-1"),
+            ("job/c", "SHIP-2"),
+            ("job/d", "SHIP-3"),
+        ]);
+        let reverse = pairs(&[
+            ("SHIP-1", "job/b"),
+            ("SHIP-2", "job/c"),
+            ("SHIP-9", "job/z"),
+        ]);
+        let (repaired, issues) = repair_assignment_maps(forward.clone(), reverse.clone());
+        assert_eq!(
+            repaired,
+            BTreeMap::from_iter(pairs(&[
+                ("job/a", "SHIP-1"),
+                ("job/c", "SHIP-2"),
+                ("job/d", "SHIP-3"),
+            ]))
+        );
+        assert_eq!(issues.len(), 5, "{:?}", issues);
+
+        // the same answer whatever order the maps were read in
+        let mut shuffled = forward;
+        shuffled.reverse();
+        assert_eq!(repair_assignment_maps(shuffled, reverse).0, repaired);
+
+        // repairing a repaired map finds nothing
+        let reverse = repaired
+            .iter()
+            .map(|(j, s)| (s.clone(), j.clone()))
+            .collect();
+        let forward = repaired.clone().into_iter().collect();
+        assert!(repair_assignment_maps(forward, reverse).1.is_empty());
+    }
+
+    // A hauler idling at home while the network system's hauler slot is empty: it's
+    // moved there. A probe idling alongside it isn't a hauler, a system with no known
+    // route can't be served, and one whose ships keep up with its work needs no help.
+    #[test]
+    fn test_idle_ship_moves_to_busy_system() {
+        let (home, busy, cut_off) = (
+            SystemSymbol::new("X1-HOME"),
+            SystemSymbol::new("X1-BUSY"),
+            SystemSymbol::new("X1-FAR"),
+        );
+        let idle_ship = |ship: &str, job: &str, model: &str| IdleShip {
+            ship_symbol: ship.to_string(),
+            job_id: job.to_string(),
+            ship_model: model.to_string(),
+            system: home.clone(),
+        };
+        let idle = vec![
+            idle_ship("S-2", "probe/0", "SHIP_PROBE"),
+            idle_ship("S-3", "logistics/0", "SHIP_LIGHT_HAULER"),
+        ];
+        let opening = |job: &str, system: &SystemSymbol| JobOpening {
+            job_id: job.to_string(),
+            ship_model: "SHIP_LIGHT_HAULER".to_string(),
+            system: system.clone(),
+        };
+        let openings = vec![
+            opening("logistics/far", &cut_off),
+            opening("logistics/busy", &busy),
+            opening("logistics/busy2", &busy),
+        ];
+        let travel_cost = |_: &SystemSymbol, to: &SystemSymbol| (to == &busy).then_some(300);
+        let starved = |system: &SystemSymbol| system != &home;
+        assert_eq!(
+            plan_rebalance(&idle, &openings, starved, travel_cost),
+            vec![Reassignment {
+                ship_symbol: "S-3".to_string(),
+                from_job: "logistics/0".to_string(),
+                from_system: home.clone(),
+                to_job: "logistics/busy".to_string(),
+                to_system: busy.clone(),
+            }]
+        );
+        assert!(plan_rebalance(&idle, &openings, |_| false, travel_cost).is_empty());
+        // an opening in the ship's own system is no reason to move
+        assert!(
+            plan_rebalance(
+                &idle,
+                &[opening("logistics/1", &home)],
+                |_| true,
+                travel_cost
+            )
+            .is_empty()
+        );
+    }
+}
@@ -9,18 +9,28 @@
 //! locked, you can't add new handles. tokio_util's TaskTracker does allow insertion without mutable access,
 //! however it doesn't actually allow you to handle the result of the tasks.
 //!
+//! A failed task is still fatal, but a panic hook gets to see it first (e.g. to
+//! persist it for safe mode, see `safe_mode`).
+//!
+//! `shutdown` aborts every task (e.g. on a server reset); `join` then returns.
+//!
 
 use futures::future::BoxFuture;
 use futures::stream::FuturesUnordered;
 use log::{debug, info};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
-use tokio::task::{JoinError, JoinHandle};
+use tokio::task::{AbortHandle, JoinError, JoinHandle};
+
+// Called with the task name when a task panics, before the panic propagates
+pub type PanicHook = Box<dyn Fn(String) -> BoxFuture<'static, ()> + Send + Sync>;
 
 #[derive(Debug)]
 pub struct JoinHandles {
     tx: mpsc::UnboundedSender<(String, JoinHandle<()>)>,
     hdl: Arc<tokio::sync::Mutex<JoinHandle<()>>>,
+    aborts: Arc<Mutex<Vec<AbortHandle>>>,
+    supervisor: AbortHandle,
 }
 impl Default for JoinHandles {
     fn default() -> Self {
@@ -30,6 +40,10 @@ impl Default for JoinHandles {
 
 impl JoinHandles {
     pub fn new() -> Self {
+        Self::with_panic_hook(Box::new(|_| Box::pin(async {})))
+    }
+
+    pub fn with_panic_hook(on_panic: PanicHook) -> Self {
         let (tx, mut rx) = mpsc::unbounded_channel::<(String, JoinHandle<()>)>();
 
         let hdl = tokio::spawn(async move {
@@ -55,6 +69,9 @@ impl JoinHandles {
                             Err(_e) => "failed",
                         };
                         debug!("handle '{}' {}", name, result);
+                        if hdl_ret.as_ref().is_err_and(|e| e.is_panic()) {
+                            on_panic(name).await;
+                        }
                         hdl_ret.unwrap();
                     }
                     handle = rx.recv() => {
@@ -68,15 +85,33 @@ impl JoinHandles {
 
         Self {
             tx,
+            supervisor: hdl.abort_handle(),
             hdl: Arc::new(tokio::sync::Mutex::new(hdl)),
+            aborts: Arc::new(Mutex::new(Vec::new())),
         }
     }
     pub fn push(&self, name: &str, handle: JoinHandle<()>) {
+        {
+            let mut aborts = self.aborts.lock().unwrap();
+            aborts.retain(|h| !h.is_finished());
+            aborts.push(handle.abort_handle());
+        }
         self.tx.send((name.to_string(), handle)).unwrap();
     }
     pub async fn join(&self) {
         let mut hdl = self.hdl.lock().await;
-        (&mut *hdl).await.unwrap();
+        match (&mut *hdl).await {
+            Err(e) if e.is_cancelled() => info!("join handles shut down"),
+            result => result.unwrap(),
+        }
+    }
+    // Abort every task. The supervisor goes first, so the cancellations aren't
+    // treated as task failures.
+    pub fn shutdown(&self) {
+        self.supervisor.abort();
+        for handle in self.aborts.lock().unwrap().drain(..) {
+            handle.abort();
+        }
     }
 }
 
@@ -86,3 +121,27 @@ fn wait_handle(
 ) -> BoxFuture<'static, (String, Result<(), JoinError>)> {
     Box::pin(async move { (name, handle.await) })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let hdls = JoinHandles::new();
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        hdls.push(
+            "forever",
+            tokio::spawn(async move {
+                let _tx = tx;
+                futures::future::pending::<()>().await;
+            }),
+        );
+        hdls.shutdown();
+        tokio::time::timeout(std::time::Duration::from_secs(1), hdls.join())
+            .await
+            .expect("join didn't return after shutdown");
+        // the task was aborted, dropping its end of the channel
+        assert!(rx.await.is_err());
+    }
+}
@@ -5,6 +5,9 @@
 ///      profit (proceeds - cost basis of the units sold) can be computed at sell
 ///      time and the in-transit cargo can be valued for net worth.
 ///
+/// Realized profit is also totalled per good (`profit_report`), to spot goods the
+/// planner keeps trading at a loss.
+///
 /// The durable record of every credit movement lives in the Postgres cash
 /// journal (agent_transaction_log), not here; this struct holds only the live
 /// state that gating and realized-margin need in memory.
@@ -33,10 +36,19 @@ impl ShipEntry {
     }
 }
 
+// Running totals of a good's sales since the start of the reset
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GoodProfit {
+    pub units_sold: i64,
+    pub proceeds: i64,
+    pub realized_profit: i64,
+}
+
 #[derive(Debug)]
 pub struct Ledger {
     total_credits: Mutex<i64>,
     ships: Mutex<BTreeMap<String, ShipEntry>>,
+    profits: Mutex<BTreeMap<String, GoodProfit>>,
     // (ts, credits) captured on the first reconciliation tick of this process;
     // the journal cash-delta since this point must equal the actual credit change.
     recon_start: Mutex<Option<(DateTime<Utc>, i64)>>,
@@ -47,6 +59,7 @@ impl Ledger {
         Ledger {
             total_credits: Mutex::new(start_credits),
             ships: Mutex::new(BTreeMap::new()),
+            profits: Mutex::new(BTreeMap::new()),
             recon_start: Mutex::new(None),
         }
     }
@@ -80,6 +93,32 @@ impl Ledger {
             .reserved_credits = amount;
     }
 
+    // Drop the reservation under `key`, keeping any in-transit cargo basis
+    pub fn release_credits(&self, key: &str) {
+        let mut ships = self.ships.lock().unwrap();
+        let Some(ship) = ships.get_mut(key) else {
+            return;
+        };
+        debug!(
+            "Releasing {} credits reserved for {}",
+            ship.reserved_credits, key
+        );
+        ship.reserved_credits = 0;
+        if ship.goods.is_empty() {
+            ships.remove(key);
+        }
+    }
+
+    // Every nonzero reservation, by key (a ship symbol, or a fleet-wide key like "FUEL")
+    pub fn reservations(&self) -> Vec<(String, i64)> {
+        let ships = self.ships.lock().unwrap();
+        ships
+            .iter()
+            .filter(|(_, s)| s.reserved_credits != 0)
+            .map(|(key, s)| (key.clone(), s.reserved_credits))
+            .collect()
+    }
+
     // Record a purchase of `units` into the ship's cargo at `price_per_unit`,
     // adding to the held cost basis.
     pub fn register_purchase(
@@ -119,9 +158,25 @@ impl Ledger {
         }
         let proceeds = units * price_per_unit;
         let cost = self.remove_basis(ship_symbol, good, units);
+        let mut profits = self.profits.lock().unwrap();
+        let totals = profits.entry(good.to_string()).or_default();
+        totals.units_sold += units;
+        totals.proceeds += proceeds;
+        totals.realized_profit += proceeds - cost;
         proceeds - cost
     }
 
+    // Realized profit per good, most profitable first
+    pub fn profit_report(&self) -> Vec<(String, GoodProfit)> {
+        let profits = self.profits.lock().unwrap();
+        let mut report: Vec<_> = profits
+            .iter()
+            .map(|(good, totals)| (good.clone(), totals.clone()))
+            .collect();
+        report.sort_by_key(|(_, totals)| std::cmp::Reverse(totals.realized_profit));
+        report
+    }
+
     // Remove `units` of cost basis for a non-sale outflow (donation, jettison,
     // refuel-from-cargo). Returns the cost basis removed.
     pub fn register_consumption(&self, ship_symbol: &str, good: &str, units: i64) -> i64 {
@@ -177,21 +232,27 @@ impl Ledger {
     // Serialize the per-ship reservations + cargo cost basis for persistence, so
     // a restart doesn't lose the basis of in-transit cargo (which would make the
     // next sale read as 100% profit). Reservations are also rebuilt by the
-    // controller as it re-assigns jobs, but persisting them is harmless.
+    // controller as it re-assigns jobs, but persisting them is harmless. The per-good
+    // profit totals go with them.
     pub fn snapshot(&self) -> LedgerSnapshot {
         LedgerSnapshot {
             ships: self.ships.lock().unwrap().clone(),
+            profits: self.profits.lock().unwrap().clone(),
         }
     }
 
     pub fn restore(&self, snapshot: LedgerSnapshot) {
         *self.ships.lock().unwrap() = snapshot.ships;
+        *self.profits.lock().unwrap() = snapshot.profits;
     }
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct LedgerSnapshot {
     ships: BTreeMap<String, ShipEntry>,
+    // absent from snapshots saved before profits were tracked
+    #[serde(default)]
+    profits: BTreeMap<String, GoodProfit>,
 }
 
 #[cfg(test)]
@@ -213,6 +274,39 @@ mod test {
         assert_eq!(l.cargo_value(), 0);
     }
 
+    #[test]
+    fn profit_report_totals_per_good() {
+        let l = Ledger::new(1_000_000);
+        l.register_purchase("A", "FOOD", 100, 50);
+        l.register_purchase("B", "FOOD", 100, 70);
+        l.register_purchase("A", "FABRICS", 10, 100);
+        l.register_sale("A", "FOOD", 100, 60); // +1000
+        l.register_sale("B", "FOOD", 50, 80); // +500
+        l.register_sale("A", "FABRICS", 10, 90); // -100
+        l.register_sale("M", "IRON_ORE", 20, 30); // mined, +600
+        let report = l.profit_report();
+        let goods: Vec<&str> = report.iter().map(|(good, _)| good.as_str()).collect();
+        assert_eq!(goods, ["FOOD", "IRON_ORE", "FABRICS"]);
+        assert_eq!(
+            report[0].1,
+            GoodProfit {
+                units_sold: 150,
+                proceeds: 10_000,
+                realized_profit: 1_500,
+            }
+        );
+        assert_eq!(report[2].1.realized_profit, -100);
+
+        // survives a restart
+        let snapshot = serde_json::to_string(&l.snapshot()).unwrap();
+        let restored = Ledger::new(0);
+        restored.restore(serde_json::from_str(&snapshot).unwrap());
+        assert_eq!(restored.profit_report(), report);
+        // an older snapshot without profits still loads
+        let old: LedgerSnapshot = serde_json::from_str(r#"{"ships":{}}"#).unwrap();
+        assert!(old.profits.is_empty());
+    }
+
     #[test]
     fn untracked_goods_are_pure_profit() {
         // mined/siphoned goods were never registered as a purchase
@@ -234,4 +328,27 @@ mod test {
         l.reserve_credits("H", 200_000);
         assert_eq!(l.effective_reserved_credits(), 50_000);
     }
+
+    #[test]
+    fn release_restores_available_credits() {
+        let l = Ledger::new(1_000_000);
+        l.register_purchase("M", "ORE", 100, 100);
+        let available = l.available_credits();
+        l.reserve_credits("FUEL", 10_000);
+        l.reserve_credits("H", 200_000);
+        assert_eq!(l.available_credits(), available - 210_000);
+        assert_eq!(
+            l.reservations(),
+            vec![("FUEL".to_string(), 10_000), ("H".to_string(), 200_000)]
+        );
+        l.release_credits("H");
+        l.release_credits("FUEL");
+        l.release_credits("NOBODY");
+        assert_eq!(l.available_credits(), available);
+        assert!(l.reservations().is_empty());
+        // cargo basis outlives the reservation
+        l.reserve_credits("M", 50_000);
+        l.release_credits("M");
+        assert_eq!(l.cargo_value(), 10_000);
+    }
 }
@@ -2,6 +2,7 @@
 mod agent_controller;
 pub mod context;
 pub mod contract_manager;
+mod dry_run;
 pub mod exploration;
 pub mod fleet;
 pub use context::AgentContext;
@@ -10,5 +11,9 @@ pub use exploration::ExplorationManager;
 pub use fleet::FleetManager;
 pub mod join_handles;
 pub mod ledger;
+pub mod pause;
+pub mod progression;
+pub mod safe_mode;
+pub mod what_if;
 
 pub use agent_controller::*;
@@ -0,0 +1,172 @@
+// Operator pauses, e.g. idling the mining fleet while the gate build needs every credit.
+// A paused job's ship parks wherever its script next reaches a safe point and waits
+// there; try_buy_ships buys nothing for it, and the logistics planner drops tasks only
+// paused ships could take. Persisted, so a pause outlives a restart.
+
+use super::fleet::logistics_job_system;
+use crate::models::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseScope {
+    // a behaviour kind, or a family of them: "mining" covers mining_surveyor,
+    // mining_drone and mining_shuttle
+    Behaviour(String),
+    // a regex on the job id
+    JobPattern(JobPattern),
+    System(SystemSymbol),
+}
+
+// A job id regex, compiled once as the scope is parsed (an invalid pattern fails to
+// parse) rather than on every match
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct JobPattern(Regex);
+
+impl JobPattern {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        Regex::new(pattern)
+            .map(JobPattern)
+            .map_err(|e| format!("Invalid job pattern: {}", e))
+    }
+}
+
+impl PartialEq for JobPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for JobPattern {}
+
+impl TryFrom<String> for JobPattern {
+    type Error = String;
+    fn try_from(pattern: String) -> Result<Self, String> {
+        JobPattern::new(&pattern)
+    }
+}
+
+impl From<JobPattern> for String {
+    fn from(pattern: JobPattern) -> String {
+        pattern.0.as_str().to_string()
+    }
+}
+
+pub fn behaviour_kind(behaviour: &ShipBehaviour) -> &'static str {
+    match behaviour {
+        ShipBehaviour::Probe(_) => "probe",
+        ShipBehaviour::Logistics(_) => "logistics",
+        ShipBehaviour::SiphonDrone => "siphon_drone",
+        ShipBehaviour::SiphonShuttle => "siphon_shuttle",
+        ShipBehaviour::MiningSurveyor => "mining_surveyor",
+        ShipBehaviour::MiningDrone => "mining_drone",
+        ShipBehaviour::MiningShuttle => "mining_shuttle",
+        ShipBehaviour::ConstructionHauler => "construction_hauler",
+        ShipBehaviour::JumpgateProbe => "jumpgate_probe",
+        ShipBehaviour::Explorer => "explorer",
+        ShipBehaviour::T5Trader => "t5_trader",
+    }
+}
+
+// The system a job is tied to, if any (roaming jobs aren't)
+pub fn job_system(job: &ShipConfig, home: &SystemSymbol) -> Option<SystemSymbol> {
+    match &job.behaviour {
+        ShipBehaviour::Logistics(config) => Some(logistics_job_system(config, home)),
+        ShipBehaviour::Probe(config) => config.waypoints.first().map(|w| w.system()),
+        ShipBehaviour::JumpgateProbe | ShipBehaviour::Explorer | ShipBehaviour::T5Trader => None,
+        _ => Some(home.clone()),
+    }
+}
+
+impl PauseScope {
+    // `system` is where the job operates: its own system, or for a roaming job the
+    // ship's current one
+    pub fn matches(&self, job: &ShipConfig, system: &SystemSymbol) -> bool {
+        match self {
+            PauseScope::Behaviour(kind) => {
+                let job_kind = behaviour_kind(&job.behaviour);
+                job_kind == kind || job_kind.starts_with(&format!("{}_", kind))
+            }
+            PauseScope::JobPattern(pattern) => pattern.0.is_match(&job.id),
+            PauseScope::System(paused) => paused == system,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn job(id: &str, behaviour: ShipBehaviour) -> ShipConfig {
+        ShipConfig {
+            id: id.to_string(),
+            ship_model: "SHIP_MINING_DRONE".to_string(),
+            purchase_criteria: PurchaseCriteria::default(),
+            behaviour,
+        }
+    }
+
+    #[test]
+    fn test_pause_mining_keeps_logistics_running() {
+        let home = SystemSymbol::new("X1-S1");
+        let logistics = job(
+            "logistics/0",
+            ShipBehaviour::Logistics(LogisticsScriptConfig {
+                use_planner: false,
+                planner_config: None,
+                allow_shipbuying: true,
+                allow_construction: true,
+                allow_market_refresh: true,
+                waypoint_allowlist: None,
+                min_profit: 0,
+            }),
+        );
+        let fleet = [
+            job("mining_drone/0", ShipBehaviour::MiningDrone),
+            job("mining_shuttle/0", ShipBehaviour::MiningShuttle),
+            job("mining_surveyor/0", ShipBehaviour::MiningSurveyor),
+            job("siphon_drone/0", ShipBehaviour::SiphonDrone),
+            logistics.clone(),
+        ];
+        let paused = |scope: &PauseScope| {
+            fleet
+                .iter()
+                .filter(|job| scope.matches(job, &home))
+                .map(|job| job.id.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        let mining = PauseScope::Behaviour("mining".to_string());
+        assert_eq!(
+            paused(&mining),
+            vec!["mining_drone/0", "mining_shuttle/0", "mining_surveyor/0"]
+        );
+        // a prefix only matches whole words of the kind
+        assert!(paused(&PauseScope::Behaviour("min".to_string())).is_empty());
+        assert_eq!(
+            paused(&PauseScope::JobPattern(JobPattern::new("^siphon").unwrap())),
+            vec!["siphon_drone/0"]
+        );
+        assert_eq!(paused(&PauseScope::System(home.clone())).len(), fleet.len());
+        assert!(paused(&PauseScope::System(SystemSymbol::new("X1-S2"))).is_empty());
+
+        // patterns are checked as they're parsed, and round-trip as plain strings
+        let scope: PauseScope = serde_json::from_str(r#"{"job_pattern":"^siphon"}"#).unwrap();
+        assert_eq!(
+            scope,
+            PauseScope::JobPattern(JobPattern::new("^siphon").unwrap())
+        );
+        assert_eq!(
+            serde_json::to_string(&scope).unwrap(),
+            r#"{"job_pattern":"^siphon"}"#
+        );
+        assert!(serde_json::from_str::<PauseScope>(r#"{"job_pattern":"("}"#).is_err());
+        assert_eq!(job_system(&logistics, &home), Some(home.clone()));
+        assert_eq!(
+            job_system(&job("t5_trader/0", ShipBehaviour::T5Trader), &home),
+            None
+        );
+    }
+}
@@ -0,0 +1,166 @@
+// When the agent moves from one era to the next. The rules live behind a trait so
+// alternative strategies can be tried (ERA_STRATEGY) without touching the controller.
+
+use super::AgentEra;
+use strum::EnumString;
+
+// What a strategy gets to look at when deciding
+#[derive(Debug, Clone, Copy)]
+pub struct ProgressionContext {
+    pub credits: i64,
+    pub jumpgate_finished: bool,
+    pub fleet_size: usize,
+}
+
+pub trait ProgressionStrategy: Send + Sync {
+    // The era to advance to from `current`, or None to stay put
+    fn next_era(&self, current: AgentEra, ctx: &ProgressionContext) -> Option<AgentEra>;
+}
+
+// Enter StartingSystem2 on a credit threshold, then InterSystem1 once the home jump
+// gate is built. The gate-building fleet is bought in StartingSystem2, so a lower
+// threshold starts construction sooner at the cost of a thinner trading fleet.
+pub struct CreditThresholdProgression {
+    pub starting_system2_credits: i64,
+}
+
+impl ProgressionStrategy for CreditThresholdProgression {
+    fn next_era(&self, current: AgentEra, ctx: &ProgressionContext) -> Option<AgentEra> {
+        match current {
+            AgentEra::StartingSystem1 => {
+                if ctx.credits >= self.starting_system2_credits {
+                    Some(AgentEra::StartingSystem2)
+                } else {
+                    None
+                }
+            }
+            AgentEra::StartingSystem2 => {
+                // Once the home jump gate is built, start charting the network.
+                if ctx.jumpgate_finished {
+                    Some(AgentEra::InterSystem1)
+                } else {
+                    None
+                }
+            }
+            AgentEra::InterSystem1 => None,
+            AgentEra::InterSystem2 => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum EraStrategy {
+    #[default]
+    Default,
+    Aggressive,
+    Conservative,
+    ConstructionFirst,
+}
+
+impl EraStrategy {
+    pub fn strategy(&self) -> Box<dyn ProgressionStrategy> {
+        let starting_system2_credits = match self {
+            EraStrategy::Default => 800_000,
+            EraStrategy::Aggressive => 400_000,
+            EraStrategy::Conservative => 1_500_000,
+            EraStrategy::ConstructionFirst => 0,
+        };
+        Box::new(CreditThresholdProgression {
+            starting_system2_credits,
+        })
+    }
+}
+
+// Every era the strategy passes through from `current` (several transitions can fire
+// at once), in order. Empty if it stays put.
+pub fn advance(
+    strategy: &dyn ProgressionStrategy,
+    current: AgentEra,
+    ctx: &ProgressionContext,
+) -> Vec<AgentEra> {
+    let mut eras = Vec::new();
+    let mut era = current;
+    while let Some(next) = strategy.next_era(era, ctx) {
+        assert_ne!(era, next);
+        assert!(
+            !eras.contains(&next),
+            "Era strategy cycles back to {:?}",
+            next
+        );
+        eras.push(next);
+        era = next;
+    }
+    eras
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn ctx(credits: i64, jumpgate_finished: bool) -> ProgressionContext {
+        ProgressionContext {
+            credits,
+            jumpgate_finished,
+            fleet_size: 10,
+        }
+    }
+
+    // Skips the credit wait and the gate: out of the start system as soon as the fleet
+    // is big enough.
+    struct FleetSizeProgression;
+
+    impl ProgressionStrategy for FleetSizeProgression {
+        fn next_era(&self, current: AgentEra, ctx: &ProgressionContext) -> Option<AgentEra> {
+            match current {
+                AgentEra::StartingSystem1 if ctx.fleet_size >= 5 => Some(AgentEra::InterSystem1),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_strategy_early_transition() {
+        let default = EraStrategy::Default.strategy();
+        assert!(
+            advance(
+                default.as_ref(),
+                AgentEra::StartingSystem1,
+                &ctx(100_000, false)
+            )
+            .is_empty()
+        );
+        assert_eq!(
+            advance(
+                default.as_ref(),
+                AgentEra::StartingSystem1,
+                &ctx(800_000, true)
+            ),
+            vec![AgentEra::StartingSystem2, AgentEra::InterSystem1]
+        );
+
+        assert_eq!(
+            advance(
+                &FleetSizeProgression,
+                AgentEra::StartingSystem1,
+                &ctx(100_000, false)
+            ),
+            vec![AgentEra::InterSystem1]
+        );
+
+        assert_eq!(
+            EraStrategy::from_str("construction_first").unwrap(),
+            EraStrategy::ConstructionFirst
+        );
+        let construction_first = EraStrategy::ConstructionFirst.strategy();
+        assert_eq!(
+            advance(
+                construction_first.as_ref(),
+                AgentEra::StartingSystem1,
+                &ctx(0, false)
+            ),
+            vec![AgentEra::StartingSystem2]
+        );
+    }
+}
@@ -0,0 +1,112 @@
+// Safe mode: an observe-only holding pattern for when ship scripts keep panicking.
+// A ship-script panic still takes the agent down (see join_handles), so the panic
+// times are persisted on the way out and survive the restart. If more than
+// SAFE_MODE_PANIC_THRESHOLD of them fall inside SAFE_MODE_WINDOW_MINUTES, the agent
+// comes back up in safe mode: no ship scripts, no buying, no contracts — only metrics
+// and the web API. Something systemic (e.g. an API schema change) then can't keep
+// burning credits through crash loops. Safe mode lasts until the process restarts
+// with the window clear of panics.
+
+use chrono::{DateTime, Duration, Utc};
+use log::*;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub struct PanicTracker {
+    threshold: usize,
+    window: Duration,
+    panics: Mutex<Vec<DateTime<Utc>>>,
+    tripped: AtomicBool,
+}
+
+// Ship scripts are spawned as "<ship>:<job>" (see FleetManager::_spawn_run_ship)
+pub fn is_ship_task(name: &str) -> bool {
+    name.contains(':')
+}
+
+impl PanicTracker {
+    // `history` is the persisted panic times; trips straight away if they already
+    // exceed the threshold
+    pub fn new(
+        threshold: usize,
+        window: Duration,
+        history: Vec<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let tracker = Self {
+            threshold,
+            window,
+            panics: Mutex::new(history),
+            tripped: AtomicBool::new(false),
+        };
+        tracker.evaluate(now);
+        tracker
+    }
+
+    // Drop panics older than the window and trip if too many remain
+    fn evaluate(&self, now: DateTime<Utc>) -> bool {
+        let mut panics = self.panics.lock().unwrap();
+        panics.retain(|t| now - *t <= self.window);
+        if panics.len() > self.threshold && !self.tripped.swap(true, Ordering::Relaxed) {
+            error!(
+                "SAFE MODE: {} ship task panics in the last {} minutes (threshold {}). Halting all buying, trading and contracts.",
+                panics.len(),
+                self.window.num_minutes(),
+                self.threshold
+            );
+        }
+        self.is_tripped()
+    }
+
+    // Returns whether safe mode is now tripped
+    pub fn record(&self, at: DateTime<Utc>) -> bool {
+        self.panics.lock().unwrap().push(at);
+        self.evaluate(at)
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        self.tripped.load(Ordering::Relaxed)
+    }
+
+    pub fn panics(&self) -> Vec<DateTime<Utc>> {
+        self.panics.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_panic_rate_trips_safe_mode() {
+        let t0 = Utc::now();
+        let window = Duration::minutes(30);
+        let tracker = PanicTracker::new(3, window, vec![], t0);
+        assert!(!tracker.is_tripped());
+
+        // spread out, the old panics age out of the window
+        for i in 0..5 {
+            assert!(!tracker.record(t0 + Duration::minutes(20 * i)));
+        }
+        assert_eq!(tracker.panics().len(), 2);
+
+        // a burst exceeding the threshold trips it, and it stays tripped
+        let t1 = t0 + Duration::hours(4);
+        for i in 0..3 {
+            assert!(!tracker.record(t1 + Duration::minutes(i)));
+        }
+        assert!(tracker.record(t1 + Duration::minutes(3)));
+        assert!(tracker.is_tripped());
+        assert!(tracker.record(t1 + Duration::hours(2)));
+
+        // persisted history restored after a restart trips it at startup
+        let history = (0..4).map(|i| t1 + Duration::minutes(i)).collect();
+        let restored = PanicTracker::new(3, window, history, t1 + Duration::minutes(10));
+        assert!(restored.is_tripped());
+        let restored = PanicTracker::new(3, window, restored.panics(), t1 + Duration::hours(1));
+        assert!(!restored.is_tripped());
+
+        assert!(is_ship_task("BADGER-1:logistics/0"));
+        assert!(!is_ship_task("controller loop"));
+    }
+}
@@ -0,0 +1,95 @@
+// "If I bought one more of these now, what would it earn?" Plans a hypothetical ship
+// at the cheapest home-system shipyard selling the model, against the trades no ship
+// has taken yet, and weighs the schedule's earnings against the purchase price. Purely
+// an estimate: nothing is bought, reserved or assigned.
+
+use super::AgentController;
+use crate::models::WaypointSymbol;
+use serde::Serialize;
+
+// Recommend the purchase if it pays for itself within this long
+const MAX_PAYBACK_HOURS: f64 = 12.0;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShipWhatIf {
+    pub model: String,
+    pub shipyard: WaypointSymbol,
+    pub purchase_price: i64,
+    pub tasks_available: usize,
+    pub tasks_assigned: usize,
+    pub plan_value: i64,
+    pub plan_length_secs: i64,
+    pub credits_per_hour: f64,
+    // None if the plan earns nothing
+    pub payback_hours: Option<f64>,
+    pub affordable: bool,
+    pub recommend: bool,
+}
+
+fn payback(
+    purchase_price: i64,
+    plan_value: i64,
+    plan_length_secs: i64,
+    available_credits: i64,
+) -> (f64, Option<f64>, bool, bool) {
+    let credits_per_hour = plan_value as f64 / (plan_length_secs as f64 / 3600.0);
+    let payback_hours = match credits_per_hour > 0.0 {
+        true => Some(purchase_price as f64 / credits_per_hour),
+        false => None,
+    };
+    let affordable = available_credits >= purchase_price;
+    let recommend = affordable && payback_hours.is_some_and(|h| h <= MAX_PAYBACK_HOURS);
+    (credits_per_hour, payback_hours, affordable, recommend)
+}
+
+impl AgentController {
+    // `model` is a ship type, with or without the SHIP_ prefix (LIGHT_HAULER). None if
+    // no known home-system shipyard sells it.
+    pub async fn what_if_ship(&self, model: &str) -> Option<ShipWhatIf> {
+        let model = match model.starts_with("SHIP_") {
+            true => model.to_string(),
+            false => format!("SHIP_{}", model),
+        };
+        let universe = &self.ctx.universe;
+        let (shipyard, purchase_price) = universe
+            .search_shipyards(&self.starting_system(), &model)
+            .await
+            .into_iter()
+            .min_by_key(|(_, price)| *price)?;
+        let spec = universe
+            .get_shipyard(&shipyard)?
+            .data
+            .ships
+            .iter()
+            .find(|ship| ship.ship_type == model)?
+            .clone();
+        let plan = self
+            .task_manager
+            .plan_hypothetical_ship(
+                &shipyard,
+                spec.cargo_capacity(),
+                spec.engine.speed,
+                spec.frame.fuel_capacity,
+            )
+            .await;
+        let (credits_per_hour, payback_hours, affordable, recommend) = payback(
+            purchase_price,
+            plan.value,
+            plan.plan_length_secs,
+            self.ctx.ledger.available_credits(),
+        );
+        Some(ShipWhatIf {
+            model,
+            shipyard,
+            purchase_price,
+            tasks_available: plan.tasks_available,
+            tasks_assigned: plan.tasks_assigned,
+            plan_value: plan.value,
+            plan_length_secs: plan.plan_length_secs,
+            credits_per_hour,
+            payback_hours,
+            affordable,
+            recommend,
+        })
+    }
+}
@@ -0,0 +1,182 @@
+// Typed failure of a `try_*` request, for callers that handle an error rather than
+// panic on it. The plain get/post/patch helpers still panic.
+
+use reqwest::StatusCode;
+
+// The server's token errors (empty, missing or invalid subject, ...). After a server
+// reset every request with the old slice's token fails with one of these or a bare 401.
+const TOKEN_ERROR_CODES: std::ops::RangeInclusive<i64> = 4100..=4105;
+
+#[derive(Debug)]
+pub enum ApiError {
+    // still 429 after every retry
+    RateLimited,
+    // 404, whatever the body: the resource doesn't exist (or isn't visible to us)
+    NotFound,
+    // 401 or a token error code: our agent token is no longer accepted, most likely
+    // because the server has reset (see main.rs)
+    TokenInvalid {
+        code: Option<i64>,
+        message: String,
+    },
+    // 5xx without a SpaceTraders error body (the retryable ones already retried)
+    ServerError(StatusCode),
+    // the server's own `{"error":{"code":..,"message":..,"data":..}}`, e.g. 4224 survey
+    // exhausted. `data` is the code-specific detail, when sent.
+    ApiErrorCode {
+        status: StatusCode,
+        code: i64,
+        message: String,
+        data: Option<serde_json::Value>,
+    },
+    // any other non-2xx, with its raw body
+    Http {
+        status: StatusCode,
+        body: String,
+    },
+    Deserialize(serde_json::Error),
+    Network(reqwest::Error),
+    // a mutation withheld in dry-run mode (CONFIG.dry_run); never sent
+    DryRun,
+}
+
+impl ApiError {
+    pub fn from_response(status: StatusCode, body: &str) -> ApiError {
+        match status {
+            StatusCode::TOO_MANY_REQUESTS => return ApiError::RateLimited,
+            StatusCode::NOT_FOUND => return ApiError::NotFound,
+            _ => {}
+        }
+        let error = serde_json::from_str::<serde_json::Value>(body)
+            .ok()
+            .and_then(|v| {
+                let code = v.pointer("/error/code")?.as_i64()?;
+                let message = v.pointer("/error/message")?.as_str()?.to_string();
+                let data = v.pointer("/error/data").cloned();
+                Some(ApiError::ApiErrorCode {
+                    status,
+                    code,
+                    message,
+                    data,
+                })
+            });
+        match error {
+            Some(ApiError::ApiErrorCode { code, message, .. })
+                if status == StatusCode::UNAUTHORIZED || TOKEN_ERROR_CODES.contains(&code) =>
+            {
+                ApiError::TokenInvalid {
+                    code: Some(code),
+                    message,
+                }
+            }
+            None if status == StatusCode::UNAUTHORIZED => ApiError::TokenInvalid {
+                code: None,
+                message: body.to_string(),
+            },
+            Some(error) => error,
+            None if status.is_server_error() => ApiError::ServerError(status),
+            None => ApiError::Http {
+                status,
+                body: body.to_string(),
+            },
+        }
+    }
+
+    // The SpaceTraders error code, if the server sent one
+    pub fn code(&self) -> Option<i64> {
+        match self {
+            ApiError::ApiErrorCode { code, .. } => Some(*code),
+            ApiError::TokenInvalid { code, .. } => *code,
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::RateLimited => write!(f, "rate limited"),
+            ApiError::NotFound => write!(f, "not found"),
+            ApiError::TokenInvalid { message, .. } => write!(f, "token invalid: {}", message),
+            ApiError::ServerError(status) => write!(f, "server error {}", status.as_u16()),
+            ApiError::ApiErrorCode {
+                status,
+                code,
+                message,
+                ..
+            } => write!(f, "{} error {}: {}", status.as_u16(), code, message),
+            ApiError::Http { status, body } => write!(f, "{}: {}", status.as_u16(), body),
+            ApiError::Deserialize(e) => write!(f, "unable to parse response: {}", e),
+            ApiError::Network(e) => write!(f, "network error: {}", e),
+            ApiError::DryRun => write!(f, "dry run: not sent"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_response() {
+        let body = r#"{"error":{"message":"Ship survey failed. Target signature is no longer in range or valid.","code":4221}}"#;
+        let e = ApiError::from_response(StatusCode::BAD_REQUEST, body);
+        assert_eq!(e.code(), Some(4221));
+        let body = r#"{"error":{"message":"Ship extract failed. Survey X1-S1-A1-BD5F47 has been exhausted.","code":4224}}"#;
+        let e = ApiError::from_response(StatusCode::CONFLICT, body);
+        assert!(matches!(
+            e,
+            ApiError::ApiErrorCode { code: 4224, ref message, .. } if message.contains("exhausted")
+        ));
+        // code-specific detail comes through in `data`
+        let body = r#"{"error":{"message":"Navigate request failed. Ship requires 12 more fuel for navigation.","code":4203,"data":{"shipSymbol":"BADGER-1","fuelRequired":112,"fuelAvailable":100}}}"#;
+        match ApiError::from_response(StatusCode::BAD_REQUEST, body) {
+            ApiError::ApiErrorCode { status, data, .. } => {
+                assert_eq!(status, StatusCode::BAD_REQUEST);
+                assert_eq!(data.unwrap()["fuelRequired"], 112);
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+
+        let e = ApiError::from_response(StatusCode::TOO_MANY_REQUESTS, r#"{"error":{"code":429}}"#);
+        assert!(matches!(e, ApiError::RateLimited));
+        let body = r#"{"error":{"message":"Failed to parse token. Token reset_date does not match the server. Server resets happen on a weekly to bi-weekly frequency during alpha. After a reset, you should re-register your agent.","code":401,"data":{"expected":"2026-10-11","actual":"2026-09-27"}}}"#;
+        let e = ApiError::from_response(StatusCode::UNAUTHORIZED, body);
+        assert!(matches!(
+            e,
+            ApiError::TokenInvalid {
+                code: Some(401),
+                ..
+            }
+        ));
+        let body = r#"{"error":{"message":"Invalid token subject.","code":4104}}"#;
+        let e = ApiError::from_response(StatusCode::BAD_REQUEST, body);
+        assert!(matches!(
+            e,
+            ApiError::TokenInvalid {
+                code: Some(4104),
+                ..
+            }
+        ));
+        let e = ApiError::from_response(StatusCode::UNAUTHORIZED, "Unauthorized");
+        assert!(matches!(e, ApiError::TokenInvalid { code: None, .. }));
+        let e = ApiError::from_response(StatusCode::BAD_GATEWAY, "<html>bad gateway</html>");
+        assert!(matches!(e, ApiError::ServerError(StatusCode::BAD_GATEWAY)));
+        let body = r#"{"error":{"message":"Waypoint X1-S1-Z9 not found.","code":404}}"#;
+        assert!(matches!(
+            ApiError::from_response(StatusCode::NOT_FOUND, body),
+            ApiError::NotFound
+        ));
+        let e = ApiError::from_response(StatusCode::FORBIDDEN, "forbidden");
+        assert!(matches!(
+            e,
+            ApiError::Http {
+                status: StatusCode::FORBIDDEN,
+                ..
+            }
+        ));
+        assert_eq!(e.code(), None);
+    }
+}
@@ -120,6 +120,27 @@ pub struct TradeResponse {
     pub transaction: models::MarketTransaction,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MountResponse {
+    pub agent: models::Agent,
+    pub mounts: Vec<models::ShipMount>,
+    pub cargo: models::ShipCargo,
+    pub transaction: models::ShipModificationTransaction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairResponse {
+    pub agent: models::Agent,
+    pub ship: models::Ship,
+    pub transaction: models::RepairTransaction,
+}
+
+// GET on the repair endpoint: a quote, nothing is charged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairQuote {
+    pub transaction: models::RepairTransaction,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RefuelResponse {
     pub agent: models::Agent,
@@ -192,6 +213,49 @@ pub struct WaypointScanResponse {
     pub waypoints: Vec<WaypointDetailed>,
 }
 
+// What a sensor scan shows of another ship: its registration, nav and the symbols of
+// whichever components the scan resolved
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScannedShip {
+    pub symbol: String,
+    pub registration: models::ShipRegistration,
+    pub nav: models::ShipNav,
+    pub frame: Option<ScannedComponent>,
+    pub reactor: Option<ScannedComponent>,
+    pub engine: ScannedComponent,
+    #[serde(default)]
+    pub mounts: Vec<ScannedComponent>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScannedComponent {
+    pub symbol: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShipScanResponse {
+    pub cooldown: models::ShipCooldown,
+    pub ships: Vec<ScannedShip>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScannedSystem {
+    pub symbol: SystemSymbol,
+    pub sector_symbol: String,
+    #[serde(rename = "type")]
+    pub system_type: String,
+    pub x: i64,
+    pub y: i64,
+    pub distance: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemScanResponse {
+    pub cooldown: models::ShipCooldown,
+    pub systems: Vec<ScannedSystem>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TransferResponse {
@@ -236,4 +300,24 @@ mod test {
         assert_eq!(waypoints.data[0].symbol, WaypointSymbol::new("X1-RB8-A1"));
         assert_eq!(waypoints.data.len(), 10);
     }
+
+    #[test]
+    fn test_mount_response() {
+        let mount_json = r#"{"data":{"agent":{"accountId":"cls7fi0omrnrys60cqtfmv24h","symbol":"WHYANDO","headquarters":"X1-TZ26-A1","credits":171234,"startingFaction":"CORSAIRS","shipCount":12},"mounts":[{"symbol":"MOUNT_SURVEYOR_II","name":"Surveyor II","description":"An advanced survey probe.","strength":2,"deposits":["IRON_ORE","COPPER_ORE"],"requirements":{"crew":4,"power":3}}],"cargo":{"capacity":40,"units":0,"inventory":[]},"transaction":{"waypointSymbol":"X1-TZ26-H51","shipSymbol":"WHYANDO-3","tradeSymbol":"MOUNT_SURVEYOR_II","totalPrice":6212,"timestamp":"2024-02-04T12:01:02.345Z"}}}"#;
+        let response: Data<MountResponse> = serde_json::from_str(mount_json).unwrap();
+        assert_eq!(response.data.mounts[0].symbol, "MOUNT_SURVEYOR_II");
+        assert_eq!(response.data.transaction.total_price, 6212);
+        assert_eq!(response.data.cargo.units, 0);
+    }
+
+    #[test]
+    fn test_repair_quote() {
+        let quote_json = r#"{"data":{"transaction":{"waypointSymbol":"X1-TZ26-H51","shipSymbol":"WHYANDO-3","totalPrice":1843,"timestamp":"2024-02-04T12:01:02.345Z"}}}"#;
+        let quote: Data<RepairQuote> = serde_json::from_str(quote_json).unwrap();
+        assert_eq!(quote.data.transaction.total_price, 1843);
+        assert_eq!(
+            quote.data.transaction.waypoint_symbol,
+            WaypointSymbol::new("X1-TZ26-H51")
+        );
+    }
 }
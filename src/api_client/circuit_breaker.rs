@@ -0,0 +1,161 @@
+// Circuit breaker for server outages. After THRESHOLD consecutive 5xx within WINDOW
+// the circuit opens: every request waits instead of spending the rate budget on
+// certain failures. While open, one caller at a time probes the status endpoint
+// (`/`), as main.rs does at startup for maintenance mode; a 200 closes the circuit
+// and the waiting requests carry on. Ship scripts simply stall inside their request
+// for the outage rather than panicking on a 5xx.
+
+use std::time::Duration;
+use tokio::time::Instant;
+
+pub const DEFAULT_THRESHOLD: usize = 10;
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+pub const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_secs(5);
+// A probe that hasn't reported back by now (its task was cancelled) is given up on
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    Closed,
+    // failing; the next probe is due at `until`
+    Open { until: Instant },
+    // a probe has been in flight since `since`
+    HalfOpen { since: Instant },
+}
+
+// What a request should do before going out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Pass,
+    Wait(Instant),
+    // this caller probes the status endpoint, then reports via `probe_result`
+    Probe,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: usize,
+    window: Duration,
+    probe_interval: Duration,
+    // the current run of consecutive server errors
+    failures: Vec<Instant>,
+    state: BreakerState,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: usize, window: Duration, probe_interval: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            window,
+            probe_interval,
+            failures: Vec::new(),
+            state: BreakerState::Closed,
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.state
+    }
+
+    pub fn admit(&mut self, now: Instant) -> Admission {
+        match self.state {
+            BreakerState::Closed => Admission::Pass,
+            BreakerState::Open { until } if now < until => Admission::Wait(until),
+            BreakerState::Open { .. } => {
+                self.state = BreakerState::HalfOpen { since: now };
+                Admission::Probe
+            }
+            BreakerState::HalfOpen { since } if now < since + PROBE_TIMEOUT => {
+                Admission::Wait(now + Duration::from_secs(1))
+            }
+            BreakerState::HalfOpen { .. } => {
+                self.state = BreakerState::HalfOpen { since: now };
+                Admission::Probe
+            }
+        }
+    }
+
+    // Record a response to a normal request. Returns true if this opened the circuit.
+    pub fn record(&mut self, now: Instant, server_error: bool) -> bool {
+        if !server_error {
+            self.failures.clear();
+            return false;
+        }
+        self.failures.push(now);
+        self.failures
+            .retain(|t| now.saturating_duration_since(*t) <= self.window);
+        if self.state == BreakerState::Closed && self.failures.len() >= self.threshold {
+            self.state = BreakerState::Open {
+                until: now + self.probe_interval,
+            };
+            return true;
+        }
+        false
+    }
+
+    pub fn probe_result(&mut self, now: Instant, ok: bool) {
+        if ok {
+            self.failures.clear();
+            self.state = BreakerState::Closed;
+        } else {
+            self.state = BreakerState::Open {
+                until: now + self.probe_interval,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_open_half_open_closed() {
+        let t0 = Instant::now();
+        let secs = Duration::from_secs;
+        let mut breaker = CircuitBreaker::new(3, secs(60), secs(5));
+
+        // a success in between resets the run; failures outside the window age out
+        assert!(!breaker.record(t0, true));
+        assert!(!breaker.record(t0 + secs(1), true));
+        assert!(!breaker.record(t0 + secs(2), false));
+        assert!(!breaker.record(t0 + secs(3), true));
+        assert!(!breaker.record(t0 + secs(100), true));
+        assert!(!breaker.record(t0 + secs(101), true));
+        assert_eq!(breaker.admit(t0 + secs(101)), Admission::Pass);
+
+        // the threshold-th consecutive 5xx opens it; requests wait for the probe slot
+        let t1 = t0 + secs(102);
+        assert!(breaker.record(t1, true));
+        assert_eq!(
+            breaker.state(),
+            BreakerState::Open {
+                until: t1 + secs(5)
+            }
+        );
+        assert_eq!(breaker.admit(t1 + secs(1)), Admission::Wait(t1 + secs(5)));
+
+        // one caller probes; the rest keep waiting while it's in flight
+        assert_eq!(breaker.admit(t1 + secs(5)), Admission::Probe);
+        assert_eq!(
+            breaker.state(),
+            BreakerState::HalfOpen {
+                since: t1 + secs(5)
+            }
+        );
+        assert!(matches!(breaker.admit(t1 + secs(5)), Admission::Wait(_)));
+        // ...unless it never reports back
+        assert_eq!(breaker.admit(t1 + secs(40)), Admission::Probe);
+
+        // a failed probe reopens it until the next probe
+        breaker.probe_result(t1 + secs(41), false);
+        assert_eq!(breaker.admit(t1 + secs(42)), Admission::Wait(t1 + secs(46)));
+        assert_eq!(breaker.admit(t1 + secs(46)), Admission::Probe);
+
+        // a good probe closes it and clears the failure run
+        breaker.probe_result(t1 + secs(47), true);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert_eq!(breaker.admit(t1 + secs(47)), Admission::Pass);
+        assert!(!breaker.record(t1 + secs(48), true));
+    }
+}
@@ -0,0 +1,258 @@
+// Per-endpoint request counters, so we can see where the request budget goes. Paths
+// are collapsed to their route (`/my/ships/{ship}/navigate`) so every ship's calls
+// land in one bucket. Counted per HTTP round trip, so a retried request counts once
+// per attempt — it spent the budget each time. Each route tallies its status codes and
+// estimates p50/p99 latency from a histogram. Gauges of the requests queued (circuit
+// breaker, rate limit, in-flight cap) and in flight ride along in the snapshot.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+// Upper bounds (ms) of the latency histogram buckets; one more bucket catches the rest
+const LATENCY_BUCKETS_MS: [u64; 8] = [50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+lazy_static! {
+    // A path segment following one of these names an instance of it
+    static ref ENDPOINT_PARAMS: Vec<(Regex, &'static str)> = [
+        (r"/my/ships/[^/]+", "/my/ships/{ship}"),
+        (r"/my/contracts/[^/]+", "/my/contracts/{contract}"),
+        (r"/systems/[^/]+", "/systems/{system}"),
+        (r"/waypoints/[^/]+", "/waypoints/{waypoint}"),
+        (r"/agents/[^/]+", "/agents/{agent}"),
+        (r"/factions/[^/]+", "/factions/{faction}"),
+    ]
+    .into_iter()
+    .map(|(re, rep)| (Regex::new(re).unwrap(), rep))
+    .collect();
+}
+
+// The route a request path belongs to: the query string dropped, and ids replaced by
+// placeholders
+pub fn endpoint(path: &str) -> String {
+    let path = path.split('?').next().unwrap_or_default();
+    ENDPOINT_PARAMS
+        .iter()
+        .fold(path.to_string(), |path, (re, rep)| {
+            re.replace_all(&path, *rep).into_owned()
+        })
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct EndpointMetrics {
+    pub requests: u64,
+    // non-2xx responses and requests that never got one
+    pub errors: u64,
+    pub total_latency_ms: u64,
+    pub max_latency_ms: u64,
+    // counts per LATENCY_BUCKETS_MS bucket, plus the overflow bucket
+    pub latency_histogram: Vec<u64>,
+    // responses by HTTP status; requests that never got one only count as errors
+    pub status_codes: BTreeMap<u16, u64>,
+}
+
+impl EndpointMetrics {
+    pub fn mean_latency_ms(&self) -> u64 {
+        self.total_latency_ms
+            .checked_div(self.requests)
+            .unwrap_or(0)
+    }
+
+    // Latency at quantile `q` (0..=1), to histogram resolution: the upper bound of the
+    // bucket it falls in, or the max for the overflow bucket
+    pub fn latency_percentile_ms(&self, q: f64) -> u64 {
+        let rank = ((q * self.requests as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.latency_histogram.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return LATENCY_BUCKETS_MS
+                    .get(bucket)
+                    .map_or(self.max_latency_ms, |&le| le.min(self.max_latency_ms));
+            }
+        }
+        0
+    }
+
+    pub fn p50_latency_ms(&self) -> u64 {
+        self.latency_percentile_ms(0.5)
+    }
+
+    pub fn p99_latency_ms(&self) -> u64 {
+        self.latency_percentile_ms(0.99)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsSnapshot {
+    pub latency_buckets_ms: Vec<u64>,
+    // keyed by "<METHOD> <endpoint>"
+    pub endpoints: BTreeMap<String, EndpointMetrics>,
+    // requests waiting to go out, and requests on the wire, right now
+    pub queued: usize,
+    pub in_flight: usize,
+}
+
+impl MetricsSnapshot {
+    pub fn total_requests(&self) -> u64 {
+        self.endpoints.values().map(|e| e.requests).sum()
+    }
+
+    // Endpoints by request count, busiest first
+    pub fn busiest(&self) -> Vec<(&String, &EndpointMetrics)> {
+        let mut endpoints: Vec<_> = self.endpoints.iter().collect();
+        endpoints.sort_by_key(|(_, e)| std::cmp::Reverse(e.requests));
+        endpoints
+    }
+}
+
+#[derive(Default)]
+pub struct ApiMetrics {
+    endpoints: Mutex<BTreeMap<String, EndpointMetrics>>,
+    queued: AtomicUsize,
+    in_flight: AtomicUsize,
+}
+
+// Holds a gauge up by one until dropped, so a cancelled request still lets go
+pub struct GaugeGuard<'a>(&'a AtomicUsize);
+
+impl<'a> GaugeGuard<'a> {
+    fn new(gauge: &'a AtomicUsize) -> Self {
+        gauge.fetch_add(1, Ordering::Relaxed);
+        GaugeGuard(gauge)
+    }
+}
+
+impl Drop for GaugeGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ApiMetrics {
+    // `status` is None when the request never got a response
+    pub fn record(
+        &self,
+        method: &reqwest::Method,
+        path: &str,
+        status: Option<reqwest::StatusCode>,
+        latency: Duration,
+    ) {
+        let key = format!("{} {}", method, endpoint(path));
+        let latency_ms = latency.as_millis() as u64;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&le| latency_ms <= le)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let e = endpoints.entry(key).or_insert_with(|| EndpointMetrics {
+            latency_histogram: vec![0; LATENCY_BUCKETS_MS.len() + 1],
+            ..Default::default()
+        });
+        e.requests += 1;
+        if !status.is_some_and(|status| status.is_success()) {
+            e.errors += 1;
+        }
+        if let Some(status) = status {
+            *e.status_codes.entry(status.as_u16()).or_default() += 1;
+        }
+        e.total_latency_ms += latency_ms;
+        e.max_latency_ms = e.max_latency_ms.max(latency_ms);
+        e.latency_histogram[bucket] += 1;
+    }
+
+    pub fn queued(&self) -> GaugeGuard<'_> {
+        GaugeGuard::new(&self.queued)
+    }
+
+    pub fn in_flight(&self) -> GaugeGuard<'_> {
+        GaugeGuard::new(&self.in_flight)
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            latency_buckets_ms: LATENCY_BUCKETS_MS.to_vec(),
+            endpoints: self.endpoints.lock().unwrap().clone(),
+            queued: self.queued.load(Ordering::Relaxed),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use reqwest::{Method, StatusCode};
+
+    #[test]
+    fn test_endpoint() {
+        assert_eq!(
+            endpoint("/my/ships/BADGER-1/navigate"),
+            "/my/ships/{ship}/navigate"
+        );
+        assert_eq!(
+            endpoint("/systems/X1-S1/waypoints/X1-S1-A1/market"),
+            "/systems/{system}/waypoints/{waypoint}/market"
+        );
+        assert_eq!(
+            endpoint("/systems/X1-S1/waypoints?traits=MARKETPLACE&page=2&limit=20"),
+            "/systems/{system}/waypoints"
+        );
+        assert_eq!(endpoint("/my/ships?page=1&limit=20"), "/my/ships");
+        assert_eq!(endpoint("/my/agent"), "/my/agent");
+    }
+
+    #[test]
+    fn test_ships_share_a_bucket() {
+        let metrics = ApiMetrics::default();
+        let ms = Duration::from_millis;
+        let ok = Some(StatusCode::OK);
+        metrics.record(&Method::POST, "/my/ships/BADGER-1/navigate", ok, ms(80));
+        metrics.record(&Method::POST, "/my/ships/BADGER-2/navigate", ok, ms(300));
+        metrics.record(
+            &Method::POST,
+            "/my/ships/BADGER-3/navigate",
+            Some(StatusCode::BAD_REQUEST),
+            ms(20000),
+        );
+        metrics.record(&Method::POST, "/my/ships/BADGER-1/dock", ok, ms(40));
+        metrics.record(&Method::POST, "/my/ships/BADGER-1/dock", None, ms(30));
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.endpoints.len(), 2);
+        assert_eq!(snapshot.total_requests(), 5);
+        let (key, navigate) = snapshot.busiest()[0];
+        assert_eq!(key, "POST /my/ships/{ship}/navigate");
+        assert_eq!((navigate.requests, navigate.errors), (3, 1));
+        assert_eq!(navigate.max_latency_ms, 20000);
+        assert_eq!(navigate.latency_histogram, [0, 1, 0, 1, 0, 0, 0, 0, 1]);
+        assert_eq!(navigate.status_codes, BTreeMap::from([(200, 2), (400, 1)]));
+
+        // a request without a response is an error with no status
+        let dock = &snapshot.endpoints["POST /my/ships/{ship}/dock"];
+        assert_eq!((dock.requests, dock.errors), (2, 1));
+        assert_eq!(dock.status_codes, BTreeMap::from([(200, 1)]));
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let metrics = ApiMetrics::default();
+        let ms = Duration::from_millis;
+        for latency in 1..=98 {
+            metrics.record(&Method::GET, "/my/agent", Some(StatusCode::OK), ms(latency));
+        }
+        metrics.record(&Method::GET, "/my/agent", Some(StatusCode::OK), ms(700));
+        metrics.record(&Method::GET, "/my/agent", Some(StatusCode::OK), ms(12000));
+
+        let agent = &metrics.snapshot().endpoints["GET /my/agent"];
+        assert_eq!(agent.p50_latency_ms(), 50);
+        assert_eq!(agent.p99_latency_ms(), 1000);
+        // the overflow bucket reports the max
+        assert_eq!(agent.latency_percentile_ms(1.0), 12000);
+        assert_eq!(EndpointMetrics::default().p50_latency_ms(), 0);
+    }
+}
@@ -1,16 +1,28 @@
+pub mod api_error;
 pub mod api_models;
+pub mod circuit_breaker;
+pub mod metrics;
+mod rate_limit;
+pub mod response_cache;
 
 use crate::models::*;
 use crate::{api_client::api_models::RegisterResponse, config::CONFIG};
+pub use api_error::ApiError;
+use circuit_breaker::{Admission, BreakerState, CircuitBreaker};
 use core::panic;
 use log::*;
+pub use rate_limit::Priority;
+use rate_limit::RateLimiter;
 use reqwest::{self, Method, StatusCode};
+use response_cache::ResponseCache;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::sync::{Arc, Mutex, RwLock};
 use tokio::time::Instant;
 
 const API_MAX_PAGE_SIZE: usize = 20;
+// What `request_string` reports for a mutation withheld in dry-run mode
+const DRY_RUN_STATUS: StatusCode = StatusCode::NOT_IMPLEMENTED;
 
 tokio::task_local! {
     // When set (via `no_io_section`), naming the section, any HTTP request issued on
@@ -48,7 +60,28 @@ pub struct ApiClient {
     base_url: String,
     client: reqwest::Client,
     agent_token: Arc<RwLock<Option<String>>>,
-    next_request_ts: Arc<Mutex<Option<Instant>>>,
+    rate_limit: Arc<RateLimiter>,
+    // caps concurrent HTTP requests, separately from the rate limit
+    in_flight: Arc<tokio::sync::Semaphore>,
+    metrics: Arc<metrics::ApiMetrics>,
+    breaker: Arc<Mutex<CircuitBreaker>>,
+    // flips to true the first time the server rejects our agent token
+    token_invalid: Arc<tokio::sync::watch::Sender<bool>>,
+    // opt-in, see `set_response_cache`
+    cache: Arc<RwLock<Option<Arc<ResponseCache>>>>,
+    // false on an `uncached()` handle: always fetch (and refresh the cache)
+    use_cache: bool,
+    // where this client's requests queue for the rate limit (see `with_priority`)
+    priority: Priority,
+    // Attempts per request on a 429 or transient 5xx, first try included
+    max_attempts: u32,
+    // First retry waits about this long, doubling per attempt (see `backoff`)
+    backoff_base: std::time::Duration,
+    // ...up to this long per wait, and no retry past this long since the first try
+    backoff_max: std::time::Duration,
+    max_retry_elapsed: std::time::Duration,
+    // log mutations instead of sending them (see `withhold_dry_run`)
+    dry_run: bool,
 }
 
 impl Default for ApiClient {
@@ -67,7 +100,39 @@ impl ApiClient {
             client: reqwest::Client::new(),
             base_url: "http://test.invalid".to_string(),
             agent_token: Arc::new(RwLock::new(None)),
-            next_request_ts: Arc::new(Mutex::new(None)),
+            rate_limit: Arc::new(RateLimiter::new(
+                rate_limit::DEFAULT_RATE_PER_SECOND,
+                rate_limit::DEFAULT_BURST,
+            )),
+            in_flight: Arc::new(tokio::sync::Semaphore::new(10)),
+            metrics: Arc::new(metrics::ApiMetrics::default()),
+            breaker: Arc::new(Mutex::new(CircuitBreaker::new(
+                circuit_breaker::DEFAULT_THRESHOLD,
+                circuit_breaker::DEFAULT_WINDOW,
+                circuit_breaker::DEFAULT_PROBE_INTERVAL,
+            ))),
+            token_invalid: Arc::new(tokio::sync::watch::Sender::new(false)),
+            cache: Arc::new(RwLock::new(None)),
+            use_cache: true,
+            priority: Priority::Normal,
+            max_attempts: 5,
+            backoff_base: std::time::Duration::from_millis(500),
+            backoff_max: std::time::Duration::from_secs(30),
+            max_retry_elapsed: std::time::Duration::from_secs(120),
+            dry_run: false,
+        }
+    }
+
+    // for_test, but dialing `app` served on a local port: the fixture for tests that
+    // need a real server on the other end
+    #[cfg(test)]
+    pub(crate) async fn mock_server(app: axum::Router) -> ApiClient {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        ApiClient {
+            base_url: format!("http://{}", addr),
+            ..ApiClient::for_test()
         }
     }
 
@@ -84,10 +149,124 @@ impl ApiClient {
             client,
             base_url: CONFIG.api_base_url.to_string(),
             agent_token: Arc::new(RwLock::new(None)),
-            next_request_ts: Arc::new(Mutex::new(None)),
+            rate_limit: Arc::new(RateLimiter::new(
+                rate_limit::DEFAULT_RATE_PER_SECOND,
+                rate_limit::DEFAULT_BURST,
+            )),
+            in_flight: Arc::new(tokio::sync::Semaphore::new(CONFIG.api_max_in_flight.max(1))),
+            metrics: Arc::new(metrics::ApiMetrics::default()),
+            breaker: Arc::new(Mutex::new(CircuitBreaker::new(
+                CONFIG.circuit_breaker_threshold,
+                std::time::Duration::from_secs(CONFIG.circuit_breaker_window_secs),
+                circuit_breaker::DEFAULT_PROBE_INTERVAL,
+            ))),
+            token_invalid: Arc::new(tokio::sync::watch::Sender::new(false)),
+            cache: Arc::new(RwLock::new(None)),
+            use_cache: true,
+            priority: Priority::Normal,
+            max_attempts: CONFIG.api_max_attempts.max(1),
+            backoff_base: std::time::Duration::from_millis(CONFIG.api_backoff_base_ms),
+            backoff_max: std::time::Duration::from_millis(CONFIG.api_backoff_max_ms),
+            max_retry_elapsed: std::time::Duration::from_secs(CONFIG.api_retry_max_elapsed_secs),
+            dry_run: CONFIG.dry_run,
+        }
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    // Override the retry policy from CONFIG. `max_attempts` includes the first try.
+    pub fn with_retry(mut self, max_attempts: u32, backoff_base: std::time::Duration) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self.backoff_base = backoff_base;
+        self
+    }
+
+    // Override CONFIG's cap on a single backoff and on the total time spent retrying
+    pub fn with_retry_limits(
+        mut self,
+        backoff_max: std::time::Duration,
+        max_retry_elapsed: std::time::Duration,
+    ) -> Self {
+        self.backoff_max = backoff_max;
+        self.max_retry_elapsed = max_retry_elapsed;
+        self
+    }
+
+    // Override the default request budget (sustained rate and burst size). Clones
+    // made after this share the new bucket.
+    pub fn with_rate_limit(mut self, rate_per_second: f64, burst: u32) -> Self {
+        self.rate_limit = Arc::new(RateLimiter::new(rate_per_second, burst));
+        self
+    }
+
+    // Override the in-flight cap from CONFIG. Clones made after this share it.
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.in_flight = Arc::new(tokio::sync::Semaphore::new(max_in_flight.max(1)));
+        self
+    }
+
+    // Override the circuit breaker from CONFIG: open after `threshold` consecutive 5xx
+    // within `window`, probing `/` every `probe_interval` while open
+    pub fn with_circuit_breaker(
+        mut self,
+        threshold: usize,
+        window: std::time::Duration,
+        probe_interval: std::time::Duration,
+    ) -> Self {
+        self.breaker = Arc::new(Mutex::new(CircuitBreaker::new(
+            threshold,
+            window,
+            probe_interval,
+        )));
+        self
+    }
+
+    // A handle on the same client (token, rate limit) whose requests queue at
+    // `priority`, e.g. `api_client.with_priority(Priority::High).post(..)`
+    pub fn with_priority(&self, priority: Priority) -> ApiClient {
+        ApiClient {
+            priority,
+            ..self.clone()
+        }
+    }
+
+    // Serve the immutable system/waypoint GETs (see `response_cache`) from `cache` while
+    // fresh. Shared by every clone; set once the DB is up.
+    pub fn set_response_cache(&self, cache: ResponseCache) {
+        *self.cache.write().unwrap() = Some(Arc::new(cache));
+    }
+
+    // A handle on the same client that bypasses the response cache, refreshing it
+    // with what it fetches
+    pub fn uncached(&self) -> ApiClient {
+        ApiClient {
+            use_cache: false,
+            ..self.clone()
+        }
+    }
+
+    // Drop a cached GET `path` (and its pages)
+    pub async fn invalidate_cache(&self, path: &str) {
+        let cache = self.cache.read().unwrap().clone();
+        if let Some(cache) = cache {
+            cache.invalidate(path).await;
         }
     }
 
+    // Request counts, errors and latency per endpoint since startup, across all clones
+    pub fn metrics_snapshot(&self) -> metrics::MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    // Closed in normal operation; Open/HalfOpen while the API is down and every
+    // request is held back
+    pub fn breaker_state(&self) -> BreakerState {
+        self.breaker.lock().unwrap().state()
+    }
+
     pub fn set_agent_token(&self, token: &str) {
         let mut agent_token = self.agent_token.write().unwrap();
         if agent_token.is_some() {
@@ -104,6 +283,37 @@ impl ApiClient {
         self.request(Method::GET, "/", None::<&()>).await
     }
 
+    // Resolves once any request has been rejected for an invalid agent token. Requests
+    // through the panicking helpers park rather than panic after that, so the caller
+    // (main.rs) can shut down in an orderly way.
+    pub async fn token_invalidated(&self) {
+        let mut rx = self.token_invalid.subscribe();
+        let _ = rx.wait_for(|invalid| *invalid).await;
+    }
+
+    // The server's current reset date, if it differs from `reset_date` (the one we
+    // started with). Waits out maintenance like startup does; None if the status can't
+    // be read at all, since that proves nothing.
+    pub async fn server_reset_since(&self, reset_date: &str) -> Option<String> {
+        loop {
+            let (status_code, status) = self.status().await;
+            match (status_code, status) {
+                (StatusCode::OK, Ok(status)) if status.reset_date != reset_date => {
+                    return Some(status.reset_date);
+                }
+                (StatusCode::OK, Ok(_)) => return None,
+                (StatusCode::SERVICE_UNAVAILABLE, _) => {
+                    warn!("Status unavailable, assumed maintenance, retrying in 1 second");
+                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                }
+                (status_code, status) => {
+                    error!("Failed to get status: {}\nbody: {:?}", status_code, status);
+                    return None;
+                }
+            }
+        }
+    }
+
     pub fn agent_token(&self) -> Option<String> {
         self.agent_token.read().unwrap().clone()
     }
@@ -217,12 +427,10 @@ impl ApiClient {
             symbol.system(),
             symbol
         );
-        let (code, construction): (StatusCode, Result<Data<Construction>, String>) =
-            self.request(Method::GET, &path, None::<&()>).await;
-        let construction = match code {
-            StatusCode::OK => Some(construction.unwrap().data),
-            StatusCode::NOT_FOUND => None,
-            _ => panic!("Request failed: {} {} {}", code.as_u16(), Method::GET, path),
+        let construction = match self.try_get::<Data<Construction>>(&path).await {
+            Ok(construction) => Some(construction.data),
+            Err(ApiError::NotFound) => None,
+            Err(e) => panic!("Request failed: {} {}: {}", Method::GET, path, e),
         };
         WithTimestamp::<Option<Construction>> {
             timestamp: chrono::Utc::now(),
@@ -374,6 +582,31 @@ impl ApiClient {
         })
     }
 
+    // Fallible counterparts of get/post/patch: a non-2xx, unparseable or undelivered
+    // response comes back as an ApiError instead of a panic
+    pub async fn try_get<T>(&self, path: &str) -> Result<T, ApiError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.try_request(Method::GET, path, None::<&()>).await
+    }
+
+    pub async fn try_post<T, U>(&self, path: &str, json_body: &U) -> Result<T, ApiError>
+    where
+        T: serde::de::DeserializeOwned,
+        U: Serialize,
+    {
+        self.try_request(Method::POST, path, Some(json_body)).await
+    }
+
+    pub async fn try_patch<T, U>(&self, path: &str, json_body: &U) -> Result<T, ApiError>
+    where
+        T: serde::de::DeserializeOwned,
+        U: Serialize,
+    {
+        self.try_request(Method::PATCH, path, Some(json_body)).await
+    }
+
     pub async fn get_string(&self, path: &str) -> String {
         let (status, body_result) = self.request_string(Method::GET, path, None::<&()>).await;
         body_result.unwrap_or_else(|body| {
@@ -436,26 +669,38 @@ impl ApiClient {
     }
 
     async fn wait_rate_limit(&self) {
-        let now = Instant::now();
-        let request_instant = {
-            let mut next_request_ts = self.next_request_ts.lock().unwrap();
-            let request_instant = match *next_request_ts {
-                Some(ts) if ts > now => ts,
-                _ => now,
-            };
-            *next_request_ts = Some(request_instant + std::time::Duration::from_millis(501));
-            request_instant
-        };
-        let wait_duration = request_instant
-            .checked_duration_since(now)
-            .unwrap_or_default();
-        if wait_duration >= std::time::Duration::from_secs(10) {
-            warn!(
-                "Rate limit queue exceeds 10 seconds: {:.3}s",
-                wait_duration.as_secs_f64()
-            );
+        self.rate_limit.acquire(self.priority).await;
+    }
+
+    // Hold the request while the circuit is open. Whichever waiter is admitted as the
+    // probe checks `/` (bypassing the breaker itself) and closes or re-opens it.
+    async fn wait_circuit(&self) {
+        loop {
+            let admission = self.breaker.lock().unwrap().admit(Instant::now());
+            match admission {
+                Admission::Pass => return,
+                Admission::Wait(until) => tokio::time::sleep_until(until).await,
+                Admission::Probe => {
+                    self.rate_limit.acquire(Priority::High).await;
+                    let status = self
+                        .client
+                        .get(format!("{}/", self.base_url))
+                        .send()
+                        .await
+                        .map(|response| response.status());
+                    let ok = status.as_ref().is_ok_and(|status| status.is_success());
+                    self.breaker
+                        .lock()
+                        .unwrap()
+                        .probe_result(Instant::now(), ok);
+                    match status {
+                        Ok(_) if ok => info!("API is back, closing circuit"),
+                        Ok(status) => debug!("API probe: {}, circuit stays open", status),
+                        Err(e) => debug!("API probe failed: {}, circuit stays open", e),
+                    }
+                }
+            }
         }
-        tokio::time::sleep_until(request_instant).await;
     }
 
     pub async fn request<T, U>(
@@ -483,6 +728,35 @@ impl ApiClient {
         }
     }
 
+    // Push back the shared bucket, so every task backs off together after a 429
+    // rather than each hitting the limiter again on its own.
+    fn delay_rate_limit(&self, until: Instant) {
+        self.rate_limit.delay_until(until);
+    }
+
+    pub async fn try_request<T, U>(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        json_body: Option<&U>,
+    ) -> Result<T, ApiError>
+    where
+        T: serde::de::DeserializeOwned,
+        U: Serialize,
+    {
+        if self.withhold_dry_run(&method, path, json_body) {
+            return Err(ApiError::DryRun);
+        }
+        let (status, result) = self
+            .try_request_string(method, path, json_body)
+            .await
+            .map_err(ApiError::Network)?;
+        match result {
+            Ok(content) => serde_json::from_str(&content).map_err(ApiError::Deserialize),
+            Err(body) => Err(ApiError::from_response(status, &body)),
+        }
+    }
+
     pub async fn request_string<U>(
         &self,
         method: reqwest::Method,
@@ -492,34 +766,256 @@ impl ApiClient {
     where
         U: Serialize,
     {
-        guard_no_io(&method, path);
-        self.wait_rate_limit().await;
-        let url = format!("{}{}", self.base_url, path);
-        let mut request = self.client.request(method.clone(), &url);
-        if let Some(body) = json_body {
-            request = request.json(body);
+        if self.withhold_dry_run(&method, path, json_body) {
+            // A stub failure, so the panicking helpers panic rather than hang. Nothing
+            // that calls them runs in a dry run: ship scripts aren't started, and the
+            // controller skips its purchase and contract phases.
+            return (DRY_RUN_STATUS, Err(ApiError::DryRun.to_string()));
         }
-        // override auth type for /register
-        if path == "/register" {
-            let account_token = std::env::var("SPACETRADERS_ACCOUNT_TOKEN")
-                .expect("SPACETRADERS_ACCOUNT_TOKEN env var must be set to register");
-            request = request.header("Authorization", format!("Bearer {}", account_token));
-        } else if let Some(token) = self.agent_token() {
-            request = request.header("Authorization", format!("Bearer {}", token));
+        let (status, result) = self
+            .try_request_string(method.clone(), path, json_body)
+            .await
+            .expect("Failed to send request");
+        if let Err(body) = &result
+            && let e @ ApiError::TokenInvalid { .. } = ApiError::from_response(status, body)
+        {
+            // don't panic the ship script: main.rs is already shutting down
+            error!("{} {}: {}, parking request until shutdown", method, path, e);
+            std::future::pending::<()>().await;
         }
-        let response = request.send().await.expect("Failed to send request");
-        let status = response.status();
-        debug!("{} {} {}", status.as_u16(), method, path);
-        let response_body = response.text().await.unwrap();
+        (status, result)
+    }
 
-        if status.is_success() {
-            (status, Ok(response_body))
-        } else {
-            (status, Err(response_body))
+    // In dry-run mode, log a mutation (anything but a GET) and report that it must not be
+    // sent. `/` is a GET, so server reset detection is unaffected.
+    fn withhold_dry_run<U: Serialize>(
+        &self,
+        method: &reqwest::Method,
+        path: &str,
+        json_body: Option<&U>,
+    ) -> bool {
+        if !self.dry_run || method == Method::GET {
+            return false;
+        }
+        let body = json_body
+            .map(|body| serde_json::to_string(body).unwrap())
+            .unwrap_or_default();
+        info!("DRY RUN: {} {} {}", method, path, body);
+        true
+    }
+
+    // The request funnel. Err only if the request couldn't be sent or its body read.
+    async fn try_request_string<U>(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        json_body: Option<&U>,
+    ) -> Result<(StatusCode, Result<String, String>), reqwest::Error>
+    where
+        U: Serialize,
+    {
+        guard_no_io(&method, path);
+        let cache = match method == Method::GET && response_cache::is_cacheable(path) {
+            true => self.cache.read().unwrap().clone(),
+            false => None,
+        };
+        if let Some(cache) = cache.as_ref().filter(|_| self.use_cache)
+            && let Some(body) = cache.get(path).await
+        {
+            debug!("cached {} {}", method, path);
+            return Ok((StatusCode::OK, Ok(body)));
+        }
+        let url = format!("{}{}", self.base_url, path);
+        // Serialised once up front: a request body can't be cloned, so each retry
+        // re-sends these bytes
+        let body = json_body.map(|body| serde_json::to_vec(body).unwrap());
+        let started = Instant::now();
+        let mut attempt = 1;
+        loop {
+            let queued = self.metrics.queued();
+            self.wait_circuit().await;
+            self.wait_rate_limit().await;
+            // after the rate limit, so a permit is only held on the wire
+            let permit = self.in_flight.acquire().await.unwrap();
+            drop(queued);
+            let in_flight = self.metrics.in_flight();
+            let mut request = self.client.request(method.clone(), &url);
+            if let Some(body) = &body {
+                request = request
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(body.clone());
+            }
+            // override auth type for /register
+            if path == "/register" {
+                let account_token = std::env::var("SPACETRADERS_ACCOUNT_TOKEN")
+                    .expect("SPACETRADERS_ACCOUNT_TOKEN env var must be set to register");
+                request = request.header("Authorization", format!("Bearer {}", account_token));
+            } else if path == "/" {
+                // the status endpoint is public, and has to stay readable once our
+                // token is dead to find out whether the server reset
+            } else if let Some(token) = self.agent_token() {
+                request = request.header("Authorization", format!("Bearer {}", token));
+            }
+            let sent = Instant::now();
+            let response = async {
+                let response = request.send().await?;
+                let status = response.status();
+                let headers = response.headers().clone();
+                Ok::<_, reqwest::Error>((status, headers, response.text().await?))
+            }
+            .await;
+            drop((permit, in_flight));
+            let status = response.as_ref().ok().map(|(status, _, _)| *status);
+            self.metrics.record(&method, path, status, sent.elapsed());
+            let (status, headers, response_body) = match response {
+                Ok(response) => response,
+                Err(e) => {
+                    let wait = self.retry_wait(attempt);
+                    if !is_retryable_error(&method, &e) || !self.may_retry(attempt, started, wait) {
+                        return Err(e);
+                    }
+                    warn!(
+                        "{} {}: {} (attempt {}/{}, {:.1}s elapsed), retrying in {:.3}s",
+                        method,
+                        path,
+                        e,
+                        attempt,
+                        self.max_attempts,
+                        started.elapsed().as_secs_f64(),
+                        wait.as_secs_f64()
+                    );
+                    tokio::time::sleep(wait).await;
+                    attempt += 1;
+                    continue;
+                }
+            };
+            debug!("{} {} {}", status.as_u16(), method, path);
+            let opened = self
+                .breaker
+                .lock()
+                .unwrap()
+                .record(Instant::now(), status.is_server_error());
+            if opened {
+                error!(
+                    "{} {} {}: too many server errors, opening circuit until the API recovers",
+                    status.as_u16(),
+                    method,
+                    path
+                );
+            }
+
+            let backoff = self.retry_wait(attempt);
+            let wait = match status {
+                // honour the server's wait; it's shared by every task, so push back the
+                // whole queue rather than just this request
+                StatusCode::TOO_MANY_REQUESTS => {
+                    retry_after(&headers, &response_body).unwrap_or(backoff)
+                }
+                _ => backoff,
+            };
+            if is_retryable(&method, status) && self.may_retry(attempt, started, wait) {
+                if status == StatusCode::TOO_MANY_REQUESTS {
+                    self.delay_rate_limit(Instant::now() + wait);
+                }
+                warn!(
+                    "{} {} {} (attempt {}/{}, {:.1}s elapsed), retrying in {:.3}s",
+                    status.as_u16(),
+                    method,
+                    path,
+                    attempt,
+                    self.max_attempts,
+                    started.elapsed().as_secs_f64(),
+                    wait.as_secs_f64()
+                );
+                tokio::time::sleep(wait).await;
+                attempt += 1;
+                continue;
+            }
+            if !status.is_success()
+                && path != "/register"
+                && matches!(
+                    ApiError::from_response(status, &response_body),
+                    ApiError::TokenInvalid { .. }
+                )
+            {
+                self.token_invalid.send_replace(true);
+            }
+            if status.is_success() {
+                if let Some(cache) = &cache {
+                    cache.put(path, &response_body).await;
+                }
+                return Ok((status, Ok(response_body)));
+            } else {
+                return Ok((status, Err(response_body)));
+            }
         }
     }
 }
 
+impl ApiClient {
+    // The jittered backoff before the retry following `attempt`
+    fn retry_wait(&self, attempt: u32) -> std::time::Duration {
+        jitter(backoff(self.backoff_base, attempt).min(self.backoff_max))
+    }
+
+    // Attempts left, and the retry would start within the elapsed-time cap
+    fn may_retry(&self, attempt: u32, started: Instant, wait: std::time::Duration) -> bool {
+        attempt < self.max_attempts && started.elapsed() + wait <= self.max_retry_elapsed
+    }
+}
+
+// A request that failed without a response is only safe to repeat if it can't have
+// reached the server (the connection never opened), or if it's a read
+fn is_retryable_error(method: &Method, e: &reqwest::Error) -> bool {
+    e.is_connect() || (*method == Method::GET && (e.is_timeout() || e.is_request() || e.is_body()))
+}
+
+// Rate limiting and transient gateway/server errors; anything else is the caller's
+//
+// A 429 is refused before any processing, so it's safe to repeat for any method. A 5xx
+// can come after the server acted (a purchase made, a jump taken), so like a dropped
+// response in `is_retryable_error`, only a GET is repeated on one.
+fn is_retryable(method: &Method, status: StatusCode) -> bool {
+    match status {
+        StatusCode::TOO_MANY_REQUESTS => true,
+        StatusCode::INTERNAL_SERVER_ERROR
+        | StatusCode::BAD_GATEWAY
+        | StatusCode::SERVICE_UNAVAILABLE
+        | StatusCode::GATEWAY_TIMEOUT => *method == Method::GET,
+        _ => false,
+    }
+}
+
+// Wait before the retry following `attempt` (1-based): base, 2x base, 4x base, ...
+fn backoff(base: std::time::Duration, attempt: u32) -> std::time::Duration {
+    base.saturating_mul(1 << (attempt - 1).min(16))
+}
+
+// Randomise a wait to between half and all of it, so tasks that failed together don't
+// retry together
+fn jitter(wait: std::time::Duration) -> std::time::Duration {
+    wait.mul_f64(0.5 + rand::random::<f64>() / 2.0)
+}
+
+// How long a 429 asks us to wait: the Retry-After header (seconds), falling back to
+// the `error.data.retryAfter` field SpaceTraders puts in the body.
+fn retry_after(headers: &reqwest::header::HeaderMap, body: &str) -> Option<std::time::Duration> {
+    let from_header = headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<f64>().ok());
+    let from_body = || {
+        serde_json::from_str::<serde_json::Value>(body)
+            .ok()?
+            .pointer("/error/data/retryAfter")?
+            .as_f64()
+    };
+    from_header
+        .or_else(from_body)
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(std::time::Duration::from_secs_f64)
+}
+
 #[cfg(test)]
 mod no_io_tests {
     use super::*;
@@ -552,3 +1048,387 @@ mod no_io_tests {
         guard_no_io(&Method::POST, "/my/ships"); // must not panic after scope exits
     }
 }
+
+#[cfg(test)]
+mod retry_tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+
+    #[test]
+    fn test_retry_after() {
+        let body = r#"{"error":{"message":"Too many requests","code":429,"data":{"type":"IP","retryAfter":0.75,"limitBurst":30}}}"#;
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            retry_after(&headers, body),
+            Some(std::time::Duration::from_millis(750))
+        );
+
+        // the header wins over the body
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("2"));
+        assert_eq!(
+            retry_after(&headers, body),
+            Some(std::time::Duration::from_secs(2))
+        );
+
+        // an HTTP-date header isn't understood; the body still is
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(
+            retry_after(&headers, body),
+            Some(std::time::Duration::from_millis(750))
+        );
+        assert_eq!(retry_after(&HeaderMap::new(), "rate limited"), None);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let base = std::time::Duration::from_millis(500);
+        assert_eq!(backoff(base, 1), base);
+        assert_eq!(backoff(base, 3), base * 4);
+        for _ in 0..100 {
+            let wait = jitter(backoff(base, 2));
+            assert!(wait >= base && wait <= base * 2);
+        }
+        assert!(is_retryable(&Method::GET, StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable(&Method::GET, StatusCode::BAD_REQUEST));
+        // a mutation may have gone through before the 5xx; a 429 never did
+        assert!(!is_retryable(&Method::POST, StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable(
+            &Method::POST,
+            StatusCode::SERVICE_UNAVAILABLE
+        ));
+        assert!(is_retryable(&Method::POST, StatusCode::TOO_MANY_REQUESTS));
+    }
+
+    // Serve two 429s then a 200 on a local port; the caller sees only the 200
+    #[tokio::test]
+    async fn test_retries_through_rate_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let hits = Arc::new(AtomicUsize::new(0));
+        let app = axum::Router::new().route(
+            "/my/agent",
+            axum::routing::post({
+                let hits = hits.clone();
+                move |body: String| async move {
+                    use axum::response::IntoResponse as _;
+                    assert_eq!(body, r#"{"ping":1}"#);
+                    match hits.fetch_add(1, Ordering::SeqCst) {
+                        0 => (StatusCode::TOO_MANY_REQUESTS, [("retry-after", "0")], "{}")
+                            .into_response(),
+                        1 => (StatusCode::TOO_MANY_REQUESTS, "{}").into_response(),
+                        _ => (StatusCode::OK, r#"{"data":"ok"}"#).into_response(),
+                    }
+                }
+            }),
+        );
+        let api = ApiClient::mock_server(app)
+            .await
+            .with_retry(5, std::time::Duration::from_millis(1));
+        let response: Data<String> = api.post("/my/agent", &json!({"ping": 1})).await;
+        assert_eq!(response.data, "ok");
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        // out of attempts, the 429 reaches the caller
+        hits.store(0, Ordering::SeqCst);
+        let api = api.with_retry(2, std::time::Duration::from_millis(1));
+        let (status, _): (StatusCode, Result<String, String>) = api
+            .request_string(Method::POST, "/my/agent", Some(&json!({"ping": 1})))
+            .await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    // A server that hangs up on every request: reads are retried, but a mutation that
+    // may have arrived isn't repeated
+    #[tokio::test]
+    async fn test_retries_transport_errors() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let _ = tokio::io::AsyncReadExt::read(&mut socket, &mut buf).await;
+            }
+        });
+        let ms = std::time::Duration::from_millis;
+        let mut api = ApiClient::for_test().with_retry(3, ms(1));
+        api.base_url = format!("http://{}", addr);
+        let result: Result<(StatusCode, Result<String, String>), _> = api
+            .try_request_string(Method::GET, "/my/agent", None::<&()>)
+            .await;
+        assert!(result.is_err());
+        assert_eq!(api.metrics_snapshot().total_requests(), 3);
+
+        let result: Result<(StatusCode, Result<String, String>), _> = api
+            .try_request_string(Method::POST, "/my/agent", Some(&json!({"ping": 1})))
+            .await;
+        assert!(result.is_err());
+        assert_eq!(api.metrics_snapshot().total_requests(), 4);
+
+        // nothing listening: the connection never opened, so even a mutation is retried
+        drop(api);
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed_addr = closed.local_addr().unwrap();
+        drop(closed);
+        let mut api = ApiClient::for_test().with_retry(3, ms(1));
+        api.base_url = format!("http://{}", closed_addr);
+        let result: Result<(StatusCode, Result<String, String>), _> = api
+            .try_request_string(Method::POST, "/my/agent", Some(&json!({"ping": 1})))
+            .await;
+        assert!(result.unwrap_err().is_connect());
+        assert_eq!(api.metrics_snapshot().total_requests(), 3);
+
+        // the elapsed cap stops retries even with attempts to spare
+        let api = api
+            .with_retry(5, ms(100))
+            .with_retry_limits(ms(100), ms(10));
+        let result: Result<(StatusCode, Result<String, String>), _> = api
+            .try_request_string(Method::GET, "/my/agent", None::<&()>)
+            .await;
+        assert!(result.is_err());
+        assert_eq!(api.metrics_snapshot().total_requests(), 4);
+    }
+
+    #[test]
+    fn test_backoff_cap() {
+        let api = ApiClient::for_test().with_retry_limits(
+            std::time::Duration::from_millis(800),
+            std::time::Duration::from_secs(120),
+        );
+        for _ in 0..100 {
+            let wait = api.retry_wait(10);
+            assert!(wait >= std::time::Duration::from_millis(400));
+            assert!(wait <= std::time::Duration::from_millis(800));
+        }
+    }
+}
+
+#[cfg(test)]
+mod reset_tests {
+    use super::*;
+
+    // A server that has reset under us: our token is rejected and `/` reports a new
+    // reset date, which is what main.rs checks before shutting down
+    #[tokio::test]
+    async fn test_token_invalid_after_reset() {
+        let status = |reset_date: &'static str| {
+            json!({
+                "status": "SpaceTraders is currently online",
+                "version": "v2.3.0",
+                "resetDate": reset_date,
+                "stats": { "agents": 1, "ships": 2, "systems": 3, "waypoints": 4 },
+            })
+            .to_string()
+        };
+        let reset_date = Arc::new(Mutex::new("2026-10-11"));
+        let app = axum::Router::new()
+            .route(
+                "/",
+                axum::routing::get({
+                    let reset_date = reset_date.clone();
+                    move |headers: axum::http::HeaderMap| async move {
+                        // the status check goes out without our (dead) token
+                        assert!(!headers.contains_key("authorization"));
+                        status(*reset_date.lock().unwrap())
+                    }
+                }),
+            )
+            .route(
+                "/my/agent",
+                axum::routing::get(|| async {
+                    (
+                        StatusCode::UNAUTHORIZED,
+                        r#"{"error":{"message":"Failed to parse token. Token reset_date does not match the server.","code":401}}"#,
+                    )
+                }),
+            );
+        let api = ApiClient::mock_server(app).await;
+        api.set_agent_token("old-slice-token");
+        assert_eq!(api.server_reset_since("2026-10-11").await, None);
+
+        // the try_ path surfaces the rejection, and the shutdown signal fires
+        let e = api.try_get::<Data<Agent>>("/my/agent").await.unwrap_err();
+        assert!(matches!(
+            e,
+            ApiError::TokenInvalid {
+                code: Some(401),
+                ..
+            }
+        ));
+        tokio::time::timeout(std::time::Duration::from_secs(1), api.token_invalidated())
+            .await
+            .expect("token invalidation not signalled");
+
+        // the panicking path parks instead of panicking the caller
+        let parked = tokio::time::timeout(
+            std::time::Duration::from_millis(200),
+            api.request_string(Method::GET, "/my/agent", None::<&()>),
+        )
+        .await;
+        assert!(parked.is_err());
+
+        *reset_date.lock().unwrap() = "2026-10-25";
+        assert_eq!(
+            api.server_reset_since("2026-10-11").await.as_deref(),
+            Some("2026-10-25")
+        );
+    }
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_cached_within_ttl() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let app = axum::Router::new().route(
+            "/systems/{system}",
+            axum::routing::get({
+                let hits = hits.clone();
+                move |axum::extract::Path(system): axum::extract::Path<String>| async move {
+                    hits.fetch_add(1, Ordering::SeqCst);
+                    json!({"data": {
+                        "symbol": system, "sectorSymbol": "X1", "constellation": "C", "name": "S1", "type": "RED_STAR",
+                        "x": 1, "y": 2, "waypoints": [], "factions": [],
+                    }})
+                    .to_string()
+                }
+            }),
+        );
+        let api = ApiClient::mock_server(app).await;
+        let system = SystemSymbol::new("X1-S1");
+
+        // uncached until opted in
+        api.get_system(&system).await;
+        api.get_system(&system).await;
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        api.set_response_cache(ResponseCache::in_memory(chrono::Duration::hours(1)));
+        api.get_system(&system).await;
+        let before = api.metrics_snapshot().total_requests();
+        let cached = api.get_system(&system).await;
+        assert_eq!(cached.symbol, system);
+        // the second call within the TTL makes no HTTP request at all
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert_eq!(api.metrics_snapshot().total_requests(), before);
+
+        // a forced refresh fetches; so does anything after invalidation
+        api.uncached().get_system(&system).await;
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+        api.invalidate_cache("/systems/X1-S1").await;
+        api.get_system(&system).await;
+        api.get_system(&system).await;
+        assert_eq!(hits.load(Ordering::SeqCst), 5);
+
+        // past the TTL, entries are refetched
+        api.set_response_cache(ResponseCache::in_memory(chrono::Duration::zero()));
+        api.get_system(&system).await;
+        api.get_system(&system).await;
+        assert_eq!(hits.load(Ordering::SeqCst), 7);
+    }
+}
+
+#[cfg(test)]
+mod dry_run_tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_dry_run_withholds_mutations() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let app = axum::Router::new().fallback({
+            let hits = hits.clone();
+            move || async move {
+                hits.fetch_add(1, Ordering::SeqCst);
+                json!({"data": {"symbol": "X1-S1-A1"}}).to_string()
+            }
+        });
+        let api = ApiClient::mock_server(app).await.with_dry_run(true);
+        let body = json!({"waypointSymbol": "X1-S1-A2"});
+        let result = api
+            .try_post::<serde_json::Value, _>("/my/ships/BADGER-1/navigate", &body)
+            .await;
+        assert!(matches!(result, Err(ApiError::DryRun)));
+        let result = api
+            .try_patch::<serde_json::Value, _>("/my/ships/BADGER-1/nav", &body)
+            .await;
+        assert!(matches!(result, Err(ApiError::DryRun)));
+        assert_eq!(hits.load(Ordering::SeqCst), 0);
+        assert_eq!(api.metrics_snapshot().total_requests(), 0);
+
+        // reads still go out
+        api.try_get::<serde_json::Value>("/systems/X1-S1/waypoints/X1-S1-A1")
+            .await
+            .unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // the panicking helpers get a stub error back rather than hanging
+        let (status, result) = tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            api.request_string(Method::POST, "/my/ships/BADGER-1/orbit", Some(&json!({}))),
+        )
+        .await
+        .expect("dry-run mutation hung");
+        assert_eq!(status, DRY_RUN_STATUS);
+        assert_eq!(result, Err(ApiError::DryRun.to_string()));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+}
+
+#[cfg(test)]
+mod in_flight_tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // 100 requests at once against a slow server never have more than the cap open
+    #[tokio::test]
+    async fn test_in_flight_cap() {
+        let current = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let app = axum::Router::new().route(
+            "/my/agent",
+            axum::routing::get({
+                let (current, peak) = (current.clone(), peak.clone());
+                move || async move {
+                    let now = current.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                    current.fetch_sub(1, Ordering::SeqCst);
+                    r#"{"data":"ok"}"#
+                }
+            }),
+        );
+        let api = ApiClient::mock_server(app)
+            .await
+            .with_rate_limit(10_000.0, 1_000)
+            .with_max_in_flight(10);
+        let tasks: Vec<_> = (0..100)
+            .map(|_| {
+                let api = api.clone();
+                tokio::spawn(async move {
+                    let response: Data<String> = api.get("/my/agent").await;
+                    assert_eq!(response.data, "ok");
+                })
+            })
+            .collect();
+
+        // while they drain, the gauges split the 100 between queued and in flight
+        tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+        let snapshot = api.metrics_snapshot();
+        assert!(snapshot.in_flight <= 10);
+        assert!(snapshot.queued > 0);
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), 10);
+        let snapshot = api.metrics_snapshot();
+        assert_eq!(snapshot.total_requests(), 100);
+        assert_eq!((snapshot.queued, snapshot.in_flight), (0, 0));
+    }
+}
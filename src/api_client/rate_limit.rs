@@ -0,0 +1,232 @@
+// Rate limiter shared by every clone of an ApiClient, so all ship tasks draw from the
+// one request budget. A single dispatcher task takes tokens from the bucket and hands
+// each to the most urgent queued request, so a High request queued behind a run of
+// Low ones (probe market refreshes) still goes out next. The bucket is a theoretical
+// arrival time (GCRA) rather than a counter refilled on a timer; locks are only held
+// for the arithmetic, never across an await.
+
+use log::*;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Once};
+use std::time::Duration;
+use tokio::sync::{Notify, oneshot};
+use tokio::time::Instant;
+
+// SpaceTraders allows 2 requests/s sustained, with bursts of up to 10
+pub const DEFAULT_RATE_PER_SECOND: f64 = 2.0;
+pub const DEFAULT_BURST: u32 = 10;
+
+// A request queued this long is served next whatever its priority, so Low requests
+// are delayed under load but never starved
+const MAX_QUEUE_WAIT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    // market/shipyard refreshes: fine to run late
+    Low,
+    #[default]
+    Normal,
+    // navigation and trades: a delay here idles a ship
+    High,
+}
+
+#[derive(Debug)]
+pub struct TokenBucket {
+    // time between tokens
+    interval: Duration,
+    // how far ahead of `tat` a request may go: the burst, less the token it uses
+    tolerance: Duration,
+    // theoretical arrival time: when the bucket will next be full-rate again
+    tat: Option<Instant>,
+}
+
+impl TokenBucket {
+    pub fn new(rate_per_second: f64, burst: u32) -> Self {
+        assert!(rate_per_second > 0.0, "rate limit must be positive");
+        let interval = Duration::from_secs_f64(1.0 / rate_per_second);
+        Self {
+            interval,
+            tolerance: interval * burst.max(1).saturating_sub(1),
+            tat: None,
+        }
+    }
+
+    // Take the next token, returning when it may be used
+    pub fn reserve(&mut self, now: Instant) -> Instant {
+        let tat = self.tat.map_or(now, |tat| tat.max(now));
+        let at = tat
+            .checked_sub(self.tolerance)
+            .map_or(now, |at| at.max(now));
+        self.tat = Some(tat + self.interval);
+        at
+    }
+
+    // Hand out no more tokens before `until`, and empty the bucket so requests
+    // resume at the sustained rate rather than in a burst
+    pub fn delay_until(&mut self, until: Instant) {
+        let tat = until + self.tolerance;
+        if self.tat.is_none_or(|t| t < tat) {
+            self.tat = Some(tat);
+        }
+    }
+}
+
+// Requests waiting for a token, FIFO within each priority
+struct WaitQueue<W> {
+    // indexed by Priority
+    queues: [VecDeque<(Instant, W)>; 3],
+}
+
+impl<W> WaitQueue<W> {
+    fn new() -> Self {
+        Self {
+            queues: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queues.iter().all(|q| q.is_empty())
+    }
+
+    fn push(&mut self, priority: Priority, enqueued: Instant, waiter: W) {
+        self.queues[priority as usize].push_back((enqueued, waiter));
+    }
+
+    // Highest priority first, unless a request has waited past MAX_QUEUE_WAIT, in
+    // which case the longest-waiting goes
+    fn pop(&mut self, now: Instant) -> Option<W> {
+        let starved = self
+            .queues
+            .iter()
+            .enumerate()
+            .filter_map(|(i, q)| q.front().map(|(enqueued, _)| (*enqueued, i)))
+            .filter(|(enqueued, _)| now.saturating_duration_since(*enqueued) >= MAX_QUEUE_WAIT)
+            .min();
+        let idx = match starved {
+            Some((_, i)) => i,
+            None => (0..self.queues.len())
+                .rev()
+                .find(|&i| !self.queues[i].is_empty())?,
+        };
+        self.queues[idx].pop_front().map(|(_, waiter)| waiter)
+    }
+}
+
+pub struct RateLimiter {
+    bucket: Mutex<TokenBucket>,
+    queue: Mutex<WaitQueue<oneshot::Sender<()>>>,
+    // wakes the dispatcher when a request is queued
+    queued: Notify,
+    dispatcher: Once,
+}
+
+impl RateLimiter {
+    pub fn new(rate_per_second: f64, burst: u32) -> Self {
+        Self {
+            bucket: Mutex::new(TokenBucket::new(rate_per_second, burst)),
+            queue: Mutex::new(WaitQueue::new()),
+            queued: Notify::new(),
+            dispatcher: Once::new(),
+        }
+    }
+
+    // Wait for a token. The dispatcher is started on first use, as the client may be
+    // built before the runtime.
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) {
+        self.dispatcher.call_once(|| {
+            tokio::spawn(self.clone().dispatch());
+        });
+        let (tx, rx) = oneshot::channel();
+        let enqueued = Instant::now();
+        self.queue.lock().unwrap().push(priority, enqueued, tx);
+        self.queued.notify_one();
+        rx.await.expect("rate limiter dispatcher stopped");
+        let waited = enqueued.elapsed();
+        if waited >= Duration::from_secs(10) {
+            warn!(
+                "Rate limit queue exceeds 10 seconds: {:.3}s ({:?})",
+                waited.as_secs_f64(),
+                priority
+            );
+        }
+    }
+
+    pub fn delay_until(&self, until: Instant) {
+        self.bucket.lock().unwrap().delay_until(until);
+    }
+
+    async fn dispatch(self: Arc<Self>) {
+        loop {
+            while self.queue.lock().unwrap().is_empty() {
+                self.queued.notified().await;
+            }
+            let at = self.bucket.lock().unwrap().reserve(Instant::now());
+            tokio::time::sleep_until(at).await;
+            // the token goes to whoever is most urgent now, not when it was reserved;
+            // skip any request that was dropped while queued
+            let mut queue = self.queue.lock().unwrap();
+            while let Some(tx) = queue.pop(Instant::now()) {
+                if tx.send(()).is_ok() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let t0 = Instant::now();
+        let mut bucket = TokenBucket::new(2.0, 3);
+        let ms = Duration::from_millis;
+
+        // a full bucket bursts, then drains at the sustained rate
+        let slots: Vec<_> = (0..5).map(|_| bucket.reserve(t0) - t0).collect();
+        assert_eq!(slots, [ms(0), ms(0), ms(0), ms(500), ms(1000)]);
+
+        // idle time refills it, but never past the burst
+        let t1 = t0 + Duration::from_secs(60);
+        let slots: Vec<_> = (0..4).map(|_| bucket.reserve(t1) - t1).collect();
+        assert_eq!(slots, [ms(0), ms(0), ms(0), ms(500)]);
+
+        // after a 429, nothing goes before the server's wait, and then only at the
+        // sustained rate
+        let t2 = t1 + Duration::from_secs(60);
+        bucket.delay_until(t2 + Duration::from_secs(2));
+        let slots: Vec<_> = (0..2).map(|_| bucket.reserve(t2) - t2).collect();
+        assert_eq!(slots, [ms(2000), ms(2500)]);
+    }
+
+    #[test]
+    fn test_high_priority_jumps_queue() {
+        let t0 = Instant::now();
+        let mut queue = WaitQueue::new();
+        for i in 0..10 {
+            queue.push(Priority::Low, t0, format!("low/{}", i));
+        }
+        queue.push(Priority::Normal, t0, "normal".to_string());
+        queue.push(
+            Priority::High,
+            t0 + Duration::from_secs(1),
+            "high".to_string(),
+        );
+
+        let now = t0 + Duration::from_secs(2);
+        assert_eq!(queue.pop(now).as_deref(), Some("high"));
+        assert_eq!(queue.pop(now).as_deref(), Some("normal"));
+        assert_eq!(queue.pop(now).as_deref(), Some("low/0"));
+
+        // a High arriving after the Lows have waited too long no longer jumps them
+        let late = t0 + MAX_QUEUE_WAIT;
+        queue.push(Priority::High, late, "high/late".to_string());
+        assert_eq!(queue.pop(late).as_deref(), Some("low/1"));
+        let drained: Vec<_> = std::iter::from_fn(|| queue.pop(t0)).collect();
+        assert_eq!(drained.len(), 9);
+        assert_eq!(drained[0], "high/late");
+        assert!(queue.is_empty());
+    }
+}
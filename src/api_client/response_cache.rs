@@ -0,0 +1,113 @@
+// Opt-in cache of GET responses for endpoints whose data doesn't change within a reset:
+// the systems list, a system, and a system's waypoints. Entries live in memory and in
+// the DB (`generic_lookup`, under `api_cache/`), so a crash loop doesn't re-fetch the
+// galaxy on every restart. Trait-filtered waypoint queries aren't cached: charting
+// changes them, and they're used precisely to see through UNCHARTED.
+
+use crate::database::DbClient;
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+const DB_KEY_PREFIX: &str = "api_cache/GET ";
+
+lazy_static! {
+    // /systems, /systems/{s}, /systems/{s}/waypoints; optionally paginated, nothing else
+    static ref CACHEABLE: Regex =
+        Regex::new(r"^/systems(/[^/?]+(/waypoints)?)?(\?page=\d+&limit=\d+)?$").unwrap();
+}
+
+pub fn is_cacheable(path: &str) -> bool {
+    CACHEABLE.is_match(path)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedResponse {
+    fetched_at: DateTime<Utc>,
+    body: String,
+}
+
+pub struct ResponseCache {
+    ttl: Duration,
+    memory: DashMap<String, CachedResponse>,
+    db: Option<DbClient>,
+}
+
+impl ResponseCache {
+    pub fn new(db: &DbClient, ttl: Duration) -> Self {
+        Self {
+            ttl,
+            memory: DashMap::new(),
+            db: Some(db.clone()),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn in_memory(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            memory: DashMap::new(),
+            db: None,
+        }
+    }
+
+    fn is_fresh(&self, entry: &CachedResponse) -> bool {
+        Utc::now() - entry.fetched_at < self.ttl
+    }
+
+    // The body of an unexpired GET `path`, from memory or else the DB
+    pub async fn get(&self, path: &str) -> Option<String> {
+        if let Some(entry) = self.memory.get(path) {
+            return self.is_fresh(&entry).then(|| entry.body.clone());
+        }
+        let db = self.db.as_ref()?;
+        let entry: CachedResponse = db.get_value(&format!("{DB_KEY_PREFIX}{path}")).await?;
+        let fresh = self.is_fresh(&entry);
+        let body = entry.body.clone();
+        self.memory.insert(path.to_string(), entry);
+        fresh.then_some(body)
+    }
+
+    pub async fn put(&self, path: &str, body: &str) {
+        let entry = CachedResponse {
+            fetched_at: Utc::now(),
+            body: body.to_string(),
+        };
+        if let Some(db) = &self.db {
+            db.set_value(&format!("{DB_KEY_PREFIX}{path}"), &entry)
+                .await;
+        }
+        self.memory.insert(path.to_string(), entry);
+    }
+
+    // Drop `path` and all its pages
+    pub async fn invalidate(&self, path: &str) {
+        let is_page = |key: &str| key == path || key.starts_with(&format!("{path}?"));
+        self.memory.retain(|key, _| !is_page(key));
+        if let Some(db) = &self.db {
+            db.delete_value(&format!("{DB_KEY_PREFIX}{path}")).await;
+            db.delete_values_with_prefix(&format!("{DB_KEY_PREFIX}{path}?"))
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_cacheable() {
+        assert!(is_cacheable("/systems?page=3&limit=20"));
+        assert!(is_cacheable("/systems/X1-S1"));
+        assert!(is_cacheable("/systems/X1-S1/waypoints?page=1&limit=20"));
+        assert!(!is_cacheable(
+            "/systems/X1-S1/waypoints?traits=MARKETPLACE&page=1&limit=20"
+        ));
+        assert!(!is_cacheable("/systems/X1-S1/waypoints/X1-S1-A1"));
+        assert!(!is_cacheable("/systems/X1-S1/waypoints/X1-S1-A1/market"));
+        assert!(!is_cacheable("/my/ships"));
+    }
+}
@@ -2,6 +2,7 @@ use log::*;
 use reqwest::StatusCode;
 use st::agent_controller::AgentController;
 use st::api_client::ApiClient;
+use st::api_client::response_cache::ResponseCache;
 use st::config::CONFIG;
 use st::database::DbClient;
 use st::models::Faction;
@@ -9,6 +10,10 @@ use st::universe::Universe;
 use std::env;
 use std::sync::Arc;
 
+// Exit code when the server has reset under a running agent, so the supervisor can
+// restart it into the new slice
+const EXIT_SERVER_RESET: i32 = 3;
+
 #[tokio::main]
 async fn main() {
     dotenvy::dotenv().ok();
@@ -49,6 +54,12 @@ async fn main() {
     };
     // Use the reset date on the status response as a unique identifier to partition data between resets
     let db = DbClient::new(&slice_id).await;
+    if CONFIG.api_cache_ttl_hours > 0 {
+        api_client.set_response_cache(ResponseCache::new(
+            &db,
+            chrono::Duration::hours(CONFIG.api_cache_ttl_hours),
+        ));
+    }
 
     let universe = Arc::new(Universe::new(&api_client, &db).await);
     // Kick off the one-time background load of every system (no-op if already done
@@ -86,6 +97,81 @@ async fn main() {
     log::info!("Setting token {}", agent_token);
     api_client.set_agent_token(&agent_token);
 
-    let agent_controller = AgentController::new(&api_client, &db, &universe, &callsign).await;
-    agent_controller.run().await;
+    // From here a rejected token (the server reset, most likely) parks every request,
+    // so race startup and the run against it
+    let agent_controller = tokio::select! {
+        agent_controller = AgentController::new(&api_client, &db, &universe, &callsign) => agent_controller,
+        _ = api_client.token_invalidated() => {
+            on_token_invalid(&api_client, &db, &callsign, &status.reset_date, None).await
+        }
+    };
+    tokio::select! {
+        _ = agent_controller.run() => {}
+        _ = api_client.token_invalidated() => {
+            on_token_invalid(&api_client, &db, &callsign, &status.reset_date, Some(&agent_controller)).await
+        }
+        signal = shutdown_signal() => {
+            // Ships park at their next safe point; a second signal kills us outright
+            info!("{} received, shutting down", signal);
+            agent_controller.shutdown();
+            tokio::select! {
+                _ = agent_controller.drain() => {}
+                signal = shutdown_signal() => warn!("{} received while draining, not waiting", signal),
+            }
+            agent_controller.abort();
+            std::process::exit(0);
+        }
+    }
+}
+
+// ctrl-c, or SIGTERM: what Kubernetes sends a pod it's stopping
+async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => "SIGINT",
+            _ = terminate.recv() => "SIGTERM",
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await.unwrap();
+        "SIGINT"
+    }
+}
+
+// Our token has been rejected. If the server has reset since startup, forget the old
+// slice's token and exit for a restart into the new slice; otherwise there's nothing
+// to retry with, so exit with a plain failure.
+async fn on_token_invalid(
+    api_client: &ApiClient,
+    db: &DbClient,
+    callsign: &str,
+    startup_reset_date: &str,
+    agent_controller: Option<&AgentController>,
+) -> ! {
+    let reset = api_client.server_reset_since(startup_reset_date).await;
+    if let Some(agent_controller) = agent_controller {
+        agent_controller.abort();
+    }
+    match reset {
+        Some(reset_date) => {
+            error!(
+                "SERVER RESET: reset date is now {} (started on {}), agent token rejected. \
+                 Exiting to restart into the new slice",
+                reset_date, startup_reset_date
+            );
+            db.remove_agent_token(callsign).await;
+            std::process::exit(EXIT_SERVER_RESET);
+        }
+        None => {
+            error!(
+                "Agent token rejected, but the server has not reset (reset date {}). Exiting",
+                startup_reset_date
+            );
+            std::process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,72 @@
+//! Where ships get the time from.
+//!
+//! Transit and cooldown waits, and the script loops' idle sleeps, go through a `Clock`
+//! rather than `Utc::now()`/`tokio::time::sleep` directly. Production uses the system
+//! clock; tests use a `ManualClock` they advance by hand, so time-dependent behaviour
+//! runs in milliseconds and deterministically.
+
+use chrono::{DateTime, Duration, Utc};
+use futures::future::BoxFuture;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+    fn sleep_until(&self, timestamp: DateTime<Utc>) -> BoxFuture<'_, ()>;
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'_, ()> {
+        self.sleep_until(self.now() + duration)
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn sleep_until(&self, timestamp: DateTime<Utc>) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            // Multiple sleep calls are necessary due to WSL2 tendency to 'skip time'
+            loop {
+                let wait_time = timestamp - Utc::now();
+                if wait_time <= Duration::zero() {
+                    break;
+                }
+                tokio::time::sleep(wait_time.to_std().unwrap()).await;
+            }
+        })
+    }
+}
+
+// Only moves when told to. Sleepers wake as soon as an `advance` reaches their deadline.
+#[cfg(test)]
+pub struct ManualClock {
+    now: tokio::sync::watch::Sender<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn new(start: DateTime<Utc>) -> ManualClock {
+        ManualClock {
+            now: tokio::sync::watch::Sender::new(start),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now.send_modify(|now| *now += duration);
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.borrow()
+    }
+
+    fn sleep_until(&self, timestamp: DateTime<Utc>) -> BoxFuture<'_, ()> {
+        let mut now = self.now.subscribe();
+        Box::pin(async move {
+            now.wait_for(|now| *now >= timestamp).await.unwrap();
+        })
+    }
+}
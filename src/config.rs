@@ -2,6 +2,7 @@ use lazy_static::lazy_static;
 use regex::Regex;
 
 use crate::agent_controller::AgentEra;
+use crate::agent_controller::progression::EraStrategy;
 
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -13,7 +14,141 @@ pub struct Config {
     pub no_gate_mode: bool,
     pub disable_trading_tasks: bool,
     pub disable_contract_tasks: bool,
+    // how many ships may head to sell the same good at the same market at once
+    pub max_ships_per_sell_target: usize,
+    // let mining drones with a full hold jettison low-value cargo to extract a better survey
+    pub mining_jettison_for_survey: bool,
+    // keep leftover goods no in-system market buys instead of jettisoning them
+    pub keep_unsellable_leftovers: bool,
+    // failed offload attempts before a logistics or siphon ship jettisons a good nobody
+    // buys (0 = carry it indefinitely)
+    pub unsellable_jettison_attempts: u32,
+    // stop a probe job outright if any of its waypoints is invalid, rather than skipping them
+    pub probe_fail_on_invalid_waypoint: bool,
+    // Sell FUEL cargo a logistics ship has no task for, but only where it fetches at
+    // least this much per unit; elsewhere it's carried on. None keeps cargo fuel aboard.
+    pub logistics_min_fuel_sell_price: Option<i64>,
+    // in inter-system eras, move long-idle logistics ships to open logistics jobs in
+    // other systems
+    pub fleet_rebalancing: bool,
+    // have the logistics planner explain its task choices (costs some planning time)
+    pub debug_planner_rationale: bool,
     pub era_override: Option<AgentEra>,
+    pub era_strategy: EraStrategy,
+    // Credits held back per unit of cargo capacity when a logistics ship reserves
+    // funds for its trades. Should track the expected per-unit cost of goods.
+    pub credits_per_cargo_unit_reservation: i64,
+    // Seconds between controller ticks. Each tick runs the buy and contract phases, so
+    // a shorter interval means more API calls.
+    pub controller_tick_seconds: u64,
+    // Safe mode trips when more than this many ship tasks panic within the window
+    pub safe_mode_panic_threshold: usize,
+    pub safe_mode_window_minutes: i64,
+    // How often a roaming probe should get round each market it tours (sizes the probe
+    // fleet and paces its laps; scaled per era, see fleet::probe_refresh_target)
+    pub probe_refresh_minutes: i64,
+    // Attempts per API request on a 429 or transient 5xx (first try included), and
+    // the first retry's backoff, doubling per attempt
+    pub api_max_attempts: u32,
+    pub api_backoff_base_ms: u64,
+    // Longest single backoff, and the most time spent retrying one request
+    pub api_backoff_max_ms: u64,
+    pub api_retry_max_elapsed_secs: u64,
+    // Cap on concurrent HTTP requests, on top of the rate limit
+    pub api_max_in_flight: usize,
+    // Consecutive 5xx within the window that open the API circuit breaker
+    pub circuit_breaker_threshold: usize,
+    pub circuit_breaker_window_secs: u64,
+    // TTL of cached system/waypoint responses; 0 disables the cache
+    pub api_cache_ttl_hours: i64,
+    // A ship whose worst component condition is below this is repaired before it
+    // starts its job
+    pub repair_condition_threshold: f64,
+    // log API mutations (anything but a GET) instead of sending them, see ApiClient
+    pub dry_run: bool,
+    // Fuel over a hop's need that a ship tops up to when it can buy fuel, so a short
+    // fill or stale reading doesn't strand it at the next stop
+    pub nav_fuel_margin: FuelMargin,
+    // Trade tasks ignore market prices older than this (0 = no limit); construction
+    // supply-chain goods are exempt
+    pub trade_max_market_age_minutes: i64,
+    // Bearer token for the web API's operator (pause/resume) endpoints; unset disables them
+    pub operator_token: Option<String>,
+    // Credits/hour a hull could earn elsewhere (e.g. as a trade hauler): a mining site
+    // whose ships earn less than this each isn't paying for itself
+    pub mining_opportunity_cost_per_hour: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FuelMargin {
+    Units(i64),
+    // of the ship's fuel capacity
+    Percent(i64),
+}
+
+impl FuelMargin {
+    pub fn units(&self, fuel_capacity: i64) -> i64 {
+        match *self {
+            FuelMargin::Units(units) => units,
+            FuelMargin::Percent(percent) => fuel_capacity * percent / 100,
+        }
+    }
+}
+
+impl Config {
+    // Every credit reservation sized by a hold goes through here.
+    pub fn cargo_reservation(&self, cargo_capacity: i64) -> i64 {
+        cargo_capacity * self.credits_per_cargo_unit_reservation
+    }
+}
+
+fn parse_credits_per_cargo_unit_reservation(val: Option<String>) -> i64 {
+    match val {
+        Some(val) if val.is_empty() => 5000,
+        Some(val) => val
+            .parse()
+            .expect("Invalid CREDITS_PER_CARGO_UNIT_RESERVATION"),
+        None => 5000,
+    }
+}
+
+pub const MIN_CONTROLLER_TICK_SECONDS: u64 = 5;
+pub const MAX_CONTROLLER_TICK_SECONDS: u64 = 3600;
+
+fn parse_controller_tick_seconds(val: Option<String>) -> Result<u64, String> {
+    let secs = match val {
+        Some(val) if val.is_empty() => 60,
+        Some(val) => val
+            .parse()
+            .map_err(|_| format!("Invalid CONTROLLER_TICK_SECONDS: {}", val))?,
+        None => 60,
+    };
+    if !(MIN_CONTROLLER_TICK_SECONDS..=MAX_CONTROLLER_TICK_SECONDS).contains(&secs) {
+        return Err(format!(
+            "CONTROLLER_TICK_SECONDS must be between {} and {}, got {}",
+            MIN_CONTROLLER_TICK_SECONDS, MAX_CONTROLLER_TICK_SECONDS, secs
+        ));
+    }
+    Ok(secs)
+}
+
+// "20" is 20 units, "10%" a tenth of the tank
+fn parse_nav_fuel_margin(val: Option<String>) -> Result<FuelMargin, String> {
+    let val = match val {
+        Some(val) if !val.is_empty() => val,
+        _ => return Ok(FuelMargin::Units(0)),
+    };
+    let invalid = || format!("Invalid NAV_FUEL_MARGIN: {}", val);
+    match val.strip_suffix('%') {
+        Some(percent) => match percent.parse() {
+            Ok(percent @ 0..=100) => Ok(FuelMargin::Percent(percent)),
+            _ => Err(invalid()),
+        },
+        None => match val.parse() {
+            Ok(units @ 0..) => Ok(FuelMargin::Units(units)),
+            _ => Err(invalid()),
+        },
+    }
 }
 
 lazy_static! {
@@ -50,11 +185,135 @@ lazy_static! {
         let disable_contract_tasks = std::env::var("DEBUG_DISABLE_CONTRACT_TASKS")
             .map(|val| val == "1")
             .unwrap_or(false);
+        let max_ships_per_sell_target = match std::env::var("MAX_SHIPS_PER_SELL_TARGET") {
+            Ok(val) if val.is_empty() => 2,
+            Ok(val) => val.parse().expect("Invalid MAX_SHIPS_PER_SELL_TARGET"),
+            Err(_) => 2,
+        };
+        let mining_jettison_for_survey = std::env::var("MINING_JETTISON_FOR_SURVEY")
+            .map(|val| val == "1")
+            .unwrap_or(false);
+        let keep_unsellable_leftovers = std::env::var("KEEP_UNSELLABLE_LEFTOVERS")
+            .map(|val| val == "1")
+            .unwrap_or(false);
+        let unsellable_jettison_attempts = match std::env::var("UNSELLABLE_JETTISON_ATTEMPTS") {
+            Ok(val) if val.is_empty() => 1,
+            Ok(val) => val.parse().expect("Invalid UNSELLABLE_JETTISON_ATTEMPTS"),
+            Err(_) => 1,
+        };
+        let probe_fail_on_invalid_waypoint = std::env::var("PROBE_FAIL_ON_INVALID_WAYPOINT")
+            .map(|val| val == "1")
+            .unwrap_or(false);
+        let logistics_min_fuel_sell_price = match std::env::var("LOGISTICS_MIN_FUEL_SELL_PRICE") {
+            Ok(val) if val.is_empty() => None,
+            Ok(val) => Some(val.parse().expect("Invalid LOGISTICS_MIN_FUEL_SELL_PRICE")),
+            Err(_) => None,
+        };
+        let fleet_rebalancing = std::env::var("FLEET_REBALANCING")
+            .map(|val| val == "1")
+            .unwrap_or(false);
+        let debug_planner_rationale = std::env::var("DEBUG_PLANNER_RATIONALE")
+            .map(|val| val == "1")
+            .unwrap_or(false);
         let era_override = match std::env::var("ERA_OVERRIDE") {
             Ok(val) if val.is_empty() => None,
             Ok(val) => Some(val.parse().expect("Invalid ERA_OVERRIDE")),
             Err(_) => None,
         };
+        let era_strategy = match std::env::var("ERA_STRATEGY") {
+            Ok(val) if val.is_empty() => EraStrategy::default(),
+            Ok(val) => val.parse().expect("Invalid ERA_STRATEGY"),
+            Err(_) => EraStrategy::default(),
+        };
+        let credits_per_cargo_unit_reservation = parse_credits_per_cargo_unit_reservation(
+            std::env::var("CREDITS_PER_CARGO_UNIT_RESERVATION").ok(),
+        );
+        let controller_tick_seconds =
+            parse_controller_tick_seconds(std::env::var("CONTROLLER_TICK_SECONDS").ok())
+                .unwrap_or_else(|e| panic!("{}", e));
+        let safe_mode_panic_threshold = match std::env::var("SAFE_MODE_PANIC_THRESHOLD") {
+            Ok(val) if val.is_empty() => 5,
+            Ok(val) => val.parse().expect("Invalid SAFE_MODE_PANIC_THRESHOLD"),
+            Err(_) => 5,
+        };
+        let safe_mode_window_minutes = match std::env::var("SAFE_MODE_WINDOW_MINUTES") {
+            Ok(val) if val.is_empty() => 30,
+            Ok(val) => val.parse().expect("Invalid SAFE_MODE_WINDOW_MINUTES"),
+            Err(_) => 30,
+        };
+        let probe_refresh_minutes = match std::env::var("PROBE_REFRESH_MINUTES") {
+            Ok(val) if val.is_empty() => 15,
+            Ok(val) => val.parse().expect("Invalid PROBE_REFRESH_MINUTES"),
+            Err(_) => 15,
+        };
+        let api_max_attempts = match std::env::var("API_MAX_ATTEMPTS") {
+            Ok(val) if val.is_empty() => 5,
+            Ok(val) => val.parse().expect("Invalid API_MAX_ATTEMPTS"),
+            Err(_) => 5,
+        };
+        let api_backoff_base_ms = match std::env::var("API_BACKOFF_BASE_MS") {
+            Ok(val) if val.is_empty() => 500,
+            Ok(val) => val.parse().expect("Invalid API_BACKOFF_BASE_MS"),
+            Err(_) => 500,
+        };
+        let api_backoff_max_ms = match std::env::var("API_BACKOFF_MAX_MS") {
+            Ok(val) if val.is_empty() => 30000,
+            Ok(val) => val.parse().expect("Invalid API_BACKOFF_MAX_MS"),
+            Err(_) => 30000,
+        };
+        let api_retry_max_elapsed_secs = match std::env::var("API_RETRY_MAX_ELAPSED_SECS") {
+            Ok(val) if val.is_empty() => 120,
+            Ok(val) => val.parse().expect("Invalid API_RETRY_MAX_ELAPSED_SECS"),
+            Err(_) => 120,
+        };
+        let api_max_in_flight = match std::env::var("API_MAX_IN_FLIGHT") {
+            Ok(val) if val.is_empty() => 10,
+            Ok(val) => val.parse().expect("Invalid API_MAX_IN_FLIGHT"),
+            Err(_) => 10,
+        };
+        let circuit_breaker_threshold = match std::env::var("CIRCUIT_BREAKER_THRESHOLD") {
+            Ok(val) if val.is_empty() => 10,
+            Ok(val) => val.parse().expect("Invalid CIRCUIT_BREAKER_THRESHOLD"),
+            Err(_) => 10,
+        };
+        let circuit_breaker_window_secs = match std::env::var("CIRCUIT_BREAKER_WINDOW_SECS") {
+            Ok(val) if val.is_empty() => 60,
+            Ok(val) => val.parse().expect("Invalid CIRCUIT_BREAKER_WINDOW_SECS"),
+            Err(_) => 60,
+        };
+        let api_cache_ttl_hours = match std::env::var("API_CACHE_TTL_HOURS") {
+            Ok(val) if val.is_empty() => 0,
+            Ok(val) => val.parse().expect("Invalid API_CACHE_TTL_HOURS"),
+            Err(_) => 0,
+        };
+        let repair_condition_threshold = match std::env::var("REPAIR_CONDITION_THRESHOLD") {
+            Ok(val) if val.is_empty() => 0.3,
+            Ok(val) => val.parse().expect("Invalid REPAIR_CONDITION_THRESHOLD"),
+            Err(_) => 0.3,
+        };
+        let dry_run = std::env::var("DRY_RUN")
+            .map(|val| val == "1")
+            .unwrap_or(false);
+        let nav_fuel_margin = parse_nav_fuel_margin(std::env::var("NAV_FUEL_MARGIN").ok())
+            .unwrap_or_else(|e| panic!("{}", e));
+        let trade_max_market_age_minutes = match std::env::var("TRADE_MAX_MARKET_AGE_MINUTES") {
+            Ok(val) if val.is_empty() => 180,
+            Ok(val) => val.parse().expect("Invalid TRADE_MAX_MARKET_AGE_MINUTES"),
+            Err(_) => 180,
+        };
+        let mining_opportunity_cost_per_hour =
+            match std::env::var("MINING_OPPORTUNITY_COST_PER_HOUR") {
+                Ok(val) if val.is_empty() => 2000.,
+                Ok(val) => val
+                    .parse()
+                    .expect("Invalid MINING_OPPORTUNITY_COST_PER_HOUR"),
+                Err(_) => 2000.,
+            };
+        let operator_token = match std::env::var("OPERATOR_TOKEN") {
+            Ok(val) if val.is_empty() => None,
+            Ok(val) => Some(val),
+            Err(_) => None,
+        };
         Config {
             api_base_url,
             job_id_filter,
@@ -62,9 +321,163 @@ lazy_static! {
             scrap_all_ships,
             scrap_unassigned,
             era_override,
+            era_strategy,
             no_gate_mode,
             disable_trading_tasks,
             disable_contract_tasks,
+            max_ships_per_sell_target,
+            mining_jettison_for_survey,
+            keep_unsellable_leftovers,
+            unsellable_jettison_attempts,
+            probe_fail_on_invalid_waypoint,
+            logistics_min_fuel_sell_price,
+            fleet_rebalancing,
+            debug_planner_rationale,
+            credits_per_cargo_unit_reservation,
+            controller_tick_seconds,
+            safe_mode_panic_threshold,
+            safe_mode_window_minutes,
+            probe_refresh_minutes,
+            api_max_attempts,
+            api_backoff_base_ms,
+            api_backoff_max_ms,
+            api_retry_max_elapsed_secs,
+            api_max_in_flight,
+            circuit_breaker_threshold,
+            circuit_breaker_window_secs,
+            api_cache_ttl_hours,
+            repair_condition_threshold,
+            dry_run,
+            nav_fuel_margin,
+            trade_max_market_age_minutes,
+            operator_token,
+            mining_opportunity_cost_per_hour,
         }
     };
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(credits_per_cargo_unit_reservation: i64) -> Config {
+        Config {
+            api_base_url: "http://localhost".to_string(),
+            job_id_filter: Regex::new(".*").unwrap(),
+            override_construction_supply_check: false,
+            scrap_all_ships: false,
+            scrap_unassigned: false,
+            no_gate_mode: false,
+            disable_trading_tasks: false,
+            disable_contract_tasks: false,
+            max_ships_per_sell_target: 2,
+            mining_jettison_for_survey: false,
+            keep_unsellable_leftovers: false,
+            unsellable_jettison_attempts: 1,
+            probe_fail_on_invalid_waypoint: false,
+            logistics_min_fuel_sell_price: None,
+            fleet_rebalancing: false,
+            debug_planner_rationale: false,
+            era_override: None,
+            era_strategy: EraStrategy::Default,
+            credits_per_cargo_unit_reservation,
+            controller_tick_seconds: 60,
+            safe_mode_panic_threshold: 5,
+            safe_mode_window_minutes: 30,
+            probe_refresh_minutes: 15,
+            api_max_attempts: 5,
+            api_backoff_base_ms: 500,
+            api_backoff_max_ms: 30000,
+            api_retry_max_elapsed_secs: 120,
+            api_max_in_flight: 10,
+            circuit_breaker_threshold: 10,
+            circuit_breaker_window_secs: 60,
+            api_cache_ttl_hours: 0,
+            repair_condition_threshold: 0.3,
+            dry_run: false,
+            nav_fuel_margin: FuelMargin::Units(0),
+            trade_max_market_age_minutes: 180,
+            operator_token: None,
+            mining_opportunity_cost_per_hour: 2000.,
+        }
+    }
+
+    #[test]
+    fn test_credits_per_cargo_unit_reservation() {
+        assert_eq!(parse_credits_per_cargo_unit_reservation(None), 5000);
+        assert_eq!(
+            parse_credits_per_cargo_unit_reservation(Some("".to_string())),
+            5000
+        );
+        assert_eq!(
+            parse_credits_per_cargo_unit_reservation(Some("1200".to_string())),
+            1200
+        );
+        assert_eq!(config(5000).cargo_reservation(40), 200_000);
+        assert_eq!(config(1200).cargo_reservation(40), 48_000);
+    }
+
+    #[test]
+    fn test_controller_tick_seconds() {
+        assert_eq!(parse_controller_tick_seconds(None), Ok(60));
+        assert_eq!(parse_controller_tick_seconds(Some("".to_string())), Ok(60));
+        assert_eq!(
+            parse_controller_tick_seconds(Some("15".to_string())),
+            Ok(15)
+        );
+        assert_eq!(
+            parse_controller_tick_seconds(Some(MIN_CONTROLLER_TICK_SECONDS.to_string())),
+            Ok(MIN_CONTROLLER_TICK_SECONDS)
+        );
+        assert!(parse_controller_tick_seconds(Some("1".to_string())).is_err());
+        assert!(parse_controller_tick_seconds(Some("7200".to_string())).is_err());
+        assert!(parse_controller_tick_seconds(Some("1m".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_nav_fuel_margin() {
+        let parse = |val: &str| parse_nav_fuel_margin(Some(val.to_string()));
+        assert_eq!(parse_nav_fuel_margin(None), Ok(FuelMargin::Units(0)));
+        assert_eq!(parse(""), Ok(FuelMargin::Units(0)));
+        assert_eq!(parse("20"), Ok(FuelMargin::Units(20)));
+        assert_eq!(parse("10%"), Ok(FuelMargin::Percent(10)));
+        assert_eq!(FuelMargin::Percent(10).units(400), 40);
+        assert!(parse("-5").is_err());
+        assert!(parse("150%").is_err());
+        assert!(parse("ten").is_err());
+    }
+
+    // A logistics ship taking a job holds back credits sized from config; other
+    // jobs hold back nothing.
+    #[test]
+    fn test_job_reservation() {
+        use crate::agent_controller::fleet::job_reservation;
+        use crate::agent_controller::ledger::Ledger;
+        use crate::models::*;
+
+        let job = |behaviour| ShipConfig {
+            id: "job".to_string(),
+            ship_model: "SHIP_LIGHT_HAULER".to_string(),
+            purchase_criteria: PurchaseCriteria::default(),
+            behaviour,
+        };
+        let logistics = job(ShipBehaviour::Logistics(LogisticsScriptConfig {
+            use_planner: false,
+            planner_config: None,
+            allow_shipbuying: true,
+            allow_construction: true,
+            allow_market_refresh: true,
+            waypoint_allowlist: None,
+            min_profit: 0,
+        }));
+        let mining = job(ShipBehaviour::MiningSurveyor);
+
+        let config = config(1200);
+        assert_eq!(job_reservation(&logistics, 40, &config), Some(48_000));
+        assert_eq!(job_reservation(&mining, 40, &config), None);
+
+        let ledger = Ledger::new(1_000_000);
+        ledger.reserve_credits("SHIP-1", job_reservation(&logistics, 40, &config).unwrap());
+        assert_eq!(ledger.available_credits(), 952_000);
+    }
+}
@@ -18,6 +18,7 @@ use diesel::OptionalExtension as _;
 use diesel::QueryDsl as _;
 use diesel::QueryableByName;
 use diesel::SelectableHelper as _;
+use diesel::TextExpressionMethods as _;
 use diesel::sql_types::{BigInt, Integer, Nullable, Text};
 use diesel::upsert::excluded;
 use diesel_async::AsyncPgConnection;
@@ -79,6 +80,12 @@ impl DbClient {
 
     pub async fn new(slice_id: &str) -> DbClient {
         let database_url = std::env::var("POSTGRES_URI").expect("POSTGRES_URI must be set");
+        Self::connect(&database_url, slice_id).await
+    }
+
+    // Connect to `database_url`, creating schema `slice_id` if needed. `new` is this
+    // with POSTGRES_URI; the integration tests pass their own server.
+    pub async fn connect(database_url: &str, slice_id: &str) -> DbClient {
         info!("Using schema: {}", slice_id);
         let db = {
             let database_url = format!("{}?options=-c%20search_path%3D{}", database_url, slice_id);
@@ -172,6 +179,30 @@ impl DbClient {
             .expect("DB Query error");
     }
 
+    pub async fn delete_value(&self, key: &str) {
+        debug!("db delete: {}", key);
+        diesel::delete(generic_lookup::table.filter(generic_lookup::key.eq(key)))
+            .execute(&mut self.conn().await)
+            .await
+            .expect("DB Query error");
+    }
+
+    // Delete every key starting with `prefix` (matched literally)
+    pub async fn delete_values_with_prefix(&self, prefix: &str) {
+        debug!("db delete prefix: {}", prefix);
+        let pattern = format!(
+            "{}%",
+            prefix
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        diesel::delete(generic_lookup::table.filter(generic_lookup::key.like(pattern)))
+            .execute(&mut self.conn().await)
+            .await
+            .expect("DB Query error");
+    }
+
     pub async fn get_agent_token(&self, callsign: &str) -> Option<String> {
         self.get_value(&format!("registrations/{}", callsign)).await
     }
@@ -181,6 +212,12 @@ impl DbClient {
             .await
     }
 
+    // Forget the token, e.g. once the server has reset and it's no longer valid
+    pub async fn remove_agent_token(&self, callsign: &str) {
+        self.delete_value(&format!("registrations/{}", callsign))
+            .await
+    }
+
     pub async fn get_market_remote(&self, symbol: &WaypointSymbol) -> Option<MarketRemoteView> {
         let market: Option<db_models::RemoteMarket> = remote_markets::table
             .filter(remote_markets::waypoint_symbol.eq(symbol.to_string()))
@@ -1048,6 +1085,14 @@ impl DbClient {
             .expect("DB Query error");
     }
 
+    // Delete surveys that expired before `now`; returns how many went
+    pub async fn remove_expired_surveys(&self, now: chrono::DateTime<Utc>) -> usize {
+        diesel::delete(surveys::table.filter(surveys::expires_at.le(now)))
+            .execute(&mut self.conn().await)
+            .await
+            .expect("DB Query error")
+    }
+
     pub async fn get_systems(&self) -> Vec<db_models::System> {
         systems::table
             .select(db_models::System::as_select())
@@ -1158,3 +1203,136 @@ impl DbClient {
             .collect()
     }
 }
+
+// Round-trips against a real Postgres (with TimescaleDB, which the schema needs).
+// Ignored by default; to run, point TEST_POSTGRES_URI at a scratch server and
+// `cargo test -- --ignored`. Each test works in its own throwaway schema, dropped
+// afterwards.
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::logistics_planner::{Action, LogisticShip, ScheduledAction};
+    use serde_json::json;
+
+    async fn scratch_db() -> (DbClient, String) {
+        let database_url =
+            std::env::var("TEST_POSTGRES_URI").expect("TEST_POSTGRES_URI must be set");
+        let schema = format!("test_{}", Uuid::new_v4().simple());
+        (DbClient::connect(&database_url, &schema).await, schema)
+    }
+
+    async fn drop_schema(db: &DbClient, schema: &str) {
+        use diesel_async::SimpleAsyncConnection as _;
+        let sql = format!("DROP SCHEMA {} CASCADE", schema);
+        db.conn().await.batch_execute(&sql).await.unwrap();
+    }
+
+    fn json_of<T: Serialize>(value: &T) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_persisted_state_round_trips() {
+        let (db, schema) = scratch_db().await;
+
+        // unset keys read back as absent / empty
+        assert!(db.load_schedule("SHIP-1").await.is_none());
+        assert!(db.get_agent_token("TEST").await.is_none());
+        assert!(db.get_probe_jumpgate_reservations("TEST").await.is_empty());
+
+        db.save_agent_token("TEST", "token-1").await;
+        db.save_agent_token("TEST", "token-2").await;
+        assert_eq!(db.get_agent_token("TEST").await.as_deref(), Some("token-2"));
+        db.remove_agent_token("TEST").await;
+        assert!(db.get_agent_token("TEST").await.is_none());
+        db.save_agent_token("TEST", "token-2").await;
+
+        // prefix deletes take `_` and `%` literally
+        db.set_value("cache/a_b?page=1", &1).await;
+        db.set_value("cache/a_b?page=2", &2).await;
+        db.set_value("cache/axb", &3).await;
+        db.delete_values_with_prefix("cache/a_b").await;
+        assert!(db.get_value::<i64>("cache/a_b?page=1").await.is_none());
+        assert_eq!(db.get_value::<i64>("cache/axb").await, Some(3));
+        db.delete_value("cache/axb").await;
+        assert!(db.get_value::<i64>("cache/axb").await.is_none());
+
+        let assignments = DashMap::new();
+        assignments.insert("logistics/0".to_string(), "SHIP-1".to_string());
+        db.set_value("TEST/ship_assignments", &assignments).await;
+        let loaded: DashMap<String, String> = db.get_value("TEST/ship_assignments").await.unwrap();
+        assert_eq!(json_of(&loaded), json_of(&assignments));
+
+        let schedule = ShipSchedule {
+            ship: LogisticShip {
+                symbol: "SHIP-1".to_string(),
+                capacity: 40,
+                speed: 30,
+                start_waypoint: WaypointSymbol::new("X1-S1-A1"),
+            },
+            actions: vec![ScheduledAction {
+                timestamp: 12.5,
+                waypoint: WaypointSymbol::new("X1-S1-B2"),
+                action: Action::SellGoods("IRON".to_string(), 40),
+                task_id: "X1-S1/trade_IRON".to_string(),
+                completes_task: true,
+            }],
+        };
+        db.save_schedule("SHIP-1", &schedule).await;
+        db.save_schedule_progress("SHIP-1", 0).await;
+        db.update_schedule_progress("SHIP-1", 1).await;
+        let loaded = db.load_schedule("SHIP-1").await.unwrap();
+        assert_eq!(loaded.actions, schedule.actions);
+        assert_eq!(loaded.ship.start_waypoint, schedule.ship.start_waypoint);
+        assert_eq!(db.load_schedule_progress("SHIP-1").await, Some(1));
+
+        let reservations = DashMap::new();
+        reservations.insert("probe/0".to_string(), WaypointSymbol::new("X1-S2-I1"));
+        db.save_probe_jumpgate_reservations("TEST", &reservations)
+            .await;
+        let loaded = db.get_probe_jumpgate_reservations("TEST").await;
+        assert_eq!(json_of(&loaded), json_of(&reservations));
+        let systems = DashMap::new();
+        systems.insert("t5_trader/0".to_string(), SystemSymbol::new("X1-S3"));
+        db.save_t5_system_reservations("TEST", &systems).await;
+        let loaded = db.get_t5_system_reservations("TEST").await;
+        assert_eq!(json_of(&loaded), json_of(&systems));
+
+        let survey = |signature: &str, expiration| KeyedSurvey {
+            uuid: Uuid::new_v4(),
+            survey: crate::models::Survey {
+                signature: signature.to_string(),
+                symbol: WaypointSymbol::new("X1-S1-B2"),
+                deposits: vec![],
+                expiration,
+                size: "SMALL".to_string(),
+            },
+        };
+        let now = Utc::now();
+        db.insert_surveys(&[
+            survey("LIVE", now + chrono::Duration::minutes(10)),
+            survey("STALE", now - chrono::Duration::minutes(10)),
+        ])
+        .await;
+        assert_eq!(db.remove_expired_surveys(now).await, 1);
+        let loaded = db.get_surveys().await;
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].survey.signature, "LIVE");
+
+        // TaskManagerState is opaque outside tasks; build it from its serialised form
+        let state: TaskManagerState = serde_json::from_value(json!({
+            "in_progress_tasks": {},
+            "ship_tasks": { "SHIP-1": [json_of(&schedule.actions[0])] },
+            "logistics_ships": {},
+            "planner_run_count": 7,
+        }))
+        .unwrap();
+        let system = SystemSymbol::new("X1-S1");
+        db.save_task_manager_state(&system, &state).await;
+        let loaded = db.load_task_manager_state(&system).await.unwrap();
+        assert_eq!(json_of(&loaded), json_of(&state));
+
+        drop_schema(&db, &schema).await;
+    }
+}
@@ -7,8 +7,11 @@ pub mod database;
 pub mod universe;
 
 pub mod broker;
+pub mod clock;
 pub mod config;
 pub mod logistics_planner;
+pub mod mining_stats;
+pub mod nav_calibration;
 pub mod pathfinding;
 pub mod prelude;
 pub mod ship_config;
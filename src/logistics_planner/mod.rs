@@ -3,6 +3,8 @@ pub mod value_feature;
 
 use crate::models::WaypointSymbol;
 use serde::{Deserialize, Serialize};
+use std::cmp::min;
+use std::collections::BTreeMap;
 
 // An action that can be taken at a waypoint
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize, Hash)]
@@ -42,6 +44,66 @@ pub struct Task {
     pub value: i64,
 }
 
+impl Task {
+    // Size a cargo task to one ship. Generated tasks only carry the market-side bound on
+    // units (trade volume / contract shortfall), so the same task can be taken by ships of
+    // any size: each clamps the units to its own hold when it plans. Trade value is profit
+    // and contract value is the payment those units earn, so both scale with the units
+    // carried; construction values are priorities.
+    pub fn sized_for_capacity(&self, capacity: i64) -> Task {
+        let TaskActions::TransportCargo {
+            src,
+            dest,
+            src_action,
+            dest_action,
+        } = &self.actions
+        else {
+            return self.clone();
+        };
+        let resize = |action: &Action| match action {
+            Action::BuyGoods(good, units) => Action::BuyGoods(good.clone(), min(*units, capacity)),
+            Action::SellGoods(good, units) => {
+                Action::SellGoods(good.clone(), min(*units, capacity))
+            }
+            Action::DeliverContract(good, units) => {
+                Action::DeliverContract(good.clone(), min(*units, capacity))
+            }
+            Action::DeliverConstruction(good, units) => {
+                Action::DeliverConstruction(good.clone(), min(*units, capacity))
+            }
+            other => other.clone(),
+        };
+        let value = match dest_action {
+            Action::SellGoods(_, units) | Action::DeliverContract(_, units)
+                if *units > capacity =>
+            {
+                self.value * capacity / units
+            }
+            _ => self.value,
+        };
+        Task {
+            id: self.id.clone(),
+            actions: TaskActions::TransportCargo {
+                src: src.clone(),
+                dest: dest.clone(),
+                src_action: resize(src_action),
+                dest_action: resize(dest_action),
+            },
+            value,
+        }
+    }
+
+    pub fn is_trade(&self) -> bool {
+        matches!(
+            &self.actions,
+            TaskActions::TransportCargo {
+                dest_action: Action::SellGoods(_, _),
+                ..
+            }
+        )
+    }
+}
+
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize, Hash)]
 pub enum TaskActions {
     VisitLocation {
@@ -68,6 +130,8 @@ pub struct LogisticShip {
 pub struct PlannerConstraints {
     pub plan_length: i64, // in seconds
     pub max_compute_time: chrono::Duration,
+    // collect a PlannerRationale alongside the schedules (debug aid, off in production)
+    pub explain: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -84,3 +148,101 @@ pub struct ShipSchedule {
     pub ship: LogisticShip,
     pub actions: Vec<ScheduledAction>,
 }
+
+// Why the planner did what it did: every input task with its value and outcome, plus the
+// resulting route of each ship.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannerRationale {
+    pub tasks: Vec<TaskRationale>,
+    pub routes: BTreeMap<String, Vec<WaypointSymbol>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskRationale {
+    pub task_id: String,
+    pub value: i64,
+    pub outcome: TaskOutcome,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum TaskOutcome {
+    Assigned { ship: String },
+    Skipped { reason: String },
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // One trade task, two ships: each carries what fits in its own hold, and the value
+    // (profit) is scaled to the units it actually moves.
+    #[test]
+    fn test_trade_task_sized_per_ship() {
+        let task = Task {
+            id: "X1-S1/trade_IRON".to_string(),
+            actions: TaskActions::TransportCargo {
+                src: WaypointSymbol::new("X1-S1-A1"),
+                dest: WaypointSymbol::new("X1-S1-A2"),
+                src_action: Action::BuyGoods("IRON".to_string(), 100),
+                dest_action: Action::SellGoods("IRON".to_string(), 100),
+            },
+            value: 10_000,
+        };
+        assert!(task.is_trade());
+
+        let small = task.sized_for_capacity(40);
+        let large = task.sized_for_capacity(120);
+        let units = |task: &Task| match &task.actions {
+            TaskActions::TransportCargo {
+                src_action,
+                dest_action,
+                ..
+            } => (src_action.clone(), dest_action.clone()),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            units(&small),
+            (
+                Action::BuyGoods("IRON".to_string(), 40),
+                Action::SellGoods("IRON".to_string(), 40)
+            )
+        );
+        assert_eq!(small.value, 4_000);
+        assert_eq!(
+            units(&large),
+            (
+                Action::BuyGoods("IRON".to_string(), 100),
+                Action::SellGoods("IRON".to_string(), 100)
+            )
+        );
+        assert_eq!(large.value, 10_000);
+        // The generated task itself is untouched, so it can be re-sized for the next ship
+        assert_eq!(units(&task).0, Action::BuyGoods("IRON".to_string(), 100));
+    }
+
+    // A contract delivery is worth the payment for the units a ship actually delivers
+    #[test]
+    fn test_contract_task_sized_per_ship() {
+        let task = Task {
+            id: "X1-S1/contract_IRON".to_string(),
+            actions: TaskActions::TransportCargo {
+                src: WaypointSymbol::new("X1-S1-A1"),
+                dest: WaypointSymbol::new("X1-S1-A2"),
+                src_action: Action::BuyGoods("IRON".to_string(), 100),
+                dest_action: Action::DeliverContract("IRON".to_string(), 100),
+            },
+            value: 50_000,
+        };
+        assert!(!task.is_trade());
+        let small = task.sized_for_capacity(40);
+        assert_eq!(small.value, 20_000);
+        assert!(matches!(
+            small.actions,
+            TaskActions::TransportCargo {
+                dest_action: Action::DeliverContract(_, 40),
+                ..
+            }
+        ));
+        assert_eq!(task.sized_for_capacity(120).value, 50_000);
+    }
+}
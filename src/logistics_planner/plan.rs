@@ -1,16 +1,22 @@
 use crate::logistics_planner::{
-    Action, LogisticShip, PlannerConstraints, ScheduledAction, ShipSchedule, Task, TaskActions,
+    Action, LogisticShip, PlannerConstraints, PlannerRationale, ScheduledAction, ShipSchedule,
+    Task, TaskActions, TaskOutcome, TaskRationale,
 };
 use crate::models::WaypointSymbol;
 use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use super::value_feature::JobValueDimension as _;
+use vrp_core::construction::heuristics::UnassignmentInfo;
 use vrp_core::models::common::*;
 use vrp_core::models::problem::*;
 use vrp_core::prelude::*;
 use vrp_core::rosomaxa::prelude::TelemetryMode;
 
+// Violation codes reported back for unassigned jobs
+const CAPACITY_VIOLATION: ViolationCode = ViolationCode(1);
+const TIME_VIOLATION: ViolationCode = ViolationCode(2);
+
 struct Planner<'a> {
     ships: &'a [LogisticShip],
     tasks: &'a [Task],
@@ -177,11 +183,13 @@ impl<'a> Planner<'a> {
 
         // Goal
         let capacity_feature = CapacityFeatureBuilder::<SingleDimLoad>::new("capacity")
+            .set_violation_code(CAPACITY_VIOLATION)
             .build()
             .unwrap();
         let transport_feature = TransportFeatureBuilder::new("min-distance")
             .set_transport_cost(routing_matrix.clone())
             .set_time_constrained(true)
+            .set_violation_code(TIME_VIOLATION)
             .build_minimize_duration()
             .unwrap();
         let minimize_unassigned = MinimizeUnassignedBuilder::new("min-unassigned")
@@ -217,7 +225,7 @@ pub fn run_planner(
     duration_matrix: &[Vec<f64>],
     distance_matrix: &[Vec<f64>],
     constraints: &PlannerConstraints,
-) -> Vec<ShipSchedule> {
+) -> (Vec<ShipSchedule>, Option<PlannerRationale>) {
     let planner = Planner {
         ships,
         tasks,
@@ -239,7 +247,7 @@ pub fn run_planner(
 
     let solution = Solver::new(problem.clone(), config).solve().unwrap();
 
-    ships
+    let schedules: Vec<ShipSchedule> = ships
         .iter()
         .map(|ship| {
             let route = solution
@@ -279,7 +287,86 @@ pub fn run_planner(
                 actions,
             }
         })
-        .collect()
+        .collect();
+
+    let rationale = constraints.explain.then(|| {
+        let unassigned = solution
+            .unassigned
+            .iter()
+            .map(|(job, info)| {
+                let single = match job {
+                    Job::Single(single) => single,
+                    Job::Multi(multi) => &multi.jobs[0],
+                };
+                let job_id = single.dimens.get_job_id().unwrap();
+                let task_id = job_id_map[job_id.as_str()].task_id.clone();
+                (task_id, unassigned_reason(info))
+            })
+            .collect::<BTreeMap<_, _>>();
+        build_rationale(tasks, &schedules, &unassigned)
+    });
+    (schedules, rationale)
+}
+
+fn unassigned_reason(info: &UnassignmentInfo) -> String {
+    let code = match info {
+        UnassignmentInfo::Simple(code) => Some(*code),
+        UnassignmentInfo::Detailed(codes) => codes.first().map(|(_, code)| *code),
+        UnassignmentInfo::Unknown => None,
+    };
+    match code {
+        Some(CAPACITY_VIOLATION) => "exceeds ship cargo capacity".to_string(),
+        Some(TIME_VIOLATION) => "doesn't fit within the plan length".to_string(),
+        Some(ViolationCode(code)) => format!("violates constraint {}", code),
+        None => "not worth the detour".to_string(),
+    }
+}
+
+fn build_rationale(
+    tasks: &[Task],
+    schedules: &[ShipSchedule],
+    unassigned: &BTreeMap<String, String>,
+) -> PlannerRationale {
+    let tasks = tasks
+        .iter()
+        .map(|task| {
+            let ship = schedules.iter().find(|schedule| {
+                schedule
+                    .actions
+                    .iter()
+                    .any(|a| a.task_id == task.id && a.completes_task)
+            });
+            let outcome = match ship {
+                Some(schedule) => TaskOutcome::Assigned {
+                    ship: schedule.ship.symbol.clone(),
+                },
+                None => TaskOutcome::Skipped {
+                    reason: unassigned
+                        .get(&task.id)
+                        .cloned()
+                        .unwrap_or_else(|| "not scheduled".to_string()),
+                },
+            };
+            TaskRationale {
+                task_id: task.id.clone(),
+                value: task.value,
+                outcome,
+            }
+        })
+        .collect();
+    let routes = schedules
+        .iter()
+        .map(|schedule| {
+            let mut route = vec![schedule.ship.start_waypoint.clone()];
+            for action in &schedule.actions {
+                if route.last() != Some(&action.waypoint) {
+                    route.push(action.waypoint.clone());
+                }
+            }
+            (schedule.ship.symbol.clone(), route)
+        })
+        .collect();
+    PlannerRationale { tasks, routes }
 }
 
 #[cfg(test)]
@@ -339,6 +426,7 @@ mod test {
         let constraints = PlannerConstraints {
             plan_length: 24 * 60 * 60,
             max_compute_time: Duration::seconds(1),
+            explain: true,
         };
         let market_waypoints = vec![
             WaypointSymbol::new("X1-S1-W1"),
@@ -346,7 +434,7 @@ mod test {
         ];
         let duration_matrix = vec![vec![0.0, 100.0], vec![100.0, 0.0]];
         let distance_matrix = vec![vec![0.0, 100.0], vec![100.0, 0.0]];
-        let schedule = run_planner(
+        let (schedule, rationale) = run_planner(
             &ships,
             &tasks,
             &market_waypoints,
@@ -356,5 +444,25 @@ mod test {
         );
         println!("schedule: {:?}", schedule);
         assert_eq!(schedule.len(), 2);
+
+        // every input task is accounted for, assigned or not
+        let rationale = rationale.unwrap();
+        let ids = |ids: Vec<&String>| ids.into_iter().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            ids(rationale.tasks.iter().map(|t| &t.task_id).collect()),
+            ids(tasks.iter().map(|t| &t.id).collect())
+        );
+        for (task, explained) in tasks.iter().zip(&rationale.tasks) {
+            assert_eq!(task.value, explained.value);
+            let scheduled_on = schedule
+                .iter()
+                .find(|s| s.actions.iter().any(|a| a.task_id == task.id))
+                .map(|s| s.ship.symbol.clone());
+            match &explained.outcome {
+                TaskOutcome::Assigned { ship } => assert_eq!(Some(ship), scheduled_on.as_ref()),
+                TaskOutcome::Skipped { .. } => assert_eq!(scheduled_on, None),
+            }
+        }
+        assert_eq!(rationale.routes.len(), 2);
     }
 }
@@ -0,0 +1,433 @@
+use crate::models::WaypointSymbol;
+use chrono::{DateTime, Duration, Utc};
+use log::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::Mutex;
+
+// Events older than the longest reported window are dropped.
+const RETENTION_HOURS: i64 = 24;
+
+// Something a mining/siphon ship did with its cargo. Emitted from the ship controller
+// (extract/siphon/jettison/sell) and the cargo broker (transfers); the aggregation
+// only counts ships registered to a site, so the emitters don't need to care.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MiningEvent {
+    Extracted {
+        good: String,
+        units: i64,
+    },
+    Jettisoned {
+        good: String,
+        units: i64,
+    },
+    Transferred {
+        good: String,
+        units: i64,
+    },
+    Sold {
+        good: String,
+        units: i64,
+        credits: i64,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ThroughputCounters {
+    pub extractions: i64,
+    // units by good
+    pub extracted: BTreeMap<String, i64>,
+    pub jettisoned: BTreeMap<String, i64>,
+    pub transferred: BTreeMap<String, i64>,
+    pub sold_units: i64,
+    pub sold_credits: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SiteThroughput {
+    pub site: WaypointSymbol,
+    pub ships: Vec<String>,
+    pub counters: ThroughputCounters,
+    pub credits_per_hour: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShipThroughput {
+    pub ship: String,
+    pub site: WaypointSymbol,
+    pub counters: ThroughputCounters,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ThroughputReport {
+    pub window_hours: i64,
+    pub sites: Vec<SiteThroughput>,
+    pub ships: Vec<ShipThroughput>,
+}
+
+pub struct MiningStats {
+    inner: Mutex<MiningStatsInner>,
+}
+
+struct MiningStatsInner {
+    // ship -> the site (asteroid / gas giant) it works
+    sites: BTreeMap<String, WaypointSymbol>,
+    // when each site first had a ship registered, so a brand new site isn't judged on a
+    // partial window
+    site_since: BTreeMap<WaypointSymbol, DateTime<Utc>>,
+    events: VecDeque<(DateTime<Utc>, String, MiningEvent)>,
+    last_report: Option<DateTime<Utc>>,
+    // sites already warned about, so the warning is logged once until they recover
+    underperforming: BTreeSet<WaypointSymbol>,
+}
+
+impl Default for MiningStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MiningStats {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(MiningStatsInner {
+                sites: BTreeMap::new(),
+                site_since: BTreeMap::new(),
+                events: VecDeque::new(),
+                last_report: None,
+                underperforming: BTreeSet::new(),
+            }),
+        }
+    }
+
+    pub fn register_ship(&self, ship_symbol: &str, site: &WaypointSymbol) {
+        self.register_ship_at(Utc::now(), ship_symbol, site);
+    }
+
+    fn register_ship_at(&self, now: DateTime<Utc>, ship_symbol: &str, site: &WaypointSymbol) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.sites.insert(ship_symbol.to_string(), site.clone());
+        inner.site_since.entry(site.clone()).or_insert(now);
+    }
+
+    pub fn record(&self, ship_symbol: &str, event: MiningEvent) {
+        self.record_at(Utc::now(), ship_symbol, event);
+    }
+
+    fn record_at(&self, now: DateTime<Utc>, ship_symbol: &str, event: MiningEvent) {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.sites.contains_key(ship_symbol) {
+            return;
+        }
+        inner
+            .events
+            .push_back((now, ship_symbol.to_string(), event));
+        let cutoff = now - Duration::hours(RETENTION_HOURS);
+        while inner.events.front().is_some_and(|(ts, _, _)| *ts < cutoff) {
+            inner.events.pop_front();
+        }
+    }
+
+    pub fn report(&self, window_hours: i64) -> ThroughputReport {
+        self.report_at(Utc::now(), window_hours)
+    }
+
+    fn report_at(&self, now: DateTime<Utc>, window_hours: i64) -> ThroughputReport {
+        let inner = self.inner.lock().unwrap();
+        let cutoff = now - Duration::hours(window_hours);
+        let mut ship_counters: BTreeMap<String, ThroughputCounters> = inner
+            .sites
+            .keys()
+            .map(|ship| (ship.clone(), ThroughputCounters::default()))
+            .collect();
+        for (_, ship, event) in inner.events.iter().filter(|(ts, _, _)| *ts >= cutoff) {
+            let c = ship_counters.get_mut(ship).unwrap();
+            match event {
+                MiningEvent::Extracted { good, units } => {
+                    c.extractions += 1;
+                    *c.extracted.entry(good.clone()).or_default() += units;
+                }
+                MiningEvent::Jettisoned { good, units } => {
+                    *c.jettisoned.entry(good.clone()).or_default() += units;
+                }
+                MiningEvent::Transferred { good, units } => {
+                    *c.transferred.entry(good.clone()).or_default() += units;
+                }
+                MiningEvent::Sold { units, credits, .. } => {
+                    c.sold_units += units;
+                    c.sold_credits += credits;
+                }
+            }
+        }
+
+        let mut sites: BTreeMap<WaypointSymbol, SiteThroughput> = BTreeMap::new();
+        let mut ships = Vec::new();
+        for (ship, counters) in ship_counters {
+            let site = inner.sites.get(&ship).unwrap().clone();
+            let entry = sites.entry(site.clone()).or_insert_with(|| SiteThroughput {
+                site: site.clone(),
+                ships: vec![],
+                counters: ThroughputCounters::default(),
+                credits_per_hour: 0.,
+            });
+            entry.ships.push(ship.clone());
+            let total = &mut entry.counters;
+            total.extractions += counters.extractions;
+            for (src, dst) in [
+                (&counters.extracted, &mut total.extracted),
+                (&counters.jettisoned, &mut total.jettisoned),
+                (&counters.transferred, &mut total.transferred),
+            ] {
+                for (good, units) in src {
+                    *dst.entry(good.clone()).or_default() += units;
+                }
+            }
+            total.sold_units += counters.sold_units;
+            total.sold_credits += counters.sold_credits;
+            ships.push(ShipThroughput {
+                ship,
+                site,
+                counters,
+            });
+        }
+        let mut sites: Vec<SiteThroughput> = sites.into_values().collect();
+        for site in &mut sites {
+            site.credits_per_hour = site.counters.sold_credits as f64 / window_hours as f64;
+        }
+        ThroughputReport {
+            window_hours,
+            sites,
+            ships,
+        }
+    }
+
+    // Sites that have been running for at least an hour and whose last hour of sales is
+    // below what their ships would earn elsewhere (`cost_per_ship_hour` each).
+    pub fn underperforming_sites(&self, cost_per_ship_hour: f64) -> Vec<SiteThroughput> {
+        self.underperforming_sites_at(Utc::now(), cost_per_ship_hour)
+    }
+
+    fn underperforming_sites_at(
+        &self,
+        now: DateTime<Utc>,
+        cost_per_ship_hour: f64,
+    ) -> Vec<SiteThroughput> {
+        let established: BTreeSet<WaypointSymbol> = {
+            let inner = self.inner.lock().unwrap();
+            inner
+                .site_since
+                .iter()
+                .filter(|(_, since)| now - **since >= Duration::hours(1))
+                .map(|(site, _)| site.clone())
+                .collect()
+        };
+        self.report_at(now, 1)
+            .sites
+            .into_iter()
+            .filter(|s| established.contains(&s.site))
+            .filter(|s| s.credits_per_hour < s.ships.len() as f64 * cost_per_ship_hour)
+            .collect()
+    }
+
+    // Sites that have started underperforming since the last call, and those that have
+    // stopped
+    fn underperformance_changes_at(
+        &self,
+        now: DateTime<Utc>,
+        cost_per_ship_hour: f64,
+    ) -> (Vec<SiteThroughput>, Vec<WaypointSymbol>) {
+        let sites = self.underperforming_sites_at(now, cost_per_ship_hour);
+        let current: BTreeSet<WaypointSymbol> = sites.iter().map(|s| s.site.clone()).collect();
+        let mut inner = self.inner.lock().unwrap();
+        let started = sites
+            .into_iter()
+            .filter(|s| !inner.underperforming.contains(&s.site))
+            .collect();
+        let recovered = inner
+            .underperforming
+            .difference(&current)
+            .cloned()
+            .collect();
+        inner.underperforming = current;
+        (started, recovered)
+    }
+
+    // Called every controller tick: warn when a site stops paying for its ships (once,
+    // until it recovers), and log the 24h table once a day.
+    pub fn tick(&self, cost_per_ship_hour: f64) {
+        let now = Utc::now();
+        let (started, recovered) = self.underperformance_changes_at(now, cost_per_ship_hour);
+        for site in started {
+            warn!(
+                "Mining site {} earned {:.0} credits/h over the last hour with {} ships \
+                 (opportunity cost {:.0} credits/h)",
+                site.site,
+                site.credits_per_hour,
+                site.ships.len(),
+                site.ships.len() as f64 * cost_per_ship_hour
+            );
+        }
+        for site in recovered {
+            info!("Mining site {} is paying for its ships again", site);
+        }
+        let due = {
+            let mut inner = self.inner.lock().unwrap();
+            let due = inner
+                .last_report
+                .is_none_or(|last| now - last >= Duration::hours(24));
+            if due {
+                inner.last_report = Some(now);
+            }
+            due
+        };
+        if due {
+            for site in self.report(24).sites {
+                info!(
+                    "Mining site {} (24h): {} ships, {} extractions, extracted {:?}, \
+                     jettisoned {:?}, transferred {:?}, sold {} units for {} credits",
+                    site.site,
+                    site.ships.len(),
+                    site.counters.extractions,
+                    site.counters.extracted,
+                    site.counters.jettisoned,
+                    site.counters.transferred,
+                    site.counters.sold_units,
+                    site.counters.sold_credits
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extracted(good: &str, units: i64) -> MiningEvent {
+        MiningEvent::Extracted {
+            good: good.to_string(),
+            units,
+        }
+    }
+
+    #[test]
+    fn test_throughput_windows_and_sites() {
+        let stats = MiningStats::new();
+        let site = WaypointSymbol::new("X1-S1-B7");
+        let t0 = Utc::now() - Duration::hours(30);
+        stats.register_ship_at(t0, "DRONE-1", &site);
+        stats.register_ship_at(t0, "SHUTTLE-1", &site);
+
+        // Unregistered ships (e.g. traders selling goods) aren't counted.
+        stats.record_at(t0, "HAULER-1", extracted("IRON_ORE", 99));
+
+        // Outside the 24h retention entirely
+        stats.record_at(t0, "DRONE-1", extracted("IRON_ORE", 10));
+        let now = t0 + Duration::hours(30);
+        // Within 24h but not 1h
+        stats.record_at(
+            now - Duration::hours(5),
+            "DRONE-1",
+            extracted("IRON_ORE", 7),
+        );
+        // Within 1h
+        stats.record_at(
+            now - Duration::minutes(10),
+            "DRONE-1",
+            extracted("IRON_ORE", 5),
+        );
+        stats.record_at(
+            now - Duration::minutes(10),
+            "DRONE-1",
+            MiningEvent::Jettisoned {
+                good: "ICE_WATER".to_string(),
+                units: 3,
+            },
+        );
+        stats.record_at(
+            now - Duration::minutes(9),
+            "DRONE-1",
+            MiningEvent::Transferred {
+                good: "IRON_ORE".to_string(),
+                units: 12,
+            },
+        );
+        stats.record_at(
+            now - Duration::minutes(5),
+            "SHUTTLE-1",
+            MiningEvent::Sold {
+                good: "IRON_ORE".to_string(),
+                units: 12,
+                credits: 600,
+            },
+        );
+
+        let day = stats.report_at(now, 24);
+        assert_eq!(day.sites.len(), 1);
+        assert_eq!(day.sites[0].ships, vec!["DRONE-1", "SHUTTLE-1"]);
+        assert_eq!(day.sites[0].counters.extractions, 2);
+        assert_eq!(day.sites[0].counters.extracted["IRON_ORE"], 12);
+        assert_eq!(day.sites[0].counters.sold_credits, 600);
+        assert_eq!(day.sites[0].credits_per_hour, 25.);
+
+        let hour = stats.report_at(now, 1);
+        let drone = hour.ships.iter().find(|s| s.ship == "DRONE-1").unwrap();
+        assert_eq!(drone.counters.extractions, 1);
+        assert_eq!(drone.counters.extracted["IRON_ORE"], 5);
+        assert_eq!(drone.counters.jettisoned["ICE_WATER"], 3);
+        assert_eq!(drone.counters.transferred["IRON_ORE"], 12);
+        let shuttle = hour.ships.iter().find(|s| s.ship == "SHUTTLE-1").unwrap();
+        assert_eq!(shuttle.counters.sold_units, 12);
+        assert_eq!(hour.sites[0].credits_per_hour, 600.);
+
+        // 600 credits/h with 2 ships is below the opportunity cost
+        let low = stats.underperforming_sites_at(now, 2000.);
+        assert_eq!(low.len(), 1);
+        assert_eq!(low[0].site, site);
+    }
+
+    #[test]
+    fn test_new_site_not_flagged() {
+        let stats = MiningStats::new();
+        let site = WaypointSymbol::new("X1-S1-B7");
+        let now = Utc::now();
+        stats.register_ship_at(now - Duration::minutes(20), "DRONE-1", &site);
+        assert!(stats.underperforming_sites_at(now, 2000.).is_empty());
+        assert!(
+            !stats
+                .underperforming_sites_at(now + Duration::hours(1), 2000.)
+                .is_empty()
+        );
+    }
+
+    // A site is reported when it starts underperforming and when it recovers, not on
+    // every tick in between
+    #[test]
+    fn test_underperformance_reported_on_change() {
+        let stats = MiningStats::new();
+        let site = WaypointSymbol::new("X1-S1-B7");
+        let t0 = Utc::now() - Duration::hours(3);
+        stats.register_ship_at(t0, "DRONE-1", &site);
+        let sold = |credits| MiningEvent::Sold {
+            good: "IRON_ORE".to_string(),
+            units: 10,
+            credits,
+        };
+
+        let now = t0 + Duration::hours(1);
+        let (started, recovered) = stats.underperformance_changes_at(now, 2000.);
+        assert_eq!(started.len(), 1);
+        assert!(recovered.is_empty());
+        let (started, _) = stats.underperformance_changes_at(now + Duration::minutes(1), 2000.);
+        assert!(started.is_empty());
+
+        let now = now + Duration::minutes(30);
+        stats.record_at(now, "DRONE-1", sold(5000));
+        let (started, recovered) = stats.underperformance_changes_at(now, 2000.);
+        assert!(started.is_empty());
+        assert_eq!(recovered, vec![site.clone()]);
+        // judged against a pricier alternative it's still underperforming
+        let (started, _) = stats.underperformance_changes_at(now, 10_000.);
+        assert_eq!(started.len(), 1);
+    }
+}
@@ -115,6 +115,16 @@ pub struct ShipyardShip {
     // pub crew: ShipCrew,
 }
 
+impl ShipyardShip {
+    pub fn cargo_capacity(&self) -> i64 {
+        self.modules
+            .iter()
+            .filter(|m| m.symbol.starts_with("MODULE_CARGO_HOLD"))
+            .filter_map(|m| m.capacity)
+            .sum()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipyardRemoteView {
@@ -152,6 +162,26 @@ pub struct ScrapTransaction {
     pub timestamp: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairTransaction {
+    pub waypoint_symbol: WaypointSymbol,
+    pub ship_symbol: String,
+    pub total_price: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+// Mount install/remove fee, charged by the shipyard
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShipModificationTransaction {
+    pub waypoint_symbol: WaypointSymbol,
+    pub ship_symbol: String,
+    pub trade_symbol: String,
+    pub total_price: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -0,0 +1,218 @@
+//! Calibrates the in-system travel time model against observed navigations.
+//!
+//! The model (`15 + modifier / speed * distance`) is only a guess at the server's nav
+//! math. Every completed navigate compares the model's prediction with the ship's
+//! actual departure -> arrival time, and a smoothed per-flight-mode multiplier is applied
+//! to future predictions. Persisted so a restart doesn't start from scratch.
+
+use crate::models::ShipFlightMode;
+use lazy_static::lazy_static;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+
+pub const NAV_CALIBRATION_KEY: &str = "nav_calibration";
+
+// Weight of each new observation in the moving average
+const EWMA_ALPHA: f64 = 0.1;
+// Observations are clamped to this range before smoothing (server lag, clock skew), and
+// the multiplier can never leave it
+const MIN_MULTIPLIER: f64 = 0.5;
+const MAX_MULTIPLIER: f64 = 2.0;
+// Prediction error worth logging
+const LOG_ERROR_FRACTION: f64 = 0.05;
+// Relative multiplier drift after which durations cached from an older calibration
+// (the planner's travel matrices) are considered stale
+const EPOCH_CHANGE_FRACTION: f64 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationState {
+    pub cruise: f64,
+    pub burn: f64,
+}
+
+impl Default for CalibrationState {
+    fn default() -> Self {
+        CalibrationState {
+            cruise: 1.0,
+            burn: 1.0,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct NavCalibration {
+    state: RwLock<CalibrationState>,
+    // (epoch, the state when it was last bumped): bumped once a multiplier has drifted
+    // more than EPOCH_CHANGE_FRACTION from that state
+    epoch: RwLock<(u64, CalibrationState)>,
+}
+
+lazy_static! {
+    pub static ref NAV_CALIBRATION: NavCalibration = NavCalibration::default();
+}
+
+impl NavCalibration {
+    pub fn state(&self) -> CalibrationState {
+        *self.state.read().unwrap()
+    }
+
+    pub fn restore(&self, state: CalibrationState) {
+        let clamp = |m: f64| m.clamp(MIN_MULTIPLIER, MAX_MULTIPLIER);
+        let state = CalibrationState {
+            cruise: clamp(state.cruise),
+            burn: clamp(state.burn),
+        };
+        *self.state.write().unwrap() = state;
+        self.update_epoch(state);
+    }
+
+    // Changes whenever the calibration has moved enough to matter; caches of calibrated
+    // durations keep it alongside and rebuild when it differs.
+    pub fn epoch(&self) -> u64 {
+        self.epoch.read().unwrap().0
+    }
+
+    fn update_epoch(&self, state: CalibrationState) {
+        let mut epoch = self.epoch.write().unwrap();
+        let drifted = |now: f64, then: f64| (now - then).abs() > EPOCH_CHANGE_FRACTION * then;
+        if drifted(state.cruise, epoch.1.cruise) || drifted(state.burn, epoch.1.burn) {
+            *epoch = (epoch.0 + 1, state);
+        }
+    }
+
+    pub fn multiplier(&self, flight_mode: &ShipFlightMode) -> f64 {
+        let state = self.state.read().unwrap();
+        match flight_mode {
+            ShipFlightMode::Cruise => state.cruise,
+            ShipFlightMode::Burn => state.burn,
+            _ => 1.0,
+        }
+    }
+
+    // Model duration scaled by the current calibration
+    pub fn calibrated(&self, flight_mode: &ShipFlightMode, model_duration: i64) -> i64 {
+        (model_duration as f64 * self.multiplier(flight_mode)).round() as i64
+    }
+
+    // Feed one navigation: `model_duration` is the uncalibrated prediction. Returns None
+    // for flight modes we don't calibrate, otherwise the updated state to persist.
+    pub fn observe(
+        &self,
+        flight_mode: &ShipFlightMode,
+        model_duration: i64,
+        actual_duration: i64,
+    ) -> Option<CalibrationState> {
+        if model_duration <= 0 || actual_duration <= 0 {
+            return None;
+        }
+        let mut state = self.state.write().unwrap();
+        let multiplier = match flight_mode {
+            ShipFlightMode::Cruise => &mut state.cruise,
+            ShipFlightMode::Burn => &mut state.burn,
+            _ => return None,
+        };
+        let predicted = model_duration as f64 * *multiplier;
+        let error = (actual_duration as f64 - predicted) / actual_duration as f64;
+        if error.abs() > LOG_ERROR_FRACTION {
+            info!(
+                "{:?} travel time off by {:.1}%: predicted {:.0}s, took {}s",
+                flight_mode,
+                error * 100.0,
+                predicted,
+                actual_duration
+            );
+        }
+        let ratio =
+            (actual_duration as f64 / model_duration as f64).clamp(MIN_MULTIPLIER, MAX_MULTIPLIER);
+        *multiplier = (*multiplier + EWMA_ALPHA * (ratio - *multiplier))
+            .clamp(MIN_MULTIPLIER, MAX_MULTIPLIER);
+        let state = *state;
+        self.update_epoch(state);
+        Some(state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_converges_to_observed_ratio() {
+        let calibration = NavCalibration::default();
+        for _ in 0..100 {
+            calibration.observe(&ShipFlightMode::Cruise, 200, 220);
+        }
+        let cruise = calibration.multiplier(&ShipFlightMode::Cruise);
+        assert!((cruise - 1.1).abs() < 0.001, "{}", cruise);
+        assert_eq!(calibration.calibrated(&ShipFlightMode::Cruise, 200), 220);
+        // modes are calibrated independently
+        assert_eq!(calibration.multiplier(&ShipFlightMode::Burn), 1.0);
+        assert!(
+            calibration
+                .observe(&ShipFlightMode::Drift, 200, 400)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_outliers_are_smoothed_and_bounded() {
+        let calibration = NavCalibration::default();
+        // an accurate model, with every tenth observation hit by a huge server lag
+        for i in 0..200 {
+            let actual = if i % 10 == 0 { 5000 } else { 100 };
+            calibration.observe(&ShipFlightMode::Burn, 100, actual);
+            let burn = calibration.multiplier(&ShipFlightMode::Burn);
+            assert!((MIN_MULTIPLIER..=MAX_MULTIPLIER).contains(&burn));
+        }
+        // the spikes are clamped and averaged out: a few percent off, not 50x
+        let burn = calibration.multiplier(&ShipFlightMode::Burn);
+        assert!(burn < 1.1, "{}", burn);
+
+        // a persistently wrong model can't push it out of bounds either
+        for _ in 0..200 {
+            calibration.observe(&ShipFlightMode::Burn, 100, 1000);
+        }
+        let burn = calibration.multiplier(&ShipFlightMode::Burn);
+        assert!(
+            burn <= MAX_MULTIPLIER && MAX_MULTIPLIER - burn < 1e-6,
+            "{}",
+            burn
+        );
+
+        calibration.restore(CalibrationState {
+            cruise: 0.01,
+            burn: 1.2,
+        });
+        assert_eq!(
+            calibration.multiplier(&ShipFlightMode::Cruise),
+            MIN_MULTIPLIER
+        );
+        assert_eq!(calibration.multiplier(&ShipFlightMode::Burn), 1.2);
+    }
+
+    // Small drift keeps the epoch; once a multiplier has moved 5% it bumps, and the
+    // next bump is measured from there
+    #[test]
+    fn test_epoch_bumps_on_drift() {
+        let calibration = NavCalibration::default();
+        assert_eq!(calibration.epoch(), 0);
+        calibration.observe(&ShipFlightMode::Cruise, 100, 102);
+        assert_eq!(calibration.epoch(), 0);
+        let mut observations = 0;
+        while calibration.epoch() == 0 {
+            calibration.observe(&ShipFlightMode::Cruise, 100, 140);
+            observations += 1;
+        }
+        assert!(observations > 1);
+        assert_eq!(calibration.epoch(), 1);
+        calibration.observe(&ShipFlightMode::Cruise, 100, 101);
+        assert_eq!(calibration.epoch(), 1);
+
+        calibration.restore(CalibrationState {
+            cruise: 1.5,
+            burn: 1.0,
+        });
+        assert_eq!(calibration.epoch(), 2);
+    }
+}
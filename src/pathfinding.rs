@@ -3,17 +3,39 @@ use std::{collections::BTreeMap, sync::Arc};
 use crate::{
     api_client::api_models::WaypointDetailed,
     models::{ShipFlightMode, System, WaypointSymbol},
+    nav_calibration::NAV_CALIBRATION,
 };
 use std::cmp::max;
 
 #[allow(non_snake_case)]
 const CRUISE_NAV_MODIFIER: f64 = 25.0;
 const BURN_NAV_MODIFIER: f64 = 12.5;
+const DRIFT_NAV_MODIFIER: f64 = 250.0;
+// Added to a drift hop's cost in the route search only (not its travel_duration), so
+// any route that burns or cruises wins and drift is left for when nothing else fits
+// the fuel budget
+const DRIFT_ROUTE_PENALTY: i64 = 1_000_000;
+
+// Systems with too few markets (absolutely, or relative to their waypoint count) are
+// treated as fuel-scarce: burn's double fuel cost strands ships between the few refuel
+// points, so every hop there is planned in cruise.
+const FUEL_SCARCE_MIN_MARKETS: usize = 3;
+const FUEL_SCARCE_MIN_MARKET_DENSITY: f64 = 0.1;
+
+pub fn is_fuel_scarce(waypoints: &[WaypointDetailed]) -> bool {
+    if waypoints.is_empty() {
+        return false;
+    }
+    let num_markets = waypoints.iter().filter(|w| w.is_market()).count();
+    let density = num_markets as f64 / waypoints.len() as f64;
+    num_markets < FUEL_SCARCE_MIN_MARKETS || density < FUEL_SCARCE_MIN_MARKET_DENSITY
+}
 
 #[derive(Debug)]
 pub struct Pathfinding {
     waypoints: Arc<BTreeMap<WaypointSymbol, WaypointDetailed>>,
     closest_market: BTreeMap<WaypointSymbol, Option<(WaypointSymbol, i64)>>,
+    fuel_scarce: bool,
 }
 
 pub struct Route {
@@ -45,6 +67,27 @@ impl Pathfinding {
         Pathfinding {
             waypoints: Arc::new(waypoint_map),
             closest_market,
+            fuel_scarce: is_fuel_scarce(&waypoints),
+        }
+    }
+
+    pub fn is_fuel_scarce(&self) -> bool {
+        self.fuel_scarce
+    }
+
+    // Fuel-scarce systems route cruise-only (drift still being the last resort)
+    fn edge(
+        &self,
+        a: &WaypointDetailed,
+        b: &WaypointDetailed,
+        speed: i64,
+        fuel_max: i64,
+    ) -> Option<Edge> {
+        match self.fuel_scarce {
+            true => {
+                cruise_edge(a, b, speed, fuel_max).or_else(|| drift_edge(a, b, speed, fuel_max))
+            }
+            false => edge(a, b, speed, fuel_max),
         }
     }
 
@@ -55,7 +98,7 @@ impl Pathfinding {
         speed: i64,
         start_fuel: i64, // ruins the cacheability slightly, since the graph changes
         fuel_capacity: i64,
-    ) -> Route {
+    ) -> Option<Route> {
         use pathfinding::directed::dijkstra::dijkstra;
         // log::debug!(
         //     "Finding route from {} to {} sp: {} sf: {} fc: {}",
@@ -100,11 +143,8 @@ impl Pathfinding {
                             if x_symbol == y_symbol {
                                 return None;
                             }
-                            if let Some(e) = edge(x, y, speed, fuel_capacity) {
-                                Some((y_symbol.clone(), e.travel_duration))
-                            } else {
-                                None
-                            }
+                            self.edge(x, y, speed, fuel_capacity)
+                                .map(|e| (y_symbol.clone(), e.route_cost()))
                         })
                         .collect::<Vec<_>>()
                 } else {
@@ -117,11 +157,8 @@ impl Pathfinding {
                         .iter()
                         .filter(|(_y_symbol, y)| y.is_market())
                         .filter_map(|(y_symbol, y)| {
-                            if let Some(e) = edge(x, y, speed, start_fuel) {
-                                Some((y_symbol.clone(), e.travel_duration))
-                            } else {
-                                None
-                            }
+                            self.edge(x, y, speed, start_fuel)
+                                .map(|e| (y_symbol.clone(), e.route_cost()))
                         })
                         .collect::<Vec<_>>();
                     edges.extend(edges1);
@@ -136,25 +173,24 @@ impl Pathfinding {
                 // start_fuel) would find that edge infeasible and panic on unwrap.
                 if !dest_is_market
                     && x.is_market()
-                    && let Some(e) = edge(x, dst, speed, fuel_capacity - req_escape_fuel)
+                    && let Some(e) = self.edge(x, dst, speed, fuel_capacity - req_escape_fuel)
                 {
-                    edges.push((dest_symbol.clone(), e.travel_duration));
+                    edges.push((dest_symbol.clone(), e.route_cost()));
                 }
                 // finally add non-market -> non-market edge ( fuel_cost <= start_fuel - req_escape_fuel )
                 if !src_is_market
                     && !dest_is_market
                     && x_symbol == src_symbol
-                    && let Some(e) = edge(src, dst, speed, start_fuel - req_escape_fuel)
+                    && let Some(e) = self.edge(src, dst, speed, start_fuel - req_escape_fuel)
                 {
-                    edges.push((dest_symbol.clone(), e.travel_duration));
+                    edges.push((dest_symbol.clone(), e.route_cost()));
                 }
                 edges
             },
             |x_symbol| *x_symbol == *dest_symbol,
-        )
-        .expect("No path found");
+        )?;
 
-        let hops = path
+        let hops: Vec<_> = path
             .0
             .iter()
             .zip(path.0.iter().skip(1))
@@ -167,15 +203,17 @@ impl Pathfinding {
                     (false, true) => start_fuel,
                     (false, false) => start_fuel - req_escape_fuel,
                 };
-                let e = edge(a, b, speed, fuel_max).unwrap();
+                let e = self.edge(a, b, speed, fuel_max).unwrap();
                 (b_symbol.clone(), e, a.is_market(), b.is_market())
             })
             .collect();
-        Route {
+        // path.1 includes any drift penalty
+        let min_travel_duration = hops.iter().map(|(_, e, ..)| e.travel_duration).sum();
+        Some(Route {
             hops,
-            min_travel_duration: path.1,
+            min_travel_duration,
             req_terminal_fuel: req_escape_fuel,
-        }
+        })
     }
 }
 
@@ -206,6 +244,90 @@ pub struct Edge {
     pub flight_mode: ShipFlightMode,
 }
 
+impl Edge {
+    // Cost of the hop in the route search
+    fn route_cost(&self) -> i64 {
+        match self.flight_mode {
+            ShipFlightMode::Drift => self.travel_duration + DRIFT_ROUTE_PENALTY,
+            _ => self.travel_duration,
+        }
+    }
+}
+
+// Uncalibrated travel time model (see nav_calibration)
+pub fn model_travel_duration(flight_mode: &ShipFlightMode, speed: i64, distance: i64) -> i64 {
+    let modifier = match flight_mode {
+        ShipFlightMode::Burn => BURN_NAV_MODIFIER,
+        ShipFlightMode::Drift => DRIFT_NAV_MODIFIER,
+        _ => CRUISE_NAV_MODIFIER,
+    };
+    (15.0 + modifier / (speed as f64) * (distance as f64)).round() as i64
+}
+
+pub fn edge(a: &WaypointDetailed, b: &WaypointDetailed, speed: i64, fuel_max: i64) -> Option<Edge> {
+    let distance = a.distance(b);
+
+    // burn
+    if 2 * distance <= fuel_max {
+        let travel_duration = NAV_CALIBRATION.calibrated(
+            &ShipFlightMode::Burn,
+            model_travel_duration(&ShipFlightMode::Burn, speed, distance),
+        );
+        return Some(Edge {
+            distance,
+            travel_duration,
+            fuel_cost: 2 * distance,
+            flight_mode: ShipFlightMode::Burn,
+        });
+    }
+    cruise_edge(a, b, speed, fuel_max).or_else(|| drift_edge(a, b, speed, fuel_max))
+}
+
+pub fn cruise_edge(
+    a: &WaypointDetailed,
+    b: &WaypointDetailed,
+    speed: i64,
+    fuel_max: i64,
+) -> Option<Edge> {
+    let distance = a.distance(b);
+    if distance <= fuel_max {
+        let travel_duration = NAV_CALIBRATION.calibrated(
+            &ShipFlightMode::Cruise,
+            model_travel_duration(&ShipFlightMode::Cruise, speed, distance),
+        );
+        return Some(Edge {
+            distance,
+            travel_duration,
+            fuel_cost: distance,
+            flight_mode: ShipFlightMode::Cruise,
+        });
+    }
+    None
+}
+
+// Drift costs 1 fuel whatever the distance, at 10x cruise's travel time
+pub fn drift_edge(
+    a: &WaypointDetailed,
+    b: &WaypointDetailed,
+    speed: i64,
+    fuel_max: i64,
+) -> Option<Edge> {
+    if fuel_max < 1 {
+        return None;
+    }
+    let distance = a.distance(b);
+    let travel_duration = NAV_CALIBRATION.calibrated(
+        &ShipFlightMode::Drift,
+        model_travel_duration(&ShipFlightMode::Drift, speed, distance),
+    );
+    Some(Edge {
+        distance,
+        travel_duration,
+        fuel_cost: 1,
+        flight_mode: ShipFlightMode::Drift,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -253,37 +375,92 @@ mod tests {
         // At A1 with only 120 fuel: A2 is 200 away and its closest market (the gate)
         // is 300 away, so a direct A1 -> A2 hop is infeasible. Expect a refuel stop at
         // the gate, i.e. hops [gate, A2], rather than a panic.
-        let route = pf.get_route(&a1.symbol, &a2.symbol, 30, 120, 800);
+        let route = pf.get_route(&a1.symbol, &a2.symbol, 30, 120, 800).unwrap();
         let stops: Vec<_> = route.hops.iter().map(|(w, ..)| w.clone()).collect();
         assert_eq!(stops, vec![gate.symbol.clone(), a2.symbol.clone()]);
     }
-}
 
-pub fn edge(a: &WaypointDetailed, b: &WaypointDetailed, speed: i64, fuel_max: i64) -> Option<Edge> {
-    let distance = a.distance(b);
+    // A system with a single market among many waypoints is fuel-scarce, so even hops
+    // the tank could burn are planned in cruise. The same hop in a market-dense system
+    // burns.
+    #[test]
+    fn sparse_market_system_routes_cruise_only() {
+        let market = wp("X1-T-M1", 0, 0, true);
+        let mut sparse = vec![market.clone()];
+        for i in 0..9 {
+            sparse.push(wp(&format!("X1-T-A{i}"), 10 * (i + 1), 0, false));
+        }
+        assert!(is_fuel_scarce(&sparse));
+        let pf = Pathfinding::new(sparse);
+        assert!(pf.is_fuel_scarce());
+        let dest = WaypointSymbol::new("X1-T-A4");
+        let route = pf.get_route(&market.symbol, &dest, 30, 400, 400).unwrap();
+        assert!(!route.hops.is_empty());
+        for (_, edge, ..) in &route.hops {
+            assert_eq!(edge.flight_mode, ShipFlightMode::Cruise);
+        }
 
-    // burn
-    if 2 * distance <= fuel_max {
-        let travel_duration =
-            (15.0 + BURN_NAV_MODIFIER / (speed as f64) * (distance as f64)).round() as i64;
-        return Some(Edge {
-            distance,
-            travel_duration,
-            fuel_cost: 2 * distance,
-            flight_mode: ShipFlightMode::Burn,
-        });
+        let dense = vec![
+            market.clone(),
+            wp("X1-T-M2", 50, 0, true),
+            wp("X1-T-M3", 0, 50, true),
+            wp("X1-T-A0", 100, 0, false),
+        ];
+        assert!(!is_fuel_scarce(&dense));
+        let pf = Pathfinding::new(dense);
+        let route = pf
+            .get_route(
+                &market.symbol,
+                &WaypointSymbol::new("X1-T-M2"),
+                30,
+                400,
+                400,
+            )
+            .unwrap();
+        assert_eq!(route.hops.len(), 1);
+        assert_eq!(route.hops[0].1.flight_mode, ShipFlightMode::Burn);
     }
 
-    // cruise
-    if distance <= fuel_max {
-        let travel_duration =
-            (15.0 + CRUISE_NAV_MODIFIER / (speed as f64) * (distance as f64)).round() as i64;
-        return Some(Edge {
-            distance,
-            travel_duration,
-            fuel_cost: distance,
-            flight_mode: ShipFlightMode::Cruise,
-        });
+    // A ship with 5 fuel on an asteroid, its market 200 away: neither burn nor cruise
+    // fits, so it drifts there rather than panicking with "No path found". Once it can
+    // cruise it never drifts, even when the cruise route is longer.
+    #[test]
+    fn route_drifts_only_when_nothing_else_fits() {
+        let market = wp("X1-T-M1", 0, 0, true);
+        let other = wp("X1-T-M2", 0, 150, true);
+        let asteroid = wp("X1-T-A1", 200, 0, false);
+        let pf = Pathfinding::new(vec![market.clone(), other.clone(), asteroid.clone()]);
+
+        let route = pf
+            .get_route(&asteroid.symbol, &market.symbol, 30, 5, 400)
+            .unwrap();
+        assert_eq!(route.hops.len(), 1);
+        let (stop, edge, ..) = &route.hops[0];
+        assert_eq!(*stop, market.symbol);
+        assert_eq!(edge.flight_mode, ShipFlightMode::Drift);
+        assert_eq!(edge.fuel_cost, 1);
+        assert_eq!(
+            edge.travel_duration,
+            (15.0 + 250.0 * 200.0 / 30.0_f64).round() as i64
+        );
+        // the search penalty isn't reported as travel time
+        assert_eq!(route.min_travel_duration, edge.travel_duration);
+
+        // 250 fuel: the direct hop to M2 (250 away) cruises
+        let route = pf
+            .get_route(&asteroid.symbol, &other.symbol, 30, 250, 400)
+            .unwrap();
+        assert!(
+            route
+                .hops
+                .iter()
+                .all(|(_, e, ..)| e.flight_mode != ShipFlightMode::Drift)
+        );
+
+        // an empty tank away from a market can't even drift: no route, no panic
+        assert!(
+            pf.get_route(&asteroid.symbol, &market.symbol, 30, 0, 400)
+                .is_none()
+        );
     }
-    None
 }
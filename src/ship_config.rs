@@ -1,8 +1,105 @@
 use chrono::Duration;
 
-use crate::{api_client::api_models::WaypointDetailed, models::*};
+use crate::{
+    api_client::api_models::WaypointDetailed,
+    models::*,
+    pathfinding::model_travel_duration,
+    util::{self, Coord},
+};
 use std::collections::BTreeMap;
 
+// A probe's cruise speed (ENGINE_IMPULSE_DRIVE_I)
+const PROBE_SPEED: i64 = 3;
+// Time a roaming probe spends at each stop (orbit/dock and the market refresh)
+const PROBE_STOP_SECONDS: i64 = 5;
+
+fn origin_distance(x: i64, y: i64) -> i64 {
+    ((x * x + y * y) as f64).sqrt() as i64
+}
+
+// One stop on a roaming probe's tour: the markets sharing a location (co-orbitals)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeSite {
+    pub waypoints: Vec<WaypointSymbol>,
+    pub x: i64,
+    pub y: i64,
+}
+
+impl Coord for ProbeSite {
+    fn x(&self) -> i64 {
+        self.x
+    }
+    fn y(&self) -> i64 {
+        self.y
+    }
+}
+
+pub fn probe_sites(markets: &[&WaypointDetailed]) -> Vec<ProbeSite> {
+    let mut sites: BTreeMap<(i64, i64), Vec<WaypointSymbol>> = BTreeMap::new();
+    for w in markets {
+        sites.entry((w.x, w.y)).or_default().push(w.symbol.clone());
+    }
+    sites
+        .into_iter()
+        .map(|((x, y), waypoints)| ProbeSite { waypoints, x, y })
+        .collect()
+}
+
+// Seconds for one lap of a tour, back to its first stop
+fn tour_seconds(tour: &[ProbeSite], travel_secs: &impl Fn(i64) -> i64) -> i64 {
+    let legs = match tour.len() {
+        0 | 1 => 0,
+        n => (0..n)
+            .map(|i| travel_secs(util::distance(&tour[i], &tour[(i + 1) % n])))
+            .sum(),
+    };
+    legs + PROBE_STOP_SECONDS * tour.len() as i64
+}
+
+// Split the sites into roaming probe tours, each short enough to lap within `target`.
+// Sites are chained nearest-neighbour from the one closest to the origin and the chain
+// is cut into tours greedily, so neighbouring sites share a probe. A site too far out to
+// lap in time gets a probe to itself. `travel_secs` is the probe's travel time over a
+// distance.
+pub fn partition_probe_sites(
+    mut sites: Vec<ProbeSite>,
+    target: Duration,
+    travel_secs: impl Fn(i64) -> i64,
+) -> Vec<Vec<ProbeSite>> {
+    let mut chain: Vec<ProbeSite> = Vec::with_capacity(sites.len());
+    let start = sites
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, site)| (origin_distance(site.x, site.y), site.waypoints[0].clone()))
+        .map(|(i, _)| i);
+    if let Some(start) = start {
+        chain.push(sites.swap_remove(start));
+    }
+    while !sites.is_empty() {
+        let last = chain.last().unwrap();
+        let (next, _) = sites
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, site)| (util::distance(last, *site), site.waypoints[0].clone()))
+            .unwrap();
+        chain.push(sites.swap_remove(next));
+    }
+
+    let mut tours: Vec<Vec<ProbeSite>> = vec![];
+    let mut tour: Vec<ProbeSite> = vec![];
+    for site in chain {
+        tour.push(site);
+        if tour.len() > 1 && tour_seconds(&tour, &travel_secs) > target.num_seconds() {
+            let site = tour.pop().unwrap();
+            tours.push(std::mem::replace(&mut tour, vec![site]));
+        }
+    }
+    if !tour.is_empty() {
+        tours.push(tour);
+    }
+    tours
+}
+
 pub fn market_waypoints(waypoints: &[WaypointDetailed], range: Option<i64>) -> Vec<WaypointSymbol> {
     waypoints
         .iter()
@@ -34,6 +131,8 @@ pub fn ship_config_starter_system(
     // as never_purchase so any leftover ships stay assigned and self-scrap (see the
     // mining/siphon/construction scripts).
     in_home_phase: bool,
+    // how often each market should be refreshed by a roaming probe
+    probe_refresh_target: Duration,
 ) -> Vec<ShipConfig> {
     let mut ships = vec![];
 
@@ -69,43 +168,85 @@ pub fn ship_config_starter_system(
         },
     ));
 
-    // Send probes to all inner markets with shipyards getting priority
-    // probes rotate through all waypoints at a location
-    let mut probe_locations = BTreeMap::new();
-    for w in waypoints
+    // Probes: a static probe at every shipyard (it doubles as the purchaser there), and
+    // roaming probes touring the other markets, as many as it takes for each tour to
+    // come round within the refresh target. Inner markets only at first; every market
+    // once the outer ring is in play.
+    let probe_market_waypoints = match incl_outer_and_siphons {
+        true => &all_market_waypoints,
+        false => &inner_market_waypoints,
+    };
+    let probe_markets = waypoints
+        .iter()
+        .filter(|w| probe_market_waypoints.contains(&w.symbol))
+        .collect::<Vec<_>>();
+    let probe_order = |inner: bool, dist: i64| match inner {
+        true => (2.0, dist as f64),
+        false => (5.0, dist as f64),
+    };
+    let roaming_markets = probe_markets
+        .iter()
+        .filter(|w| !w.is_shipyard() && use_nonstatic_probes)
+        .copied()
+        .collect::<Vec<_>>();
+    for w in probe_markets
         .iter()
-        .filter(|w| inner_market_waypoints.contains(&w.symbol))
+        .filter(|w| !roaming_markets.contains(w))
     {
-        let loc = if !w.is_shipyard() && use_nonstatic_probes {
-            // use coordinate-grouped probe
-            format!("({},{})", w.x, w.y)
-        } else {
-            w.symbol.to_string()
+        let inner = inner_market_waypoints.contains(&w.symbol);
+        let (category, dist) = probe_order(inner, origin_distance(w.x, w.y));
+        let order = match w.is_shipyard() {
+            true => dist - 10000.0,
+            false => dist,
         };
-        let e = probe_locations.entry(loc).or_insert_with(|| {
-            let dist = ((w.x * w.x + w.y * w.y) as f64).sqrt() as i64;
-            (vec![], w.is_shipyard(), dist)
-        });
-        e.0.push(w.symbol.clone());
+        ships.push((
+            (category, order),
+            ShipConfig {
+                id: format!("probe/{}", w.symbol),
+                ship_model: "SHIP_PROBE".to_string(),
+                behaviour: ShipBehaviour::Probe(ProbeScriptConfig {
+                    waypoints: vec![w.symbol.clone()],
+                    refresh_market: true,
+                }),
+                purchase_criteria: PurchaseCriteria {
+                    allow_logistic_task: inner,
+                    require_cheapest: !inner,
+                    ..PurchaseCriteria::default()
+                },
+            },
+        ));
     }
-    for (loc, (waypoints, has_shipyard, dist)) in probe_locations {
+    let tours = partition_probe_sites(probe_sites(&roaming_markets), probe_refresh_target, |d| {
+        model_travel_duration(&ShipFlightMode::Cruise, PROBE_SPEED, d)
+    });
+    for tour in tours {
+        let inner = tour
+            .iter()
+            .flat_map(|site| &site.waypoints)
+            .all(|w| inner_market_waypoints.contains(w));
+        let dist = tour
+            .iter()
+            .map(|site| origin_distance(site.x, site.y))
+            .min()
+            .unwrap();
         let config = ProbeScriptConfig {
-            waypoints,
+            waypoints: tour
+                .iter()
+                .flat_map(|site| site.waypoints.clone())
+                .collect(),
             refresh_market: true,
         };
-        if !use_nonstatic_probes {
-            assert_eq!(config.waypoints.len(), 1);
-        }
-        let order = -10000.0 * (has_shipyard as i64 as f64) + (dist as f64);
         ships.push((
-            (2.0, order),
+            probe_order(inner, dist),
             ShipConfig {
-                id: format!("probe/{}", loc),
+                // named after the tour's first stop, so a tour that doesn't change keeps
+                // its job (and its ship)
+                id: format!("probe/({},{})", tour[0].x, tour[0].y),
                 ship_model: "SHIP_PROBE".to_string(),
                 behaviour: ShipBehaviour::Probe(config),
                 purchase_criteria: PurchaseCriteria {
-                    allow_logistic_task: true,
-                    require_cheapest: false,
+                    allow_logistic_task: inner,
+                    require_cheapest: !inner,
                     ..PurchaseCriteria::default()
                 },
             },
@@ -176,27 +317,6 @@ pub fn ship_config_starter_system(
     }
 
     if incl_outer_and_siphons {
-        // Add probes for the remaining markets - should we convert the old ones to static probes everywhere??
-        for w in waypoints
-            .iter()
-            .filter(|w| all_market_waypoints.contains(&w.symbol))
-            .filter(|w| !inner_market_waypoints.contains(&w.symbol))
-        {
-            let config = ProbeScriptConfig {
-                waypoints: vec![w.symbol.clone()],
-                refresh_market: true,
-            };
-            ships.push((
-                (5.0, 0.0),
-                ShipConfig {
-                    id: format!("probe/{}", w.symbol),
-                    ship_model: "SHIP_PROBE".to_string(),
-                    behaviour: ShipBehaviour::Probe(config),
-                    purchase_criteria: PurchaseCriteria::default(),
-                },
-            ));
-        }
-
         // Add 2 logistics haulers - not using planner
         const NUM_LHAULERS: i64 = 2;
         for i in 0..NUM_LHAULERS {
@@ -673,3 +793,116 @@ pub fn ship_config_starter_system(
 //     ships.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
 //     ships.into_iter().map(|(_, c)| c).collect()
 // }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn site(symbol: &str, x: i64, y: i64) -> ProbeSite {
+        ProbeSite {
+            waypoints: vec![WaypointSymbol::new(symbol)],
+            x,
+            y,
+        }
+    }
+
+    // One second per unit of distance
+    fn travel(distance: i64) -> i64 {
+        distance
+    }
+
+    fn tour_symbols(tours: &[Vec<ProbeSite>]) -> Vec<Vec<String>> {
+        tours
+            .iter()
+            .map(|tour| tour.iter().map(|s| s.waypoints[0].to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_partition_probe_sites() {
+        // two clusters 1000 apart, each of three sites 10 apart
+        let sites = vec![
+            site("X1-S1-B1", 1000, 0),
+            site("X1-S1-A2", 10, 0),
+            site("X1-S1-B2", 1010, 0),
+            site("X1-S1-A1", 0, 0),
+            site("X1-S1-A3", 20, 0),
+            site("X1-S1-B3", 1020, 0),
+        ];
+        // a lap of one cluster is 10 + 10 + 20 travel + 3 stops = 55s
+        let tours = partition_probe_sites(sites.clone(), Duration::seconds(60), travel);
+        assert_eq!(
+            tour_symbols(&tours),
+            vec![
+                vec!["X1-S1-A1", "X1-S1-A2", "X1-S1-A3"],
+                vec!["X1-S1-B1", "X1-S1-B2", "X1-S1-B3"],
+            ]
+        );
+        for tour in &tours {
+            assert!(tour_seconds(tour, &travel) <= 60);
+        }
+
+        // a looser target covers both clusters with one probe
+        let tours = partition_probe_sites(sites.clone(), Duration::hours(1), travel);
+        assert_eq!(tours.len(), 1);
+        assert_eq!(tours[0].len(), 6);
+
+        // a tighter one needs more probes, and a site that can't be lapped in time
+        // still gets one
+        let tours = partition_probe_sites(sites.clone(), Duration::seconds(30), travel);
+        assert_eq!(tours.len(), 4);
+        let tours = partition_probe_sites(sites, Duration::seconds(1), travel);
+        assert_eq!(tours.len(), 6);
+
+        assert!(partition_probe_sites(vec![], Duration::minutes(15), travel).is_empty());
+    }
+
+    // More markets, more probes: the fleet scales with the system
+    #[test]
+    fn test_probe_count_scales_with_markets() {
+        let ring = |n: i64| {
+            (0..n)
+                .map(|i| {
+                    let angle = (i as f64) * std::f64::consts::TAU / (n as f64);
+                    let (x, y) = ((angle.cos() * 150.0) as i64, (angle.sin() * 150.0) as i64);
+                    site(&format!("X1-S1-W{}", i), x, y)
+                })
+                .collect::<Vec<_>>()
+        };
+        let probes = |n: i64| {
+            partition_probe_sites(ring(n), Duration::minutes(15), |d| {
+                model_travel_duration(&ShipFlightMode::Cruise, PROBE_SPEED, d)
+            })
+            .len()
+        };
+        assert!(probes(12) < probes(40));
+    }
+
+    #[test]
+    fn test_probe_sites_group_co_orbitals() {
+        let waypoint = |symbol: &str, x: i64, y: i64| WaypointDetailed {
+            system_symbol: SystemSymbol::new("X1-S1"),
+            symbol: WaypointSymbol::new(symbol),
+            waypoint_type: "PLANET".to_string(),
+            x,
+            y,
+            orbitals: vec![],
+            orbits: None,
+            faction: None,
+            traits: vec![],
+            modifiers: vec![],
+            chart: None,
+            is_under_construction: false,
+        };
+        let planet = waypoint("X1-S1-A1", 5, 5);
+        let moon = waypoint("X1-S1-A2", 5, 5);
+        let station = waypoint("X1-S1-B1", 50, 0);
+        let sites = probe_sites(&[&planet, &station, &moon]);
+        assert_eq!(sites.len(), 2);
+        assert_eq!(
+            sites[0].waypoints,
+            vec![planet.symbol.clone(), moon.symbol.clone()]
+        );
+        assert_eq!(sites[1], site("X1-S1-B1", 50, 0));
+    }
+}
@@ -1,11 +1,20 @@
 use crate::agent_controller::AgentContext;
 use crate::api_client::api_models::{
-    ExtractResponse, JettisonResponse, NavigateResponse, OrbitResponse, RefuelResponse,
-    SiphonResponse, SurveyResponse, TradeResponse, WaypointDetailed, WaypointScanResponse,
+    ExtractResponse, JettisonResponse, MountResponse, NavigateResponse, OrbitResponse,
+    RefuelResponse, RepairQuote, RepairResponse, ScannedShip, ScannedSystem, ShipScanResponse,
+    SiphonResponse, SurveyResponse, SystemScanResponse, TradeResponse, WaypointDetailed,
+    WaypointScanResponse,
 };
+use crate::api_client::{ApiError, Priority};
+use crate::config::{CONFIG, FuelMargin};
+use crate::mining_stats::MiningEvent;
 use crate::models::*;
 use crate::models::{ShipCargoItem, ShipCooldown};
+use crate::nav_calibration::{NAV_CALIBRATION, NAV_CALIBRATION_KEY};
+use crate::pathfinding::model_travel_duration;
 use crate::ship_controller::ShipNavStatus::*;
+use crate::universe::pathfinding::{EdgeType, WarpEdge, plan_travel};
+use crate::universe::{MarketDataError, WaypointFilter};
 use chrono::{DateTime, Duration, Utc};
 use log::*;
 use reqwest::{Method, StatusCode};
@@ -14,6 +23,138 @@ use serde_json::{Value, json};
 use std::cmp::min;
 use std::sync::{Arc, Mutex};
 
+// If a hop would leave less than this much fuel in the tank (by our cached fuel value),
+// confirm the real fuel level with the API before committing to it.
+const PREFLIGHT_FUEL_MARGIN: i64 = 5;
+
+// How often goto_waypoint may re-plan from its current position on a fuel mismatch
+// before giving up.
+const MAX_FUEL_REPLANS: usize = 3;
+
+// goto_waypoint's wait before trying again after a fuel failure, doubling per attempt
+// up to the cap
+const NAV_RETRY_BASE_SECONDS: i64 = 30;
+const NAV_RETRY_MAX_SECONDS: i64 = 600;
+
+// "Navigate request failed. Ship requires X more fuel for navigation."
+const INSUFFICIENT_FUEL_ERROR_CODE: i64 = 4203;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NavigateError {
+    InsufficientFuel,
+    // No route at our fuel, even after topping up / re-reading the tank
+    NoRoute,
+    // The tank kept disagreeing with the plan, MAX_FUEL_REPLANS times over
+    FuelDiverged,
+    // Any other 4xx: the server won't take us there (e.g. the waypoint doesn't exist)
+    Rejected(u16),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadCargoError {
+    Market(MarketDataError),
+    // The market here doesn't list the good
+    NotSold,
+}
+
+impl From<MarketDataError> for LoadCargoError {
+    fn from(e: MarketDataError) -> Self {
+        LoadCargoError::Market(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HopFuel {
+    Proceed,
+    Refuel,
+    // not enough fuel and nowhere to buy it: plan again from here with the real fuel
+    Replan,
+}
+
+fn hop_fuel_action(current_fuel: i64, required_fuel: i64, at_market: bool) -> HopFuel {
+    if current_fuel >= required_fuel {
+        HopFuel::Proceed
+    } else if at_market {
+        HopFuel::Refuel
+    } else {
+        HopFuel::Replan
+    }
+}
+
+// The fuel to leave a hop with: at a market, the margin on top of the hop's need (as
+// far as the tank allows); elsewhere there's nothing to buy, so just the need
+fn departure_fuel(required_fuel: i64, at_market: bool, capacity: i64, margin: FuelMargin) -> i64 {
+    match at_market {
+        true => (required_fuel + margin.units(capacity))
+            .min(capacity)
+            .max(required_fuel),
+        false => required_fuel,
+    }
+}
+
+fn needs_fuel_confirmation(current_fuel: i64, required_fuel: i64) -> bool {
+    current_fuel - required_fuel < PREFLIGHT_FUEL_MARGIN
+}
+
+// A cruise warp burns fuel equal to the distance, a burn warp twice that at half the
+// duration. Burn only if it still leaves `reserve` fuel for the leg after.
+fn warp_flight_mode(current_fuel: i64, distance: i64, reserve: i64) -> Option<ShipFlightMode> {
+    if current_fuel - 2 * distance >= reserve {
+        Some(ShipFlightMode::Burn)
+    } else if current_fuel >= distance {
+        Some(ShipFlightMode::Cruise)
+    } else {
+        None
+    }
+}
+
+fn is_transient_nav_error(e: &NavigateError) -> bool {
+    match e {
+        NavigateError::InsufficientFuel | NavigateError::NoRoute | NavigateError::FuelDiverged => {
+            true
+        }
+        NavigateError::Rejected(_) => false,
+    }
+}
+
+fn nav_retry_wait(attempt: u32) -> Duration {
+    let seconds = NAV_RETRY_BASE_SECONDS.saturating_mul(1 << (attempt - 1).min(16));
+    Duration::seconds(seconds.min(NAV_RETRY_MAX_SECONDS))
+}
+
+fn is_insufficient_fuel_error(body: &str) -> bool {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| v["error"]["code"].as_i64())
+        == Some(INSUFFICIENT_FUEL_ERROR_CODE)
+}
+
+// "Ship survey failed. Target signature is no longer in range or valid." and
+// "Survey has been exhausted": either way the survey is no use any more
+const SURVEY_INVALID_ERROR_CODE: i64 = 4221;
+const SURVEY_EXHAUSTED_ERROR_CODE: i64 = 4224;
+
+// "Ship action is still on cooldown for N second(s)." (409), with the cooldown in data
+const COOLDOWN_ERROR_CODE: i64 = 4000;
+
+fn cooldown_from_error(e: &ApiError) -> Option<ShipCooldown> {
+    match e {
+        ApiError::ApiErrorCode {
+            code: COOLDOWN_ERROR_CODE,
+            data: Some(data),
+            ..
+        } => serde_json::from_value(data.get("cooldown")?.clone()).ok(),
+        _ => None,
+    }
+}
+
+fn is_spent_survey_error(e: &ApiError) -> bool {
+    matches!(
+        e.code(),
+        Some(SURVEY_INVALID_ERROR_CODE | SURVEY_EXHAUSTED_ERROR_CODE)
+    )
+}
+
 #[derive(Clone)]
 pub struct ShipController {
     pub ship_symbol: String,
@@ -92,6 +233,26 @@ impl ShipController {
         let mut ship = self.ship.lock().unwrap();
         ship.cargo = cargo;
     }
+    pub fn update_mounts(&self, mounts: Vec<ShipMount>) {
+        let mut ship = self.ship.lock().unwrap();
+        ship.mounts = mounts;
+    }
+    // Lowest of the engine, frame and reactor condition (1.0 is pristine)
+    pub fn worst_condition(&self) -> f64 {
+        let ship = self.ship.lock().unwrap();
+        [
+            ship.engine.condition,
+            ship.frame.condition,
+            ship.reactor.condition,
+        ]
+        .into_iter()
+        .flatten()
+        .fold(1.0, f64::min)
+    }
+    pub fn has_mount(&self, mount_symbol: &str) -> bool {
+        let ship = self.ship.lock().unwrap();
+        ship.mounts.iter().any(|m| m.symbol == mount_symbol)
+    }
     pub fn update_cooldown(&self, cooldown: ShipCooldown) {
         let mut ship = self.ship.lock().unwrap();
         ship.cooldown = cooldown;
@@ -176,25 +337,18 @@ impl ShipController {
 
     pub fn is_in_transit(&self) -> bool {
         let arrival_time = self.ship.lock().unwrap().nav.route.arrival;
-        let now = chrono::Utc::now();
-        arrival_time >= now
+        arrival_time >= self.ctx.clock.now()
     }
 
     async fn wait_until_timestamp(&self, timestamp: DateTime<Utc>, event: &str) {
-        // Multiple sleep calls are necessary due to WSL2 tendency to 'skip time'
-        loop {
-            let now = Utc::now();
-            let wait_time = timestamp - now;
-            if wait_time > chrono::Duration::zero() {
-                debug!(
-                    "Waiting for {}: {:.3}s",
-                    event,
-                    wait_time.num_milliseconds() as f64 / 1000.0
-                );
-            } else {
-                break;
-            }
-            tokio::time::sleep(wait_time.to_std().unwrap()).await;
+        let wait_time = timestamp - self.ctx.clock.now();
+        if wait_time > chrono::Duration::zero() {
+            debug!(
+                "Waiting for {}: {:.3}s",
+                event,
+                wait_time.num_milliseconds() as f64 / 1000.0
+            );
+            self.ctx.clock.sleep_until(timestamp).await;
         }
     }
 
@@ -239,6 +393,7 @@ impl ShipController {
         } = self
             .ctx
             .api_client
+            .with_priority(Priority::High)
             .post::<Data<TradeResponse>, _>(&uri, &body)
             .await
             .data;
@@ -291,6 +446,14 @@ impl ShipController {
                     realized_profit: Some(realized),
                 })
                 .await;
+            self.ctx.mining_stats.record(
+                &self.ship_symbol,
+                MiningEvent::Sold {
+                    good: transaction.trade_symbol.clone(),
+                    units: transaction.units,
+                    credits: transaction.total_price,
+                },
+            );
         }
         self.debug(&format!(
             "{} {} {} for ${} (total ${})",
@@ -312,23 +475,108 @@ impl ShipController {
             .await;
     }
 
-    pub async fn sell_all_cargo(&self) {
-        self.refresh_market().await;
-        let market = self.ctx.universe.get_market(&self.waypoint()).unwrap();
-        while let Some(cargo_item) = self.cargo_first_item() {
-            let market_good = market
-                .data
-                .trade_goods
-                .iter()
-                .find(|g| g.symbol == cargo_item.symbol)
-                .unwrap();
-            let units = min(market_good.trade_volume, cargo_item.units);
-            assert!(units > 0);
-            self.sell_goods(&cargo_item.symbol, units, false).await;
-            let new_units = self.cargo_good_count(&cargo_item.symbol);
-            assert!(new_units == cargo_item.units - units);
+    // The market at the ship's current waypoint, or None if there's no market here. A
+    // market we've never read is refreshed on the spot; stale data is used as is.
+    pub async fn current_market(&self) -> Option<Arc<WithTimestamp<Market>>> {
+        let waypoint = self.waypoint();
+        match self.ctx.universe.require_market(&waypoint).await {
+            Ok(market) => Some(market),
+            Err(MarketDataError::NeverProbed) => {
+                self.refresh_market().await;
+                Some(self.ctx.universe.get_market(&waypoint).unwrap())
+            }
+            Err(MarketDataError::Stale(age)) => {
+                self.debug(&format!(
+                    "Using {}m old market data at {}",
+                    age.num_minutes(),
+                    waypoint
+                ));
+                Some(self.ctx.universe.get_market(&waypoint).unwrap())
+            }
+            Err(MarketDataError::NotAMarket) => None,
         }
-        self.refresh_market().await;
+    }
+
+    // Offload `units` of each of `goods`: sold here if this market buys the good,
+    // otherwise (or for whatever this market won't absorb) carried to the best-paying
+    // in-system buyer and sold there. Returns what's still aboard because nobody would
+    // take it, for the caller to carry on with or jettison.
+    pub async fn sell_or_carry(&self, goods: &[(String, i64)]) -> Vec<(String, i64)> {
+        let mut unsold = vec![];
+        for (good, units) in goods {
+            let held = self.cargo_good_count(good);
+            let target = held - min(*units, held);
+            self.sell_down_to(good, target).await;
+            if self.cargo_good_count(good) > target {
+                let here = self.waypoint();
+                let buyers = self
+                    .buyers(good)
+                    .await
+                    .into_iter()
+                    .filter(|(market, _)| *market != here)
+                    .collect::<Vec<_>>();
+                if let Some(market) = best_buyer(&buyers) {
+                    self.goto_waypoint(market).await;
+                    self.refresh_market().await;
+                    self.sell_down_to(good, target).await;
+                }
+            }
+            let remaining = self.cargo_good_count(good) - target;
+            if remaining > 0 {
+                warn!(
+                    "{}: couldn't offload {} {} (at {})",
+                    self.ship_symbol,
+                    remaining,
+                    good,
+                    self.waypoint()
+                );
+                unsold.push((good.clone(), remaining));
+            }
+        }
+        unsold
+    }
+
+    // Sell `good` at the current market, in trade-volume lots, until only `target` units
+    // are held or the market stops buying it
+    pub async fn sell_down_to(&self, good: &str, target: i64) {
+        while self.cargo_good_count(good) > target {
+            let Some(market) = self.current_market().await else {
+                break;
+            };
+            let Some(trade) = market.data.trade_goods.iter().find(|g| g.symbol == good) else {
+                break;
+            };
+            let units = min(trade.trade_volume, self.cargo_good_count(good) - target);
+            self.sell_goods(good, units, true).await;
+            self.refresh_market().await;
+        }
+    }
+
+    // The in-system markets that import or exchange `good`, with their sell price if we
+    // have one
+    pub async fn buyers(&self, good: &str) -> Vec<(WaypointSymbol, Option<i64>)> {
+        let system = self.system();
+        let mut buyers = Vec::new();
+        for filter in [
+            WaypointFilter::Imports(good.to_string()),
+            WaypointFilter::Exchanges(good.to_string()),
+        ] {
+            for waypoint in self.ctx.universe.search_waypoints(&system, &[filter]).await {
+                let price = self
+                    .ctx
+                    .universe
+                    .get_market(&waypoint.symbol)
+                    .and_then(|m| {
+                        m.data
+                            .trade_goods
+                            .iter()
+                            .find(|g| g.symbol == good)
+                            .map(|g| g.sell_price)
+                    });
+                buyers.push((waypoint.symbol, price));
+            }
+        }
+        buyers
     }
 
     pub async fn jettison_cargo(&self, good: &str, units: i64) {
@@ -346,6 +594,13 @@ impl ShipController {
             .await
             .data;
         self.update_cargo(cargo);
+        self.ctx.mining_stats.record(
+            &self.ship_symbol,
+            MiningEvent::Jettisoned {
+                good: good.to_string(),
+                units,
+            },
+        );
     }
 
     // Fuel is bought in multiples of 100, so refuel as the highest multiple of 100
@@ -442,39 +697,129 @@ impl ShipController {
         self.ctx.update_agent(agent);
     }
 
-    pub async fn full_load_cargo(&self, good: &str) {
+    // Fill the hold with `good` from the market here. Nothing is bought if the market
+    // doesn't sell it; the caller decides whether the task can go on without it.
+    pub async fn full_load_cargo(&self, good: &str) -> Result<(), LoadCargoError> {
         let cargo_units = self.cargo_good_count(good);
         assert_eq!(cargo_units, self.cargo_units());
 
-        let buy_units = self.cargo_capacity() - cargo_units;
-        if buy_units > 0 {
-            // Makes assumptions about the TV of the good
-            self.buy_goods(good, buy_units, false).await;
+        let waypoint = self.waypoint();
+        let mut buy_units = self.cargo_capacity() - cargo_units;
+        while buy_units > 0 {
+            let market = match self.ctx.universe.require_market(&waypoint).await {
+                Ok(market) => market,
+                // we're parked here, so read it rather than trade blind
+                Err(MarketDataError::NeverProbed | MarketDataError::Stale(_)) => {
+                    self.refresh_market().await;
+                    self.ctx.universe.require_market(&waypoint).await?
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let trade_volume = market
+                .data
+                .trade_goods
+                .iter()
+                .find(|g| g.symbol == good)
+                .map(|g| g.trade_volume)
+                .ok_or(LoadCargoError::NotSold)?;
+            let units = min(trade_volume, buy_units);
+            self.buy_goods(good, units, false).await;
             self.refresh_market().await;
+            buy_units -= units;
         }
+        Ok(())
     }
 
-    async fn navigate(&self, flight_mode: ShipFlightMode, waypoint: &WaypointSymbol) {
+    async fn navigate(
+        &self,
+        flight_mode: ShipFlightMode,
+        waypoint: &WaypointSymbol,
+    ) -> Result<(), NavigateError> {
         assert!(!self.is_in_transit(), "Ship is already in transit");
         if self.waypoint() == *waypoint {
-            return;
+            return Ok(());
         }
         assert_eq!(self.waypoint().system(), waypoint.system());
         self.set_flight_mode(flight_mode).await;
         self.orbit().await;
         self.debug(&format!("Navigating to waypoint: {}", waypoint));
         let uri = format!("/my/ships/{}/navigate", self.ship_symbol);
-        let NavigateResponse { nav, fuel, events } = self
+        let req_body = json!({ "waypointSymbol": waypoint });
+        let (code, resp_body) = self
             .ctx
             .api_client
-            .post::<Data<NavigateResponse>, _>(&uri, &json!({ "waypointSymbol": waypoint }))
-            .await
-            .data;
+            .with_priority(Priority::High)
+            .request_string(Method::POST, &uri, Some(&req_body))
+            .await;
+        let NavigateResponse { nav, fuel, events } = match code {
+            StatusCode::OK => {
+                serde_json::from_str::<Data<NavigateResponse>>(&resp_body.unwrap())
+                    .unwrap()
+                    .data
+            }
+            StatusCode::BAD_REQUEST
+                if resp_body
+                    .as_ref()
+                    .is_err_and(|body| is_insufficient_fuel_error(body)) =>
+            {
+                self.debug(&format!(
+                    "Navigation to {} failed: insufficient fuel",
+                    waypoint
+                ));
+                return Err(NavigateError::InsufficientFuel);
+            }
+            code if code.is_client_error() => {
+                warn!(
+                    "{}: navigation to {} rejected: {} {:?}",
+                    self.ship_symbol,
+                    waypoint,
+                    code.as_u16(),
+                    resp_body
+                );
+                return Err(NavigateError::Rejected(code.as_u16()));
+            }
+            _ => panic!(
+                "Request failed: {} {} {}\nbody: {:?}",
+                code.as_u16(),
+                Method::POST,
+                uri,
+                resp_body
+            ),
+        };
         self.handle_ship_condition_events(&events);
+        self.calibrate_travel_time(&nav).await;
         self.update_nav(nav);
         self.update_fuel(fuel);
         self.wait_for_transit().await;
         self.update_nav_status(InOrbit);
+        Ok(())
+    }
+
+    async fn calibrate_travel_time(&self, nav: &ShipNav) {
+        let route = &nav.route;
+        let distance2 = (route.origin.x - route.destination.x).pow(2)
+            + (route.origin.y - route.destination.y).pow(2);
+        let distance = std::cmp::max(1, (distance2 as f64).sqrt().round() as i64);
+        let model = model_travel_duration(&nav.flight_mode, self.engine_speed(), distance);
+        let actual = (route.arrival - route.departure_time).num_seconds();
+        if let Some(state) = NAV_CALIBRATION.observe(&nav.flight_mode, model, actual) {
+            self.ctx.db.set_value(NAV_CALIBRATION_KEY, &state).await;
+        }
+    }
+
+    // Re-read the ship's fuel from the API, in case the cached value has drifted (a
+    // refuel that silently didn't happen, a condition event, ...)
+    pub async fn refresh_fuel(&self) {
+        let ship = self.ctx.api_client.get_ship(&self.ship_symbol).await;
+        if ship.fuel.current != self.current_fuel() {
+            warn!(
+                "{}: cached fuel {} differs from actual {}",
+                self.ship_symbol,
+                self.current_fuel(),
+                ship.fuel.current
+            );
+        }
+        self.update_fuel(ship.fuel);
     }
 
     pub async fn warp(&self, flight_mode: ShipFlightMode, waypoint: &WaypointSymbol) {
@@ -490,6 +835,7 @@ impl ShipController {
         let NavigateResponse { nav, fuel, events } = self
             .ctx
             .api_client
+            .with_priority(Priority::High)
             .post::<Data<NavigateResponse>, _>(&uri, &json!({ "waypointSymbol": waypoint }))
             .await
             .data;
@@ -523,6 +869,7 @@ impl ShipController {
         } = self
             .ctx
             .api_client
+            .with_priority(Priority::High)
             .post::<Data<JumpResponse>, _>(&uri, &body)
             .await
             .data;
@@ -548,41 +895,262 @@ impl ShipController {
         }
     }
 
+    // A fuel failure is usually transient (a stale tank reading, a market out of fuel),
+    // so wait it out and go again rather than take the ship's task down. A refusal from
+    // the server won't change, and still panics.
     pub async fn goto_waypoint(&self, target: &WaypointSymbol) {
+        let mut attempt = 1;
+        loop {
+            match self.try_goto_waypoint(target).await {
+                Ok(()) => return,
+                Err(e) if is_transient_nav_error(&e) => {
+                    let wait = nav_retry_wait(attempt);
+                    warn!(
+                        "{}: navigation to {} failed: {:?} (attempt {}), retrying in {}s",
+                        self.ship_symbol,
+                        target,
+                        e,
+                        attempt,
+                        wait.num_seconds()
+                    );
+                    self.ctx.clock.sleep(wait).await;
+                    attempt += 1;
+                }
+                Err(e) => panic!(
+                    "{}: navigation to {} failed: {:?}",
+                    self.ship_symbol, target, e
+                ),
+            }
+        }
+    }
+
+    // goto_waypoint for callers that can do something else when the server refuses the
+    // trip. Fuel shortfalls are still handled (re-planned) here.
+    pub async fn try_goto_waypoint(&self, target: &WaypointSymbol) -> Result<(), NavigateError> {
         assert!(!self.is_in_transit(), "Ship is already in transit");
         if self.fuel_capacity() == 0 {
-            self.navigate(ShipFlightMode::Cruise, target).await;
+            let result = self.navigate(ShipFlightMode::Cruise, target).await;
+            assert_ne!(
+                result,
+                Err(NavigateError::InsufficientFuel),
+                "Ship without a fuel tank can't run out of fuel"
+            );
+            result?;
             self.debug(&format!("Arrived at waypoint: {}", target));
-            return;
+            return Ok(());
         }
-        if self.waypoint() == *target {
-            return;
+        let mut replans = 0;
+        'replan: while self.waypoint() != *target {
+            if replans > MAX_FUEL_REPLANS {
+                error!(
+                    "{}: fuel kept diverging from the plan en route to {}",
+                    self.ship_symbol, target
+                );
+                return Err(NavigateError::FuelDiverged);
+            }
+            replans += 1;
+            let route = self
+                .ctx
+                .universe
+                .get_route(
+                    &self.waypoint(),
+                    target,
+                    self.engine_speed(),
+                    self.current_fuel(),
+                    self.fuel_capacity(),
+                )
+                .await;
+            let Some(route) = route else {
+                // Most likely a transient fuel state: fill up if we can buy fuel here,
+                // else re-read the tank in case our value is stale, and plan again
+                if replans > MAX_FUEL_REPLANS {
+                    error!(
+                        "{}: no route to {} with {}/{} fuel",
+                        self.ship_symbol,
+                        target,
+                        self.current_fuel(),
+                        self.fuel_capacity()
+                    );
+                    return Err(NavigateError::NoRoute);
+                }
+                warn!(
+                    "{}: no route to {} with {} fuel, refuelling and re-planning",
+                    self.ship_symbol,
+                    target,
+                    self.current_fuel()
+                );
+                let here = self.ctx.universe.detailed_waypoint(&self.waypoint()).await;
+                match here.is_market() {
+                    true => self.refuel(self.fuel_capacity(), false).await,
+                    false => self.refresh_fuel().await,
+                }
+                continue 'replan;
+            };
+            for (waypoint, edge, a_market, b_market) in route.hops {
+                // calculate fuel required before leaving
+                let required_fuel = if b_market {
+                    edge.fuel_cost
+                } else {
+                    assert!(waypoint == *target);
+                    edge.fuel_cost + route.req_terminal_fuel
+                };
+                // Cutting it fine on a possibly stale fuel value: confirm first. At a
+                // market a shortfall just means buying a bit more, so only bother when
+                // there's nowhere to refuel.
+                if !a_market && needs_fuel_confirmation(self.current_fuel(), required_fuel) {
+                    self.refresh_fuel().await;
+                }
+                let departure_fuel = departure_fuel(
+                    required_fuel,
+                    a_market,
+                    self.fuel_capacity(),
+                    CONFIG.nav_fuel_margin,
+                );
+                match hop_fuel_action(self.current_fuel(), departure_fuel, a_market) {
+                    HopFuel::Proceed => {}
+                    HopFuel::Refuel => self.refuel(departure_fuel, false).await,
+                    HopFuel::Replan => {
+                        warn!(
+                            "{}: {} fuel is short of the {} needed to reach {}, re-planning",
+                            self.ship_symbol,
+                            self.current_fuel(),
+                            required_fuel,
+                            waypoint
+                        );
+                        continue 'replan;
+                    }
+                }
+                match self.navigate(edge.flight_mode, &waypoint).await {
+                    Ok(()) => {}
+                    Err(NavigateError::InsufficientFuel) => {
+                        self.refresh_fuel().await;
+                        continue 'replan;
+                    }
+                    Err(e) => return Err(e),
+                }
+                self.debug(&format!("Arrived at waypoint: {}", waypoint));
+            }
         }
-        let route = self
-            .ctx
-            .universe
-            .get_route(
-                &self.waypoint(),
-                target,
-                self.engine_speed(),
-                self.current_fuel(),
-                self.fuel_capacity(),
-            )
-            .await;
-        for (waypoint, edge, a_market, b_market) in route.hops {
-            // calculate fuel required before leaving
-            let required_fuel = if b_market {
-                edge.fuel_cost
-            } else {
-                assert!(waypoint == *target);
-                edge.fuel_cost + route.req_terminal_fuel
+        Ok(())
+    }
+
+    pub async fn travel_to(&self, target: &WaypointSymbol) {
+        if let Err(e) = self.try_travel_to(target).await {
+            panic!("{}: travel to {} failed: {:?}", self.ship_symbol, target, e);
+        }
+    }
+
+    // goto_waypoint for a target in any system: jumps where the gates are connected,
+    // warps over the ship's own range otherwise, then the intra-system legs at the far end
+    pub async fn try_travel_to(&self, target: &WaypointSymbol) -> Result<(), NavigateError> {
+        assert!(!self.is_in_transit(), "Ship is already in transit");
+        let dest = target.system();
+        if self.system() != dest {
+            let graph = self
+                .ctx
+                .universe
+                .ship_warp_jump_graph(self.fuel_capacity(), self.engine_speed())
+                .await;
+            let Some((path, duration)) = plan_travel(&graph, &self.system(), &dest) else {
+                error!(
+                    "{}: no warp/jump route from {} to {}",
+                    self.ship_symbol,
+                    self.system(),
+                    dest
+                );
+                return Err(NavigateError::NoRoute);
             };
+            self.debug(&format!(
+                "Travelling to {} in {}s via {}",
+                target,
+                duration,
+                path.iter()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            ));
+            for (i, pair) in path.windows(2).enumerate() {
+                let (s, t) = (&pair[0], &pair[1]);
+                let edge = &graph[s][t];
+                let next_edge = path.get(i + 2).map(|u| &graph[t][u]);
+                match edge.edge_type {
+                    EdgeType::Jumpgate => {
+                        let src_gate = self.ctx.universe.get_jumpgate(s).await;
+                        let dst_gate = self.ctx.universe.get_jumpgate(t).await;
+                        self.try_goto_waypoint(&src_gate).await?;
+                        // waits out the cooldown from any previous jump
+                        self.jump(&dst_gate).await;
+                    }
+                    EdgeType::Warp => {
+                        self.fuel_for_warp(edge.fuel).await?;
+                        // keep enough for a following warp, there may be no fuel there
+                        let reserve = match next_edge {
+                            Some(WarpEdge {
+                                edge_type: EdgeType::Warp,
+                                fuel,
+                                ..
+                            }) => *fuel,
+                            _ => 0,
+                        };
+                        let Some(flight_mode) =
+                            warp_flight_mode(self.current_fuel(), edge.fuel, reserve)
+                        else {
+                            error!(
+                                "{}: {} fuel can't warp {} to {}",
+                                self.ship_symbol,
+                                self.current_fuel(),
+                                edge.fuel,
+                                t
+                            );
+                            return Err(NavigateError::InsufficientFuel);
+                        };
+                        let arrival = match next_edge {
+                            None => target.clone(),
+                            Some(next) => self.warp_arrival(t, next).await,
+                        };
+                        self.warp(flight_mode, &arrival).await;
+                    }
+                }
+            }
+        }
+        self.try_goto_waypoint(target).await
+    }
+
+    // Top up for a warp needing `required_fuel`: to a full tank at a market, else from
+    // fuel cargo, else at the nearest market in the system
+    async fn fuel_for_warp(&self, required_fuel: i64) -> Result<(), NavigateError> {
+        let here = self.ctx.universe.detailed_waypoint(&self.waypoint()).await;
+        if !here.is_market() && self.current_fuel() < required_fuel {
+            self.refuel(required_fuel, true).await;
             if self.current_fuel() < required_fuel {
-                assert!(a_market);
-                self.refuel(required_fuel, false).await;
+                let waypoints = self.ctx.universe.get_system_waypoints(&self.system()).await;
+                let market = waypoints
+                    .iter()
+                    .filter(|w| w.is_market())
+                    .min_by_key(|w| (w.x - here.x).pow(2) + (w.y - here.y).pow(2));
+                if let Some(market) = market {
+                    self.try_goto_waypoint(&market.symbol).await?;
+                }
             }
-            self.navigate(edge.flight_mode, &waypoint).await;
-            self.debug(&format!("Arrived at waypoint: {}", waypoint));
+        }
+        let here = self.ctx.universe.detailed_waypoint(&self.waypoint()).await;
+        if here.is_market() {
+            self.refuel(self.fuel_capacity(), false).await;
+        }
+        Ok(())
+    }
+
+    // Where to land in `system` ahead of the `next` leg out of it: its gate for a jump,
+    // a market (to refuel) for a warp
+    async fn warp_arrival(&self, system: &SystemSymbol, next: &WarpEdge) -> WaypointSymbol {
+        let waypoints = self.ctx.universe.get_system_waypoints(system).await;
+        let preferred = waypoints.iter().find(|w| match next.edge_type {
+            EdgeType::Jumpgate => w.is_jump_gate(),
+            EdgeType::Warp => w.is_market(),
+        });
+        match preferred {
+            Some(waypoint) => waypoint.symbol.clone(),
+            None => self.ctx.universe.first_waypoint(system).await,
         }
     }
 
@@ -679,7 +1247,12 @@ impl ShipController {
         }
         self.debug(&format!("Refreshing market at waypoint {}", &waypoint));
         let uri = format!("/systems/{}/waypoints/{}/market", &system, &waypoint);
-        let response: Data<Market> = self.ctx.api_client.get(&uri).await;
+        let response: Data<Market> = self
+            .ctx
+            .api_client
+            .with_priority(Priority::Low)
+            .get(&uri)
+            .await;
         let market = WithTimestamp::<Market> {
             timestamp: chrono::Utc::now(),
             data: response.data,
@@ -698,7 +1271,12 @@ impl ShipController {
         let system = self.system();
         self.debug(&format!("Refreshing shipyard at waypoint {}", &waypoint));
         let uri = format!("/systems/{}/waypoints/{}/shipyard", &system, &waypoint);
-        let response: Data<Shipyard> = self.ctx.api_client.get(&uri).await;
+        let response: Data<Shipyard> = self
+            .ctx
+            .api_client
+            .with_priority(Priority::Low)
+            .get(&uri)
+            .await;
         let shipyard = WithTimestamp::<Shipyard> {
             timestamp: chrono::Utc::now(),
             data: response.data,
@@ -753,25 +1331,64 @@ impl ShipController {
     // Sensor-array waypoint scan: reveals nearby waypoints' traits (markets/shipyards),
     // bypassing their uncharted state. Requires a MOUNT_SENSOR_ARRAY; triggers a cooldown.
     // Ingests the revealed traits into the universe so the agent learns the markets.
+    // POST a cooldown-gated action (the scans) once the ship's cooldown is up. Our
+    // cached cooldown can be behind the server's (another action, a restart), so a 409
+    // "still on cooldown" takes the server's cooldown and waits again.
+    async fn post_after_cooldown<T>(&self, uri: &str) -> T
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        loop {
+            self.wait_for_cooldown().await;
+            match self
+                .ctx
+                .api_client
+                .try_post::<Data<T>, _>(uri, &json!({}))
+                .await
+            {
+                Ok(response) => return response.data,
+                Err(e) => match cooldown_from_error(&e) {
+                    Some(cooldown) => {
+                        self.debug(&format!("Still on cooldown: {}", e));
+                        self.update_cooldown(cooldown);
+                    }
+                    None => panic!("Request failed: {} {}: {}", Method::POST, uri, e),
+                },
+            }
+        }
+    }
+
     pub async fn scan_waypoints(&self) -> Vec<WaypointDetailed> {
         assert!(!self.is_in_transit());
-        self.wait_for_cooldown().await;
         self.debug(&format!("Scanning waypoints from {}", self.waypoint()));
         let uri = format!("/my/ships/{}/scan/waypoints", self.ship_symbol);
         let WaypointScanResponse {
             cooldown,
             waypoints,
-        } = self
-            .ctx
-            .api_client
-            .post::<Data<WaypointScanResponse>, _>(&uri, &json!({}))
-            .await
-            .data;
+        } = self.post_after_cooldown(&uri).await;
         self.update_cooldown(cooldown);
         self.ctx.universe.ingest_scanned_waypoints(&waypoints).await;
         waypoints
     }
 
+    pub async fn scan_ships(&self) -> Vec<ScannedShip> {
+        assert!(!self.is_in_transit());
+        self.debug(&format!("Scanning ships from {}", self.waypoint()));
+        let uri = format!("/my/ships/{}/scan/ships", self.ship_symbol);
+        let ShipScanResponse { cooldown, ships } = self.post_after_cooldown(&uri).await;
+        self.update_cooldown(cooldown);
+        ships
+    }
+
+    pub async fn scan_systems(&self) -> Vec<ScannedSystem> {
+        assert!(!self.is_in_transit());
+        self.debug(&format!("Scanning systems from {}", self.system()));
+        let uri = format!("/my/ships/{}/scan/systems", self.ship_symbol);
+        let SystemScanResponse { cooldown, systems } = self.post_after_cooldown(&uri).await;
+        self.update_cooldown(cooldown);
+        systems
+    }
+
     pub async fn transfer_cargo(&self) {
         assert!(!self.is_in_transit(), "Ship is in transit");
         self.orbit().await;
@@ -823,6 +1440,9 @@ impl ShipController {
         self.debug(&format!("Siphoned {} units of {}", units, good));
         self.update_cooldown(cooldown);
         self.update_cargo(cargo);
+        self.ctx
+            .mining_stats
+            .record(&self.ship_symbol, MiningEvent::Extracted { good, units });
     }
 
     pub async fn extract_survey(&self, survey: &KeyedSurvey) {
@@ -833,22 +1453,21 @@ impl ShipController {
         let uri = format!("/my/ships/{}/extract/survey", self.ship_symbol);
         let req_body = &survey.survey;
 
-        let (code, resp_body): (StatusCode, Result<String, String>) = self
+        let result = self
             .ctx
             .api_client
-            .request_string(Method::POST, &uri, Some(req_body))
+            .try_post::<Data<ExtractResponse>, _>(&uri, req_body)
             .await;
-        match code {
-            StatusCode::CREATED => {
-                let response: String = resp_body.unwrap();
-                let ExtractResponse {
-                    cargo,
-                    cooldown,
-                    extraction,
-                    events,
-                } = serde_json::from_str::<Data<ExtractResponse>>(&response)
-                    .unwrap()
-                    .data;
+        match result {
+            Ok(Data {
+                data:
+                    ExtractResponse {
+                        cargo,
+                        cooldown,
+                        extraction,
+                        events,
+                    },
+            }) => {
                 self.handle_ship_condition_events(&events);
                 self.debug(&format!(
                     "Extracted {} units of {}",
@@ -856,37 +1475,20 @@ impl ShipController {
                 ));
                 self.update_cooldown(cooldown);
                 self.update_cargo(cargo);
+                self.ctx.mining_stats.record(
+                    &self.ship_symbol,
+                    MiningEvent::Extracted {
+                        good: extraction._yield.symbol,
+                        units: extraction._yield.units,
+                    },
+                );
             }
-            StatusCode::BAD_REQUEST | StatusCode::CONFLICT => {
-                let response: Value = serde_json::from_str(&resp_body.unwrap_err()).unwrap();
-                // variety of responses we might get here: exhausted, expired, asteroid overmined
-                let code = response["error"]["code"].as_i64().unwrap();
-                if code == 4221 {
-                    // Request failed: 400 {"error":{"message":"Ship survey failed. Target signature is no longer in range or valid.","code":4221}}
-                    self.debug(
-                        "Extraction failed: Target signature is no longer in range or valid",
-                    );
-                    self.ctx.survey_manager.remove_survey(survey).await;
-                } else if code == 4224 {
-                    self.debug("Extraction failed: Survey has been exhausted");
-                    self.ctx.survey_manager.remove_survey(survey).await;
-                } else {
-                    panic!(
-                        "Request failed: {} {} {}\nbody: {:?}",
-                        code,
-                        Method::POST,
-                        uri,
-                        response
-                    );
-                }
+            // variety of responses we might get here: exhausted, expired, asteroid overmined
+            Err(e) if is_spent_survey_error(&e) => {
+                self.debug(&format!("Extraction failed: {}", e));
+                self.ctx.survey_manager.remove_survey(survey).await;
             }
-            _ => panic!(
-                "Request failed: {} {} {}\nbody: {:?}",
-                code.as_u16(),
-                Method::POST,
-                uri,
-                resp_body
-            ),
+            Err(e) => panic!("Request failed: {} {}: {}", Method::POST, uri, e),
         };
     }
 
@@ -927,6 +1529,120 @@ impl ShipController {
         self.ctx.update_agent(agent);
     }
 
+    // What the shipyard here would charge to repair the ship
+    pub async fn get_repair_cost(&self) -> i64 {
+        let uri = format!("/my/ships/{}/repair", self.ship_symbol);
+        let quote: Data<RepairQuote> = self.ctx.api_client.get(&uri).await;
+        quote.data.transaction.total_price
+    }
+
+    // Restore the frame, reactor and engine condition at the shipyard here. Returns
+    // the price paid.
+    pub async fn repair(&self) -> i64 {
+        assert!(!self.is_in_transit(), "Ship is in transit");
+        self.dock().await;
+        self.debug("Repairing ship");
+        let uri = format!("/my/ships/{}/repair", self.ship_symbol);
+        let RepairResponse {
+            agent,
+            ship,
+            transaction,
+        } = self
+            .ctx
+            .api_client
+            .post::<Data<RepairResponse>, _>(&uri, &json!({}))
+            .await
+            .data;
+        *self.ship.lock().unwrap() = ship;
+        info!(
+            "{} Repaired ship for ${}",
+            self.ship_symbol, transaction.total_price
+        );
+        self.ctx
+            .db
+            .record_cash_txn(crate::database::CashTxn {
+                ts: transaction.timestamp,
+                type_: "repair",
+                ship_symbol: Some(&self.ship_symbol),
+                reference: None,
+                waypoint: Some(&transaction.waypoint_symbol.to_string()),
+                units: None,
+                amount: -transaction.total_price,
+                realized_profit: None,
+            })
+            .await;
+        self.ctx.update_agent(agent);
+        transaction.total_price
+    }
+
+    // Fit a mount from the ship's cargo. Returns the shipyard's fee.
+    pub async fn install_mount(&self, mount_symbol: &str) -> i64 {
+        assert!(
+            self.cargo_good_count(mount_symbol) > 0,
+            "Mount {} not in cargo",
+            mount_symbol
+        );
+        self.modify_mount("install", mount_symbol).await
+    }
+
+    // Take a mount off into the ship's cargo. Returns the shipyard's fee.
+    pub async fn remove_mount(&self, mount_symbol: &str) -> i64 {
+        assert!(
+            self.has_mount(mount_symbol),
+            "Mount {} not fitted",
+            mount_symbol
+        );
+        assert!(
+            self.cargo_units() < self.cargo_capacity(),
+            "No cargo space for the removed mount"
+        );
+        self.modify_mount("remove", mount_symbol).await
+    }
+
+    async fn modify_mount(&self, _type: &str, mount_symbol: &str) -> i64 {
+        assert!(!self.is_in_transit(), "Ship is in transit");
+        let waypoint = self.waypoint();
+        assert!(
+            self.ctx
+                .universe
+                .detailed_waypoint(&waypoint)
+                .await
+                .is_shipyard(),
+            "Mounts can only be changed at a shipyard"
+        );
+        self.dock().await;
+        self.debug(&format!("Mount {}: {}", _type, mount_symbol));
+        let uri = format!("/my/ships/{}/mounts/{}", self.ship_symbol, _type);
+        let MountResponse {
+            agent,
+            mounts,
+            cargo,
+            transaction,
+        } = self
+            .ctx
+            .api_client
+            .post::<Data<MountResponse>, _>(&uri, &json!({ "symbol": mount_symbol }))
+            .await
+            .data;
+        self.update_mounts(mounts);
+        self.update_cargo(cargo);
+        self.ctx
+            .db
+            .record_cash_txn(crate::database::CashTxn {
+                ts: transaction.timestamp,
+                type_: &format!("mount_{}", _type),
+                ship_symbol: Some(&self.ship_symbol),
+                reference: Some(&transaction.trade_symbol),
+                waypoint: Some(&waypoint.to_string()),
+                units: None,
+                amount: -transaction.total_price,
+                realized_profit: None,
+            })
+            .await;
+        self.ctx.update_agent(agent);
+        transaction.total_price
+    }
+
     pub fn handle_ship_condition_events(&self, events: &Vec<ShipConditionEvent>) {
         for e in events {
             self.debug(&format!(
@@ -940,3 +1656,279 @@ impl ShipController {
         self.ctx.set_state_description(&self.ship_symbol, desc);
     }
 }
+
+// Where to sell a good, given its `buyers` (with their sell price if known): the best
+// known price wins; with no prices at all, any buyer will do
+pub fn best_buyer(buyers: &[(WaypointSymbol, Option<i64>)]) -> Option<&WaypointSymbol> {
+    buyers
+        .iter()
+        .filter_map(|(market, price)| price.map(|p| (market, p)))
+        .max_by_key(|(_, price)| *price)
+        .map(|(market, _)| market)
+        .or(buyers.first().map(|(market, _)| market))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::pathfinding::Pathfinding;
+
+    fn wp(sym: &str, x: i64, market: bool) -> WaypointDetailed {
+        let symbol = WaypointSymbol::new(sym);
+        let traits = match market {
+            true => vec![SymbolNameDescr {
+                symbol: "MARKETPLACE".to_string(),
+                name: String::new(),
+                description: String::new(),
+            }],
+            false => vec![],
+        };
+        WaypointDetailed {
+            system_symbol: symbol.system(),
+            symbol,
+            waypoint_type: "PLANET".to_string(),
+            x,
+            y: 0,
+            orbitals: vec![],
+            orbits: None,
+            faction: None,
+            traits,
+            modifiers: vec![],
+            chart: None,
+            is_under_construction: false,
+        }
+    }
+
+    #[test]
+    fn test_insufficient_fuel_error() {
+        let body = r#"{"error":{"message":"Navigate request failed. Ship requires 12 more fuel for navigation.","code":4203,"data":{"shipSymbol":"BADGER-1","fuelRequired":112,"fuelAvailable":100}}}"#;
+        assert!(is_insufficient_fuel_error(body));
+        let body = r#"{"error":{"message":"Ship is currently in-transit","code":4214}}"#;
+        assert!(!is_insufficient_fuel_error(body));
+        assert!(!is_insufficient_fuel_error("not json"));
+    }
+
+    #[test]
+    fn test_cooldown_from_error() {
+        let body = r#"{"error":{"message":"Ship action is still on cooldown for 17 second(s).","code":4000,"data":{"cooldown":{"shipSymbol":"BADGER-1","totalSeconds":70,"remainingSeconds":17,"expiration":"2024-02-04T12:01:19.000Z"}}}}"#;
+        let cooldown =
+            cooldown_from_error(&ApiError::from_response(StatusCode::CONFLICT, body)).unwrap();
+        assert_eq!(cooldown.remaining_seconds, 17);
+        assert!(cooldown.expiration.is_some());
+        // other errors aren't a cooldown to wait out
+        let body = r#"{"error":{"message":"Ship is currently in-transit","code":4214}}"#;
+        assert!(
+            cooldown_from_error(&ApiError::from_response(StatusCode::BAD_REQUEST, body)).is_none()
+        );
+    }
+
+    #[test]
+    fn test_spent_survey_error() {
+        let spent = |status, body| is_spent_survey_error(&ApiError::from_response(status, body));
+        assert!(spent(
+            StatusCode::BAD_REQUEST,
+            r#"{"error":{"message":"Ship survey failed. Target signature is no longer in range or valid.","code":4221}}"#
+        ));
+        assert!(spent(
+            StatusCode::CONFLICT,
+            r#"{"error":{"message":"Ship extract failed. Survey X1-S1-A1-BD5F47 has been exhausted.","code":4224}}"#
+        ));
+        // anything else (e.g. cooldown) is still fatal
+        assert!(!spent(
+            StatusCode::CONFLICT,
+            r#"{"error":{"message":"Ship action is still on cooldown for 10 second(s).","code":4000}}"#
+        ));
+        assert!(!spent(StatusCode::BAD_GATEWAY, "bad gateway"));
+    }
+
+    // The cached fuel says a direct hop from a non-market is fine, but the real tank
+    // has less (e.g. a refuel silently failed). The pre-flight check catches the thin
+    // margin, the refreshed value says the hop is impossible, and re-planning with the
+    // corrected fuel detours via the market instead.
+    #[test]
+    fn test_stale_fuel_divergence_replans() {
+        let market = wp("X1-T-M1", 0, true);
+        let here = wp("X1-T-A1", 100, false);
+        let target = wp("X1-T-A2", 250, false);
+        let pf = Pathfinding::new(vec![market.clone(), here.clone(), target.clone()]);
+        // target is 250 from the market, so the terminal reserve is 250
+        let (cached_fuel, real_fuel, capacity) = (400, 300, 800);
+
+        let route = pf
+            .get_route(&here.symbol, &target.symbol, 30, cached_fuel, capacity)
+            .unwrap();
+        assert_eq!(route.hops.len(), 1);
+        let required = route.hops[0].1.fuel_cost + route.req_terminal_fuel;
+        assert_eq!(required, 400);
+        assert!(needs_fuel_confirmation(cached_fuel, required));
+        assert_eq!(hop_fuel_action(real_fuel, required, false), HopFuel::Replan);
+
+        let route = pf
+            .get_route(&here.symbol, &target.symbol, 30, real_fuel, capacity)
+            .unwrap();
+        let stops: Vec<_> = route.hops.iter().map(|(w, ..)| w.clone()).collect();
+        assert_eq!(stops, vec![market.symbol.clone(), target.symbol.clone()]);
+        let first_hop_fuel = route.hops[0].1.fuel_cost;
+        assert_eq!(
+            hop_fuel_action(real_fuel, first_hop_fuel, false),
+            HopFuel::Proceed
+        );
+        // ...and at the market the shortfall is simply bought
+        assert_eq!(hop_fuel_action(0, 10, true), HopFuel::Refuel);
+    }
+
+    // Markets every 150 out to a non-market target. With a margin, each departure from a
+    // market tops up past the hop's need, so the ship reaches the next market with at
+    // least the margin left; without one it can roll in on empty.
+    #[test]
+    fn test_fuel_margin_at_market_hops() {
+        let waypoints = vec![
+            wp("X1-T-M1", 0, true),
+            wp("X1-T-M2", 150, true),
+            wp("X1-T-M3", 300, true),
+            wp("X1-T-M4", 450, true),
+            wp("X1-T-A1", 500, false),
+        ];
+        let pf = Pathfinding::new(waypoints.clone());
+        let capacity = 200;
+        let arrivals = |margin: FuelMargin| {
+            let route = pf
+                .get_route(&waypoints[0].symbol, &waypoints[4].symbol, 30, 0, capacity)
+                .unwrap();
+            let mut fuel = 0;
+            let mut market_arrivals = vec![];
+            for (waypoint, edge, a_market, b_market) in route.hops {
+                let required = match b_market {
+                    true => edge.fuel_cost,
+                    false => edge.fuel_cost + route.req_terminal_fuel,
+                };
+                let departure = departure_fuel(required, a_market, capacity, margin);
+                assert!(departure <= capacity);
+                match hop_fuel_action(fuel, departure, a_market) {
+                    HopFuel::Proceed => {}
+                    // the least a refuel leaves us with
+                    HopFuel::Refuel => fuel = departure,
+                    HopFuel::Replan => panic!("stranded before {}", waypoint),
+                }
+                fuel -= edge.fuel_cost;
+                assert!(fuel >= 0);
+                if b_market {
+                    market_arrivals.push(fuel);
+                }
+            }
+            market_arrivals
+        };
+        assert!(arrivals(FuelMargin::Units(0)).contains(&0));
+        for margin in [FuelMargin::Units(20), FuelMargin::Percent(10)] {
+            let arrivals = arrivals(margin);
+            assert_eq!(arrivals.len(), 3);
+            assert!(arrivals.iter().all(|&fuel| fuel >= margin.units(capacity)));
+        }
+        // never more than the tank, and nothing extra where fuel can't be bought
+        assert_eq!(departure_fuel(190, true, 200, FuelMargin::Units(20)), 200);
+        assert_eq!(departure_fuel(190, false, 200, FuelMargin::Units(20)), 190);
+    }
+
+    #[test]
+    fn test_nav_retry() {
+        assert!(is_transient_nav_error(&NavigateError::NoRoute));
+        assert!(is_transient_nav_error(&NavigateError::FuelDiverged));
+        assert!(!is_transient_nav_error(&NavigateError::Rejected(400)));
+        assert_eq!(nav_retry_wait(1), Duration::seconds(30));
+        assert_eq!(nav_retry_wait(3), Duration::seconds(120));
+        assert_eq!(nav_retry_wait(10), Duration::seconds(600));
+        assert_eq!(nav_retry_wait(100), Duration::seconds(600));
+    }
+
+    #[test]
+    fn test_warp_flight_mode() {
+        assert_eq!(warp_flight_mode(800, 300, 0), Some(ShipFlightMode::Burn));
+        // burning would strand us short of the next warp
+        assert_eq!(
+            warp_flight_mode(800, 300, 250),
+            Some(ShipFlightMode::Cruise)
+        );
+        assert_eq!(warp_flight_mode(300, 300, 0), Some(ShipFlightMode::Cruise));
+        assert_eq!(warp_flight_mode(299, 300, 0), None);
+    }
+
+    fn test_ship(arrival: DateTime<Utc>, cooldown_expiration: DateTime<Utc>) -> Ship {
+        let waypoint = json!({"symbol": "X1-S1-A1", "type": "PLANET", "systemSymbol": "X1-S1", "x": 0, "y": 0});
+        serde_json::from_value(json!({
+            "symbol": "TEST-1",
+            "nav": {
+                "systemSymbol": "X1-S1",
+                "waypointSymbol": "X1-S1-A1",
+                "route": {"origin": waypoint, "destination": waypoint, "arrival": arrival, "departureTime": arrival},
+                "status": "IN_TRANSIT",
+                "flightMode": "CRUISE"
+            },
+            "crew": {"current": 0, "capacity": 0, "required": 0, "rotation": "STRICT", "morale": 100, "wages": 0},
+            "fuel": {"current": 0, "capacity": 0, "consumed": {"amount": 0, "timestamp": arrival}},
+            "cooldown": {"shipSymbol": "TEST-1", "totalSeconds": 4000, "remainingSeconds": 4000, "expiration": cooldown_expiration},
+            "frame": {"symbol": "FRAME_PROBE", "name": "", "description": "", "moduleSlots": 0, "mountingPoints": 0, "fuelCapacity": 0, "condition": 1.0, "requirements": {}},
+            "reactor": {"symbol": "REACTOR_SOLAR_I", "name": "", "description": "", "condition": 1.0, "powerOutput": 3, "requirements": {}},
+            "engine": {"symbol": "ENGINE_IMPULSE_DRIVE_I", "name": "", "description": "", "condition": 1.0, "speed": 3, "requirements": {}},
+            "modules": [],
+            "mounts": [],
+            "registration": {"name": "TEST-1", "factionSymbol": "COSMIC", "role": "SATELLITE"},
+            "cargo": {"capacity": 0, "units": 0, "inventory": []}
+        }))
+        .unwrap()
+    }
+
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    // A transit wait and a 4000s cooldown, stepped through on a manual clock: no real
+    // sleeping, and each wait ends exactly when the clock passes its deadline.
+    #[tokio::test]
+    async fn test_transit_and_cooldown_waits_follow_clock() {
+        let start = Utc::now();
+        let clock = Arc::new(ManualClock::new(start));
+        let ctx = Arc::new(AgentContext::for_test(clock.clone()));
+        let ship = ShipController::new(
+            &ctx,
+            Arc::new(Mutex::new(test_ship(
+                start + Duration::seconds(120),
+                start + Duration::seconds(4000),
+            ))),
+        );
+        assert!(ship.is_in_transit());
+
+        let waiting = ship.clone();
+        let wait = tokio::spawn(async move {
+            waiting.wait_for_transit().await;
+            waiting.ctx.set_state_description("TEST-1", "arrived");
+            waiting.wait_for_cooldown().await;
+        });
+        settle().await;
+        assert!(!wait.is_finished());
+
+        // waits run a second past the deadline
+        clock.advance(Duration::seconds(120));
+        settle().await;
+        assert!(ctx.ship_state_description.get("TEST-1").is_none());
+        clock.advance(Duration::seconds(1));
+        settle().await;
+        assert!(!ship.is_in_transit());
+        assert_eq!(
+            *ctx.ship_state_description.get("TEST-1").unwrap(),
+            "arrived"
+        );
+        assert!(!wait.is_finished());
+
+        clock.advance(Duration::seconds(4000 - 121));
+        settle().await;
+        assert!(!wait.is_finished());
+        clock.advance(Duration::seconds(1));
+        tokio::time::timeout(std::time::Duration::from_secs(1), wait)
+            .await
+            .expect("cooldown wait should end with the clock")
+            .unwrap();
+    }
+}
@@ -13,6 +13,7 @@ use crate::{
     database::DbClient,
     models::{Construction, MarketTradeGood, WaypointSymbol},
     ship_controller::ShipController,
+    ship_scripts::leftovers,
     universe::WaypointFilter,
 };
 use ConstructionHaulerState::*;
@@ -415,6 +416,7 @@ pub async fn run_hauler(ship: ShipController, db: DbClient, ac: AgentController)
         if super::home_phase_done(&ac) {
             return super::scrap::run(ship).await;
         }
+        super::wait_while_paused(&ship, &ac).await;
         let next_state = tick(
             &ship,
             &ac,
@@ -616,7 +618,19 @@ async fn tick(
             if ship.cargo_empty() {
                 return Some(Buying);
             }
-            // todo - handle case where materials are no longer needed
+            let construction = ship.ctx.universe.get_construction(jump_gate_symbol).await;
+            let construction = construction.data.as_ref();
+            if construction.is_none_or(|c| c.is_complete) {
+                return Some(Completed);
+            }
+            // Another hauler may have covered part of what we bought: the site rejects
+            // supply beyond what it still needs, so get rid of the surplus first.
+            for (good, units) in ship.cargo_map() {
+                let leftover = leftovers::construction_leftover(construction, &good, units);
+                if leftover > 0 {
+                    leftovers::dispose_of(ship, &good, leftover).await;
+                }
+            }
             ship.goto_waypoint(jump_gate_symbol).await;
             while let Some(cargo_item) = ship.cargo_first_item() {
                 ship.supply_construction(&cargo_item.symbol, cargo_item.units)
@@ -629,6 +643,8 @@ async fn tick(
             // run_hauler's era check (so we don't scrap-then-rebuy in the brief window
             // before the era advances). Idle until then. TerminalState is a legacy
             // value from older runs, handled the same way.
+            // Anything still held was bought for the gate and is no use now.
+            leftovers::clear_leftovers(ship, "construction complete").await;
             ship.set_state_description("Gate built; awaiting scrap");
             tokio::time::sleep(std::time::Duration::from_secs(60)).await;
             None
@@ -26,6 +26,7 @@ pub async fn run_explorer(ship: ShipController, _db: DbClient, ac: AgentControll
     let mut state = Init;
 
     while state != Exit {
+        super::wait_while_paused(&ship, &ac).await;
         let next_state = tick(&ship, &state, &ac).await;
         if let Some(next_state) = next_state {
             state = next_state;
@@ -40,7 +41,7 @@ pub async fn run_explorer(ship: ShipController, _db: DbClient, ac: AgentControll
         info!("Explorer trading in target system {}", system);
         ship.set_state_description(&format!("Trading in {}", system));
 
-        let task_manager = ac.task_manager.clone();
+        let task_manager = ac.task_managers.get(&system).await;
         // let waypoints = ship.universe.get_system_waypoints(&system).await;
         // let inner_market_waypoints = market_waypoints(&waypoints, Some(200));
         let config = LogisticsScriptConfig {
@@ -143,7 +144,15 @@ async fn tick(
                         let waypoint = ship.ctx.universe.waypoint(&ship.waypoint());
                         if waypoint.is_market() {
                             ship.refuel(ship.fuel_capacity(), false).await;
-                            ship.full_load_cargo("FUEL").await;
+                            if let Err(e) = ship.full_load_cargo("FUEL").await {
+                                warn!(
+                                    "{} can't load FUEL cargo at {}: {:?}",
+                                    ship.symbol(),
+                                    waypoint.symbol,
+                                    e
+                                );
+                                return Some(Exit);
+                            }
                         } else {
                             let required_fuel = edge.fuel;
                             ship.refuel(required_fuel, true).await;
@@ -0,0 +1,223 @@
+// Cleaning up cargo a ship no longer has a use for: goods left over after a contract is
+// fulfilled or construction completes, or stray goods from a crash-interrupted trade.
+// Each good is sold at the in-system market paying the most for it, or jettisoned if
+// nobody buys it (unless KEEP_UNSELLABLE_LEFTOVERS=1). FUEL is never touched — cargo
+// fuel is intentional for long jumps. Scripts that retry every loop track their failed
+// attempts in UnsellableCargo and carry a good a while before jettisoning it.
+
+use crate::config::CONFIG;
+use crate::models::*;
+use crate::ship_controller::ShipController;
+use log::*;
+use std::collections::BTreeMap;
+
+// How many times a ship tries to offload a good nobody will take before jettisoning
+// it (UNSELLABLE_JETTISON_ATTEMPTS). 0 never jettisons, nor does KEEP_UNSELLABLE_LEFTOVERS.
+pub fn max_offload_attempts() -> u32 {
+    match CONFIG.keep_unsellable_leftovers {
+        true => 0,
+        false => CONFIG.unsellable_jettison_attempts,
+    }
+}
+
+// Failed offload attempts per good, for a ship that keeps trying (e.g. on every loop of
+// its script): a good is carried on until it has failed `max_attempts` times in a row,
+// in case a buyer turns up, and only then jettisoned.
+#[derive(Debug, Default)]
+pub struct UnsellableCargo {
+    attempts: BTreeMap<String, u32>,
+}
+
+impl UnsellableCargo {
+    // Record one attempt's leftovers (from ShipController::sell_or_carry). Returns the
+    // goods now due to be jettisoned; goods that did sell start over.
+    pub fn record(&mut self, unsold: &[(String, i64)], max_attempts: u32) -> Vec<(String, i64)> {
+        self.attempts
+            .retain(|good, _| unsold.iter().any(|(g, _)| g == good));
+        let mut jettison = vec![];
+        for (good, units) in unsold {
+            let attempts = self.attempts.entry(good.clone()).or_default();
+            *attempts += 1;
+            if max_attempts > 0 && *attempts >= max_attempts {
+                self.attempts.remove(good);
+                jettison.push((good.clone(), *units));
+            }
+        }
+        jettison
+    }
+}
+
+// Units of `good` beyond what the contract still needs, once we've delivered
+pub fn contract_leftover(contract: &Contract, good: &str, held: i64) -> i64 {
+    if contract.fulfilled {
+        return held;
+    }
+    let outstanding = contract
+        .terms
+        .deliver
+        .iter()
+        .filter(|d| d.trade_symbol == good)
+        .map(|d| d.units_required - d.units_fulfilled)
+        .sum::<i64>();
+    (held - outstanding).max(0)
+}
+
+// Units of `good` beyond what the construction site still needs
+pub fn construction_leftover(construction: Option<&Construction>, good: &str, held: i64) -> i64 {
+    let Some(construction) = construction.filter(|c| !c.is_complete) else {
+        return held;
+    };
+    let outstanding = construction
+        .materials
+        .iter()
+        .filter(|m| m.trade_symbol == good)
+        .map(|m| m.required - m.fulfilled)
+        .sum::<i64>();
+    (held - outstanding).max(0)
+}
+
+// Get rid of `units` of one good: sold at the best in-system buyer, or jettisoned if
+// nobody takes it (unless KEEP_UNSELLABLE_LEFTOVERS=1). A single attempt.
+pub async fn dispose_of(ship: &ShipController, good: &str, units: i64) {
+    for (good, unsold) in ship.sell_or_carry(&[(good.to_string(), units)]).await {
+        match CONFIG.keep_unsellable_leftovers {
+            true => warn!("{}: keeping {} unsellable {}", ship.symbol(), unsold, good),
+            false => {
+                warn!(
+                    "{}: jettisoning {} unsellable {}",
+                    ship.symbol(),
+                    unsold,
+                    good
+                );
+                ship.jettison_cargo(&good, unsold).await;
+            }
+        }
+    }
+}
+
+// Dispose of everything in the hold except fuel
+pub async fn clear_leftovers(ship: &ShipController, reason: &str) {
+    for (good, units) in ship.cargo_map() {
+        if good == "FUEL" {
+            continue;
+        }
+        warn!(
+            "{}: leftover cargo {} x{} ({}) — disposing",
+            ship.symbol(),
+            good,
+            units,
+            reason
+        );
+        dispose_of(ship, &good, units).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ship_controller::best_buyer;
+    use chrono::Utc;
+
+    fn contract(fulfilled: bool, required: i64, delivered: i64) -> Contract {
+        Contract {
+            id: "c1".to_string(),
+            faction_symbol: "COSMIC".to_string(),
+            contract_type: "PROCUREMENT".to_string(),
+            terms: Terms {
+                deadline: String::new(),
+                payment: Payment {
+                    on_fulfilled: 100_000,
+                    on_accepted: 10_000,
+                },
+                deliver: vec![Deliver {
+                    trade_symbol: "COPPER_ORE".to_string(),
+                    destination_symbol: WaypointSymbol::new("X1-S1-H1"),
+                    units_required: required,
+                    units_fulfilled: delivered,
+                }],
+            },
+            accepted: true,
+            fulfilled,
+            expiration: Utc::now(),
+            deadline_to_accept: Utc::now(),
+        }
+    }
+
+    // The hauler bought 40 for the last 30 units of a contract: once it's fulfilled, the
+    // spare 10 go to the best-paying buyer rather than sitting in the hold.
+    #[test]
+    fn test_contract_leftovers_are_sold() {
+        assert_eq!(
+            contract_leftover(&contract(false, 100, 70), "COPPER_ORE", 40),
+            10
+        );
+        assert_eq!(
+            contract_leftover(&contract(true, 100, 100), "COPPER_ORE", 10),
+            10
+        );
+        // still needed: nothing left over
+        assert_eq!(
+            contract_leftover(&contract(false, 100, 40), "COPPER_ORE", 40),
+            0
+        );
+        // goods the contract never wanted
+        assert_eq!(
+            contract_leftover(&contract(false, 100, 40), "IRON_ORE", 5),
+            5
+        );
+    }
+
+    #[test]
+    fn test_best_buyer() {
+        let (a, b, c) = (
+            WaypointSymbol::new("X1-S1-A1"),
+            WaypointSymbol::new("X1-S1-B1"),
+            WaypointSymbol::new("X1-S1-C1"),
+        );
+        let buyers = vec![
+            (a.clone(), Some(50)),
+            (b.clone(), Some(80)),
+            (c.clone(), None),
+        ];
+        assert_eq!(best_buyer(&buyers), Some(&b));
+        // unpriced buyers are still better than jettisoning
+        assert_eq!(best_buyer(&[(c.clone(), None)]), Some(&c));
+        assert_eq!(best_buyer(&[]), None);
+    }
+
+    // Unsellable goods are carried for a few attempts before they go over the side
+    #[test]
+    fn test_unsellable_cargo_jettisoned_after_attempts() {
+        let unsold = |goods: &[(&str, i64)]| {
+            goods
+                .iter()
+                .map(|(g, u)| (g.to_string(), *u))
+                .collect::<Vec<_>>()
+        };
+        let mut cargo = UnsellableCargo::default();
+        assert!(cargo.record(&unsold(&[("ICE_WATER", 10)]), 3).is_empty());
+        assert!(
+            cargo
+                .record(&unsold(&[("ICE_WATER", 10), ("QUARTZ_SAND", 4)]), 3)
+                .is_empty()
+        );
+        assert_eq!(
+            cargo.record(&unsold(&[("ICE_WATER", 8), ("QUARTZ_SAND", 4)]), 3),
+            unsold(&[("ICE_WATER", 8)])
+        );
+        // QUARTZ_SAND sold in between: its count starts over
+        assert!(cargo.record(&unsold(&[]), 3).is_empty());
+        assert!(cargo.record(&unsold(&[("QUARTZ_SAND", 4)]), 3).is_empty());
+
+        // 1 jettisons straight away, 0 never does
+        let mut cargo = UnsellableCargo::default();
+        assert_eq!(
+            cargo.record(&unsold(&[("ICE_WATER", 10)]), 1),
+            unsold(&[("ICE_WATER", 10)])
+        );
+        let mut cargo = UnsellableCargo::default();
+        for _ in 0..10 {
+            assert!(cargo.record(&unsold(&[("ICE_WATER", 10)]), 0).is_empty());
+        }
+    }
+}
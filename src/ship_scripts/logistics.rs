@@ -1,8 +1,17 @@
-use std::{cmp::min, sync::Arc};
+use std::{
+    cmp::min,
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
 
 use crate::{
-    agent_controller::AgentController, logistics_planner::Action, models::LogisticsScriptConfig,
-    ship_controller::ShipController, tasks::LogisticTaskManager, universe::WaypointFilter,
+    agent_controller::AgentController,
+    config::CONFIG,
+    logistics_planner::{Action, ScheduledAction},
+    models::{Construction, LogisticsScriptConfig, Market, MarketTradeGood, WaypointSymbol},
+    ship_controller::ShipController,
+    ship_scripts::{leftovers, probe},
+    tasks::LogisticTaskManager,
 };
 use log::*;
 
@@ -16,6 +25,9 @@ pub async fn run(
     ship_controller.wait_for_transit().await;
 
     let ship_symbol = ship_controller.symbol();
+    let job_id = ac.fleet.assigned_job(&ship_symbol);
+    // A job in another system (e.g. after rebalancing): get there first
+    probe::goto_system(&ship_controller, taskmanager.system()).await;
     let system_symbol = ship_controller.system();
     assert_eq!(
         config.use_planner,
@@ -35,13 +47,38 @@ pub async fn run(
         )
         .await;
 
+    let mut unsellable = leftovers::UnsellableCargo::default();
     loop {
-        // Before taking work: if the ship has no in-progress task, its hold should be
-        // empty. Anything in it is stray — e.g. a trade whose sell leg never ran because
-        // a crash interrupted it — which silently eats capacity and can overflow the next
-        // buy. Clear it here (safe: no in-flight task good to protect).
-        if taskmanager.get_next_action(&ship_symbol).is_none() {
-            reconcile_stray_cargo(&ship_controller).await;
+        super::wait_while_paused(&ship_controller, &ac).await;
+
+        // Moved to another job while idle: let that job's script take over
+        let current_job = ac.fleet.assigned_job(&ship_symbol);
+        if taskmanager.get_next_action(&ship_symbol).is_none()
+            && current_job.is_some()
+            && current_job != job_id
+        {
+            info!(
+                "Ship {} reassigned from {:?}; handing over",
+                ship_symbol, job_id
+            );
+            taskmanager.unregister_ship(&ship_symbol).await;
+            ac.spawn_run_ship(ship_symbol).await;
+            return;
+        }
+
+        // Before each action: the hold should contain exactly the goods the remaining
+        // queue still has to sell or deliver. Anything else is stray — e.g. a trade whose
+        // sell leg never ran because a crash interrupted it, or was skipped as invalid —
+        // which silently eats capacity and can overflow the next buy. Clear it here.
+        let stray = stray_cargo(
+            &ship_controller.cargo_map(),
+            &taskmanager.queued_actions(&ship_symbol),
+        );
+        if !stray.is_empty() {
+            reconcile_stray_cargo(&ship_controller, &stray, &mut unsellable).await;
+        }
+        if let Some(floor) = CONFIG.logistics_min_fuel_sell_price {
+            sell_stray_fuel(&ship_controller, &taskmanager, floor).await;
         }
 
         // Get next action from task manager
@@ -55,14 +92,33 @@ pub async fn run(
                     "Ship {} was scheduled no tasks to perform. Sleeping 5-10 minutes.",
                     ship_controller.symbol()
                 );
-                let rand_seconds = rand::random::<u64>() % 300;
-                tokio::time::sleep(tokio::time::Duration::from_secs(300 + rand_seconds)).await;
+                let rand_seconds = rand::random::<i64>().rem_euclid(300);
+                ship_controller
+                    .ctx
+                    .clock
+                    .sleep(chrono::Duration::seconds(300 + rand_seconds))
+                    .await;
                 continue;
             }
         };
 
+        // The plan may be older than the universe data: don't fly somewhere to do
+        // something that can no longer be done.
+        if let Some(reason) = preflight_action(&ship_controller, &action).await {
+            warn!(
+                "Ship {} skipping {:?} at {} ({:?}); aborting task {}",
+                ship_symbol, action.action, action.waypoint, reason, action.task_id
+            );
+            taskmanager.abort_task(&ship_symbol, &action.task_id).await;
+            continue;
+        }
+
+        if action.action == Action::TryBuyShips {
+            defer_for_probe_refresh(&ship_controller, &ac, &action.waypoint).await;
+        }
+
         ship_controller.goto_waypoint(&action.waypoint).await;
-        execute_logistics_action(&ship_controller, &action.action, &ac).await;
+        execute_logistics_action(&ship_controller, &action.action, &ac, &taskmanager).await;
 
         // Mark the action as complete
         taskmanager.complete_action(&ship_symbol, &action).await;
@@ -75,79 +131,235 @@ pub async fn run(
     }
 }
 
-// Dispose of cargo the ship is holding while it owns no task. Called only when the task
-// queue is empty, so every held good is stray (a completed task always empties the hold)
-// — most commonly a good bought for a trade whose sell leg was lost to a crash. Each stray
-// good is sold at an in-system market that buys it (import or exchange), or jettisoned as a
-// last resort so it can't permanently occupy the hold. FUEL is never touched — cargo fuel
-// is intentional for long jumps.
-async fn reconcile_stray_cargo(ship: &ShipController) {
-    let stray: Vec<_> = ship
-        .cargo_inventory()
-        .into_iter()
-        .filter(|item| item.symbol != "FUEL")
-        .collect();
-    if stray.is_empty() {
+// Goods the queue accounts for aboard: the cargo of tasks whose buy leg is done, plus
+// up to a queued buy's units of its good, since a buy interrupted by a restart (or
+// finished but not yet completed) resumes from what's already held. A task's legs share
+// a task_id, and an aborted task loses all its remaining legs, so its cargo stops being
+// expected.
+fn expected_cargo(queue: &[ScheduledAction]) -> BTreeMap<String, i64> {
+    let mut loading = BTreeSet::new();
+    let mut expected = BTreeMap::new();
+    for action in queue {
+        match &action.action {
+            Action::BuyGoods(good, units) => {
+                loading.insert(&action.task_id);
+                *expected.entry(good.clone()).or_insert(0) += units;
+            }
+            Action::SellGoods(good, units)
+            | Action::DeliverContract(good, units)
+            | Action::DeliverConstruction(good, units)
+                if !loading.contains(&action.task_id) =>
+            {
+                *expected.entry(good.clone()).or_insert(0) += units;
+            }
+            _ => {}
+        }
+    }
+    expected
+}
+
+// Held cargo beyond what the queue expects (never FUEL — cargo fuel is intentional)
+fn stray_cargo(held: &BTreeMap<String, i64>, queue: &[ScheduledAction]) -> Vec<(String, i64)> {
+    let expected = expected_cargo(queue);
+    held.iter()
+        .filter(|(good, _)| good.as_str() != "FUEL")
+        .map(|(good, units)| {
+            (
+                good.clone(),
+                units - expected.get(good).copied().unwrap_or(0),
+            )
+        })
+        .filter(|(_, units)| *units > 0)
+        .collect()
+}
+
+// FUEL cargo beyond what the queue expects. Unlike other stray cargo it's harmless to
+// carry, so it's only sold where the price is right.
+fn stray_fuel(held: &BTreeMap<String, i64>, queue: &[ScheduledAction]) -> i64 {
+    let expected = expected_cargo(queue).get("FUEL").copied().unwrap_or(0);
+    (held.get("FUEL").copied().unwrap_or(0) - expected).max(0)
+}
+
+// `local_price` is what the current market pays for fuel, if it buys it at all
+fn sell_fuel_here(floor: i64, local_price: Option<i64>) -> bool {
+    local_price.is_some_and(|price| price >= floor)
+}
+
+async fn sell_stray_fuel(ship: &ShipController, taskmanager: &LogisticTaskManager, floor: i64) {
+    let held = ship.cargo_map();
+    let stray = stray_fuel(&held, &taskmanager.queued_actions(&ship.symbol()));
+    if stray == 0 {
         return;
     }
-    let system = ship.system();
-    for item in stray {
-        let good = item.symbol;
+    let local_price = match ship
+        .ctx
+        .universe
+        .detailed_waypoint(&ship.waypoint())
+        .await
+        .is_market()
+    {
+        true => ship.current_market().await.and_then(|market| {
+            market
+                .data
+                .trade_goods
+                .iter()
+                .find(|g| g.symbol == "FUEL")
+                .map(|g| g.sell_price)
+        }),
+        false => None,
+    };
+    if !sell_fuel_here(floor, local_price) {
+        debug!(
+            "{}: keeping {} stray FUEL (local price {:?} < {})",
+            ship.symbol(),
+            stray,
+            local_price,
+            floor
+        );
+        return;
+    }
+    info!(
+        "{}: selling {} stray FUEL at {:?}",
+        ship.symbol(),
+        stray,
+        local_price
+    );
+    ship.sell_down_to("FUEL", held["FUEL"] - stray).await;
+}
+
+// Offload stray cargo, jettisoning what nobody has taken for max_offload_attempts rounds
+async fn reconcile_stray_cargo(
+    ship: &ShipController,
+    stray: &[(String, i64)],
+    unsellable: &mut leftovers::UnsellableCargo,
+) {
+    for (good, units) in stray {
         warn!(
             "{}: stray cargo {} x{} with no owning task — disposing",
             ship.symbol(),
             good,
-            item.units
+            units
         );
-        // A market buys a good if it imports or exchanges it.
-        let mut buyers = ship
-            .ctx
-            .universe
-            .search_waypoints(&system, &[WaypointFilter::Imports(good.clone())])
-            .await;
-        if buyers.is_empty() {
-            buyers = ship
-                .ctx
-                .universe
-                .search_waypoints(&system, &[WaypointFilter::Exchanges(good.clone())])
-                .await;
-        }
-        match buyers.first() {
-            Some(dest) => {
-                ship.goto_waypoint(&dest.symbol).await;
-                ship.refresh_market().await;
-                let mut remaining = ship.cargo_good_count(&good);
-                while remaining > 0 {
-                    let market = ship.ctx.universe.get_market(&ship.waypoint()).unwrap();
-                    let Some(trade) = market.data.trade_goods.iter().find(|g| g.symbol == good)
-                    else {
-                        break;
-                    };
-                    let units = min(trade.trade_volume, remaining);
-                    ship.sell_goods(&good, units, true).await;
-                    ship.refresh_market().await;
-                    remaining -= units;
-                }
-                // Market couldn't absorb all of it: jettison the rest to free the hold.
-                let leftover = ship.cargo_good_count(&good);
-                if leftover > 0 {
-                    ship.jettison_cargo(&good, leftover).await;
-                }
-            }
-            None => {
-                warn!(
-                    "{}: no in-system market buys {}; jettisoning {}",
-                    ship.symbol(),
-                    good,
-                    item.units
-                );
-                ship.jettison_cargo(&good, item.units).await;
-            }
+    }
+    let unsold = ship.sell_or_carry(stray).await;
+    for (good, units) in unsellable.record(&unsold, leftovers::max_offload_attempts()) {
+        warn!(
+            "{}: jettisoning {} unsellable {}",
+            ship.symbol(),
+            units,
+            good
+        );
+        ship.jettison_cargo(&good, units).await;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SkipReason {
+    NotAMarket,
+    GoodNotTraded(String),
+    ConstructionSatisfied(String),
+}
+
+// Whether an action still makes sense. `market` is the latest data for the waypoint, of
+// any age (None if never probed: nothing to check against, so it proceeds).
+fn check_action(
+    action: &Action,
+    is_market: bool,
+    market: Option<&Market>,
+    construction: Option<&Construction>,
+) -> Option<SkipReason> {
+    match action {
+        Action::RefreshMarket | Action::BuyGoods(..) | Action::SellGoods(..) if !is_market => {
+            Some(SkipReason::NotAMarket)
+        }
+        Action::BuyGoods(good, _) | Action::SellGoods(good, _) => {
+            let delisted = market.is_some_and(|m| !m.trade_goods.iter().any(|g| &g.symbol == good));
+            delisted.then(|| SkipReason::GoodNotTraded(good.clone()))
+        }
+        Action::DeliverConstruction(good, _) => {
+            let outstanding = construction.filter(|c| !c.is_complete).is_some_and(|c| {
+                c.materials
+                    .iter()
+                    .any(|m| &m.trade_symbol == good && m.fulfilled < m.required)
+            });
+            (!outstanding).then(|| SkipReason::ConstructionSatisfied(good.clone()))
         }
+        _ => None,
     }
 }
 
-async fn execute_logistics_action(ship: &ShipController, action: &Action, ac: &AgentController) {
+async fn preflight_action(ship: &ShipController, action: &ScheduledAction) -> Option<SkipReason> {
+    let universe = &ship.ctx.universe;
+    let waypoint = &action.waypoint;
+    let construction = match &action.action {
+        Action::DeliverConstruction(..) => universe.get_construction(waypoint).await.data.clone(),
+        _ => None,
+    };
+    check_action(
+        &action.action,
+        universe.detailed_waypoint(waypoint).await.is_market(),
+        universe.get_market(waypoint).as_ref().map(|m| &m.data),
+        construction.as_ref(),
+    )
+}
+
+// The current market's listing for a good. None (with a warning) if the ship isn't at a
+// market or the good has vanished from it; the action is abandoned rather than the ship.
+async fn market_trade(ship: &ShipController, good: &str) -> Option<MarketTradeGood> {
+    let Some(market) = ship.current_market().await else {
+        warn!("{}: {} is not a market", ship.symbol(), ship.waypoint());
+        return None;
+    };
+    let trade = market.data.trade_goods.iter().find(|g| g.symbol == good);
+    if trade.is_none() {
+        warn!(
+            "{}: {} no longer trades {}",
+            ship.symbol(),
+            ship.waypoint(),
+            good
+        );
+    }
+    trade.cloned()
+}
+
+// A stale shipyard listing with a static probe sitting at the shipyard: the next
+// controller tick buys through the probe (re-checking the listing first), so give it
+// one tick before flying across the system on the strength of old data.
+async fn defer_for_probe_refresh(
+    ship: &ShipController,
+    ac: &AgentController,
+    shipyard: &WaypointSymbol,
+) {
+    let probe_there = ac
+        .statically_probed_waypoints()
+        .iter()
+        .any(|(_, waypoint)| waypoint == shipyard);
+    if !probe_there
+        || !ac
+            .fleet
+            .shipyard_listing_stale(shipyard, ship.ctx.clock.now())
+    {
+        return;
+    }
+    info!(
+        "{}: shipyard {} listing is stale; waiting a tick for its probe",
+        ship.symbol(),
+        shipyard
+    );
+    ship.ctx
+        .clock
+        .sleep(chrono::Duration::seconds(
+            CONFIG.controller_tick_seconds as i64,
+        ))
+        .await;
+}
+
+async fn execute_logistics_action(
+    ship: &ShipController,
+    action: &Action,
+    ac: &AgentController,
+    taskmanager: &LogisticTaskManager,
+) {
     match action {
         Action::RefreshMarket => ship.refresh_market().await,
         Action::RefreshShipyard => ship.refresh_shipyard().await,
@@ -172,13 +384,9 @@ async fn execute_logistics_action(ship: &ShipController, action: &Action, ac: &A
                     );
                     break;
                 }
-                let market = ship.ctx.universe.get_market(&ship.waypoint()).unwrap();
-                let trade = market
-                    .data
-                    .trade_goods
-                    .iter()
-                    .find(|g| g.symbol == *good)
-                    .unwrap();
+                let Some(trade) = market_trade(ship, good).await else {
+                    break;
+                };
                 let buy_units = min(min(trade.trade_volume, remaining_to_buy), space);
                 ship.buy_goods(good, buy_units, true).await;
                 ship.refresh_market().await;
@@ -190,13 +398,9 @@ async fn execute_logistics_action(ship: &ShipController, action: &Action, ac: &A
             let mut remaining_to_sell = good_count;
             ship.refresh_market().await;
             while remaining_to_sell > 0 {
-                let market = ship.ctx.universe.get_market(&ship.waypoint()).unwrap();
-                let trade = market
-                    .data
-                    .trade_goods
-                    .iter()
-                    .find(|g| g.symbol == *good)
-                    .unwrap();
+                let Some(trade) = market_trade(ship, good).await else {
+                    break;
+                };
                 let sell_units = min(trade.trade_volume, remaining_to_sell);
                 ship.sell_goods(good, sell_units, true).await;
                 ship.refresh_market().await;
@@ -207,9 +411,24 @@ async fn execute_logistics_action(ship: &ShipController, action: &Action, ac: &A
             assert!(!ship.is_in_transit());
             info!("Starting buy task for ship {}", ship.ship_symbol);
             ship.dock().await;
-            let (bought, _shipyard_waypoints) =
-                ac.try_buy_ships(Some(ship.ship_symbol.clone())).await;
+            let (bought, next_shipyard) = ac.try_buy_ships(Some(ship.ship_symbol.clone())).await;
             info!("Buy task resulted in {} ships bought", bought.len());
+            // Nothing bought here but another shipyard is worth a try: go there next
+            // rather than wait for a planning round
+            if bought.is_empty()
+                && let Some(next_shipyard) = next_shipyard
+                && next_shipyard != ship.waypoint()
+                && taskmanager
+                    .extend_with_buyships(&ship.ship_symbol, &next_shipyard)
+                    .await
+            {
+                info!(
+                    "{}: purchase failed at {}; trying {} next",
+                    ship.ship_symbol,
+                    ship.waypoint(),
+                    next_shipyard
+                );
+            }
             for ship_symbol in bought {
                 ship.debug(&format!("{} Bought ship {}", ship.ship_symbol, ship_symbol));
                 ac.spawn_run_ship(ship_symbol).await;
@@ -236,9 +455,172 @@ async fn execute_logistics_action(ship: &ShipController, action: &Action, ac: &A
             let contract_id = ac.get_current_contract_id().unwrap();
             ship.deliver_contract(&contract_id, good, units).await;
             ac.spawn_contract_task();
+            // Bought more than the contract had left to take (typically the final
+            // delivery): don't carry the surplus into the next task.
+            if let Some(contract) = ac.get_current_contract() {
+                let leftover =
+                    leftovers::contract_leftover(&contract, good, ship.cargo_good_count(good));
+                if leftover > 0 {
+                    leftovers::dispose_of(ship, good, leftover).await;
+                }
+            }
         }
         _ => {
             panic!("Action not implemented: {:?}", action);
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::ConstructionMaterial;
+
+    fn scheduled(task_id: &str, action: Action) -> ScheduledAction {
+        ScheduledAction {
+            timestamp: 0.0,
+            waypoint: WaypointSymbol::new("X1-S1-A1"),
+            action,
+            task_id: task_id.to_string(),
+            completes_task: false,
+        }
+    }
+
+    fn held(goods: &[(&str, i64)]) -> BTreeMap<String, i64> {
+        goods.iter().map(|(g, u)| (g.to_string(), *u)).collect()
+    }
+
+    // Two trades queued; the first is bought, then its sell leg is skipped and the task
+    // aborted. Its cargo has to become stray — and nothing else.
+    #[test]
+    fn test_stray_cargo_after_skipped_action() {
+        let buy_iron = scheduled("t1", Action::BuyGoods("IRON".to_string(), 40));
+        let sell_iron = scheduled("t1", Action::SellGoods("IRON".to_string(), 40));
+        let buy_food = scheduled("t2", Action::BuyGoods("FOOD".to_string(), 40));
+        let sell_food = scheduled("t2", Action::SellGoods("FOOD".to_string(), 40));
+        let refresh = scheduled("t3", Action::RefreshMarket);
+
+        // fresh schedule, empty hold (bar some cargo fuel)
+        let queue = vec![
+            buy_iron,
+            sell_iron.clone(),
+            buy_food.clone(),
+            sell_food.clone(),
+        ];
+        assert!(stray_cargo(&held(&[("FUEL", 20)]), &queue).is_empty());
+
+        // iron bought: it's expected by the queued sell
+        let queue = vec![sell_iron, buy_food.clone(), sell_food.clone(), refresh];
+        assert!(stray_cargo(&held(&[("IRON", 40), ("FUEL", 20)]), &queue).is_empty());
+        // ...but not beyond what the sell leg plans to unload
+        assert_eq!(
+            stray_cargo(&held(&[("IRON", 45)]), &queue),
+            vec![("IRON".to_string(), 5)]
+        );
+
+        // the sell leg was skipped and t1 aborted: the iron is stray, the food trade is
+        // untouched, and no more food is expected aboard than its buy leg loads
+        let mut queue = queue;
+        queue.retain(|a| a.task_id != "t1");
+        assert_eq!(
+            stray_cargo(&held(&[("IRON", 40)]), &queue),
+            vec![("IRON".to_string(), 40)]
+        );
+        assert_eq!(
+            stray_cargo(&held(&[("FOOD", 50)]), &queue),
+            vec![("FOOD".to_string(), 10)]
+        );
+
+        // skipping a buy leg aborts its sell leg with it, so nothing is expected
+        queue.retain(|a| a.task_id != "t2");
+        assert!(expected_cargo(&queue).is_empty());
+        assert!(stray_cargo(&held(&[]), &queue).is_empty());
+    }
+
+    // A restart mid-buy, or after the buy but before complete_action: the buy is still
+    // at the front and part or all of it is aboard. That's the buy resuming, not stray.
+    #[test]
+    fn test_interrupted_buy_not_stray() {
+        let queue = vec![
+            scheduled("t1", Action::BuyGoods("IRON".to_string(), 40)),
+            scheduled("t1", Action::SellGoods("IRON".to_string(), 40)),
+        ];
+        assert!(stray_cargo(&held(&[("IRON", 15)]), &queue).is_empty());
+        assert!(stray_cargo(&held(&[("IRON", 40)]), &queue).is_empty());
+        assert_eq!(
+            stray_cargo(&held(&[("IRON", 40), ("FOOD", 5)]), &queue),
+            vec![("FOOD".to_string(), 5)]
+        );
+        assert_eq!(
+            stray_cargo(&held(&[("IRON", 48)]), &queue),
+            vec![("IRON".to_string(), 8)]
+        );
+    }
+
+    // With a floor of 60, fuel at a market buying it for 40 stays aboard for the next
+    // market; fuel the queue still has to sell is never stray.
+    #[test]
+    fn test_fuel_retained_below_price_floor() {
+        assert!(!sell_fuel_here(60, Some(40)));
+        assert!(!sell_fuel_here(60, None));
+        assert!(sell_fuel_here(60, Some(60)));
+        assert!(sell_fuel_here(0, Some(1)));
+
+        let sell_fuel = scheduled("t1", Action::SellGoods("FUEL".to_string(), 30));
+        assert_eq!(stray_fuel(&held(&[("FUEL", 40)]), &[]), 40);
+        assert_eq!(stray_fuel(&held(&[("FUEL", 40)]), &[sell_fuel]), 10);
+        assert_eq!(stray_fuel(&held(&[("IRON", 40)]), &[]), 0);
+    }
+
+    #[test]
+    fn test_preflight_skips_invalid_actions() {
+        let market = Market {
+            symbol: WaypointSymbol::new("X1-S1-A1"),
+            transactions: vec![],
+            imports: vec![],
+            exports: vec![],
+            exchange: vec![],
+            trade_goods: vec![MarketTradeGood {
+                symbol: "IRON".to_string(),
+                trade_volume: 20,
+                _type: crate::models::MarketType::Export,
+                supply: crate::models::MarketSupply::Moderate,
+                activity: None,
+                purchase_price: 100,
+                sell_price: 90,
+            }],
+        };
+        let buy = |good: &str| Action::BuyGoods(good.to_string(), 40);
+        assert_eq!(check_action(&buy("IRON"), true, Some(&market), None), None);
+        assert_eq!(
+            check_action(&buy("FOOD"), true, Some(&market), None),
+            Some(SkipReason::GoodNotTraded("FOOD".to_string()))
+        );
+        // never probed: nothing to check against
+        assert_eq!(check_action(&buy("FOOD"), true, None, None), None);
+        assert_eq!(
+            check_action(&Action::RefreshMarket, false, None, None),
+            Some(SkipReason::NotAMarket)
+        );
+
+        let mut construction = Construction {
+            symbol: WaypointSymbol::new("X1-S1-I1"),
+            materials: vec![ConstructionMaterial {
+                trade_symbol: "FAB_MATS".to_string(),
+                required: 100,
+                fulfilled: 60,
+            }],
+            is_complete: false,
+        };
+        let deliver = Action::DeliverConstruction("FAB_MATS".to_string(), 40);
+        assert_eq!(
+            check_action(&deliver, false, None, Some(&construction)),
+            None
+        );
+        construction.materials[0].fulfilled = 100;
+        assert_eq!(
+            check_action(&deliver, false, None, Some(&construction)),
+            Some(SkipReason::ConstructionSatisfied("FAB_MATS".to_string()))
+        );
+    }
+}
@@ -2,6 +2,7 @@ use std::cmp::min;
 
 use crate::agent_controller::AgentController;
 use crate::api_client::api_models::WaypointDetailed;
+use crate::config::CONFIG;
 use crate::models::MarketType::*;
 use crate::ship_controller::ShipController;
 use crate::universe::WaypointFilter;
@@ -10,6 +11,10 @@ use MiningShuttleState::*;
 use lazy_static::lazy_static;
 use log::*;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Free space the drone wants before it extracts
+const EXTRACT_MIN_SPACE: i64 = 4;
 
 async fn sell_location(ship: &ShipController, cargo_symbol: &str) -> Option<WaypointSymbol> {
     let mut markets = Vec::new();
@@ -49,6 +54,72 @@ async fn sell_location(ship: &ShipController, cargo_symbol: &str) -> Option<Wayp
     sell_trade_good.map(|(market_symbol, _)| market_symbol)
 }
 
+// Best import sell price per good across the system's markets
+async fn system_sell_prices(ship: &ShipController) -> BTreeMap<String, i64> {
+    let mut prices = BTreeMap::new();
+    let waypoints = ship.ctx.universe.get_system_waypoints(&ship.system()).await;
+    for waypoint in waypoints.iter().filter(|w| w.is_market()) {
+        let Some(market) = ship.ctx.universe.get_market(&waypoint.symbol) else {
+            continue;
+        };
+        for trade in market.data.trade_goods.iter().filter(|g| g._type == Import) {
+            let price = prices.entry(trade.symbol.clone()).or_insert(0);
+            *price = (*price).max(trade.sell_price);
+        }
+    }
+    prices
+}
+
+// With a full hold, pick cargo to throw away so the drone can extract against `survey`
+// instead of stalling for the shuttle. Only goods worth less than half the survey's
+// expected unit value qualify, never goods the survey itself yields, and never more
+// units than needed to get back to EXTRACT_MIN_SPACE. Unpriced goods are left alone.
+fn make_room_jettison(
+    cargo: &BTreeMap<String, i64>,
+    space_available: i64,
+    prices: &BTreeMap<String, i64>,
+    survey: &Survey,
+) -> Option<(String, i64)> {
+    let space_needed = EXTRACT_MIN_SPACE - space_available;
+    if space_needed <= 0 || survey.deposits.is_empty() {
+        return None;
+    }
+    let survey_value = survey
+        .deposits
+        .iter()
+        .map(|d| prices.get(&d.symbol).copied().unwrap_or(0))
+        .sum::<i64>()
+        / survey.deposits.len() as i64;
+    cargo
+        .iter()
+        .filter(|(good, _)| !survey.deposits.iter().any(|d| &d.symbol == *good))
+        .filter_map(|(good, units)| prices.get(good).map(|price| (good, *units, *price)))
+        .filter(|(_, _, price)| price * 2 < survey_value)
+        .min_by_key(|(_, _, price)| *price)
+        .map(|(good, units, _)| (good.clone(), min(units, space_needed)))
+}
+
+async fn survey_jettison(
+    ship: &ShipController,
+    asteroid_location: &WaypointSymbol,
+) -> Option<(String, i64)> {
+    if !CONFIG.mining_jettison_for_survey {
+        return None;
+    }
+    let prices = system_sell_prices(ship).await;
+    let survey = ship
+        .ctx
+        .survey_manager
+        .best_survey(asteroid_location, &prices)
+        .await?;
+    make_room_jettison(
+        &ship.cargo_map(),
+        ship.cargo_space_available(),
+        &prices,
+        &survey.survey,
+    )
+}
+
 async fn engineered_asteroid_location(ship: &ShipController) -> WaypointSymbol {
     let waypoints = ship
         .ctx
@@ -70,6 +141,7 @@ pub async fn run_surveyor(ship: ShipController, ac: AgentController) {
         if super::home_phase_done(&ac) {
             return super::scrap::run(ship).await;
         }
+        super::wait_while_paused(&ship, &ac).await;
         // Automatically pushes to the survey manager
         ship.survey().await;
     }
@@ -80,18 +152,27 @@ pub async fn run_mining_drone(ship: ShipController, ac: AgentController) {
     ship.wait_for_transit().await;
 
     let asteroid_location = engineered_asteroid_location(&ship).await;
+    ship.ctx
+        .mining_stats
+        .register_ship(&ship.symbol(), &asteroid_location);
     ship.goto_waypoint(&asteroid_location).await;
 
     loop {
         if super::home_phase_done(&ac) {
             return super::scrap::run(ship).await;
         }
-        let should_extract = ship.cargo_space_available() >= 4;
+        super::wait_while_paused(&ship, &ac).await;
+        let should_extract = ship.cargo_space_available() >= EXTRACT_MIN_SPACE;
         if should_extract {
             // wait for cooldown before taking survey, helps to get a non-exhausted one
             ship.wait_for_cooldown().await;
             // get survey + extract
-            let survey = ship.ctx.survey_manager.get_survey(&asteroid_location).await;
+            let prices = system_sell_prices(&ship).await;
+            let survey = ship
+                .ctx
+                .survey_manager
+                .best_survey(&asteroid_location, &prices)
+                .await;
             let survey = match survey {
                 Some(s) => s,
                 None => {
@@ -107,6 +188,9 @@ pub async fn run_mining_drone(ship: ShipController, ac: AgentController) {
                     ship.jettison_cargo(&cargo, units).await;
                 }
             }
+        } else if let Some((good, units)) = survey_jettison(&ship, &asteroid_location).await {
+            debug!("Mining drone jettisoning {} {} to make room", units, good);
+            ship.jettison_cargo(&good, units).await;
         } else {
             // transfer goods to shuttle, and wait till completed
             debug!("Mining drone transfer initiated");
@@ -133,6 +217,9 @@ pub async fn run_shuttle(ship: ShipController, db: DbClient, ac: AgentController
     ship.wait_for_transit().await;
 
     let asteroid_location = engineered_asteroid_location(&ship).await;
+    ship.ctx
+        .mining_stats
+        .register_ship(&ship.symbol(), &asteroid_location);
 
     let key = format!("extract_shuttle_state/{}", ship.symbol());
     let mut state: MiningShuttleState = db.get_value(&key).await.unwrap_or(Loading);
@@ -141,6 +228,7 @@ pub async fn run_shuttle(ship: ShipController, db: DbClient, ac: AgentController
         if super::home_phase_done(&ac) {
             return super::scrap::run(ship).await;
         }
+        super::wait_while_paused(&ship, &ac).await;
         match state {
             Loading => {
                 if ship.cargo_space_available() == 0 {
@@ -204,3 +292,64 @@ pub async fn run_shuttle(ship: ShipController, db: DbClient, ac: AgentController
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_jettison_byproduct_for_valuable_survey() {
+        let cargo = BTreeMap::from([
+            ("IRON_ORE".to_string(), 30),
+            ("ALUMINUM_ORE".to_string(), 8),
+            ("QUARTZ_SAND".to_string(), 2),
+        ]);
+        let prices = BTreeMap::from([
+            ("IRON_ORE".to_string(), 60),
+            ("ALUMINUM_ORE".to_string(), 30),
+            ("QUARTZ_SAND".to_string(), 45),
+            ("COPPER_ORE".to_string(), 110),
+        ]);
+        let survey = |deposits: &[&str]| Survey {
+            signature: "X1-S1-A1-1".to_string(),
+            symbol: WaypointSymbol::new("X1-S1-A1"),
+            deposits: deposits
+                .iter()
+                .map(|d| Symbol {
+                    symbol: d.to_string(),
+                })
+                .collect(),
+            expiration: Utc::now(),
+            size: "MODERATE".to_string(),
+        };
+        let copper = survey(&["COPPER_ORE", "COPPER_ORE", "IRON_ORE"]);
+
+        // full hold: the cheap byproduct goes, only as much as an extraction needs
+        assert_eq!(
+            make_room_jettison(&cargo, 0, &prices, &copper),
+            Some(("ALUMINUM_ORE".to_string(), 4))
+        );
+        assert_eq!(
+            make_room_jettison(&cargo, 3, &prices, &copper),
+            Some(("ALUMINUM_ORE".to_string(), 1))
+        );
+        // enough room already
+        assert_eq!(make_room_jettison(&cargo, 4, &prices, &copper), None);
+        // a survey no better than what we hold isn't worth dumping cargo for
+        let iron = survey(&["IRON_ORE", "QUARTZ_SAND"]);
+        assert_eq!(make_room_jettison(&cargo, 0, &prices, &iron), None);
+        // never dump what the survey yields
+        let aluminum = survey(&[
+            "ALUMINUM_ORE",
+            "COPPER_ORE",
+            "COPPER_ORE",
+            "COPPER_ORE",
+            "COPPER_ORE",
+        ]);
+        assert_eq!(
+            make_room_jettison(&cargo, 0, &prices, &aluminum),
+            Some(("QUARTZ_SAND".to_string(), 2))
+        );
+    }
+}
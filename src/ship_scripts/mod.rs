@@ -1,14 +1,21 @@
 pub mod construction;
 pub mod exploration;
+pub mod leftovers;
 pub mod logistics;
 pub mod mining;
 pub mod probe;
 pub mod probe_exploration;
+pub mod repair;
 pub mod scrap;
 pub mod siphon;
 pub mod t5_trader;
 
 use crate::agent_controller::{AgentController, AgentEra};
+use crate::ship_controller::ShipController;
+use log::*;
+
+// How often a paused ship checks whether it's been resumed
+const PAUSE_POLL_SECONDS: i64 = 60;
 
 /// True once the agent has advanced past the home-system build-out (the jump gate
 /// is built). The ships that exist only to fund and feed gate construction — the
@@ -24,3 +31,27 @@ pub fn home_phase_done(ac: &AgentController) -> bool {
         AgentEra::StartingSystem1 | AgentEra::StartingSystem2
     )
 }
+
+/// Park the ship where it is while an operator pause covers its job (see
+/// `agent_controller::pause`). Scripts call this at safe points: the top of their loop,
+/// between whole actions. During a graceful shutdown the ship parks here for good.
+pub async fn wait_while_paused(ship: &ShipController, ac: &AgentController) {
+    if ac.is_stopping() {
+        info!("{} stopped for shutdown", ship.symbol());
+        ship.set_state_description("Stopped");
+        futures::future::pending::<()>().await;
+    }
+    if !ac.fleet.is_ship_paused(&ship.symbol()) {
+        return;
+    }
+    info!("{} paused", ship.symbol());
+    ship.wait_for_transit().await;
+    ship.set_state_description("Paused");
+    while ac.fleet.is_ship_paused(&ship.symbol()) {
+        ship.ctx
+            .clock
+            .sleep(chrono::Duration::seconds(PAUSE_POLL_SECONDS))
+            .await;
+    }
+    info!("{} resumed", ship.symbol());
+}
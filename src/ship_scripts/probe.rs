@@ -1,11 +1,13 @@
 use crate::{
-    models::{ProbeScriptConfig, WaypointSymbol},
-    ship_controller::ShipController,
+    agent_controller::{AgentController, fleet::probe_refresh_target},
+    config::CONFIG,
+    models::{ProbeScriptConfig, SystemSymbol, WaypointSymbol},
+    ship_controller::{NavigateError, ShipController},
 };
 use chrono::{DateTime, Duration, Utc};
 use lazy_static::lazy_static;
 use log::*;
-use pathfinding::directed::dijkstra::dijkstra;
+use std::collections::BTreeMap;
 use std::ops::Add as _;
 
 lazy_static! {
@@ -13,39 +15,136 @@ lazy_static! {
     static ref SHIPYARD_REFRESH_INTERVAL: Duration = Duration::try_minutes(60).unwrap();
 }
 
+// A probe gives up on a waypoint after this many failed trips to it in a row
+const MAX_WAYPOINT_NAV_FAILURES: u32 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+enum InvalidWaypoint {
+    // not in the galaxy we've loaded: a config typo, or the chart changed
+    Unknown,
+    // a roaming probe has no fuel tank and never jumps, so it can't leave its system
+    OtherSystem,
+}
+
+fn invalid_waypoints(
+    waypoints: &[WaypointSymbol],
+    ship_system: &SystemSymbol,
+    roaming: bool,
+    exists: impl Fn(&WaypointSymbol) -> bool,
+) -> Vec<(WaypointSymbol, InvalidWaypoint)> {
+    waypoints
+        .iter()
+        .filter_map(|waypoint| {
+            if !exists(waypoint) {
+                Some((waypoint.clone(), InvalidWaypoint::Unknown))
+            } else if roaming && &waypoint.system() != ship_system {
+                Some((waypoint.clone(), InvalidWaypoint::OtherSystem))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// The waypoints a probe cycles through, minus any it has given up on
+struct ProbeRotation {
+    waypoints: Vec<WaypointSymbol>,
+    failures: BTreeMap<WaypointSymbol, u32>,
+}
+
+impl ProbeRotation {
+    fn new(waypoints: Vec<WaypointSymbol>) -> Self {
+        ProbeRotation {
+            waypoints,
+            failures: BTreeMap::new(),
+        }
+    }
+
+    fn record_success(&mut self, waypoint: &WaypointSymbol) {
+        self.failures.remove(waypoint);
+    }
+
+    // True if the waypoint has now failed too often and was dropped
+    fn record_failure(&mut self, waypoint: &WaypointSymbol) -> bool {
+        let failures = self.failures.entry(waypoint.clone()).or_insert(0);
+        *failures += 1;
+        if *failures < MAX_WAYPOINT_NAV_FAILURES {
+            return false;
+        }
+        self.failures.remove(waypoint);
+        self.waypoints.retain(|w| w != waypoint);
+        true
+    }
+}
+
+// The job can't be done: say so and stop, leaving the ship parked
+fn fail_job(ship: &ShipController, reason: &str) {
+    error!("Probe {} job failed: {}", ship.symbol(), reason);
+    ship.set_state_description(&format!("Job failed: {}", reason));
+}
+
+// A waypoint the probe keeps failing to reach was dropped from its rotation
+fn report_dropped(ship: &ShipController, waypoint: &WaypointSymbol, remaining: usize) {
+    warn!(
+        "Probe {} dropped {} from its rotation after {} failed trips; {} waypoints left",
+        ship.symbol(),
+        waypoint,
+        MAX_WAYPOINT_NAV_FAILURES,
+        remaining
+    );
+    ship.set_state_description(&format!(
+        "Degraded: dropped unreachable {} ({} left)",
+        waypoint, remaining
+    ));
+}
+
 // Navigate to `target`, hopping gate-to-gate across the charted jump-gate network when
 // it's in another system (the home-system probes that started this code only ever do a
 // single jump). If the destination isn't reachable yet — its gate, or a gate on the
 // path, hasn't been charted — wait and retry rather than panicking, since the frontier
 // is still being charted by the jumpgate probes.
 pub async fn goto_waypoint_anywhere(ship: &ShipController, target: &WaypointSymbol) {
+    if let Err(e) = try_goto_waypoint_anywhere(ship, target).await {
+        panic!(
+            "{}: navigation to {} failed: {:?}",
+            ship.symbol(),
+            target,
+            e
+        );
+    }
+}
+
+// Travel to another system, arriving at its jump gate
+pub async fn goto_system(ship: &ShipController, system: &SystemSymbol) {
+    if ship.system() == *system {
+        return;
+    }
+    let gate = ship.ctx.universe.get_jumpgate(system).await;
+    goto_waypoint_anywhere(ship, &gate).await;
+}
+
+async fn try_goto_waypoint_anywhere(
+    ship: &ShipController,
+    target: &WaypointSymbol,
+) -> Result<(), NavigateError> {
     let target_system = target.system();
     loop {
         if ship.system() == target_system {
-            ship.goto_waypoint(target).await;
-            return;
+            return ship.try_goto_waypoint(target).await;
         }
         let start_gate = ship.ctx.universe.get_jumpgate(&ship.system()).await;
-        let dest_gate = ship.ctx.universe.get_jumpgate(&target_system).await;
-        let graph = ship.ctx.universe.jumpgate_graph().await;
-        let route = dijkstra(
-            &start_gate,
-            |node| {
-                graph
-                    .get(node)
-                    .map(|n| n.active_connections.clone())
-                    .unwrap_or_default()
-            },
-            |node| node == &dest_gate,
-        );
+        let route = ship
+            .ctx
+            .universe
+            .jump_route(&ship.system(), &target_system)
+            .await;
         match route {
-            Some((path, _)) => {
+            Some(route) => {
                 ship.goto_waypoint(&start_gate).await;
-                for gate in path.iter().skip(1) {
+                for (gate, _cooldown) in &route.hops {
                     ship.jump(gate).await;
                 }
-                ship.goto_waypoint(target).await;
-                return;
+                return ship.try_goto_waypoint(target).await;
             }
             None => {
                 ship.set_state_description(&format!(
@@ -58,11 +157,30 @@ pub async fn goto_waypoint_anywhere(ship: &ShipController, target: &WaypointSymb
     }
 }
 
-pub async fn run(ship_controller: ShipController, config: &ProbeScriptConfig) {
-    if config.waypoints.len() == 1 {
-        probe_single_location(ship_controller, config).await;
+pub async fn run(ship_controller: ShipController, config: &ProbeScriptConfig, ac: AgentController) {
+    let roaming = config.waypoints.len() > 1;
+    let invalid = invalid_waypoints(
+        &config.waypoints,
+        &ship_controller.system(),
+        roaming,
+        |waypoint| ship_controller.ctx.universe.waypoint_exists(waypoint),
+    );
+    let mut config = config.clone();
+    if !invalid.is_empty() {
+        let reason = format!("invalid waypoints {:?}", invalid);
+        if CONFIG.probe_fail_on_invalid_waypoint || invalid.len() == config.waypoints.len() {
+            fail_job(&ship_controller, &reason);
+            return;
+        }
+        warn!("Probe {} skipping {}", ship_controller.symbol(), reason);
+        config
+            .waypoints
+            .retain(|w| !invalid.iter().any(|(invalid, _)| invalid == w));
+    }
+    if config.waypoints.len() == 1 && !roaming {
+        probe_single_location(ship_controller, &config, &ac).await;
     } else {
-        probe_multiple_locations(ship_controller, config).await;
+        probe_multiple_locations(ship_controller, &config, &ac).await;
     }
 }
 
@@ -70,7 +188,11 @@ pub async fn run(ship_controller: ShipController, config: &ProbeScriptConfig) {
 // - doesn't take into account whether the market has been refreshed recently
 // - uses extra api requests to move between waypoints
 // Additionally, cannot be used to buy ships
-pub async fn probe_multiple_locations(ship: ShipController, config: &ProbeScriptConfig) {
+pub async fn probe_multiple_locations(
+    ship: ShipController,
+    config: &ProbeScriptConfig,
+    ac: &AgentController,
+) {
     assert!(config.refresh_market);
 
     let waypoint_symbols = config
@@ -86,28 +208,45 @@ pub async fn probe_multiple_locations(ship: ShipController, config: &ProbeScript
     );
     ship.wait_for_transit().await;
 
-    let mut waypoints = vec![];
+    let mut waypoints = BTreeMap::new();
     for waypoint_symbol in &config.waypoints {
         let waypoint = ship.ctx.universe.detailed_waypoint(waypoint_symbol).await;
-        waypoints.push(waypoint);
+        waypoints.insert(waypoint_symbol.clone(), waypoint);
     }
+    let mut rotation = ProbeRotation::new(config.waypoints.clone());
 
     // Random sleep for a gentler startup
-    let rand_start_sleep = rand::random::<u64>() % 60;
-    tokio::time::sleep(tokio::time::Duration::from_secs(rand_start_sleep)).await;
+    let rand_start_sleep = rand::random::<i64>().rem_euclid(60);
+    ship.ctx
+        .clock
+        .sleep(Duration::seconds(rand_start_sleep))
+        .await;
     let mut last_cycle_start: Option<DateTime<Utc>> = None;
     loop {
         if let Some(last_cycle_start) = last_cycle_start {
-            let sleep_duration =
-                last_cycle_start + Duration::try_minutes(15).unwrap() - chrono::Utc::now();
+            // read each lap, so an era advance takes effect on the next one
+            let next_cycle = last_cycle_start + probe_refresh_target(ac.state().era);
+            let sleep_duration = next_cycle - ship.ctx.clock.now();
             if sleep_duration > Duration::zero() {
                 debug!("Sleeping for {:.3}s", sleep_duration.num_seconds() as f64);
-                tokio::time::sleep(sleep_duration.to_std().unwrap()).await;
+                ship.ctx.clock.sleep_until(next_cycle).await;
             }
         }
-        last_cycle_start = Some(chrono::Utc::now());
-        for waypoint in &waypoints {
-            ship.goto_waypoint(&waypoint.symbol).await;
+        last_cycle_start = Some(ship.ctx.clock.now());
+        for symbol in rotation.waypoints.clone() {
+            if let Err(e) = ship.try_goto_waypoint(&symbol).await {
+                warn!("Probe {} couldn't reach {}: {:?}", ship.symbol(), symbol, e);
+                if rotation.record_failure(&symbol) {
+                    report_dropped(&ship, &symbol, rotation.waypoints.len());
+                    if rotation.waypoints.is_empty() {
+                        fail_job(&ship, "no reachable waypoints left");
+                        return;
+                    }
+                }
+                continue;
+            }
+            rotation.record_success(&symbol);
+            let waypoint = &waypoints[&symbol];
             ship.refresh_market().await;
 
             if waypoint.is_shipyard() {
@@ -132,7 +271,11 @@ pub async fn probe_multiple_locations(ship: ShipController, config: &ProbeScript
 
 // Sit at a single location, refreshing market and shipyards (when needed)
 // capable of being used to buy ships
-pub async fn probe_single_location(ship_controller: ShipController, config: &ProbeScriptConfig) {
+pub async fn probe_single_location(
+    ship_controller: ShipController,
+    config: &ProbeScriptConfig,
+    ac: &AgentController,
+) {
     assert_eq!(config.waypoints.len(), 1);
     let waypoint_symbol = &config.waypoints[0];
     info!(
@@ -148,7 +291,21 @@ pub async fn probe_single_location(ship_controller: ShipController, config: &Pro
         .await;
 
     // Route to the waypoint, jumping across systems as needed (may be several gates away).
-    goto_waypoint_anywhere(&ship_controller, waypoint_symbol).await;
+    let mut rotation = ProbeRotation::new(vec![waypoint_symbol.clone()]);
+    while let Err(e) = try_goto_waypoint_anywhere(&ship_controller, waypoint_symbol).await {
+        warn!(
+            "Probe {} couldn't reach {}: {:?}",
+            ship_controller.symbol(),
+            waypoint_symbol,
+            e
+        );
+        if rotation.record_failure(waypoint_symbol) {
+            report_dropped(&ship_controller, waypoint_symbol, 0);
+            fail_job(&ship_controller, "no reachable waypoints left");
+            return;
+        }
+        ship_controller.ctx.clock.sleep(Duration::seconds(60)).await;
+    }
     ship_controller.dock().await; // don't need to dock, but do so anyway to clear 'InTransit' status
 
     if !config.refresh_market {
@@ -156,12 +313,16 @@ pub async fn probe_single_location(ship_controller: ShipController, config: &Pro
     }
 
     // Random sleep for a gentler startup
-    let rand_start_sleep = rand::random::<u64>() % 60;
-    tokio::time::sleep(tokio::time::Duration::from_secs(rand_start_sleep)).await;
+    let rand_start_sleep = rand::random::<i64>().rem_euclid(60);
+    ship_controller
+        .ctx
+        .clock
+        .sleep(Duration::seconds(rand_start_sleep))
+        .await;
 
     loop {
-        let now = chrono::Utc::now();
-        let mut next: DateTime<Utc> = now + Duration::try_minutes(15).unwrap();
+        let now = ship_controller.ctx.clock.now();
+        let mut next: DateTime<Utc> = now + probe_refresh_target(ac.state().era);
         if waypoint.is_market() {
             let market = ship_controller.ctx.universe.get_market(waypoint_symbol);
             let next_refresh = match market {
@@ -191,9 +352,65 @@ pub async fn probe_single_location(ship_controller: ShipController, config: &Pro
         let sleep_duration = next - now;
         if sleep_duration > Duration::zero() {
             debug!("Sleeping for {:.3}s", sleep_duration.num_seconds() as f64);
-            tokio::time::sleep(sleep_duration.to_std().unwrap()).await;
+            ship_controller.ctx.clock.sleep_until(next).await;
         }
     }
 
     // info!("Finished script probe for {}", ship_controller.symbol());
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_failing_waypoint_dropped_from_rotation() {
+        let (a, b, c) = (
+            WaypointSymbol::new("X1-S1-A1"),
+            WaypointSymbol::new("X1-S1-B1"),
+            WaypointSymbol::new("X1-S1-C1"),
+        );
+        let mut rotation = ProbeRotation::new(vec![a.clone(), b.clone(), c.clone()]);
+        // b always fails; c fails once, then recovers
+        let reachable = |w: &WaypointSymbol, cycle: u32| w != &b && !(w == &c && cycle == 0);
+        let mut dropped = vec![];
+        for cycle in 0..5 {
+            for w in rotation.waypoints.clone() {
+                if reachable(&w, cycle) {
+                    rotation.record_success(&w);
+                } else if rotation.record_failure(&w) {
+                    dropped.push((cycle, w));
+                }
+            }
+        }
+        assert_eq!(dropped, vec![(MAX_WAYPOINT_NAV_FAILURES - 1, b.clone())]);
+        assert_eq!(rotation.waypoints, vec![a.clone(), c.clone()]);
+
+        // a single waypoint that never works empties the rotation
+        let mut single = ProbeRotation::new(vec![b.clone()]);
+        for _ in 1..MAX_WAYPOINT_NAV_FAILURES {
+            assert!(!single.record_failure(&b));
+        }
+        assert!(single.record_failure(&b));
+        assert!(single.waypoints.is_empty());
+
+        let system = a.system();
+        let typo = WaypointSymbol::new("X1-S1-ZZ9");
+        let elsewhere = WaypointSymbol::new("X1-S2-A1");
+        let invalid = invalid_waypoints(
+            &[a.clone(), typo.clone(), elsewhere.clone()],
+            &system,
+            true,
+            |w| w != &typo,
+        );
+        assert_eq!(
+            invalid,
+            vec![
+                (typo.clone(), InvalidWaypoint::Unknown),
+                (elsewhere.clone(), InvalidWaypoint::OtherSystem)
+            ]
+        );
+        // a stationary probe can jump there
+        assert!(invalid_waypoints(&[elsewhere], &system, false, |_| true).is_empty());
+    }
+}
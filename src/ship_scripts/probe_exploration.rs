@@ -25,6 +25,7 @@ pub async fn run_jumpgate_probe(ship: ShipController, ac: AgentController) {
     // gates, so an idle probe polls and resumes when new targets appear.
     let mut state = Init;
     loop {
+        super::wait_while_paused(&ship, &ac).await;
         let next_state = tick(&ship, &state, &ac).await;
         if let Some(next_state) = next_state {
             state = next_state;
@@ -0,0 +1,58 @@
+//!
+//! Repair script for ships
+//!
+//! Repair at the closest shipyard before the ship resumes its job
+//!
+
+use crate::ship_controller::ShipController;
+use log::*;
+
+// Returns whether the ship was repaired. `travel` allows going to another shipyard in
+// the system; without it, only a shipyard at the ship's current waypoint will do.
+pub async fn run(ship: &ShipController, travel: bool) -> bool {
+    info!("Starting script repair for {}", ship.symbol());
+    ship.wait_for_transit().await;
+
+    let system_symbol = ship.system();
+    let waypoints = ship.ctx.universe.get_system_waypoints(&system_symbol).await;
+    let shipyards = ship
+        .ctx
+        .universe
+        .get_system_shipyards_remote(&system_symbol)
+        .await;
+
+    let current_waypoint = waypoints
+        .iter()
+        .find(|w| w.symbol == ship.waypoint())
+        .unwrap();
+    let shipyard = shipyards
+        .iter()
+        .filter(|s| travel || s.symbol == current_waypoint.symbol)
+        .min_by_key(|s| {
+            let w = waypoints.iter().find(|w| w.symbol == s.symbol).unwrap();
+            (current_waypoint.x - w.x).pow(2) + (current_waypoint.y - w.y).pow(2)
+        });
+    let shipyard = match shipyard {
+        Some(s) => s,
+        None => {
+            info!("No shipyard in reach. Failed to repair {}", ship.symbol());
+            return false;
+        }
+    };
+
+    ship.set_state_description(&format!("Repairing ship at {}", shipyard.symbol));
+    ship.goto_waypoint(&shipyard.symbol).await;
+    let cost = ship.get_repair_cost().await;
+    let available = ship.ctx.ledger.available_credits();
+    if cost > available {
+        info!(
+            "Can't afford to repair {}: ${} (available ${})",
+            ship.symbol(),
+            cost,
+            available
+        );
+        return false;
+    }
+    ship.repair().await;
+    true
+}
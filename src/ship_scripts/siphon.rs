@@ -1,3 +1,4 @@
+use super::leftovers;
 use crate::{
     agent_controller::AgentController, database::DbClient, models::WaypointSymbol,
     ship_controller::ShipController, universe::WaypointFilter,
@@ -52,12 +53,16 @@ pub async fn run_drone(ship: ShipController, ac: AgentController) {
     ship.wait_for_transit().await;
 
     let siphon_location = siphon_location(&ship).await;
+    ship.ctx
+        .mining_stats
+        .register_ship(&ship.symbol(), &siphon_location);
     ship.goto_waypoint(&siphon_location).await;
 
     loop {
         if SIPHON_RETIRED || super::home_phase_done(&ac) {
             return super::scrap::run(ship).await;
         }
+        super::wait_while_paused(&ship, &ac).await;
         let should_siphon = ship.cargo_space_available() > 0;
         if should_siphon {
             ship.siphon().await;
@@ -83,14 +88,19 @@ pub async fn run_shuttle(ship: ShipController, db: DbClient, ac: AgentController
 
     let siphon_location = siphon_location(&ship).await;
     let sell_location = sell_location(&ship).await;
+    ship.ctx
+        .mining_stats
+        .register_ship(&ship.symbol(), &siphon_location);
 
     let key = format!("siphon_shuttle_state/{}", ship.symbol());
     let mut state: SiphonShuttleState = db.get_value(&key).await.unwrap_or(Loading);
+    let mut unsellable = leftovers::UnsellableCargo::default();
 
     loop {
         if SIPHON_RETIRED || super::home_phase_done(&ac) {
             return super::scrap::run(ship).await;
         }
+        super::wait_while_paused(&ship, &ac).await;
         match state {
             Loading => {
                 if ship.cargo_space_available() == 0 {
@@ -109,7 +119,19 @@ pub async fn run_shuttle(ship: ShipController, db: DbClient, ac: AgentController
                     continue;
                 }
                 ship.goto_waypoint(&sell_location).await;
-                ship.sell_all_cargo().await;
+                let cargo = ship.cargo_map().into_iter().collect::<Vec<_>>();
+                let unsold = ship.sell_or_carry(&cargo).await;
+                // whatever nobody would take brings us straight back here, so it only
+                // gets a few tries (UNSELLABLE_JETTISON_ATTEMPTS)
+                for (good, units) in unsellable.record(&unsold, leftovers::max_offload_attempts()) {
+                    warn!(
+                        "{}: jettisoning {} unsellable {}",
+                        ship.symbol(),
+                        units,
+                        good
+                    );
+                    ship.jettison_cargo(&good, units).await;
+                }
             }
         }
     }
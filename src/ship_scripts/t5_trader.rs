@@ -1,5 +1,3 @@
-use std::sync::Arc;
-
 use chrono::Duration;
 
 use crate::{
@@ -7,7 +5,6 @@ use crate::{
     database::DbClient,
     models::{LogisticsScriptConfig, PlanLength, PlannerConfig},
     ship_controller::ShipController,
-    tasks::LogisticTaskManager,
 };
 use log::*;
 
@@ -79,12 +76,10 @@ pub async fn run_t5_trader(ship: ShipController, _db: DbClient, ac: AgentControl
         }
     }
 
-    // Use a task manager scoped to this system — the shared one (ac.task_manager)
+    // Use the task manager scoped to this system — the home one (ac.task_manager)
     // only plans for the starting system, so its market set wouldn't contain this
-    // system's waypoints. State is persisted per-system in the DB.
-    let task_manager = LogisticTaskManager::new(&ship.ctx.universe, &ship.ctx.db, &system).await;
-    task_manager.set_agent_controller(&ac);
-    let task_manager = Arc::new(task_manager);
+    // system's waypoints. Traders in the same system share it.
+    let task_manager = ac.task_managers.get(&system).await;
 
     info!("T5 trader trading in target system {}", system);
     ship.set_state_description(&format!("Trading in {}", system));
@@ -1,6 +1,6 @@
 use crate::database::DbClient;
 use crate::models::{KeyedSurvey, Survey, WaypointSymbol};
-use chrono::Duration;
+use chrono::{DateTime, Utc};
 use std::collections::BTreeMap;
 use std::sync::Mutex;
 
@@ -14,10 +14,20 @@ struct SurveyManagerInner {
 }
 
 impl SurveyManager {
+    // Surveys are persisted as they're inserted/removed, so the ones still valid
+    // survive a restart; expired ones are pruned here
     pub async fn new(db: &DbClient) -> Self {
+        let now = Utc::now();
+        let pruned = db.remove_expired_surveys(now).await;
         let surveys = db.get_surveys().await;
+        log::info!(
+            "Loaded {} surveys ({} expired surveys pruned)",
+            surveys.len(),
+            pruned
+        );
         let surveys = surveys
             .into_iter()
+            .filter(|survey| !is_expired(&survey.survey, now))
             .fold(BTreeMap::new(), |mut map, survey| {
                 map.entry(survey.survey.symbol.clone())
                     .or_insert_with(Vec::new)
@@ -30,6 +40,16 @@ impl SurveyManager {
         }
     }
 
+    #[cfg(test)]
+    pub(crate) fn empty_for_test(db: &DbClient) -> Self {
+        Self {
+            db: db.clone(),
+            inner: Mutex::new(SurveyManagerInner {
+                surveys: BTreeMap::new(),
+            }),
+        }
+    }
+
     pub async fn insert_surveys(&self, surveys: Vec<Survey>) {
         let surveys: Vec<KeyedSurvey> = surveys
             .into_iter()
@@ -49,50 +69,36 @@ impl SurveyManager {
         }
     }
 
-    fn survey_score(&self, survey: &Survey) -> f64 {
-        let mut score = 0.0;
-        for deposit in &survey.deposits {
-            score += match deposit.symbol.as_str() {
-                // FAB_MATS:
-                "IRON_ORE" => 1.0,
-                "QUARTZ_SAND" => 1.0,
-                // ADVANCED CIRCUITS
-                "COPPER_ORE" => 1.0,
-                "SILICON_CRYSTALS" => 1.0,
-                // USELESS
-                "ALUMINUM_ORE" => 0.0,
-                "ICE_WATER" => 0.0,
-                _ => panic!("Unexpected deposit symbol: {}", deposit.symbol),
-            };
-        }
-        score / survey.deposits.len() as f64
-    }
-
-    pub async fn get_survey(&self, waypoint: &WaypointSymbol) -> Option<KeyedSurvey> {
-        let now = chrono::Utc::now();
-        loop {
-            // grab front
-            let best = {
-                let mut inner = self.inner.lock().unwrap();
-                let surveys = inner.surveys.entry(waypoint.clone()).or_default();
-                surveys.sort_by(|a, b| {
-                    self.survey_score(&a.survey)
-                        .partial_cmp(&self.survey_score(&b.survey))
-                        .unwrap()
-                });
-                surveys.last().cloned()
-            };
-            // delete or return
-            if let Some(survey) = best {
-                if survey.survey.expiration + Duration::try_minutes(5).unwrap() < now {
-                    self.remove_survey(&survey).await;
-                } else {
-                    return Some(survey.clone());
-                }
-            } else {
-                return None;
-            }
+    // The waypoint's most valuable unexpired survey by `expected_value` at
+    // `market_prices` (good -> best sell price). Expired surveys found along the way are
+    // deleted. Ties go to more deposits, then the later expiration.
+    pub async fn best_survey(
+        &self,
+        waypoint: &WaypointSymbol,
+        market_prices: &BTreeMap<String, i64>,
+    ) -> Option<KeyedSurvey> {
+        let now = Utc::now();
+        let (best, expired) = {
+            let inner = self.inner.lock().unwrap();
+            let surveys = inner.surveys.get(waypoint)?;
+            let (expired, live): (Vec<_>, Vec<_>) = surveys
+                .iter()
+                .partition(|survey| is_expired(&survey.survey, now));
+            let best = live.into_iter().max_by(|a, b| {
+                expected_value(&a.survey, market_prices)
+                    .total_cmp(&expected_value(&b.survey, market_prices))
+                    .then(a.survey.deposits.len().cmp(&b.survey.deposits.len()))
+                    .then(a.survey.expiration.cmp(&b.survey.expiration))
+            });
+            (
+                best.cloned(),
+                expired.into_iter().cloned().collect::<Vec<_>>(),
+            )
+        };
+        for survey in &expired {
+            self.remove_survey(survey).await;
         }
+        best
     }
 
     pub async fn remove_survey(&self, survey: &KeyedSurvey) {
@@ -108,3 +114,125 @@ impl SurveyManager {
             });
     }
 }
+
+fn is_expired(survey: &Survey, now: DateTime<Utc>) -> bool {
+    survey.expiration <= now
+}
+
+// Expected credits per extraction: each extraction yields one of the deposits, so the
+// mean deposit sell price (unpriced goods count 0), scaled up for larger deposits,
+// which hold out for more extractions before the survey is exhausted
+pub fn expected_value(survey: &Survey, market_prices: &BTreeMap<String, i64>) -> f64 {
+    if survey.deposits.is_empty() {
+        return 0.0;
+    }
+    let total = survey
+        .deposits
+        .iter()
+        .map(|d| market_prices.get(&d.symbol).copied().unwrap_or(0))
+        .sum::<i64>();
+    let size_factor = match survey.size.as_str() {
+        "LARGE" => 1.5,
+        "MODERATE" => 1.25,
+        _ => 1.0,
+    };
+    total as f64 / survey.deposits.len() as f64 * size_factor
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn survey(deposits: &[&str], size: &str, expiration: DateTime<Utc>) -> KeyedSurvey {
+        KeyedSurvey {
+            uuid: uuid::Uuid::new_v4(),
+            survey: Survey {
+                signature: "X1-S1-B2-ABCDEF".to_string(),
+                symbol: WaypointSymbol::new("X1-S1-B2"),
+                deposits: deposits
+                    .iter()
+                    .map(|d| crate::models::Symbol {
+                        symbol: d.to_string(),
+                    })
+                    .collect(),
+                expiration,
+                size: size.to_string(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_best_survey() {
+        let prices = BTreeMap::from([
+            ("IRON_ORE".to_string(), 40),
+            ("COPPER_ORE".to_string(), 60),
+            ("ICE_WATER".to_string(), 10),
+        ]);
+        let later = Utc::now() + chrono::Duration::minutes(30);
+        let ice = survey(&["ICE_WATER", "ICE_WATER", "IRON_ORE"], "LARGE", later);
+        let copper = survey(&["COPPER_ORE", "IRON_ORE"], "SMALL", later);
+        let copper_moderate = survey(&["COPPER_ORE", "IRON_ORE"], "MODERATE", later);
+        let unpriced = survey(&["ALUMINUM_ORE"], "LARGE", later);
+        assert_eq!(expected_value(&ice.survey, &prices), 30.0);
+        assert_eq!(expected_value(&copper.survey, &prices), 50.0);
+        assert_eq!(expected_value(&copper_moderate.survey, &prices), 62.5);
+        assert_eq!(expected_value(&unpriced.survey, &prices), 0.0);
+
+        let manager = SurveyManager::empty_for_test(&DbClient::disconnected());
+        let waypoint = WaypointSymbol::new("X1-S1-B2");
+        let set = |surveys: Vec<KeyedSurvey>| {
+            manager
+                .inner
+                .lock()
+                .unwrap()
+                .surveys
+                .insert(waypoint.clone(), surveys);
+        };
+        assert!(manager.best_survey(&waypoint, &prices).await.is_none());
+
+        set(vec![ice.clone(), copper.clone(), copper_moderate.clone()]);
+        let best = manager.best_survey(&waypoint, &prices).await.unwrap();
+        assert_eq!(best.uuid, copper_moderate.uuid);
+
+        // equal value: more deposits first, then the later expiration
+        let copper_x2 = survey(
+            &["COPPER_ORE", "IRON_ORE", "COPPER_ORE", "IRON_ORE"],
+            "SMALL",
+            later,
+        );
+        let copper_sooner = survey(
+            &["COPPER_ORE", "IRON_ORE"],
+            "SMALL",
+            later - chrono::Duration::minutes(10),
+        );
+        set(vec![
+            copper.clone(),
+            copper_x2.clone(),
+            copper_sooner.clone(),
+        ]);
+        let best = manager.best_survey(&waypoint, &prices).await.unwrap();
+        assert_eq!(best.uuid, copper_x2.uuid);
+        set(vec![copper_sooner, copper.clone()]);
+        let best = manager.best_survey(&waypoint, &prices).await.unwrap();
+        assert_eq!(best.uuid, copper.uuid);
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let now = Utc::now();
+        let survey = |expiration| Survey {
+            signature: "X1-S1-B2-ABCDEF".to_string(),
+            symbol: WaypointSymbol::new("X1-S1-B2"),
+            deposits: vec![],
+            expiration,
+            size: "SMALL".to_string(),
+        };
+        assert!(!is_expired(
+            &survey(now + chrono::Duration::seconds(1)),
+            now
+        ));
+        assert!(is_expired(&survey(now), now));
+        // expired a moment ago: never handed out, even within the old grace period
+        assert!(is_expired(&survey(now - chrono::Duration::minutes(1)), now));
+    }
+}
@@ -3,20 +3,21 @@ use crate::api_client::api_models::WaypointDetailed;
 use crate::config::CONFIG;
 use crate::database::DbClient;
 use crate::logistics_planner::{
-    self, Action, LogisticShip, PlannerConstraints, ScheduledAction, ShipSchedule, Task,
-    TaskActions,
+    self, Action, LogisticShip, PlannerConstraints, PlannerRationale, ScheduledAction,
+    ShipSchedule, Task, TaskActions,
 };
 use crate::models::MarketSupply::*;
 use crate::models::MarketType::*;
 use crate::models::*;
 use crate::models::{LogisticsScriptConfig, MarketActivity::*};
-use crate::universe::{Universe, WaypointFilter};
+use crate::universe::{TravelMatrix, Universe, WaypointFilter};
 use chrono::{DateTime, Duration, Utc};
 use dashmap::DashMap;
 use log::*;
 use serde::{Deserialize, Serialize};
 use std::cmp::min;
 use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
 
 fn is_task_allowed(task: &Task, config: &LogisticsScriptConfig) -> bool {
@@ -72,6 +73,68 @@ pub struct TaskManagerState {
     planner_run_count: u64,
 }
 
+impl TaskManagerState {
+    fn complete_action(&mut self, ship_symbol: &str, action: &ScheduledAction) {
+        // 1. Remove action from ship's queue
+        let mut ship_tasks = self.ship_tasks.get_mut(ship_symbol).unwrap();
+        let front: &ScheduledAction = ship_tasks.front().unwrap();
+        assert_eq!(front, action);
+        ship_tasks.pop_front();
+
+        // 2. If the action completes a task, remove the task from in_progress_tasks
+        if action.completes_task {
+            self.in_progress_tasks.remove(&action.task_id);
+        }
+    }
+
+    // Queue each ship's schedule and mark the tasks it completes in progress for it. A
+    // task is only ever in progress for one ship, so planning an already-taken task is a bug.
+    fn assign_schedules(&mut self, schedules: &[ShipSchedule], tasks: &[Task]) {
+        for schedule in schedules {
+            let ship_symbol = &schedule.ship.symbol;
+            for action in schedule.actions.iter().filter(|a| a.completes_task) {
+                let task = tasks.iter().find(|t| t.id == action.task_id).unwrap();
+                let previous = self.in_progress_tasks.insert(
+                    action.task_id.clone(),
+                    (task.clone(), ship_symbol.clone(), Utc::now()),
+                );
+                assert!(
+                    previous.is_none(),
+                    "Task {} is already in progress",
+                    action.task_id
+                );
+                debug!(
+                    "[{}]\tAssigned task {} to ship {}",
+                    task.value, action.task_id, ship_symbol
+                );
+            }
+            let queue = VecDeque::from(schedule.actions.clone());
+            self.ship_tasks.insert(ship_symbol.clone(), queue);
+        }
+    }
+
+    // Take a single-visit task and queue it straight after the ship's current action
+    fn insert_followup(&mut self, ship_symbol: &str, task: Task) {
+        let TaskActions::VisitLocation { waypoint, action } = &task.actions else {
+            panic!("Follow-up task {} is not a single visit", task.id);
+        };
+        let mut queue = self.ship_tasks.entry(ship_symbol.to_string()).or_default();
+        let timestamp = queue.front().map(|a| a.timestamp).unwrap_or(0.0);
+        let followup = ScheduledAction {
+            timestamp,
+            waypoint: waypoint.clone(),
+            action: action.clone(),
+            task_id: task.id.clone(),
+            completes_task: true,
+        };
+        let at = queue.len().min(1);
+        queue.insert(at, followup);
+        drop(queue);
+        self.in_progress_tasks
+            .insert(task.id.clone(), (task, ship_symbol.to_string(), Utc::now()));
+    }
+}
+
 #[derive(Clone)]
 pub struct LogisticTaskManager {
     start_system: SystemSymbol,
@@ -80,6 +143,13 @@ pub struct LogisticTaskManager {
     db_client: DbClient,
     state: Arc<RwLock<TaskManagerState>>,
     take_tasks_mutex_guard: Arc<tokio::sync::Mutex<()>>,
+    // latest planner rationale per ship, only populated with DEBUG_PLANNER_RATIONALE=1
+    planner_rationale: Arc<DashMap<String, PlannerRationale>>,
+    // ships the planner last had nothing for, and since when (not persisted: a restart
+    // re-plans every ship anyway)
+    idle_since: Arc<DashMap<String, DateTime<Utc>>>,
+    // tasks the latest planning run left to nobody (not persisted, like idle_since)
+    untaken_tasks: Arc<AtomicUsize>,
 }
 
 impl LogisticTaskManager {
@@ -104,9 +174,47 @@ impl LogisticTaskManager {
             agent_controller: Arc::new(RwLock::new(None)),
             state: Arc::new(RwLock::new(state)),
             take_tasks_mutex_guard: Arc::new(tokio::sync::Mutex::new(())),
+            planner_rationale: Arc::new(DashMap::new()),
+            idle_since: Arc::new(DashMap::new()),
+            untaken_tasks: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    // Test seam: a manager with empty state, without the DB load in `new`
+    #[cfg(test)]
+    pub(crate) fn empty_for_test(
+        universe: &Arc<Universe>,
+        db_client: &DbClient,
+        start_system: &SystemSymbol,
+    ) -> Self {
+        Self {
+            start_system: start_system.clone(),
+            universe: universe.clone(),
+            db_client: db_client.clone(),
+            agent_controller: Arc::new(RwLock::new(None)),
+            state: Arc::new(RwLock::new(TaskManagerState {
+                in_progress_tasks: DashMap::new(),
+                ship_tasks: DashMap::new(),
+                logistics_ships: DashMap::new(),
+                planner_run_count: 0,
+            })),
+            take_tasks_mutex_guard: Arc::new(tokio::sync::Mutex::new(())),
+            planner_rationale: Arc::new(DashMap::new()),
+            idle_since: Arc::new(DashMap::new()),
+            untaken_tasks: Arc::new(AtomicUsize::new(0)),
         }
     }
 
+    pub fn system(&self) -> &SystemSymbol {
+        &self.start_system
+    }
+
+    pub fn planner_rationale(&self, ship_symbol: &str) -> Option<PlannerRationale> {
+        self.planner_rationale
+            .get(ship_symbol)
+            .map(|rationale| rationale.clone())
+    }
+
     pub fn get_planner_run_count(&self) -> u64 {
         self.state.read().unwrap().planner_run_count
     }
@@ -135,12 +243,16 @@ impl LogisticTaskManager {
 
     // add trading tasks to the task list, if they don't already exist
     // (this function is not without side effects: it may buy ships)
+    // Cargo task units are only bounded by the markets (trade volume / contract
+    // shortfall), never by a ship's hold: see Task::sized_for_capacity.
+    // `live` runs the side effects that come with task generation: buying ships and
+    // ticking the contract (which can accept or fulfill it). Read-only callers (what-if
+    // evaluation) pass false and get no ship-buy or contract tasks.
     pub async fn generate_task_list(
         &self,
         system_symbol: &SystemSymbol,
-        capacity_cap: i64,
-        buy_ships: bool,
-        min_profit: i64,
+        live: bool,
+        fuel_stops: &BTreeSet<WaypointSymbol>,
     ) -> Vec<Task> {
         let now = chrono::Utc::now();
         let waypoints: Vec<WaypointDetailed> =
@@ -150,7 +262,7 @@ impl LogisticTaskManager {
         let system_prefix = format!("{}/", system_symbol);
 
         // execute ship_buy actions + generate tasks
-        let (bought, shipyard_task_waypoint) = match buy_ships {
+        let (bought, shipyard_task_waypoint) = match live {
             true => self.agent_controller().try_buy_ships(None).await,
             false => (Vec::new(), None),
         };
@@ -165,26 +277,26 @@ impl LogisticTaskManager {
         if let Some(waypoint) = shipyard_task_waypoint
             && waypoint.system() == *system_symbol
         {
-            tasks.push(Task {
-                id: format!("{}buyships_{}", system_prefix, waypoint),
-                actions: TaskActions::VisitLocation {
-                    waypoint: waypoint.clone(),
-                    action: Action::TryBuyShips,
-                },
-                value: 200000,
-            });
+            tasks.push(buyships_task(system_symbol, &waypoint));
         }
 
         // Contract tasks
-        let contract = match self.agent_controller().contract_tick(false).await {
-            ContractStatus::RequiresLogisticsTask(src_market, dst_market, trade, units) => {
-                Some((src_market, dst_market, trade, units))
-            }
-            _ => None,
+        let contract = match live {
+            true => match self.agent_controller().contract_tick(false).await {
+                ContractStatus::RequiresLogisticsTask(
+                    src_market,
+                    dst_market,
+                    trade,
+                    units,
+                    payment,
+                ) => Some((src_market, dst_market, trade, units, payment)),
+                _ => None,
+            },
+            false => None,
         };
         let contract_good = contract
             .as_ref()
-            .map(|(_, _, trade, _)| trade.symbol.clone());
+            .map(|(_, _, trade, _, _)| trade.symbol.clone());
 
         // load markets
         let markets = self.universe.get_system_markets(system_symbol).await;
@@ -400,29 +512,37 @@ impl LogisticTaskManager {
         let probe_locations = self.probe_locations();
         for (market_remote, market_opt) in &markets {
             let is_probed = probe_locations.contains(&market_remote.symbol);
-            // Some fuel stop markets only trade fuel, so not worth visiting
+            // Some fuel stop markets only trade fuel (or a handful of exchange goods), so
+            // they're only worth visiting when a route in the travel matrix actually
+            // refuels there. Even then they're refreshed on a slower, cheaper cadence.
             let is_pure_exchange =
                 market_remote.exports.is_empty() && market_remote.imports.is_empty();
-            if is_probed || is_pure_exchange {
+            if is_probed || (is_pure_exchange && !fuel_stops.contains(&market_remote.symbol)) {
                 continue;
             }
 
-            let reward: f64 = match market_opt {
-                Some(market) => {
-                    let age_minutes =
-                        now.signed_duration_since(market.timestamp).num_seconds() as f64 / 60.;
-                    match age_minutes {
-                        f64::MIN..5. => continue,
-                        // Very small reward
-                        5.0..15. => 1.,
-                        // Standard
-                        15.0..30.0 => 1000.,
-                        30.0..60.0 => 2000.,
-                        60.0..=f64::MAX => 4000.,
-                        _ => panic!("Invalid age_minutes: {}", age_minutes),
-                    }
-                }
-                None => 4000.,
+            let age_minutes = market_opt.as_ref().map(|market| {
+                now.signed_duration_since(market.timestamp).num_seconds() as f64 / 60.
+            });
+            let reward: f64 = match (is_pure_exchange, age_minutes) {
+                (false, Some(age_minutes)) => match age_minutes {
+                    f64::MIN..5. => continue,
+                    // Very small reward
+                    5.0..15. => 1.,
+                    // Standard
+                    15.0..30.0 => 1000.,
+                    30.0..60.0 => 2000.,
+                    60.0..=f64::MAX => 4000.,
+                    _ => panic!("Invalid age_minutes: {}", age_minutes),
+                },
+                (false, None) => 4000.,
+                (true, Some(age_minutes)) => match age_minutes {
+                    f64::MIN..30. => continue,
+                    30.0..120.0 => 250.,
+                    120.0..=f64::MAX => 500.,
+                    _ => panic!("Invalid age_minutes: {}", age_minutes),
+                },
+                (true, None) => 1000.,
             };
             tasks.push(Task {
                 id: format!("{}refreshmarket_{}", system_prefix, market_remote.symbol),
@@ -480,6 +600,14 @@ impl LogisticTaskManager {
             }
         }
 
+        let market_ages: BTreeMap<WaypointSymbol, f64> = markets
+            .iter()
+            .filter_map(|(remote, market_opt)| {
+                let market = market_opt.as_ref()?;
+                let age = now.signed_duration_since(market.timestamp).num_seconds() as f64 / 60.;
+                Some((remote.symbol.clone(), age))
+            })
+            .collect();
         for good in goods {
             let req_constant_flow = good_req_constant_flow.contains(good.as_str());
             let trades = markets
@@ -492,6 +620,35 @@ impl LogisticTaskManager {
                     }
                     None => None,
                 })
+                // Prices that old have likely moved on; the trade may no longer exist.
+                // Construction supply chains keep flowing regardless.
+                .filter(|(market_symbol, _)| {
+                    req_constant_flow
+                        || !is_stale_market(
+                            market_ages.get(market_symbol).copied(),
+                            CONFIG.trade_max_market_age_minutes,
+                        )
+                })
+                .collect::<Vec<_>>();
+            // Exchanges we've never priced (typically fuel stops) can still be a buy
+            // source: assume they trade at the median known price + 20%. This is what
+            // lets exchange -> import arbitrage be discovered before the first visit.
+            let estimated_exchange_trades = markets
+                .iter()
+                .filter(|(remote, market_opt)| {
+                    market_opt.is_none() && remote.exchange.iter().any(|g| g.symbol == good)
+                })
+                .filter_map(|(remote, _)| {
+                    assumed_exchange_trade(&good, &trades).map(|t| (remote.symbol.clone(), t))
+                })
+                .collect::<Vec<_>>();
+            let trades = trades
+                .into_iter()
+                .chain(
+                    estimated_exchange_trades
+                        .iter()
+                        .map(|(s, t)| (s.clone(), t)),
+                )
                 .collect::<Vec<_>>();
             let buy_trade_good = trades
                 .iter()
@@ -535,6 +692,8 @@ impl LogisticTaskManager {
                     Export => false,
                     Exchange => true,
                 })
+                // An assumed price is only a pessimistic buy estimate, never a sell target
+                .filter(|(market, _)| !estimated_exchange_trades.iter().any(|(s, _)| s == market))
                 .filter(|(market, _)| match good_import_permits.get(good.as_str()) {
                     Some(allowlist) => allowlist.contains(market),
                     None => true,
@@ -543,14 +702,14 @@ impl LogisticTaskManager {
 
             // Contract task
             if !CONFIG.disable_contract_tasks && contract_good.as_ref() == Some(&good) {
-                let (src_market, dst_market, trade, missing) = match &contract {
+                let (src_market, dst_market, trade, missing, payment) = match &contract {
                     Some(contract) => contract,
                     None => continue,
                 };
-                let units = min(*missing, capacity_cap);
+                let units = *missing;
                 debug!(
-                    "Contract task: buy {} {} @ {} for ${}",
-                    units, good, src_market, trade.purchase_price
+                    "Contract task: buy {} {} @ {} for ${}, earns ${}",
+                    units, good, src_market, trade.purchase_price, payment
                 );
                 tasks.push(Task {
                     id: format!("{}contract_{}", system_prefix, good),
@@ -560,50 +719,53 @@ impl LogisticTaskManager {
                         src_action: Action::BuyGoods(good.clone(), units),
                         dest_action: Action::DeliverContract(good.clone(), units),
                     },
-                    value: 50_000, // Very high priority
+                    // the payment the missing units earn; scaled down when a ship
+                    // carries only part of them (Task::sized_for_capacity)
+                    value: *payment,
                 });
                 continue; // Don't add a trading task for the same good
             }
 
-            // Trading task
-            if !CONFIG.disable_trading_tasks {
-                let (buy_trade_good, sell_trade_good) = match (buy_trade_good, sell_trade_good) {
-                    (Some(buy), Some(sell)) => (buy, sell),
-                    _ => continue,
-                };
-                let units = min(
-                    min(
-                        buy_trade_good.1.trade_volume,
-                        sell_trade_good.1.trade_volume,
-                    ),
-                    capacity_cap,
-                );
-                let profit = (sell_trade_good.1.sell_price - buy_trade_good.1.purchase_price)
-                    * (units as i64);
-                let can_afford = true; // logistic ships reserve their credits beforehand
-                if profit >= min_profit && can_afford {
-                    debug!(
-                        "{}: buy {} @ {} for ${}, sell @ {} for ${}, profit: ${}",
-                        good,
-                        units,
-                        buy_trade_good.0,
-                        buy_trade_good.1.purchase_price,
-                        sell_trade_good.0,
-                        sell_trade_good.1.sell_price,
-                        profit
-                    );
-                    tasks.push(Task {
-                        // full exclusivity seems a bit broad right now, but it's a start
-                        id: format!("{}trade_{}", system_prefix, good),
-                        actions: TaskActions::TransportCargo {
-                            src: buy_trade_good.0.clone(),
-                            dest: sell_trade_good.0.clone(),
-                            src_action: Action::BuyGoods(good.clone(), units),
-                            dest_action: Action::SellGoods(good.clone(), units),
-                        },
-                        value: profit,
-                    });
-                }
+            // Trading task, discounted by the age of the older of its two markets
+            let staleness = match req_constant_flow {
+                true => 1.0,
+                false => staleness_factor(
+                    [buy_trade_good, sell_trade_good]
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|(market_symbol, _)| market_ages.get(market_symbol).copied())
+                        .reduce(f64::max),
+                ),
+            };
+            if let Some(task) = trading_task(
+                &system_prefix,
+                &good,
+                buy_trade_good,
+                sell_trade_good,
+                staleness,
+                CONFIG.disable_trading_tasks,
+            ) {
+                tasks.push(task);
+            }
+        }
+        // A paused logistics ship takes nothing, so tasks that only paused ships are
+        // allowed to do would just sit there.
+        if live {
+            let fleet = &self.agent_controller().fleet;
+            let (paused, active): (Vec<_>, Vec<_>) = self
+                .state
+                .read()
+                .unwrap()
+                .logistics_ships
+                .iter()
+                .map(|ship| (fleet.is_ship_paused(ship.key()), ship.config.clone()))
+                .partition(|(paused, _)| *paused);
+            if !paused.is_empty() {
+                tasks.retain(|task| {
+                    active
+                        .iter()
+                        .any(|(_, config)| is_task_allowed(task, config))
+                });
             }
         }
         tasks
@@ -671,35 +833,161 @@ impl LogisticTaskManager {
         start_waypoint: &WaypointSymbol,
     ) -> Option<ScheduledAction> {
         let _guard = self.take_tasks_lock().await;
-        let system_symbol = &self.start_system;
+        // Another ship's joint plan may have scheduled this one while it waited for the lock
+        if let Some(action) = self.get_next_action(ship_symbol) {
+            return Some(action);
+        }
+        let mut ships = vec![(ship_symbol.to_string(), start_waypoint.clone())];
+        ships.extend(
+            self.idle_logistics_ships()
+                .into_iter()
+                .filter(|(symbol, _)| symbol != ship_symbol),
+        );
+        self.plan_ships(&ships).await;
+        self.get_next_action(ship_symbol)
+    }
 
-        // Assert there are no in progress tasks or scheduled tasks for this ship
-        self.assert_no_in_progress_tasks(ship_symbol);
-        self.assert_no_queued_tasks(ship_symbol);
+    // Batch entry point: plan every idle logistics ship in the system together, so they
+    // split the available tasks between them instead of each taking its pick in turn.
+    // Each ship then pulls its schedule from its queue (get_next_task).
+    pub async fn plan_idle_ships(&self) {
+        let _guard = self.take_tasks_lock().await;
+        let ships = self.idle_logistics_ships();
+        if !ships.is_empty() {
+            self.plan_ships(&ships).await;
+        }
+    }
 
-        let logistics_ship_config = self
-            .state
-            .read()
-            .unwrap()
+    // Registered ships with nothing queued or in progress, at their current waypoint.
+    // Ships sleeping after an empty plan (idle_since) or paused aren't waiting on one,
+    // and a ship outside the system can't start here.
+    fn idle_logistics_ships(&self) -> Vec<(String, WaypointSymbol)> {
+        let ac = self.agent_controller();
+        let state = self.state.read().unwrap();
+        state
             .logistics_ships
-            .get(ship_symbol)?
-            .value()
-            .clone();
+            .iter()
+            .map(|entry| entry.key().clone())
+            .filter(|symbol| {
+                state
+                    .ship_tasks
+                    .get(symbol)
+                    .is_none_or(|queue| queue.is_empty())
+            })
+            .filter(|symbol| {
+                !state
+                    .in_progress_tasks
+                    .iter()
+                    .any(|entry| &entry.value().1 == symbol)
+            })
+            .filter(|symbol| !self.idle_since.contains_key(symbol))
+            .filter(|symbol| !ac.fleet.is_ship_paused(symbol))
+            .filter_map(|symbol| {
+                let ship = ac.ctx.ships.get(&symbol)?;
+                let ship = ship.lock().unwrap();
+                (ship.nav.system_symbol == self.start_system)
+                    .then(|| (symbol, ship.nav.waypoint_symbol.clone()))
+            })
+            .collect()
+    }
+
+    // Plan `ships` (symbol, start waypoint) together and queue each one's schedule. Ships
+    // with the same config, hold and engine share a planner run; each further group is
+    // planned around the tasks the earlier ones took. Call with the take_tasks lock held.
+    async fn plan_ships(&self, ships: &[(String, WaypointSymbol)]) {
+        let mut groups: Vec<(LogisticsShip, Vec<(String, WaypointSymbol)>)> = vec![];
+        for (ship_symbol, start_waypoint) in ships {
+            // Assert there are no in progress tasks or scheduled tasks for this ship
+            self.assert_no_in_progress_tasks(ship_symbol);
+            self.assert_no_queued_tasks(ship_symbol);
+            let Some(ship_config) = self
+                .state
+                .read()
+                .unwrap()
+                .logistics_ships
+                .get(ship_symbol)
+                .map(|entry| entry.value().clone())
+            else {
+                continue;
+            };
+            let ship = (ship_symbol.clone(), start_waypoint.clone());
+            match groups.iter_mut().find(|(config, _)| {
+                config.config == ship_config.config
+                    && config.cargo_capacity == ship_config.cargo_capacity
+                    && config.engine_speed == ship_config.engine_speed
+                    && config.fuel_capacity == ship_config.fuel_capacity
+            }) {
+                Some((_, group)) => group.push(ship),
+                None => groups.push((ship_config, vec![ship])),
+            }
+        }
+        for (ship_config, group) in &groups {
+            self.plan_ship_group(ship_config, group).await;
+        }
+    }
+
+    async fn plan_ship_group(
+        &self,
+        logistics_ship_config: &LogisticsShip,
+        ships: &[(String, WaypointSymbol)],
+    ) {
+        let system_symbol = &self.start_system;
         let cargo_capacity = logistics_ship_config.cargo_capacity;
         let config = &logistics_ship_config.config;
         let engine_speed = logistics_ship_config.engine_speed;
         let fuel_capacity = logistics_ship_config.fuel_capacity;
 
+        let waypoints = self.universe.get_system_waypoints(system_symbol).await;
+        let fuel_scarce = crate::pathfinding::is_fuel_scarce(&waypoints);
+        let market_waypoints = waypoints
+            .into_iter()
+            .filter(|w| w.is_market())
+            .collect::<Vec<_>>();
+        let market_set: std::collections::HashSet<WaypointSymbol> =
+            market_waypoints.iter().map(|w| w.symbol.clone()).collect();
+        // The planner starts ships from a market: one elsewhere plans next time round
+        let ships = ships
+            .iter()
+            .filter(|(_, start_waypoint)| market_set.contains(start_waypoint))
+            .collect::<Vec<_>>();
+        if ships.is_empty() {
+            return;
+        }
+        let ship_symbols = ships
+            .iter()
+            .map(|(symbol, _)| symbol.as_str())
+            .collect::<Vec<_>>();
+        let fuel_stops = crate::universe::pathfinding::travel_matrix_fuel_stops(
+            &market_waypoints,
+            fuel_capacity,
+            engine_speed,
+            fuel_scarce,
+        );
+
         let all_tasks = self
-            .generate_task_list(system_symbol, cargo_capacity, true, config.min_profit)
+            .generate_task_list(system_symbol, true, &fuel_stops)
             .await;
-        self.agent_controller()
-            .ctx
-            .ledger
-            .reserve_credits(ship_symbol, 5000 * cargo_capacity);
+        for ship_symbol in &ship_symbols {
+            self.agent_controller()
+                .ctx
+                .ledger
+                .reserve_credits(ship_symbol, CONFIG.cargo_reservation(cargo_capacity));
+        }
+
+        // Ships already on their way to sell each (waypoint, good)
+        let sell_targets = sell_target_counts(
+            self.state
+                .read()
+                .unwrap()
+                .in_progress_tasks
+                .iter()
+                .map(|entry| entry.value().0.clone()),
+        );
 
-        // Filter out tasks that are already in progress
-        // Also filter tasks outlawed by the config for this ship
+        // Filter out tasks that are already in progress (including any an earlier group
+        // just took). Also filter tasks outlawed by the config for these ships
+        // Don't pile more ships onto a (waypoint, good) than the market can take
+        // Cargo tasks are sized to the ships' hold, and trades must still clear min_profit
         let available_tasks = all_tasks
             .into_iter()
             .filter(|task| {
@@ -711,27 +999,22 @@ impl LogisticTaskManager {
                     .contains_key(&task.id)
             })
             .filter(|task| is_task_allowed(task, config))
+            .filter(|task| {
+                under_sell_target_cap(task, &sell_targets, CONFIG.max_ships_per_sell_target)
+            })
+            .map(|task| task.sized_for_capacity(cargo_capacity))
+            .filter(|task| !task.is_trade() || task.value >= config.min_profit)
             .collect::<Vec<_>>();
 
         if available_tasks.is_empty() {
-            return None;
+            return;
         }
 
         // Run planner
-        let market_waypoints = self
-            .universe
-            .get_system_waypoints(system_symbol)
-            .await
-            .into_iter()
-            .filter(|w| w.is_market())
-            .collect::<Vec<_>>();
-
         // The planner indexes every task waypoint into market_waypoints and unwraps the
         // result, so any task referencing a non-market (or cross-system, e.g. a contract
         // delivered back home) waypoint would panic. Drop those — the ship trades what it
         // can actually reach as a market here.
-        let market_set: std::collections::HashSet<WaypointSymbol> =
-            market_waypoints.iter().map(|w| w.symbol.clone()).collect();
         let available_tasks = available_tasks
             .into_iter()
             .filter(|task| {
@@ -743,21 +1026,53 @@ impl LogisticTaskManager {
             })
             .collect::<Vec<_>>();
         if available_tasks.is_empty() {
-            return None;
+            return;
         }
 
-        let (duration_matrix, distance_matrix) = crate::universe::pathfinding::full_travel_matrix(
-            &market_waypoints,
-            fuel_capacity,
-            engine_speed,
-        );
-        let logistics_ship = LogisticShip {
-            symbol: ship_symbol.to_string(),
-            capacity: cargo_capacity,
-            speed: engine_speed,
-            start_waypoint: start_waypoint.clone(),
+        let travel_matrix = self
+            .universe
+            .travel_matrix(
+                system_symbol,
+                &market_waypoints,
+                fuel_capacity,
+                engine_speed,
+                fuel_scarce,
+            )
+            .await;
+        let market_symbols = market_waypoints
+            .iter()
+            .map(|w| w.symbol.clone())
+            .collect::<Vec<_>>();
+        let logistics_ships = ships
+            .iter()
+            .map(|(symbol, start_waypoint)| LogisticShip {
+                symbol: symbol.clone(),
+                capacity: cargo_capacity,
+                speed: engine_speed,
+                start_waypoint: start_waypoint.clone(),
+            })
+            .collect::<Vec<_>>();
+        // Cold start: with most markets unpriced the planner has little to work with, so
+        // skip it and just send each ship to the nearest market that needs a refresh.
+        let coverage = self.universe.market_coverage(system_symbol).await;
+        let cold_start_schedules = match coverage < COLD_START_MARKET_COVERAGE {
+            true => cold_start_schedules(
+                &logistics_ships,
+                &available_tasks,
+                &market_symbols,
+                &travel_matrix.0,
+            ),
+            false => None,
         };
-        let schedules = if config.use_planner {
+        let mut schedules = if let Some(schedules) = cold_start_schedules {
+            info!(
+                "Cold start in {} ({:.0}% of markets priced): skipping the planner for {:?}",
+                system_symbol,
+                coverage * 100.0,
+                ship_symbols
+            );
+            schedules
+        } else if config.use_planner {
             let planner_config = config.planner_config.as_ref().unwrap();
             let run_count = self.get_planner_run_count();
             let plan_length = match &planner_config.plan_length {
@@ -778,122 +1093,186 @@ impl LogisticTaskManager {
             let contraints = PlannerConstraints {
                 plan_length: plan_length.num_seconds(),
                 max_compute_time: Duration::try_seconds(5).unwrap(),
+                explain: CONFIG.debug_planner_rationale,
             };
             let available_tasks_clone = available_tasks.clone();
             info!(
-                "Planning tasks for ship {}, tasks: {}, length: {}s",
-                ship_symbol,
+                "Planning tasks for ships {:?}, tasks: {}, length: {}s",
+                ship_symbols,
                 available_tasks_clone.len(),
                 plan_length.num_seconds()
             );
             // debug!("Available tasks: {:?}", available_tasks_clone);
-            tokio::task::spawn_blocking(move || {
+            let (schedules, rationale) = tokio::task::spawn_blocking(move || {
                 logistics_planner::plan::run_planner(
-                    &[logistics_ship],
+                    &logistics_ships,
                     &available_tasks_clone,
-                    &market_waypoints
-                        .iter()
-                        .map(|w| w.symbol.clone())
-                        .collect::<Vec<_>>(),
-                    &duration_matrix,
-                    &distance_matrix,
+                    &market_symbols,
+                    &travel_matrix.0,
+                    &travel_matrix.1,
                     &contraints,
                 )
             })
             .await
-            .unwrap()
-        } else {
-            vec![ShipSchedule {
-                ship: logistics_ship,
-                actions: vec![],
-            }]
-        };
-        assert_eq!(schedules.len(), 1);
-        let mut actions = schedules.into_iter().next().unwrap().actions;
-        info!("Planner returned {} actions", actions.len());
-
-        // If 0 tasks were assigned, instead force assign the highest value task
-        if actions.is_empty() {
-            let mut highest_value_task = None;
-            let mut highest_value = 0;
-            for task in &available_tasks {
-                if task.value > highest_value {
-                    highest_value = task.value;
-                    highest_value_task = Some(task);
+            .unwrap();
+            if let Some(rationale) = rationale {
+                debug!("Planner rationale for {:?}: {:?}", ship_symbols, rationale);
+                for ship_symbol in &ship_symbols {
+                    self.planner_rationale
+                        .insert(ship_symbol.to_string(), rationale.clone());
                 }
             }
-            if let Some(task) = highest_value_task {
-                info!(
-                    "Forcing assignment of task {} value: {}",
-                    task.id, task.value
-                );
-                // add actions for the task
-                match &task.actions {
-                    TaskActions::VisitLocation { waypoint, action } => {
-                        actions.push(ScheduledAction {
-                            timestamp: 0.0,
-                            waypoint: waypoint.clone(),
-                            action: action.clone(),
-                            task_id: task.id.clone(),
-                            completes_task: true,
-                        });
-                    }
-                    TaskActions::TransportCargo {
-                        src,
-                        dest,
-                        src_action,
-                        dest_action,
-                    } => {
-                        actions.push(ScheduledAction {
-                            timestamp: 0.0,
-                            waypoint: src.clone(),
-                            action: src_action.clone(),
-                            task_id: task.id.clone(),
-                            completes_task: false,
-                        });
-                        actions.push(ScheduledAction {
-                            timestamp: 0.0,
-                            waypoint: dest.clone(),
-                            action: dest_action.clone(),
-                            task_id: task.id.clone(),
-                            completes_task: true,
-                        });
-                    }
-                };
-            }
+            schedules
+        } else {
+            logistics_ships
+                .into_iter()
+                .map(|ship| ShipSchedule {
+                    ship,
+                    actions: vec![],
+                })
+                .collect()
+        };
+        assert_eq!(schedules.len(), ships.len());
+        for schedule in &schedules {
+            info!(
+                "Planner returned {} actions for {}",
+                schedule.actions.len(),
+                schedule.ship.symbol
+            );
         }
 
-        // Store the task in the ship's queue, and also update in_progress_tasks
+        // Ships assigned 0 tasks are instead force assigned the highest value task left
+        for task in force_assign_empty(&mut schedules, &available_tasks) {
+            info!(
+                "Forcing assignment of task {} value: {}",
+                task.id, task.value
+            );
+        }
+
+        self.untaken_tasks.store(
+            untaken_task_count(&schedules, &available_tasks),
+            Ordering::Relaxed,
+        );
+
+        // Store the tasks in the ships' queues, and also update in_progress_tasks
         self.update_state(|state| {
-            for action in &actions {
-                if action.completes_task {
-                    let task = available_tasks
-                        .iter()
-                        .find(|t| t.id == action.task_id)
-                        .unwrap();
-                    state.in_progress_tasks.insert(
-                        action.task_id.clone(),
-                        (task.clone(), ship_symbol.to_string(), Utc::now()),
-                    );
-                    debug!(
-                        "[{}]\tAssigned task {} to ship {}",
-                        task.value, action.task_id, ship_symbol
-                    );
-                }
-            }
-            let queue = VecDeque::from(actions);
-            state.ship_tasks.insert(ship_symbol.to_string(), queue);
+            state.assign_schedules(&schedules, &available_tasks);
             state.planner_run_count += 1;
         })
         .await;
+    }
 
-        // Return the first action
-        self.state
-            .read()
-            .unwrap()
-            .ship_tasks
-            .get(ship_symbol)
-            .and_then(|queue| queue.front().cloned())
+    // What a ship we don't own would be planned, if it appeared at `start_waypoint` now.
+    // Reads the manager's state but never writes it: no in-progress entries, no queued
+    // actions, no credit reservation, no planner run counted.
+    pub async fn plan_hypothetical_ship(
+        &self,
+        start_waypoint: &WaypointSymbol,
+        cargo_capacity: i64,
+        engine_speed: i64,
+        fuel_capacity: i64,
+    ) -> HypotheticalPlan {
+        let waypoints = self.universe.get_system_waypoints(&self.start_system).await;
+        let fuel_scarce = crate::pathfinding::is_fuel_scarce(&waypoints);
+        let market_waypoints = waypoints
+            .into_iter()
+            .filter(|w| w.is_market())
+            .collect::<Vec<_>>();
+        let fuel_stops = crate::universe::pathfinding::travel_matrix_fuel_stops(
+            &market_waypoints,
+            fuel_capacity,
+            engine_speed,
+            fuel_scarce,
+        );
+        let all_tasks = self
+            .generate_task_list(&self.start_system, false, &fuel_stops)
+            .await;
+        let travel_matrix = self
+            .universe
+            .travel_matrix(
+                &self.start_system,
+                &market_waypoints,
+                fuel_capacity,
+                engine_speed,
+                fuel_scarce,
+            )
+            .await;
+        let ship = LogisticShip {
+            symbol: "WHATIF".to_string(),
+            capacity: cargo_capacity,
+            speed: engine_speed,
+            start_waypoint: start_waypoint.clone(),
+        };
+        self.plan_untaken_tasks(
+            ship,
+            all_tasks,
+            market_waypoints.into_iter().map(|w| w.symbol).collect(),
+            travel_matrix,
+            Duration::try_seconds(WHATIF_COMPUTE_SECONDS).unwrap(),
+        )
+        .await
+    }
+
+    // Plan `ship` against the trades nobody has taken yet
+    async fn plan_untaken_tasks(
+        &self,
+        ship: LogisticShip,
+        tasks: Vec<Task>,
+        market_waypoints: Vec<WaypointSymbol>,
+        travel_matrix: Arc<TravelMatrix>,
+        max_compute_time: Duration,
+    ) -> HypotheticalPlan {
+        let tasks = {
+            let state = self.state.read().unwrap();
+            tasks
+                .into_iter()
+                .filter(|task| task.is_trade())
+                .filter(|task| !state.in_progress_tasks.contains_key(&task.id))
+                .filter(|task| {
+                    let TaskActions::TransportCargo { src, dest, .. } = &task.actions else {
+                        return false;
+                    };
+                    market_waypoints.contains(src) && market_waypoints.contains(dest)
+                })
+                .map(|task| task.sized_for_capacity(ship.capacity))
+                .collect::<Vec<_>>()
+        };
+        let tasks_available = tasks.len();
+        let constraints = PlannerConstraints {
+            plan_length: WHATIF_PLAN_LENGTH_SECONDS,
+            max_compute_time,
+            explain: false,
+        };
+        let (actions, assigned_values) = tokio::task::spawn_blocking(move || {
+            let (schedules, _) = logistics_planner::plan::run_planner(
+                &[ship],
+                &tasks,
+                &market_waypoints,
+                &travel_matrix.0,
+                &travel_matrix.1,
+                &constraints,
+            );
+            let actions = schedules
+                .into_iter()
+                .flat_map(|s| s.actions)
+                .collect::<Vec<_>>();
+            let assigned_values = actions
+                .iter()
+                .filter(|a| a.completes_task)
+                .filter_map(|a| tasks.iter().find(|t| t.id == a.task_id))
+                .map(|t| t.value)
+                .collect::<Vec<_>>();
+            (actions, assigned_values)
+        })
+        .await
+        .unwrap();
+        HypotheticalPlan {
+            tasks_available,
+            tasks_assigned: assigned_values.len(),
+            value: assigned_values.iter().sum(),
+            plan_length_secs: WHATIF_PLAN_LENGTH_SECONDS,
+            actions,
+        }
     }
 
     pub fn get_next_action(&self, ship_symbol: &str) -> Option<ScheduledAction> {
@@ -906,21 +1285,33 @@ impl LogisticTaskManager {
     }
 
     pub async fn complete_action(&self, ship_symbol: &str, action: &ScheduledAction) {
+        self.update_state(|state| state.complete_action(ship_symbol, action))
+            .await;
+    }
+
+    // Give up on a task partway through: drop all its remaining actions from the ship's
+    // queue and release it, so the next task generation can offer it again with fresh
+    // data. It earns nothing.
+    pub async fn abort_task(&self, ship_symbol: &str, task_id: &str) {
         self.update_state(|state| {
-            // 1. Remove action from ship's queue
-            let mut ship_tasks = state.ship_tasks.get_mut(ship_symbol).unwrap();
-            let front: &ScheduledAction = ship_tasks.front().unwrap();
-            assert_eq!(front, action);
-            ship_tasks.pop_front();
-
-            // 2. If the action completes a task, remove the task from in_progress_tasks
-            if action.completes_task {
-                state.in_progress_tasks.remove(&action.task_id);
+            if let Some(mut queue) = state.ship_tasks.get_mut(ship_symbol) {
+                queue.retain(|action| action.task_id != task_id);
             }
+            state.in_progress_tasks.remove(task_id);
         })
         .await;
     }
 
+    pub fn queued_actions(&self, ship_symbol: &str) -> Vec<ScheduledAction> {
+        self.state
+            .read()
+            .unwrap()
+            .ship_tasks
+            .get(ship_symbol)
+            .map(|queue| queue.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
     pub async fn register_ship(
         &self,
         ship_symbol: &str,
@@ -956,14 +1347,1010 @@ impl LogisticTaskManager {
         }
 
         // If no existing task, try to take a new one
-        self.take_tasks(ship_symbol, start_waypoint).await
+        let action = self.take_tasks(ship_symbol, start_waypoint).await;
+        match &action {
+            Some(_) => {
+                self.idle_since.remove(ship_symbol);
+            }
+            None => {
+                self.idle_since
+                    .entry(ship_symbol.to_string())
+                    .or_insert_with(Utc::now);
+            }
+        }
+        action
+    }
+
+    // A ship purchase failed on arrival (the shipyard stopped selling the model, or the
+    // fresh price is out of reach) but another shipyard in this system is worth trying:
+    // queue a visit there straight after the ship's current action rather than leave
+    // the purchase to the next planning round. The follow-up is its own in-progress
+    // task, so the current action still completes its task as planned. False if the
+    // shipyard is elsewhere or another ship already has that task.
+    pub async fn extend_with_buyships(&self, ship_symbol: &str, shipyard: &WaypointSymbol) -> bool {
+        if shipyard.system() != self.start_system {
+            return false;
+        }
+        let task = buyships_task(&self.start_system, shipyard);
+        if self
+            .state
+            .read()
+            .unwrap()
+            .in_progress_tasks
+            .contains_key(&task.id)
+        {
+            return false;
+        }
+        self.update_state(|state| state.insert_followup(ship_symbol, task))
+            .await;
+        true
+    }
+
+    // Ships registered here that the planner has had nothing for, with since when
+    pub fn idle_ships(&self) -> Vec<(String, DateTime<Utc>)> {
+        self.idle_since
+            .iter()
+            .map(|entry| (entry.key().clone(), *entry.value()))
+            .collect()
+    }
+
+    // More work here than ships for it: the latest plan left tasks over while every
+    // ship had something to do. (A ship only idles when there was nothing left to force
+    // on it, so a system whose ships idled isn't underserved until it plans again.)
+    pub fn is_underserved(&self) -> bool {
+        self.untaken_tasks.load(Ordering::Relaxed) > 0 && self.idle_since.is_empty()
+    }
+
+    // The ship is leaving this system's trade. Only called with an empty queue.
+    pub async fn unregister_ship(&self, ship_symbol: &str) {
+        assert!(self.get_next_action(ship_symbol).is_none());
+        self.idle_since.remove(ship_symbol);
+        self.update_state(|state| {
+            state.logistics_ships.remove(ship_symbol);
+            state.ship_tasks.remove(ship_symbol);
+        })
+        .await;
+    }
+}
+
+fn buyships_task(system: &SystemSymbol, shipyard: &WaypointSymbol) -> Task {
+    Task {
+        id: format!("{}/buyships_{}", system, shipyard),
+        actions: TaskActions::VisitLocation {
+            waypoint: shipyard.clone(),
+            action: Action::TryBuyShips,
+        },
+        value: 200000,
+    }
+}
+
+// One LogisticTaskManager per system that logistics ships trade in, created on first
+// use. Every manager shares the AgentController (and through it the ledger), but has
+// its own state, persisted under its own system's key.
+pub struct TaskManagerRegistry {
+    universe: Arc<Universe>,
+    db_client: DbClient,
+    agent_controller: RwLock<Option<AgentController>>,
+    managers: DashMap<SystemSymbol, Arc<LogisticTaskManager>>,
+    create_lock: tokio::sync::Mutex<()>,
+}
+
+impl TaskManagerRegistry {
+    pub fn new(home: &Arc<LogisticTaskManager>, universe: &Arc<Universe>, db: &DbClient) -> Self {
+        Self {
+            universe: universe.clone(),
+            db_client: db.clone(),
+            agent_controller: RwLock::new(None),
+            managers: DashMap::from_iter([(home.system().clone(), home.clone())]),
+            create_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    // Also wires every manager created so far (i.e. the home one)
+    pub fn set_agent_controller(&self, ac: &AgentController) {
+        let mut agent_controller = self.agent_controller.write().unwrap();
+        assert!(agent_controller.is_none());
+        *agent_controller = Some(ac.clone());
+        for manager in self.managers.iter() {
+            manager.set_agent_controller(ac);
+        }
+    }
+
+    pub async fn get(&self, system: &SystemSymbol) -> Arc<LogisticTaskManager> {
+        self.get_with(system, || {
+            LogisticTaskManager::new(&self.universe, &self.db_client, system)
+        })
+        .await
+    }
+
+    async fn get_with<F, Fut>(&self, system: &SystemSymbol, create: F) -> Arc<LogisticTaskManager>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = LogisticTaskManager>,
+    {
+        if let Some(manager) = self.managers.get(system) {
+            return manager.clone();
+        }
+        // two ships arriving in a new system must end up sharing one manager
+        let _guard = self.create_lock.lock().await;
+        if let Some(manager) = self.managers.get(system) {
+            return manager.clone();
+        }
+        info!("Creating logistics task manager for {}", system);
+        let manager = create().await;
+        if let Some(ac) = self.agent_controller.read().unwrap().as_ref() {
+            manager.set_agent_controller(ac);
+        }
+        let manager = Arc::new(manager);
+        self.managers.insert(system.clone(), manager.clone());
+        manager
+    }
+
+    pub fn managers(&self) -> Vec<Arc<LogisticTaskManager>> {
+        self.managers
+            .iter()
+            .map(|manager| manager.value().clone())
+            .collect()
+    }
+
+    pub fn planner_rationale(&self, ship_symbol: &str) -> Option<PlannerRationale> {
+        self.managers
+            .iter()
+            .find_map(|manager| manager.planner_rationale(ship_symbol))
+    }
+}
+
+// Below this fraction of priced markets, take_tasks is in cold-start mode
+const COLD_START_MARKET_COVERAGE: f64 = 0.5;
+
+// Horizon and solve budget for what-if plans. An hour keeps the estimate to what the
+// current task pool supports, rather than assuming today's spreads last all day.
+const WHATIF_PLAN_LENGTH_SECONDS: i64 = 60 * 60;
+const WHATIF_COMPUTE_SECONDS: i64 = 2;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HypotheticalPlan {
+    pub tasks_available: usize,
+    pub tasks_assigned: usize,
+    // total value of the assigned tasks
+    pub value: i64,
+    pub plan_length_secs: i64,
+    // the schedule itself, for logging (a dry run's plans)
+    #[serde(skip)]
+    pub actions: Vec<ScheduledAction>,
+}
+
+// The nearest market refresh task, as a one-action schedule. None if there's nothing to
+// refresh (in which case the planner runs as usual).
+fn cold_start_actions(
+    tasks: &[Task],
+    market_waypoints: &[WaypointSymbol],
+    duration_matrix: &[Vec<f64>],
+    start_waypoint: &WaypointSymbol,
+) -> Option<Vec<ScheduledAction>> {
+    let start = market_waypoints.iter().position(|w| w == start_waypoint)?;
+    tasks
+        .iter()
+        .filter_map(|task| match &task.actions {
+            TaskActions::VisitLocation {
+                waypoint,
+                action: Action::RefreshMarket,
+            } => {
+                let idx = market_waypoints.iter().position(|w| w == waypoint)?;
+                Some((task, waypoint, duration_matrix[start][idx]))
+            }
+            _ => None,
+        })
+        .min_by(|a, b| a.2.total_cmp(&b.2))
+        .map(|(task, waypoint, duration)| {
+            vec![ScheduledAction {
+                timestamp: duration,
+                waypoint: waypoint.clone(),
+                action: Action::RefreshMarket,
+                task_id: task.id.clone(),
+                completes_task: true,
+            }]
+        })
+}
+
+// Cold start for a group of ships: each in turn gets the nearest refresh not already
+// handed out. None if none of them gets one (the planner runs as usual).
+fn cold_start_schedules(
+    ships: &[LogisticShip],
+    tasks: &[Task],
+    market_waypoints: &[WaypointSymbol],
+    duration_matrix: &[Vec<f64>],
+) -> Option<Vec<ShipSchedule>> {
+    let mut remaining = tasks.to_vec();
+    let schedules = ships
+        .iter()
+        .map(|ship| {
+            let actions = cold_start_actions(
+                &remaining,
+                market_waypoints,
+                duration_matrix,
+                &ship.start_waypoint,
+            )
+            .unwrap_or_default();
+            remaining.retain(|task| actions.iter().all(|a| a.task_id != task.id));
+            ShipSchedule {
+                ship: ship.clone(),
+                actions,
+            }
+        })
+        .collect::<Vec<_>>();
+    schedules
+        .iter()
+        .any(|s| !s.actions.is_empty())
+        .then_some(schedules)
+}
+
+// Give each empty schedule the highest value task that no schedule has yet, so a ship
+// the planner left out still has something to do. Returns the tasks handed out.
+fn force_assign_empty<'a>(schedules: &mut [ShipSchedule], tasks: &'a [Task]) -> Vec<&'a Task> {
+    let mut taken = schedules
+        .iter()
+        .flat_map(|s| &s.actions)
+        .map(|a| a.task_id.clone())
+        .collect::<BTreeSet<_>>();
+    let mut forced = vec![];
+    for schedule in schedules.iter_mut().filter(|s| s.actions.is_empty()) {
+        let best = tasks.iter().filter(|task| !taken.contains(&task.id)).fold(
+            None,
+            |best: Option<&Task>, task| match task.value > best.map_or(0, |b| b.value) {
+                true => Some(task),
+                false => best,
+            },
+        );
+        let Some(task) = best else {
+            break;
+        };
+        schedule.actions = task_actions(task);
+        taken.insert(task.id.clone());
+        forced.push(task);
+    }
+    forced
+}
+
+// Tasks no schedule takes on
+fn untaken_task_count(schedules: &[ShipSchedule], tasks: &[Task]) -> usize {
+    let taken = schedules
+        .iter()
+        .flat_map(|s| &s.actions)
+        .map(|a| a.task_id.as_str())
+        .collect::<BTreeSet<_>>();
+    tasks
+        .iter()
+        .filter(|task| !taken.contains(task.id.as_str()))
+        .count()
+}
+
+// A task's actions, unscheduled (timestamp 0)
+fn task_actions(task: &Task) -> Vec<ScheduledAction> {
+    match &task.actions {
+        TaskActions::VisitLocation { waypoint, action } => vec![ScheduledAction {
+            timestamp: 0.0,
+            waypoint: waypoint.clone(),
+            action: action.clone(),
+            task_id: task.id.clone(),
+            completes_task: true,
+        }],
+        TaskActions::TransportCargo {
+            src,
+            dest,
+            src_action,
+            dest_action,
+        } => vec![
+            ScheduledAction {
+                timestamp: 0.0,
+                waypoint: src.clone(),
+                action: src_action.clone(),
+                task_id: task.id.clone(),
+                completes_task: false,
+            },
+            ScheduledAction {
+                timestamp: 0.0,
+                waypoint: dest.clone(),
+                action: dest_action.clone(),
+                task_id: task.id.clone(),
+                completes_task: true,
+            },
+        ],
     }
 }
 
+// The (waypoint, good) a trade task sells into
+fn sell_target(task: &Task) -> Option<(WaypointSymbol, String)> {
+    match &task.actions {
+        TaskActions::TransportCargo {
+            dest,
+            dest_action: Action::SellGoods(good, _),
+            ..
+        } => Some((dest.clone(), good.clone())),
+        _ => None,
+    }
+}
+
+fn sell_target_counts(
+    in_progress: impl Iterator<Item = Task>,
+) -> BTreeMap<(WaypointSymbol, String), usize> {
+    let mut counts = BTreeMap::new();
+    for target in in_progress.filter_map(|task| sell_target(&task)) {
+        *counts.entry(target).or_insert(0) += 1;
+    }
+    counts
+}
+
+fn under_sell_target_cap(
+    task: &Task,
+    counts: &BTreeMap<(WaypointSymbol, String), usize>,
+    cap: usize,
+) -> bool {
+    match sell_target(task) {
+        Some(target) => counts.get(&target).copied().unwrap_or(0) < cap,
+        None => true,
+    }
+}
+
+// Market data this fresh is as good as it gets (probes tour their markets about this often)
+const TRADE_STALENESS_GRACE_MINUTES: f64 = 15.;
+// ...after which a trade's expected profit halves every this many minutes
+const TRADE_STALENESS_HALF_LIFE_MINUTES: f64 = 60.;
+
+// Share of a trade's computed profit to count on, given the age of its market data.
+// Unpriced markets (an assumed exchange price) aren't discounted: the guess is already
+// pessimistic.
+fn staleness_factor(age_minutes: Option<f64>) -> f64 {
+    match age_minutes {
+        Some(age) if age > TRADE_STALENESS_GRACE_MINUTES => {
+            0.5f64.powf((age - TRADE_STALENESS_GRACE_MINUTES) / TRADE_STALENESS_HALF_LIFE_MINUTES)
+        }
+        _ => 1.0,
+    }
+}
+
+// Market data past TRADE_MAX_MARKET_AGE_MINUTES (0 = no limit) isn't traded on
+fn is_stale_market(age_minutes: Option<f64>, max_age_minutes: i64) -> bool {
+    match age_minutes {
+        Some(age) => max_age_minutes > 0 && age > max_age_minutes as f64,
+        None => false,
+    }
+}
+
+// The trade task for `good`: buy at the cheapest source, sell at the best destination,
+// as many units as both markets' volumes allow, valued at the profit scaled by
+// `staleness` (see `staleness_factor`). None unless it makes a profit, or with trading
+// tasks disabled (DEBUG_DISABLE_TRADING_TASKS), which leaves an agent doing only
+// refreshes, contracts and construction.
+fn trading_task(
+    system_prefix: &str,
+    good: &str,
+    buy_trade_good: Option<&(WaypointSymbol, &MarketTradeGood)>,
+    sell_trade_good: Option<&(WaypointSymbol, &MarketTradeGood)>,
+    staleness: f64,
+    disable_trading_tasks: bool,
+) -> Option<Task> {
+    if disable_trading_tasks {
+        return None;
+    }
+    let (buy_trade_good, sell_trade_good) = (buy_trade_good?, sell_trade_good?);
+    let units = min(
+        buy_trade_good.1.trade_volume,
+        sell_trade_good.1.trade_volume,
+    );
+    let profit = (sell_trade_good.1.sell_price - buy_trade_good.1.purchase_price) * units;
+    // logistic ships reserve their credits beforehand, so any profitable trade is affordable
+    // min_profit is a per-ship setting, checked once the task is sized to a ship
+    let value = (profit as f64 * staleness) as i64;
+    if value <= 0 {
+        return None;
+    }
+    debug!(
+        "{}: buy {} @ {} for ${}, sell @ {} for ${}, profit: ${} (valued ${})",
+        good,
+        units,
+        buy_trade_good.0,
+        buy_trade_good.1.purchase_price,
+        sell_trade_good.0,
+        sell_trade_good.1.sell_price,
+        profit,
+        value
+    );
+    Some(Task {
+        // One per route, not per good: while one ship works a route, another can take the
+        // good elsewhere, and MAX_SHIPS_PER_SELL_TARGET keeps them off a single market
+        id: format!(
+            "{}trade_{}_{}_{}",
+            system_prefix, good, buy_trade_good.0, sell_trade_good.0
+        ),
+        actions: TaskActions::TransportCargo {
+            src: buy_trade_good.0.clone(),
+            dest: sell_trade_good.0.clone(),
+            src_action: Action::BuyGoods(good.to_string(), units),
+            dest_action: Action::SellGoods(good.to_string(), units),
+        },
+        value,
+    })
+}
+
+// Stand-in trade for an exchange with no price data: the median known purchase price
+// for the good + 20%, at the smallest known trade volume. None if nobody prices it.
+fn assumed_exchange_trade(
+    good: &str,
+    known: &[(WaypointSymbol, &MarketTradeGood)],
+) -> Option<MarketTradeGood> {
+    let mut prices = known
+        .iter()
+        .map(|(_, trade)| trade.purchase_price)
+        .collect::<Vec<_>>();
+    prices.sort();
+    let median = *prices.get(prices.len() / 2)?;
+    let trade_volume = known.iter().map(|(_, trade)| trade.trade_volume).min()?;
+    Some(MarketTradeGood {
+        symbol: good.to_string(),
+        trade_volume,
+        _type: Exchange,
+        supply: Moderate,
+        activity: None,
+        purchase_price: median * 6 / 5,
+        sell_price: 0,
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    fn trade_good(purchase_price: i64, trade_volume: i64) -> MarketTradeGood {
+        MarketTradeGood {
+            symbol: "FUEL".to_string(),
+            trade_volume,
+            _type: Exchange,
+            supply: Moderate,
+            activity: None,
+            purchase_price,
+            sell_price: purchase_price - 2,
+        }
+    }
+
+    #[test]
+    fn test_trading_task_disabled() {
+        let buy = (WaypointSymbol::new("X1-S1-A1"), &trade_good(100, 60));
+        let sell_good = MarketTradeGood {
+            _type: Import,
+            sell_price: 150,
+            ..trade_good(160, 40)
+        };
+        let sell = (WaypointSymbol::new("X1-S1-A2"), &sell_good);
+        let task = trading_task("X1-S1/", "FUEL", Some(&buy), Some(&sell), 1.0, false).unwrap();
+        assert_eq!(task.id, "X1-S1/trade_FUEL_X1-S1-A1_X1-S1-A2");
+        assert_eq!(task.value, 50 * 40);
+        assert!(task.is_trade());
+        // no profit, no task
+        assert!(trading_task("X1-S1/", "FUEL", Some(&sell), Some(&buy), 1.0, false).is_none());
+
+        // with DEBUG_DISABLE_TRADING_TASKS no trade_ task is ever emitted
+        let tasks = [(Some(&buy), Some(&sell)), (Some(&buy), None)]
+            .into_iter()
+            .filter_map(|(buy, sell)| trading_task("X1-S1/", "FUEL", buy, sell, 1.0, true))
+            .collect::<Vec<_>>();
+        assert!(!tasks.iter().any(|t| t.id.contains("trade_")));
+    }
+
+    #[test]
+    fn test_trade_staleness() {
+        assert_eq!(staleness_factor(None), 1.0);
+        assert_eq!(staleness_factor(Some(10.)), 1.0);
+        assert_eq!(staleness_factor(Some(75.)), 0.5);
+        assert_eq!(staleness_factor(Some(135.)), 0.25);
+
+        assert!(!is_stale_market(None, 180));
+        assert!(!is_stale_market(Some(120.), 180));
+        assert!(is_stale_market(Some(200.), 180));
+        assert!(!is_stale_market(Some(10_000.), 0));
+
+        // an hour-old quote is worth half its computed profit
+        let buy = (WaypointSymbol::new("X1-S1-A1"), &trade_good(100, 60));
+        let sell_good = MarketTradeGood {
+            _type: Import,
+            sell_price: 150,
+            ..trade_good(160, 40)
+        };
+        let sell = (WaypointSymbol::new("X1-S1-A2"), &sell_good);
+        let staleness = staleness_factor(Some(75.));
+        let task = trading_task("X1-S1/", "FUEL", Some(&buy), Some(&sell), staleness, false);
+        assert_eq!(task.unwrap().value, 50 * 40 / 2);
+        // discounted to nothing, no task
+        assert!(trading_task("X1-S1/", "FUEL", Some(&buy), Some(&sell), 0.0, false).is_none());
+    }
+
+    #[test]
+    fn test_assumed_exchange_trade() {
+        assert!(assumed_exchange_trade("FUEL", &[]).is_none());
+
+        let (a, b, c) = (trade_good(70, 100), trade_good(100, 60), trade_good(90, 80));
+        let known = vec![
+            (WaypointSymbol::new("X1-S1-A1"), &a),
+            (WaypointSymbol::new("X1-S1-A2"), &b),
+            (WaypointSymbol::new("X1-S1-A3"), &c),
+        ];
+        let assumed = assumed_exchange_trade("FUEL", &known).unwrap();
+        assert_eq!(assumed.purchase_price, 108); // median 90 + 20%
+        assert_eq!(assumed.trade_volume, 60);
+        assert_eq!(assumed._type, Exchange);
+    }
+
+    fn trade_task(id: &str, src: &str, dest: &str, good: &str) -> Task {
+        Task {
+            id: id.to_string(),
+            actions: TaskActions::TransportCargo {
+                src: WaypointSymbol::new(src),
+                dest: WaypointSymbol::new(dest),
+                src_action: Action::BuyGoods(good.to_string(), 40),
+                dest_action: Action::SellGoods(good.to_string(), 40),
+            },
+            value: 5000,
+        }
+    }
+
+    // A trade task as generate_task_list builds it
+    fn generated_trade(src: &str, dest: &str, good: &str) -> Task {
+        let buy = (WaypointSymbol::new(src), &trade_good(100, 60));
+        let sell_good = MarketTradeGood {
+            _type: Import,
+            sell_price: 150,
+            ..trade_good(160, 40)
+        };
+        let sell = (WaypointSymbol::new(dest), &sell_good);
+        trading_task("X1-S1/", good, Some(&buy), Some(&sell), 1.0, false).unwrap()
+    }
+
+    #[test]
+    fn test_sell_target_cap() {
+        let in_progress = vec![
+            generated_trade("X1-S1-A1", "X1-S1-B1", "IRON"),
+            generated_trade("X1-S1-A2", "X1-S1-B1", "IRON"),
+            generated_trade("X1-S1-A1", "X1-S1-B1", "COPPER"),
+        ];
+        // routes are distinct tasks, so both IRON trades can be in progress at once
+        assert_ne!(in_progress[0].id, in_progress[1].id);
+        let counts = sell_target_counts(in_progress.clone().into_iter());
+
+        // two ships are already selling IRON at B1: a third can't join them
+        let third = generated_trade("X1-S1-A3", "X1-S1-B1", "IRON");
+        assert!(in_progress.iter().all(|task| task.id != third.id));
+        assert!(!under_sell_target_cap(&third, &counts, 2));
+        assert!(under_sell_target_cap(&third, &counts, 3));
+        // but another market, or another good at the same market, is fine
+        let elsewhere = generated_trade("X1-S1-A3", "X1-S1-B2", "IRON");
+        assert!(under_sell_target_cap(&elsewhere, &counts, 2));
+        let other_good = generated_trade("X1-S1-A3", "X1-S1-B1", "FOOD");
+        assert!(under_sell_target_cap(&other_good, &counts, 2));
+        // non-trades aren't capped
+        let refresh = Task {
+            id: "refreshmarket_X1-S1-B1".to_string(),
+            actions: TaskActions::VisitLocation {
+                waypoint: WaypointSymbol::new("X1-S1-B1"),
+                action: Action::RefreshMarket,
+            },
+            value: 500,
+        };
+        assert!(under_sell_target_cap(&refresh, &counts, 0));
+    }
+
+    #[test]
+    fn test_cold_start_assigns_nearest_refresh() {
+        let markets = vec![
+            WaypointSymbol::new("X1-S1-A1"),
+            WaypointSymbol::new("X1-S1-A2"),
+            WaypointSymbol::new("X1-S1-A3"),
+        ];
+        let durations = vec![
+            vec![0.0, 300.0, 120.0],
+            vec![300.0, 0.0, 200.0],
+            vec![120.0, 200.0, 0.0],
+        ];
+        let refresh = |waypoint: &WaypointSymbol| Task {
+            id: format!("X1-S1/refreshmarket_{}", waypoint),
+            actions: TaskActions::VisitLocation {
+                waypoint: waypoint.clone(),
+                action: Action::RefreshMarket,
+            },
+            value: 1000,
+        };
+        let tasks = vec![
+            // worth far more, but cold start doesn't weigh value
+            trade_task("X1-S1/trade_IRON", "X1-S1-A1", "X1-S1-A2", "IRON"),
+            refresh(&markets[1]),
+            refresh(&markets[2]),
+        ];
+        let actions = cold_start_actions(&tasks, &markets, &durations, &markets[0]).unwrap();
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].waypoint, markets[2]);
+        assert_eq!(actions[0].action, Action::RefreshMarket);
+        assert_eq!(actions[0].task_id, "X1-S1/refreshmarket_X1-S1-A3");
+        assert!(actions[0].completes_task);
+
+        // nothing to refresh: leave it to the planner
+        assert!(cold_start_actions(&tasks[..1], &markets, &durations, &markets[0]).is_none());
+    }
+
+    // Ships in two systems resolve to one manager per system, however many ask at
+    // once, and one system's in-progress tasks never show up in the other's.
+    #[tokio::test]
+    async fn test_registry_keeps_systems_apart() {
+        use crate::api_client::ApiClient;
+        let universe = Arc::new(Universe::from_caches_for_test(
+            ApiClient::for_test(),
+            DbClient::disconnected(),
+            vec![],
+            vec![],
+            vec![],
+        ));
+        let db = DbClient::disconnected();
+        let (home, remote) = (SystemSymbol::new("X1-AA1"), SystemSymbol::new("X1-BB2"));
+        let home_manager = Arc::new(LogisticTaskManager::empty_for_test(&universe, &db, &home));
+        let registry = Arc::new(TaskManagerRegistry::new(&home_manager, &universe, &db));
+
+        let lookups = (0..8).map(|i| {
+            let registry = registry.clone();
+            let (universe, db) = (universe.clone(), db.clone());
+            let system = if i % 2 == 0 {
+                home.clone()
+            } else {
+                remote.clone()
+            };
+            tokio::spawn(async move {
+                registry
+                    .get_with(&system, || async {
+                        tokio::task::yield_now().await;
+                        LogisticTaskManager::empty_for_test(&universe, &db, &system)
+                    })
+                    .await
+            })
+        });
+        let managers = futures::future::join_all(lookups).await;
+        let managers: Vec<_> = managers.into_iter().map(|m| m.unwrap()).collect();
+        for (i, manager) in managers.iter().enumerate() {
+            let expected = if i % 2 == 0 {
+                &managers[0]
+            } else {
+                &managers[1]
+            };
+            assert!(Arc::ptr_eq(manager, expected));
+        }
+        assert!(Arc::ptr_eq(&managers[0], &home_manager));
+        assert!(!Arc::ptr_eq(&managers[0], &managers[1]));
+        assert_eq!(managers[1].system(), &remote);
+
+        let task = trade_task("X1-BB2/trade_IRON", "X1-BB2-A1", "X1-BB2-A2", "IRON");
+        managers[1]
+            .state
+            .read()
+            .unwrap()
+            .in_progress_tasks
+            .insert(task.id.clone(), (task, "SHIP-2".to_string(), Utc::now()));
+        assert_eq!(managers[1].state.read().unwrap().in_progress_tasks.len(), 1);
+        assert!(
+            home_manager
+                .state
+                .read()
+                .unwrap()
+                .in_progress_tasks
+                .is_empty()
+        );
+    }
+
+    // Evaluating a what-if ship plans against the untaken trades and leaves the real
+    // task pool exactly as it was.
+    #[tokio::test]
+    async fn test_hypothetical_ship_leaves_state_untouched() {
+        use crate::api_client::ApiClient;
+        let universe = Arc::new(Universe::from_caches_for_test(
+            ApiClient::for_test(),
+            DbClient::disconnected(),
+            vec![],
+            vec![],
+            vec![],
+        ));
+        let db = DbClient::disconnected();
+        let manager =
+            LogisticTaskManager::empty_for_test(&universe, &db, &SystemSymbol::new("X1-S1"));
+        let taken = trade_task("X1-S1/trade_FOOD", "X1-S1-W1", "X1-S1-W2", "FOOD");
+        let open = trade_task("X1-S1/trade_IRON", "X1-S1-W2", "X1-S1-W1", "IRON");
+        {
+            let state = manager.state.read().unwrap();
+            state.in_progress_tasks.insert(
+                taken.id.clone(),
+                (taken.clone(), "SHIP-1".to_string(), Utc::now()),
+            );
+            state
+                .ship_tasks
+                .insert("SHIP-1".to_string(), VecDeque::new());
+        }
+        let before = serde_json::to_string(&*manager.state.read().unwrap()).unwrap();
+
+        let ship = LogisticShip {
+            symbol: "WHATIF".to_string(),
+            capacity: 40,
+            speed: 30,
+            start_waypoint: WaypointSymbol::new("X1-S1-W1"),
+        };
+        let plan = manager
+            .plan_untaken_tasks(
+                ship,
+                vec![taken, open],
+                vec![
+                    WaypointSymbol::new("X1-S1-W1"),
+                    WaypointSymbol::new("X1-S1-W2"),
+                ],
+                Arc::new((
+                    vec![vec![0.0, 100.0], vec![100.0, 0.0]],
+                    vec![vec![0.0, 100.0], vec![100.0, 0.0]],
+                )),
+                Duration::try_seconds(1).unwrap(),
+            )
+            .await;
+        // only the open trade was on offer, and it fits in an hour
+        assert_eq!(plan.tasks_available, 1);
+        assert_eq!(plan.tasks_assigned, 1);
+        assert_eq!(plan.value, 5000);
+
+        let after = serde_json::to_string(&*manager.state.read().unwrap()).unwrap();
+        assert_eq!(before, after);
+        assert_eq!(manager.get_planner_run_count(), 0);
+        assert!(manager.planner_rationale.is_empty());
+    }
+
+    // A purchase failed at the first shipyard mid-schedule: the follow-up visit goes
+    // right after the action being executed, and completing both leaves the rest of the
+    // schedule and its bookkeeping intact.
+    #[test]
+    fn test_buyships_followup_extends_schedule() {
+        let system = SystemSymbol::new("X1-S1");
+        let alt = WaypointSymbol::new("X1-S1-B2");
+        let buy = buyships_task(&system, &WaypointSymbol::new("X1-S1-A1"));
+        let followup = buyships_task(&system, &alt);
+        let trade = trade_task("X1-S1/trade_IRON", "X1-S1-W1", "X1-S1-W2", "IRON");
+        let scheduled = |waypoint: &str, action: Action, task_id: &str| ScheduledAction {
+            timestamp: 10.0,
+            waypoint: WaypointSymbol::new(waypoint),
+            action,
+            task_id: task_id.to_string(),
+            completes_task: true,
+        };
+        let current = scheduled("X1-S1-A1", Action::TryBuyShips, &buy.id);
+        let sell = scheduled(
+            "X1-S1-W2",
+            Action::SellGoods("IRON".to_string(), 40),
+            &trade.id,
+        );
+        let mut state = TaskManagerState {
+            in_progress_tasks: DashMap::new(),
+            ship_tasks: DashMap::new(),
+            logistics_ships: DashMap::new(),
+            planner_run_count: 0,
+        };
+        for task in [&buy, &trade] {
+            state.in_progress_tasks.insert(
+                task.id.clone(),
+                (task.clone(), "SHIP-1".to_string(), Utc::now()),
+            );
+        }
+        state.ship_tasks.insert(
+            "SHIP-1".to_string(),
+            VecDeque::from(vec![current.clone(), sell.clone()]),
+        );
+
+        state.insert_followup("SHIP-1", followup.clone());
+        let queue: Vec<_> = state
+            .ship_tasks
+            .get("SHIP-1")
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect();
+        let ids: Vec<_> = queue.iter().map(|a| a.task_id.as_str()).collect();
+        assert_eq!(ids, [buy.id.as_str(), &followup.id, &trade.id]);
+        assert_eq!(followup.id, "X1-S1/buyships_X1-S1-B2");
+        assert_eq!(queue[1].waypoint, alt);
+        assert_eq!(queue[1].action, Action::TryBuyShips);
+        assert!(queue[1].completes_task);
+        assert_eq!(
+            state.in_progress_tasks.get(&followup.id).unwrap().1,
+            "SHIP-1"
+        );
+
+        // the executing action completes its own task; the follow-up is next
+        state.complete_action("SHIP-1", &current);
+        assert!(!state.in_progress_tasks.contains_key(&buy.id));
+        assert!(state.in_progress_tasks.contains_key(&followup.id));
+        let next = state
+            .ship_tasks
+            .get("SHIP-1")
+            .unwrap()
+            .front()
+            .cloned()
+            .unwrap();
+        assert_eq!(next.task_id, followup.id);
+        state.complete_action("SHIP-1", &next);
+        assert!(!state.in_progress_tasks.contains_key(&followup.id));
+        assert!(state.in_progress_tasks.contains_key(&trade.id));
+        let rest: Vec<_> = state
+            .ship_tasks
+            .get("SHIP-1")
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect();
+        assert_eq!(rest, vec![sell]);
+
+        // an idle ship just gets the follow-up
+        state.insert_followup(
+            "SHIP-2",
+            buyships_task(&system, &WaypointSymbol::new("X1-S1-C3")),
+        );
+        assert_eq!(state.ship_tasks.get("SHIP-2").unwrap().len(), 1);
+    }
+
+    // Two ships planned together split the trades between them, and bookkeeping leaves
+    // every task in progress for exactly one ship
+    #[test]
+    fn test_joint_plan_assigns_each_task_once() {
+        let (w1, w2) = (
+            WaypointSymbol::new("X1-S1-W1"),
+            WaypointSymbol::new("X1-S1-W2"),
+        );
+        let food = trade_task("X1-S1/trade_FOOD", "X1-S1-W1", "X1-S1-W2", "FOOD");
+        let iron = trade_task("X1-S1/trade_IRON", "X1-S1-W2", "X1-S1-W1", "IRON");
+        let ship = |symbol: &str, start: &WaypointSymbol| LogisticShip {
+            symbol: symbol.to_string(),
+            capacity: 40,
+            speed: 30,
+            start_waypoint: start.clone(),
+        };
+        let tasks = vec![food.clone(), iron.clone()];
+        // one trade each fits in the plan, two in a row don't
+        let (mut schedules, _) = logistics_planner::plan::run_planner(
+            &[ship("SHIP-1", &w1), ship("SHIP-2", &w2)],
+            &tasks,
+            &[w1.clone(), w2.clone()],
+            &[vec![0.0, 100.0], vec![100.0, 0.0]],
+            &[vec![0.0, 100.0], vec![100.0, 0.0]],
+            &PlannerConstraints {
+                plan_length: 150,
+                max_compute_time: Duration::try_seconds(1).unwrap(),
+                explain: false,
+            },
+        );
+        assert!(force_assign_empty(&mut schedules, &tasks).is_empty());
+        assert_eq!(untaken_task_count(&schedules, &tasks), 0);
+        let mut state = TaskManagerState {
+            in_progress_tasks: DashMap::new(),
+            ship_tasks: DashMap::new(),
+            logistics_ships: DashMap::new(),
+            planner_run_count: 0,
+        };
+        state.assign_schedules(&schedules, &tasks);
+        assert_eq!(state.in_progress_tasks.get(&food.id).unwrap().1, "SHIP-1");
+        assert_eq!(state.in_progress_tasks.get(&iron.id).unwrap().1, "SHIP-2");
+        for ship_symbol in ["SHIP-1", "SHIP-2"] {
+            assert_eq!(state.ship_tasks.get(ship_symbol).unwrap().len(), 2);
+        }
+
+        // ships the planner left empty get distinct fallbacks, best first
+        let mut fuel = trade_task("X1-S1/trade_FUEL", "X1-S1-W1", "X1-S1-W2", "FUEL");
+        fuel.value = 1000;
+        let tasks = vec![food.clone(), fuel.clone(), iron.clone()];
+        let mut schedules = vec![
+            ShipSchedule {
+                ship: ship("SHIP-1", &w1),
+                actions: task_actions(&food),
+            },
+            ShipSchedule {
+                ship: ship("SHIP-2", &w1),
+                actions: vec![],
+            },
+            ShipSchedule {
+                ship: ship("SHIP-3", &w1),
+                actions: vec![],
+            },
+            ShipSchedule {
+                ship: ship("SHIP-4", &w1),
+                actions: vec![],
+            },
+        ];
+        assert_eq!(untaken_task_count(&schedules, &tasks), 2);
+        let forced = force_assign_empty(&mut schedules, &tasks);
+        let forced: Vec<_> = forced.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(forced, [iron.id.as_str(), &fuel.id]);
+        assert_eq!(schedules[1].actions[0].task_id, iron.id);
+        assert_eq!(schedules[2].actions[0].task_id, fuel.id);
+        assert!(schedules[3].actions.is_empty());
+    }
+
+    // Units are sized when a ship plans, not when the task is generated: the same
+    // generated trade planned for a small and then a large hauler queues what each hold
+    // takes, and each ship's run completes it and frees it for the next
+    #[test]
+    fn test_generated_task_sized_per_ship() {
+        let (w1, w2) = (
+            WaypointSymbol::new("X1-S1-W1"),
+            WaypointSymbol::new("X1-S1-W2"),
+        );
+        let task = generated_trade("X1-S1-W1", "X1-S1-W2", "IRON");
+        let mut state = TaskManagerState {
+            in_progress_tasks: DashMap::new(),
+            ship_tasks: DashMap::new(),
+            logistics_ships: DashMap::new(),
+            planner_run_count: 0,
+        };
+        for (ship_symbol, capacity, units) in [("SHIP-1", 20, 20), ("SHIP-2", 80, 40)] {
+            let tasks = vec![task.sized_for_capacity(capacity)];
+            let ship = LogisticShip {
+                symbol: ship_symbol.to_string(),
+                capacity,
+                speed: 30,
+                start_waypoint: w1.clone(),
+            };
+            let (schedules, _) = logistics_planner::plan::run_planner(
+                &[ship],
+                &tasks,
+                &[w1.clone(), w2.clone()],
+                &[vec![0.0, 100.0], vec![100.0, 0.0]],
+                &[vec![0.0, 100.0], vec![100.0, 0.0]],
+                &PlannerConstraints {
+                    plan_length: 1000,
+                    max_compute_time: Duration::try_seconds(1).unwrap(),
+                    explain: false,
+                },
+            );
+            state.assign_schedules(&schedules, &tasks);
+            assert_eq!(
+                state.in_progress_tasks.get(&task.id).unwrap().1,
+                ship_symbol
+            );
+
+            let queue: Vec<_> = state.ship_tasks.get(ship_symbol).unwrap().clone().into();
+            let actions: Vec<_> = queue.iter().map(|a| a.action.clone()).collect();
+            assert_eq!(
+                actions,
+                [
+                    Action::BuyGoods("IRON".to_string(), units),
+                    Action::SellGoods("IRON".to_string(), units),
+                ]
+            );
+            for action in &queue {
+                state.complete_action(ship_symbol, action);
+            }
+            assert!(state.in_progress_tasks.is_empty());
+            assert!(state.ship_tasks.get(ship_symbol).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "already in progress")]
+    fn test_assign_schedules_rejects_taken_task() {
+        let food = trade_task("X1-S1/trade_FOOD", "X1-S1-W1", "X1-S1-W2", "FOOD");
+        let schedule = |symbol: &str| ShipSchedule {
+            ship: LogisticShip {
+                symbol: symbol.to_string(),
+                capacity: 40,
+                speed: 30,
+                start_waypoint: WaypointSymbol::new("X1-S1-W1"),
+            },
+            actions: task_actions(&food),
+        };
+        let mut state = TaskManagerState {
+            in_progress_tasks: DashMap::new(),
+            ship_tasks: DashMap::new(),
+            logistics_ships: DashMap::new(),
+            planner_run_count: 0,
+        };
+        state.assign_schedules(&[schedule("SHIP-1")], std::slice::from_ref(&food));
+        state.assign_schedules(&[schedule("SHIP-2")], std::slice::from_ref(&food));
+    }
+
     #[tokio::test]
     async fn test_logistic_task_manager_state() {
         let in_progress_tasks = DashMap::<String, (Task, String, DateTime<Utc>)>::new();
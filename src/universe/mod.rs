@@ -10,6 +10,7 @@ use crate::models::{
     SystemSymbol, Waypoint, WaypointSymbol, WithTimestamp,
 };
 use crate::models::{SymbolNameDescr, WaypointDetails};
+use crate::nav_calibration::NAV_CALIBRATION;
 use crate::pathfinding::{Pathfinding, Route};
 use crate::schema::*;
 use dashmap::DashMap;
@@ -39,6 +40,34 @@ pub enum WaypointFilter {
     JumpGate,
 }
 
+// Market data older than this is still used, but its users are told it's stale
+pub const MARKET_STALE_AFTER_MINUTES: i64 = 30;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarketDataError {
+    NotAMarket,
+    // a market we've never had a ship read
+    NeverProbed,
+    // the cached data is available through get_market, but this old
+    Stale(chrono::Duration),
+}
+
+fn check_market(
+    is_market: bool,
+    market: Option<Arc<WithTimestamp<Market>>>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<Arc<WithTimestamp<Market>>, MarketDataError> {
+    if !is_market {
+        return Err(MarketDataError::NotAMarket);
+    }
+    let market = market.ok_or(MarketDataError::NeverProbed)?;
+    let age = now - market.timestamp;
+    if age > chrono::Duration::minutes(MARKET_STALE_AFTER_MINUTES) {
+        return Err(MarketDataError::Stale(age));
+    }
+    Ok(market)
+}
+
 #[derive(Debug, Clone)]
 pub struct JumpGateInfo {
     pub is_constructed: bool,
@@ -59,15 +88,33 @@ pub struct Universe {
     shipyards: DashMap<WaypointSymbol, Arc<WithTimestamp<Shipyard>>>,
     factions: DashMap<String, Faction>,
     jumpgates: DashMap<WaypointSymbol, JumpGateInfo>,
+    // last fuel-scarcity verdict per system, so the switch is only logged when it changes
+    fuel_scarce_systems: DashMap<SystemSymbol, bool>,
 
     // flips to true once the full galaxy of systems has been loaded into the DB +
     // cache; full-galaxy consumers (jumpgate/warp graphs) await this.
     systems_ready: tokio::sync::watch::Sender<bool>,
 
     // cache
-    warp_jump_graph: Cache<(), BTreeMap<SystemSymbol, BTreeMap<SystemSymbol, WarpEdge>>>,
+    // keyed by (fuel capacity, engine speed), see `ship_warp_jump_graph`
+    warp_jump_graph: Cache<(i64, i64), BTreeMap<SystemSymbol, BTreeMap<SystemSymbol, WarpEdge>>>,
     // cached jumpgate graph; invalidated whenever a gate's connections change
     jumpgate_graph: Cache<(), Arc<BTreeMap<WaypointSymbol, JumpGate>>>,
+    // keyed by (system, fuel capacity, engine speed), see `travel_matrix`
+    travel_matrices: Cache<(SystemSymbol, i64, i64), Arc<CachedTravelMatrix>>,
+}
+
+// (durations, distances) between a system's markets, indexed like the market list
+// they were built from (see pathfinding::full_travel_matrix)
+pub type TravelMatrix = (Vec<Vec<f64>>, Vec<Vec<f64>>);
+
+struct CachedTravelMatrix {
+    // the market list it was built from: symbols and coordinates, in order
+    markets: Vec<(WaypointSymbol, i64, i64)>,
+    fuel_scarce: bool,
+    // NAV_CALIBRATION epoch its durations were calibrated at
+    calibration_epoch: u64,
+    matrix: Arc<TravelMatrix>,
 }
 
 impl Universe {
@@ -101,10 +148,12 @@ impl Universe {
             shipyards: DashMap::from_iter(shipyards),
             factions: DashMap::from_iter(factions),
             jumpgates: DashMap::from_iter(jumpgates),
+            fuel_scarce_systems: DashMap::new(),
             systems_ready,
 
-            warp_jump_graph: Cache::new(1),
+            warp_jump_graph: Cache::new(8),
             jumpgate_graph: Cache::new(1),
+            travel_matrices: Cache::new(64),
         }
     }
 
@@ -133,9 +182,11 @@ impl Universe {
             shipyards: DashMap::new(),
             factions: DashMap::new(),
             jumpgates: DashMap::from_iter(jumpgates),
+            fuel_scarce_systems: DashMap::new(),
             systems_ready,
-            warp_jump_graph: Cache::new(1),
+            warp_jump_graph: Cache::new(8),
             jumpgate_graph: Cache::new(1),
+            travel_matrices: Cache::new(64),
         }
     }
 
@@ -331,6 +382,12 @@ impl Universe {
             .value()
             .clone()
     }
+    // Whether the waypoint is in our copy of the galaxy (config typos, chart changes)
+    pub fn waypoint_exists(&self, symbol: &WaypointSymbol) -> bool {
+        self.systems
+            .get(&symbol.system())
+            .is_some_and(|s| s.waypoints.iter().any(|w| &w.symbol == symbol))
+    }
     pub fn waypoint(&self, symbol: &WaypointSymbol) -> Waypoint {
         let system_symbol = symbol.system();
         let system = self.systems.get(&system_symbol).expect("System not found");
@@ -350,6 +407,19 @@ impl Universe {
         self.markets.get(waypoint_symbol).map(|x| x.value().clone())
     }
 
+    // Like get_market, but says why there's no fresh data
+    pub async fn require_market(
+        &self,
+        waypoint_symbol: &WaypointSymbol,
+    ) -> Result<Arc<WithTimestamp<Market>>, MarketDataError> {
+        let is_market = self.detailed_waypoint(waypoint_symbol).await.is_market();
+        check_market(
+            is_market,
+            self.get_market(waypoint_symbol),
+            chrono::Utc::now(),
+        )
+    }
+
     pub async fn save_market(
         &self,
         waypoint_symbol: &WaypointSymbol,
@@ -484,10 +554,15 @@ impl Universe {
     // cache, stalling the lock past its timeout. Idempotent: the system load is
     // skipped when already cached, and each warm-up getter checks its own
     // cache/DB first (e.g. after a restart).
-    pub async fn ensure_system_loaded(&self, symbol: &SystemSymbol) {
+    // `force_refresh` re-fetches the system and its waypoint charts from the API,
+    // past both our caches and the API client's response cache
+    pub async fn ensure_system_loaded(&self, symbol: &SystemSymbol, force_refresh: bool) {
         let start = std::time::Instant::now();
-        if !self.systems.contains_key(symbol) {
-            self.load_system(symbol).await;
+        if force_refresh || !self.systems.contains_key(symbol) {
+            self.load_system(symbol, force_refresh).await;
+        }
+        if force_refresh {
+            self.refresh_system_waypoints(symbol).await;
         }
         self.get_system_waypoints(symbol).await;
         self.get_system_markets_remote(symbol).await;
@@ -500,10 +575,13 @@ impl Universe {
     }
 
     // Fetch system info from API, insert to database and cache
-    pub async fn load_system(&self, symbol: &SystemSymbol) {
+    pub async fn load_system(&self, symbol: &SystemSymbol, force_refresh: bool) {
         // 1. Get from API (single system)
-        let system: api_models::System = self
-            .api_client
+        let api_client = match force_refresh {
+            true => self.api_client.uncached(),
+            false => self.api_client.clone(),
+        };
+        let system: api_models::System = api_client
             .get::<Data<api_models::System>>(&format!("/systems/{}", symbol))
             .await
             .data;
@@ -562,9 +640,13 @@ impl Universe {
     // overwriting our cached details. Unlike get_system_waypoints (which trusts cached
     // details and only fetches when some are missing), this picks up traits that were
     // charted by other agents since we first loaded the system — e.g. markets that were
-    // uncharted at startup. Returns the fresh waypoints.
+    // uncharted at startup. Bypasses the API response cache. Returns the fresh waypoints.
     pub async fn refresh_system_waypoints(&self, symbol: &SystemSymbol) -> Vec<WaypointDetailed> {
-        let waypoints = self.api_client.get_system_waypoints(symbol).await;
+        let waypoints = self
+            .api_client
+            .uncached()
+            .get_system_waypoints(symbol)
+            .await;
         self.ingest_scanned_waypoints(&waypoints).await;
         waypoints
     }
@@ -842,6 +924,21 @@ impl Universe {
         markets
     }
 
+    // Fraction of the system's markets we hold full (priced) data for. 1.0 if there are none.
+    pub async fn market_coverage(&self, symbol: &SystemSymbol) -> f64 {
+        let markets = self
+            .get_system_waypoints(symbol)
+            .await
+            .into_iter()
+            .filter(|w| w.is_market())
+            .map(|w| self.markets.contains_key(&w.symbol))
+            .collect::<Vec<_>>();
+        if markets.is_empty() {
+            return 1.0;
+        }
+        markets.iter().filter(|priced| **priced).count() as f64 / markets.len() as f64
+    }
+
     pub async fn get_system_shipyards(
         &self,
         symbol: &SystemSymbol,
@@ -1031,14 +1128,69 @@ impl Universe {
         speed: i64,
         start_fuel: i64,
         fuel_capacity: i64,
-    ) -> Route {
+    ) -> Option<Route> {
         let system_symbol = src.system();
         assert_eq!(system_symbol, dest.system());
         let waypoints = self.get_system_waypoints(&system_symbol).await;
         let pathfinding = Pathfinding::new(waypoints);
+        let fuel_scarce = pathfinding.is_fuel_scarce();
+        let prev = self
+            .fuel_scarce_systems
+            .insert(system_symbol.clone(), fuel_scarce);
+        if fuel_scarce && prev != Some(true) {
+            info!(
+                "System {} has too few markets: treating it as fuel-scarce (cruise-only routing)",
+                system_symbol
+            );
+        }
         pathfinding.get_route(src, dest, speed, start_fuel, fuel_capacity)
     }
 
+    // The all-pairs travel matrix over `market_waypoints` for a ship with this fuel
+    // capacity and speed (cruise-only if `fuel_scarce`). Cached per system and ship type,
+    // and rebuilt when the system's market list or fuel scarcity changes, or once nav
+    // calibration has drifted enough to bump its epoch.
+    pub async fn travel_matrix(
+        &self,
+        system: &SystemSymbol,
+        market_waypoints: &[WaypointDetailed],
+        fuel_capacity: i64,
+        engine_speed: i64,
+        fuel_scarce: bool,
+    ) -> Arc<TravelMatrix> {
+        let key = (system.clone(), fuel_capacity, engine_speed);
+        let calibration_epoch = NAV_CALIBRATION.epoch();
+        let markets: Vec<_> = market_waypoints
+            .iter()
+            .map(|w| (w.symbol.clone(), w.x, w.y))
+            .collect();
+        if let Some(cached) = self.travel_matrices.get(&key).await
+            && cached.markets == markets
+            && cached.fuel_scarce == fuel_scarce
+            && cached.calibration_epoch == calibration_epoch
+        {
+            return cached.matrix.clone();
+        }
+        let matrix = Arc::new(pathfinding::full_travel_matrix(
+            market_waypoints,
+            fuel_capacity,
+            engine_speed,
+            fuel_scarce,
+        ));
+        self.travel_matrices
+            .insert(
+                key,
+                Arc::new(CachedTravelMatrix {
+                    markets,
+                    fuel_scarce,
+                    calibration_epoch,
+                    matrix: matrix.clone(),
+                }),
+            )
+            .await;
+        matrix
+    }
+
     pub async fn get_jumpgate_opt(&self, symbol: &SystemSymbol) -> Option<WaypointSymbol> {
         let waypoints = self.get_system_waypoints(symbol).await;
         waypoints
@@ -1300,3 +1452,41 @@ async fn load_shipyards(db: &DbClient) -> Vec<(WaypointSymbol, Arc<WithTimestamp
         .map(|(symbol, shipyard)| (symbol, Arc::new(shipyard)))
         .collect()
 }
+
+#[cfg(test)]
+mod market_data_tests {
+    use super::*;
+    use chrono::{DateTime, Duration, Utc};
+
+    fn market(now: DateTime<Utc>, age: Duration) -> Option<Arc<WithTimestamp<Market>>> {
+        Some(Arc::new(WithTimestamp {
+            timestamp: now - age,
+            data: Market {
+                symbol: WaypointSymbol::new("X1-S1-A1"),
+                exports: vec![],
+                imports: vec![],
+                exchange: vec![],
+                transactions: vec![],
+                trade_goods: vec![],
+            },
+        }))
+    }
+
+    #[test]
+    fn test_check_market() {
+        let now = Utc::now();
+        assert!(check_market(true, market(now, Duration::minutes(5)), now).is_ok());
+        assert_eq!(
+            check_market(false, market(now, Duration::minutes(5)), now).unwrap_err(),
+            MarketDataError::NotAMarket
+        );
+        assert_eq!(
+            check_market(true, None, now).unwrap_err(),
+            MarketDataError::NeverProbed
+        );
+        match check_market(true, market(now, Duration::hours(2)), now) {
+            Err(MarketDataError::Stale(age)) => assert!(age >= Duration::hours(2)),
+            other => panic!("expected stale, got {:?}", other.map(|_| ())),
+        }
+    }
+}
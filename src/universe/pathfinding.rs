@@ -1,13 +1,14 @@
 use super::Universe;
 use crate::api_client::api_models::WaypointDetailed;
 use crate::models::{ShipFlightMode, System, SystemSymbol, WaypointSymbol};
+use crate::nav_calibration::NAV_CALIBRATION;
 use crate::util;
 use log::*;
-use pathfinding::directed::dijkstra::dijkstra_all;
+use pathfinding::directed::dijkstra::{dijkstra, dijkstra_all};
 use quadtree_rs::area::AreaBuilder;
 use quadtree_rs::{Quadtree, point::Point};
 use std::cmp::max;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 
 pub struct NavEdge {
@@ -23,6 +24,15 @@ pub struct JumpGate {
     pub all_connections_known: bool,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct JumpRoute {
+    // each gate jumped to, with the cooldown that jump incurs
+    pub hops: Vec<(WaypointSymbol, i64)>,
+    // seconds until the last jump's cooldown has cleared: every jump waits out the one
+    // before it, and a hauler can't jump back before the last one's is up either
+    pub duration: i64,
+}
+
 #[derive(Debug, Clone)]
 pub enum EdgeType {
     Warp,
@@ -174,8 +184,17 @@ impl Universe {
         from_gate: &WaypointSymbol,
         target_gate: &WaypointSymbol,
     ) -> bool {
+        self.jumpgate_cost(from_gate, target_gate).await.is_some()
+    }
+
+    // Total jump cooldown from one gate to another, or None if unreachable
+    pub async fn jumpgate_cost(
+        &self,
+        from_gate: &WaypointSymbol,
+        target_gate: &WaypointSymbol,
+    ) -> Option<i64> {
         if from_gate == target_gate {
-            return true;
+            return Some(0);
         }
         let graph = self.jumpgate_graph().await;
         let reachables = dijkstra_all(from_gate, |node| {
@@ -184,7 +203,22 @@ impl Universe {
                 .map(|g| g.active_connections.clone())
                 .unwrap_or_default()
         });
-        reachables.contains_key(target_gate)
+        reachables.get(target_gate).map(|(_pre, cost)| *cost)
+    }
+
+    // The jump-gate route from `src` to `dest` over the constructed + charted network,
+    // or None if either system has no gate or they aren't connected
+    pub async fn jump_route(&self, src: &SystemSymbol, dest: &SystemSymbol) -> Option<JumpRoute> {
+        if src == dest {
+            return Some(JumpRoute {
+                hops: vec![],
+                duration: 0,
+            });
+        }
+        let src_gate = self.get_jumpgate_opt(src).await?;
+        let dest_gate = self.get_jumpgate_opt(dest).await?;
+        let graph = self.jumpgate_graph().await;
+        plan_jump_route(&graph, &src_gate, &dest_gate)
     }
 
     // Systems with P(T5) >= 0.5 whose jump gate is reachable from `from_gate`
@@ -230,15 +264,26 @@ impl Universe {
 
     pub async fn warp_jump_graph(
         &self,
+    ) -> BTreeMap<SystemSymbol, BTreeMap<SystemSymbol, WarpEdge>> {
+        const EXPLORER_FUEL_CAPACITY: i64 = 800;
+        const EXPLORER_SPEED: i64 = 30;
+        self.ship_warp_jump_graph(EXPLORER_FUEL_CAPACITY, EXPLORER_SPEED)
+            .await
+    }
+
+    // The warp/jump graph for a ship with this fuel capacity (its cruise warp range)
+    // and engine speed, cached per ship type
+    pub async fn ship_warp_jump_graph(
+        &self,
+        fuel_capacity: i64,
+        engine_speed: i64,
     ) -> BTreeMap<SystemSymbol, BTreeMap<SystemSymbol, WarpEdge>> {
         self.warp_jump_graph
-            .get_with((), async {
-                const EXPLORER_FUEL_CAPACITY: i64 = 800;
-                const EXPLORER_SPEED: i64 = 30;
+            .get_with((fuel_capacity, engine_speed), async {
                 // Cache-only build (reads jumpgate_graph + cached systems); forbid I/O.
                 crate::api_client::no_io_section(
                     "warp_jump_graph",
-                    self._warp_jump_graph(EXPLORER_FUEL_CAPACITY, EXPLORER_SPEED),
+                    self._warp_jump_graph(fuel_capacity, engine_speed),
                 )
                 .await
             })
@@ -350,13 +395,65 @@ impl Universe {
     }
 }
 
+// Least-cooldown route between two gates over the jumpgate graph
+pub fn plan_jump_route(
+    graph: &BTreeMap<WaypointSymbol, JumpGate>,
+    src_gate: &WaypointSymbol,
+    dest_gate: &WaypointSymbol,
+) -> Option<JumpRoute> {
+    let connections = |node: &WaypointSymbol| {
+        graph
+            .get(node)
+            .map(|g| g.active_connections.clone())
+            .unwrap_or_default()
+    };
+    let (path, duration) = dijkstra(src_gate, connections, |node| node == dest_gate)?;
+    let hops = path
+        .windows(2)
+        .map(|pair| {
+            let cooldown = connections(&pair[0])
+                .into_iter()
+                .filter(|(w, _)| w == &pair[1])
+                .map(|(_, cooldown)| cooldown)
+                .min()
+                .unwrap();
+            (pair[1].clone(), cooldown)
+        })
+        .collect();
+    Some(JumpRoute { hops, duration })
+}
+
+// Fastest route from `src` to `dest` over a warp/jump graph: the systems visited (both
+// ends included) and the total duration. Connected gates already replace the warp edge
+// between their systems, so a jump is taken wherever one exists.
+pub fn plan_travel(
+    graph: &BTreeMap<SystemSymbol, BTreeMap<SystemSymbol, WarpEdge>>,
+    src: &SystemSymbol,
+    dest: &SystemSymbol,
+) -> Option<(Vec<SystemSymbol>, i64)> {
+    dijkstra(
+        src,
+        |node| {
+            graph
+                .get(node)
+                .into_iter()
+                .flatten()
+                .map(|(s, d)| (s.clone(), d.duration))
+        },
+        |node| node == dest,
+    )
+}
+
 // Returns a matrix between market waypoints. Assumes we can refuel at any waypoint.
 // Weights are the travel duration in seconds between two waypoints
-// Preferring BURN flight mode, and only CRUISE if the fuel capacity isn't high enough
+// Preferring BURN flight mode, and only CRUISE if the fuel capacity isn't high enough.
+// Fuel-scarce systems (crate::pathfinding::is_fuel_scarce) are cruise-only, matching
+// how routes there are actually flown.
 pub fn market_adjacency_edges(
     market_waypoints: &[WaypointDetailed],
     ship_max_fuel: i64,
     ship_speed: i64,
+    fuel_scarce: bool,
 ) -> Vec<BTreeMap<usize, NavEdge>> {
     let mut edges = Vec::new();
     for w1 in market_waypoints.iter() {
@@ -365,17 +462,20 @@ pub fn market_adjacency_edges(
             let dist = util::distance(w1, w2);
             let burn_fuel = util::fuel_cost(&ShipFlightMode::Burn, dist);
             let cruise_fuel = util::fuel_cost(&ShipFlightMode::Cruise, dist);
-            if burn_fuel <= ship_max_fuel {
+            if !fuel_scarce && burn_fuel <= ship_max_fuel {
                 row.insert(
                     j,
                     NavEdge {
                         flight_mode: ShipFlightMode::Burn,
                         fuel_cost: burn_fuel,
                         distance: dist,
-                        duration: util::estimated_travel_duration(
+                        duration: NAV_CALIBRATION.calibrated(
                             &ShipFlightMode::Burn,
-                            ship_speed,
-                            dist,
+                            util::estimated_travel_duration(
+                                &ShipFlightMode::Burn,
+                                ship_speed,
+                                dist,
+                            ),
                         ),
                     },
                 );
@@ -386,10 +486,13 @@ pub fn market_adjacency_edges(
                         flight_mode: ShipFlightMode::Cruise,
                         fuel_cost: cruise_fuel,
                         distance: dist,
-                        duration: util::estimated_travel_duration(
+                        duration: NAV_CALIBRATION.calibrated(
                             &ShipFlightMode::Cruise,
-                            ship_speed,
-                            dist,
+                            util::estimated_travel_duration(
+                                &ShipFlightMode::Cruise,
+                                ship_speed,
+                                dist,
+                            ),
                         ),
                     },
                 );
@@ -405,12 +508,13 @@ pub fn full_travel_matrix(
     market_waypoints: &[WaypointDetailed],
     ship_max_fuel: i64,
     ship_speed: i64,
+    fuel_scarce: bool,
 ) -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
     let mut durations: Vec<Vec<f64>> =
         vec![vec![0.; market_waypoints.len()]; market_waypoints.len()];
     let mut distances: Vec<Vec<f64>> =
         vec![vec![0.; market_waypoints.len()]; market_waypoints.len()];
-    let edges = market_adjacency_edges(market_waypoints, ship_max_fuel, ship_speed);
+    let edges = market_adjacency_edges(market_waypoints, ship_max_fuel, ship_speed, fuel_scarce);
     for i in 0..market_waypoints.len() {
         for j in 0..market_waypoints.len() {
             if i == j {
@@ -438,6 +542,156 @@ pub fn full_travel_matrix(
     (durations, distances)
 }
 
+// Market waypoints that some shortest path in the travel matrix passes *through*, i.e.
+// the waypoint is an intermediate refuel stop between two other markets. Same edge set
+// and floyd warshall relaxation as full_travel_matrix, but tracking the next hop so the
+// paths can be reconstructed.
+pub fn travel_matrix_fuel_stops(
+    market_waypoints: &[WaypointDetailed],
+    ship_max_fuel: i64,
+    ship_speed: i64,
+    fuel_scarce: bool,
+) -> BTreeSet<WaypointSymbol> {
+    let n = market_waypoints.len();
+    let edges = market_adjacency_edges(market_waypoints, ship_max_fuel, ship_speed, fuel_scarce);
+    let mut durations: Vec<Vec<f64>> = vec![vec![f64::INFINITY; n]; n];
+    let mut next: Vec<Vec<Option<usize>>> = vec![vec![None; n]; n];
+    for i in 0..n {
+        for (&j, edge) in &edges[i] {
+            durations[i][j] = if i == j { 0. } else { edge.duration as f64 };
+            next[i][j] = Some(j);
+        }
+    }
+    for k in 0..n {
+        for i in 0..n {
+            for j in 0..n {
+                let via_k = durations[i][k] + durations[k][j];
+                if via_k < durations[i][j] {
+                    durations[i][j] = via_k;
+                    next[i][j] = next[i][k];
+                }
+            }
+        }
+    }
+    let mut stops = BTreeSet::new();
+    for (i, row) in next.iter().enumerate() {
+        for (j, hop) in row.iter().enumerate() {
+            let mut cur = match hop {
+                Some(hop) if i != j => *hop,
+                _ => continue,
+            };
+            while cur != j {
+                stops.insert(market_waypoints[cur].symbol.clone());
+                cur = next[cur][j].unwrap();
+            }
+        }
+    }
+    stops
+}
+
+#[cfg(test)]
+mod fuel_stop_tests {
+    use super::*;
+    use crate::models::SymbolNameDescr;
+
+    fn market(sym: &str, x: i64, y: i64) -> WaypointDetailed {
+        let symbol = WaypointSymbol::new(sym);
+        WaypointDetailed {
+            system_symbol: symbol.system(),
+            symbol,
+            waypoint_type: "PLANET".to_string(),
+            x,
+            y,
+            orbitals: vec![],
+            orbits: None,
+            faction: None,
+            traits: vec![SymbolNameDescr {
+                symbol: "MARKETPLACE".to_string(),
+                name: String::new(),
+                description: String::new(),
+            }],
+            modifiers: vec![],
+            chart: None,
+            is_under_construction: false,
+        }
+    }
+
+    #[test]
+    fn midpoint_market_is_a_fuel_stop_only_when_required() {
+        let a = market("X1-T-A", 0, 0);
+        let b = market("X1-T-B", 100, 0);
+        let c = market("X1-T-C", 200, 0);
+        let waypoints = vec![a, b.clone(), c];
+
+        // 150 fuel can cover 100 units of cruise but not 200: A <-> C must stop at B.
+        let stops = travel_matrix_fuel_stops(&waypoints, 150, 30, false);
+        assert_eq!(stops, BTreeSet::from([b.symbol.clone()]));
+
+        // With a big tank every pair is a direct hop.
+        let stops = travel_matrix_fuel_stops(&waypoints, 1000, 30, false);
+        assert!(stops.is_empty());
+    }
+
+    // Fuel-scarce systems are flown cruise-only, so the planner's matrix must be too
+    #[test]
+    fn fuel_scarce_edges_are_cruise_only() {
+        let waypoints = vec![market("X1-T-A", 0, 0), market("X1-T-B", 100, 0)];
+        let edges = market_adjacency_edges(&waypoints, 1000, 30, false);
+        assert!(matches!(edges[0][&1].flight_mode, ShipFlightMode::Burn));
+        let edges = market_adjacency_edges(&waypoints, 1000, 30, true);
+        assert!(matches!(edges[0][&1].flight_mode, ShipFlightMode::Cruise));
+        assert_eq!(edges[0][&1].fuel_cost, 100);
+
+        let (burn, _) = full_travel_matrix(&waypoints, 1000, 30, false);
+        let (cruise, _) = full_travel_matrix(&waypoints, 1000, 30, true);
+        assert!(cruise[0][1] > burn[0][1]);
+    }
+
+    #[tokio::test]
+    async fn travel_matrix_is_cached_until_markets_change() {
+        let universe = crate::universe::Universe::from_caches_for_test(
+            crate::api_client::ApiClient::for_test(),
+            crate::database::DbClient::disconnected(),
+            vec![],
+            vec![],
+            vec![],
+        );
+        let system = SystemSymbol::new("X1-T");
+        let mut waypoints = vec![market("X1-T-A", 0, 0), market("X1-T-B", 100, 0)];
+
+        let m1 = universe
+            .travel_matrix(&system, &waypoints, 150, 30, false)
+            .await;
+        let m2 = universe
+            .travel_matrix(&system, &waypoints, 150, 30, false)
+            .await;
+        assert!(Arc::ptr_eq(&m1, &m2));
+        assert_eq!(*m1, full_travel_matrix(&waypoints, 150, 30, false));
+
+        // a different ship type has its own entry
+        let m3 = universe
+            .travel_matrix(&system, &waypoints, 400, 30, false)
+            .await;
+        assert!(!Arc::ptr_eq(&m1, &m3));
+
+        // the system turning fuel-scarce rebuilds it cruise-only
+        let scarce = universe
+            .travel_matrix(&system, &waypoints, 400, 30, true)
+            .await;
+        assert_ne!(*scarce, *m3);
+        assert_eq!(*scarce, full_travel_matrix(&waypoints, 400, 30, true));
+
+        // a new market invalidates it
+        waypoints.push(market("X1-T-C", 200, 0));
+        let m4 = universe
+            .travel_matrix(&system, &waypoints, 150, 30, false)
+            .await;
+        assert!(!Arc::ptr_eq(&m1, &m4));
+        assert_eq!(m4.0.len(), 3);
+        assert_eq!(*m4, full_travel_matrix(&waypoints, 150, 30, false));
+    }
+}
+
 #[cfg(test)]
 mod builder_no_io_tests {
     use super::*;
@@ -538,3 +792,88 @@ mod builder_no_io_tests {
         );
     }
 }
+
+#[cfg(test)]
+mod travel_tests {
+    use super::*;
+
+    fn gate(connections: &[(&WaypointSymbol, i64)]) -> JumpGate {
+        JumpGate {
+            active_connections: connections
+                .iter()
+                .map(|(w, cooldown)| ((*w).clone(), *cooldown))
+                .collect(),
+            is_constructed: true,
+            all_connections_known: true,
+        }
+    }
+
+    #[test]
+    fn plan_jump_route_sums_cooldowns() {
+        let (a, b, c, d) = (
+            WaypointSymbol::new("X1-A-I1"),
+            WaypointSymbol::new("X1-B-I1"),
+            WaypointSymbol::new("X1-C-I1"),
+            WaypointSymbol::new("X1-D-I1"),
+        );
+        let graph = BTreeMap::from([
+            (a.clone(), gate(&[(&b, 100), (&c, 400)])),
+            (b.clone(), gate(&[(&a, 100), (&c, 150)])),
+            (c.clone(), gate(&[(&a, 400), (&b, 150)])),
+            (d.clone(), gate(&[])),
+        ]);
+
+        // two short jumps beat the long direct one
+        let route = plan_jump_route(&graph, &a, &c).unwrap();
+        assert_eq!(route.hops, vec![(b.clone(), 100), (c.clone(), 150)]);
+        assert_eq!(route.duration, 250);
+
+        let route = plan_jump_route(&graph, &a, &a).unwrap();
+        assert!(route.hops.is_empty());
+        assert_eq!(route.duration, 0);
+        assert_eq!(plan_jump_route(&graph, &a, &d), None);
+    }
+
+    fn edge(edge_type: EdgeType, duration: i64, fuel: i64) -> WarpEdge {
+        WarpEdge {
+            duration,
+            edge_type,
+            fuel,
+        }
+    }
+
+    #[test]
+    fn plan_travel_takes_the_fastest_mix_of_warps_and_jumps() {
+        let (a, b, c, d) = (
+            SystemSymbol::new("X1-A"),
+            SystemSymbol::new("X1-B"),
+            SystemSymbol::new("X1-C"),
+            SystemSymbol::new("X1-D"),
+        );
+        let graph = BTreeMap::from([
+            (
+                a.clone(),
+                BTreeMap::from([
+                    (b.clone(), edge(EdgeType::Warp, 100, 50)),
+                    (c.clone(), edge(EdgeType::Warp, 500, 400)),
+                ]),
+            ),
+            (
+                b.clone(),
+                BTreeMap::from([(c.clone(), edge(EdgeType::Jumpgate, 60, 0))]),
+            ),
+            (c.clone(), BTreeMap::new()),
+            (d.clone(), BTreeMap::new()),
+        ]);
+
+        // warp to B then jump beats the long direct warp
+        let (path, duration) = plan_travel(&graph, &a, &c).unwrap();
+        assert_eq!(path, vec![a.clone(), b.clone(), c.clone()]);
+        assert_eq!(duration, 160);
+
+        assert_eq!(plan_travel(&graph, &a, &a), Some((vec![a.clone()], 0)));
+        assert_eq!(plan_travel(&graph, &a, &d), None);
+        // systems missing from the graph have no edges
+        assert_eq!(plan_travel(&graph, &SystemSymbol::new("X1-E"), &a), None);
+    }
+}
@@ -1,18 +1,28 @@
-//! Lightweight read-only JSON API embedded in the agent.
+//! Lightweight JSON API embedded in the agent.
 //!
 //! Backed by the live in-memory agent/fleet state plus the TimescaleDB KPI
-//! history. Consumed cross-origin by the standalone dashboard SPA.
+//! history. Consumed cross-origin by the standalone dashboard SPA. Everything is
+//! read-only except the operator pause controls, which need `OPERATOR_TOKEN` (see
+//! `authorize_operator`): the server may be public behind the helm ingress, and CORS
+//! only restrains browsers. The what-if endpoint needs it too, as it runs the planner.
 
 use crate::agent_controller::AgentController;
+use crate::agent_controller::ledger::GoodProfit;
+use crate::agent_controller::pause::PauseScope;
+use crate::agent_controller::what_if::ShipWhatIf;
+use crate::config::CONFIG;
 use crate::database::DbClient;
+use crate::logistics_planner::PlannerRationale;
+use crate::mining_stats::ThroughputReport;
 use crate::models::{MarketTradeGood, ShipNavStatus, WaypointSymbol};
 use axum::{
     Json, Router,
-    extract::{Path, State},
-    routing::get,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header::AUTHORIZATION},
+    routing::{get, post},
 };
 use log::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use tower_http::cors::{Any, CorsLayer};
 
@@ -37,6 +47,16 @@ pub async fn serve(controller: AgentController, db: DbClient, port: u16) {
         .route("/api/systems", get(api_systems))
         .route("/api/systems/{system}/markets", get(api_system_markets))
         .route("/api/markets/{waypoint}", get(api_market))
+        .route("/api/mining", get(api_mining))
+        .route("/api/profits", get(api_profits))
+        .route(
+            "/api/ships/{ship}/planner_rationale",
+            get(api_planner_rationale),
+        )
+        .route("/api/whatif/ship", get(api_whatif_ship))
+        .route("/api/pauses", get(api_pauses))
+        .route("/api/pause", post(api_pause))
+        .route("/api/resume", post(api_resume))
         .layer(cors)
         .with_state(state);
 
@@ -62,6 +82,8 @@ struct AgentSummary {
     num_ships: usize,
     // current lifecycle phase (e.g. StartingSystem1, InterSystem1)
     era: String,
+    // too many ship task panics: the agent is observe-only
+    safe_mode: bool,
 }
 
 async fn api_agent(State(s): State<AppState>) -> Json<AgentSummary> {
@@ -83,6 +105,7 @@ async fn api_agent(State(s): State<AppState>) -> Json<AgentSummary> {
         starting_faction: agent.starting_faction,
         num_ships,
         era,
+        safe_mode: s.controller.safe_mode.is_tripped(),
     })
 }
 
@@ -109,6 +132,8 @@ struct ShipView {
     // + scrap + its share of contract payouts, split across deliverers by units).
     // Excludes only the agent-level on_accepted signing bonus. See net_cash_by_ship.
     net_cash: i64,
+    // an operator pause covers this ship's job
+    paused: bool,
 }
 
 async fn api_ships(State(s): State<AppState>) -> Json<Vec<ShipView>> {
@@ -127,6 +152,7 @@ async fn api_ships(State(s): State<AppState>) -> Json<Vec<ShipView>> {
         .map(|(symbol, ship, role, descr)| {
             let ship_type = ship.model().unwrap_or_else(|_| ship.frame.symbol.clone());
             let ship_net_cash = net_cash.get(&symbol).copied().unwrap_or(0);
+            let paused = s.controller.fleet.is_ship_paused(&symbol);
             ShipView {
                 symbol,
                 role,
@@ -143,6 +169,7 @@ async fn api_ships(State(s): State<AppState>) -> Json<Vec<ShipView>> {
                 cargo_units: ship.cargo.units,
                 cargo_capacity: ship.cargo.capacity,
                 net_cash: ship_net_cash,
+                paused,
             }
         })
         .collect();
@@ -701,3 +728,155 @@ async fn api_market(
         observations,
     })
 }
+
+#[derive(Serialize)]
+struct MiningThroughputView {
+    // per-site and per-ship counters over the last hour / day (in-memory since startup)
+    last_hour: ThroughputReport,
+    last_day: ThroughputReport,
+}
+
+async fn api_mining(State(s): State<AppState>) -> Json<MiningThroughputView> {
+    let stats = &s.controller.ctx.mining_stats;
+    Json(MiningThroughputView {
+        last_hour: stats.report(1),
+        last_day: stats.report(24),
+    })
+}
+
+#[derive(Serialize)]
+struct GoodProfitView {
+    symbol: String,
+    #[serde(flatten)]
+    profit: GoodProfit,
+}
+
+// Realized trade profit per good this reset, most profitable first
+async fn api_profits(State(s): State<AppState>) -> Json<Vec<GoodProfitView>> {
+    let report = s.controller.ctx.ledger.profit_report();
+    Json(
+        report
+            .into_iter()
+            .map(|(symbol, profit)| GoodProfitView { symbol, profit })
+            .collect(),
+    )
+}
+
+// The planner's reasoning behind a logistics ship's latest schedule. Null unless the
+// agent runs with DEBUG_PLANNER_RATIONALE=1.
+async fn api_planner_rationale(
+    State(s): State<AppState>,
+    Path(ship): Path<String>,
+) -> Json<Option<PlannerRationale>> {
+    Json(s.controller.task_managers.planner_rationale(&ship))
+}
+
+#[derive(Deserialize)]
+struct WhatIfShipQuery {
+    model: String,
+}
+
+// Estimated earnings of buying one more ship of a model now (nothing is bought)
+// Operator-only: each call generates tasks and runs the planner, which an anonymous
+// caller could repeat until the agent's own planning starves
+async fn api_whatif_ship(
+    State(s): State<AppState>,
+    headers: HeaderMap,
+    Query(q): Query<WhatIfShipQuery>,
+) -> Result<Json<Option<ShipWhatIf>>, (StatusCode, String)> {
+    authorize_operator(CONFIG.operator_token.as_deref(), &headers)?;
+    Ok(Json(s.controller.what_if_ship(&q.model).await))
+}
+
+async fn api_pauses(State(s): State<AppState>) -> Json<Vec<PauseScope>> {
+    Json(s.controller.fleet.pauses())
+}
+
+// Operator endpoints need `Authorization: Bearer <OPERATOR_TOKEN>`, and refuse
+// everything while no token is configured
+fn authorize_operator(
+    token: Option<&str>,
+    headers: &HeaderMap,
+) -> Result<(), (StatusCode, String)> {
+    let Some(token) = token else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Operator endpoints are disabled: OPERATOR_TOKEN is not set".to_string(),
+        ));
+    };
+    let given = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match given {
+        Some(given) if constant_time_eq(given.as_bytes(), token.as_bytes()) => Ok(()),
+        _ => Err((
+            StatusCode::UNAUTHORIZED,
+            "Missing or invalid operator token".to_string(),
+        )),
+    }
+}
+
+// Compare without returning at the first mismatch, so response timing doesn't leak the
+// token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// Body is one scope: {"behaviour": "mining"}, {"job_pattern": "^siphon"} or
+// {"system": "X1-AB12"}. Returns the pauses now in force.
+async fn api_pause(
+    State(s): State<AppState>,
+    headers: HeaderMap,
+    Json(scope): Json<PauseScope>,
+) -> Result<Json<Vec<PauseScope>>, (StatusCode, String)> {
+    authorize_operator(CONFIG.operator_token.as_deref(), &headers)?;
+    Ok(Json(s.controller.fleet.pause(scope).await))
+}
+
+// Lifts exactly the given scope
+async fn api_resume(
+    State(s): State<AppState>,
+    headers: HeaderMap,
+    Json(scope): Json<PauseScope>,
+) -> Result<Json<Vec<PauseScope>>, (StatusCode, String)> {
+    authorize_operator(CONFIG.operator_token.as_deref(), &headers)?;
+    Ok(Json(s.controller.fleet.resume(&scope).await))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_authorize_operator() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(AUTHORIZATION, value.parse().unwrap());
+            headers
+        };
+        let status = |token, headers: &HeaderMap| {
+            authorize_operator(token, headers)
+                .err()
+                .map(|(status, _)| status)
+        };
+        assert_eq!(status(Some("s3cret"), &headers("Bearer s3cret")), None);
+        assert_eq!(
+            status(Some("s3cret"), &headers("Bearer s3cre")),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            status(Some("s3cret"), &headers("s3cret")),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            status(Some("s3cret"), &HeaderMap::new()),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+        // no token configured: nobody gets in
+        assert_eq!(
+            status(None, &headers("Bearer s3cret")),
+            Some(StatusCode::FORBIDDEN)
+        );
+    }
+}